@@ -0,0 +1,316 @@
+//! API HTTP locale pour piloter l'app depuis un script : `POST /start`, `POST /stop`,
+//! `GET /status` et `GET /room`, avec `?tunnel=N` (à partir de 1) pour viser un autre onglet
+//! que celui sélectionné. N'écoute que sur une adresse de loopback, et chaque requête doit porter
+//! le jeton du lancement (`Authorization: Bearer`, voir [`TOKEN_FILE`]). Un navigateur peut joindre
+//! le loopback (CSRF, DNS rebinding) : toute requête avec un `Origin`, ou dont le `Host` n'est pas
+//! l'adresse locale, est refusée avant le jeton.
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use serde::Serialize;
+use serde_json::json;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::token;
+
+/// Dans le dossier de config, régénéré à chaque démarrage de l'API.
+pub const TOKEN_FILE: &str = "admin-token";
+
+/// Demande reçue par l'API, appliquée par l'app à la frame suivante. L'index est celui de l'onglet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminCommand {
+    Start(usize),
+    Stop(usize),
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct TunnelStatus {
+    /// Numéro affiché dans l'onglet, à partir de 1
+    pub tunnel: usize,
+    pub running: bool,
+    pub status: String,
+    /// Le statut courant est une erreur
+    pub error: bool,
+    pub peers: Vec<String>,
+    pub room_code: Option<String>,
+}
+
+/// Ce que l'API expose, recopié par l'app à chaque frame.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct AdminStatus {
+    pub selected: usize,
+    pub tunnels: Vec<TunnelStatus>,
+}
+
+type SharedStatus = Arc<Mutex<AdminStatus>>;
+
+/// Serveur arrêté quand on le lâche.
+pub struct AdminServer {
+    task: JoinHandle<()>,
+    status: SharedStatus,
+    commands: Receiver<AdminCommand>,
+}
+
+impl AdminServer {
+    /// Refuse toute adresse hors loopback. `wake` réveille l'app quand une commande arrive,
+    /// fenêtre cachée comprise.
+    pub fn start(addr: &str, token: String, wake: impl Fn() + Send + Sync + 'static) -> io::Result<Self> {
+        let parsed: SocketAddr = addr
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "expected an address like 127.0.0.1:9470"))?;
+        if !parsed.ip().is_loopback() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the admin API only listens on localhost"));
+        }
+        let listener = std::net::TcpListener::bind(parsed)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        // Le port réel, si on a demandé le port 0
+        let port = listener.local_addr()?.port();
+        let token = Arc::new(token);
+        let status = SharedStatus::default();
+        let (tx, commands) = unbounded();
+        let wake = Arc::new(wake);
+        let shared = status.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let (status, tx, wake, token) = (shared.clone(), tx.clone(), wake.clone(), token.clone());
+                tokio::spawn(async move {
+                    if serve(socket, port, &token, &status, &tx).await {
+                        wake();
+                    }
+                });
+            }
+        });
+        Ok(Self { task, status, commands })
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = AdminCommand> + '_ {
+        self.commands.try_iter()
+    }
+
+    pub fn update(&self, status: AdminStatus) {
+        if let Ok(mut shared) = self.status.lock() {
+            *shared = status;
+        }
+    }
+}
+
+impl Drop for AdminServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Refus d'une requête d'après ses en-têtes, avant toute route.
+fn authorize(headers: &[(String, &str)], port: u16, token: &str) -> Result<(), (u16, String)> {
+    let header = |name: &str| headers.iter().find(|(key, _)| key == name).map(|(_, value)| *value);
+    let host = header("host").unwrap_or_default();
+    let local = [format!("127.0.0.1:{}", port), format!("localhost:{}", port), format!("[::1]:{}", port)];
+    if !local.iter().any(|addr| addr.eq_ignore_ascii_case(host)) {
+        return Err((403, json!({ "error": format!("Host must be 127.0.0.1:{}", port) }).to_string()));
+    }
+    // Seul un navigateur envoie `Origin` ; un script n'en a pas besoin
+    if header("origin").is_some() {
+        return Err((403, json!({ "error": "browser requests are not allowed" }).to_string()));
+    }
+    let given = header("authorization").and_then(|value| value.strip_prefix("Bearer ")).unwrap_or_default();
+    if !token::matches(token, given.trim()) {
+        return Err((401, json!({ "error": format!("missing or wrong token, see {}", TOKEN_FILE) }).to_string()));
+    }
+    Ok(())
+}
+
+/// Statut HTTP et corps JSON d'une requête, d'après sa ligne de requête.
+fn respond(method: &str, target: &str, status: &AdminStatus, commands: &Sender<AdminCommand>) -> (u16, String) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    // `?tunnel=N` ou, à défaut, l'onglet sélectionné
+    let index = match query.split('&').find_map(|pair| pair.strip_prefix("tunnel=")) {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if n >= 1 => n - 1,
+            _ => return (400, json!({ "error": "tunnel must be a number from 1" }).to_string()),
+        },
+        None => status.selected,
+    };
+    let known = ["/start", "/stop", "/status", "/room"];
+    let expected = if matches!(path, "/start" | "/stop") { "POST" } else { "GET" };
+    if !known.contains(&path) {
+        return (404, json!({ "error": "not found" }).to_string());
+    }
+    if method != expected {
+        return (405, json!({ "error": format!("use {} {}", expected, path) }).to_string());
+    }
+    if path == "/status" {
+        return (200, serde_json::to_string(status).unwrap_or_default());
+    }
+    let Some(tunnel) = status.tunnels.get(index) else {
+        return (404, json!({ "error": format!("no tunnel {}", index + 1) }).to_string());
+    };
+    match path {
+        "/room" => match &tunnel.room_code {
+            Some(code) => (200, json!({ "tunnel": tunnel.tunnel, "room_code": code }).to_string()),
+            None => (404, json!({ "tunnel": tunnel.tunnel, "error": "no room code yet" }).to_string()),
+        },
+        _ => {
+            let command = if path == "/start" { AdminCommand::Start(index) } else { AdminCommand::Stop(index) };
+            let _ = commands.send(command);
+            // Appliqué à la frame suivante : `GET /status` dira quand c'est fait
+            (202, json!({ "tunnel": tunnel.tunnel, "accepted": &path[1..] }).to_string())
+        }
+    }
+}
+
+/// `true` si une commande a été transmise à l'app.
+async fn serve(
+    mut socket: TcpStream,
+    port: u16,
+    token: &str,
+    status: &SharedStatus,
+    commands: &Sender<AdminCommand>,
+) -> bool {
+    // Pas de corps attendu : la ligne de requête suffit
+    let mut request = [0u8; 1024];
+    let Ok(Ok(n)) = tokio::time::timeout(Duration::from_secs(5), socket.read(&mut request)).await else {
+        return false;
+    };
+    let request = String::from_utf8_lossy(&request[..n]);
+    let mut lines = request.lines();
+    let mut words = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+    let headers: Vec<(String, &str)> = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim()))
+        .collect();
+    let (code, body) = match authorize(&headers, port, token) {
+        Ok(()) => {
+            let snapshot = status.lock().map(|status| status.clone()).unwrap_or_default();
+            respond(method, target, &snapshot, commands)
+        }
+        Err(refused) => refused,
+    };
+    let reason = match code {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        reason,
+        body.len(),
+        body
+    );
+    let _ = socket.write_all(response.as_bytes()).await;
+    let _ = socket.shutdown().await;
+    code == 202
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_tunnels() -> AdminStatus {
+        AdminStatus {
+            selected: 1,
+            tunnels: vec![
+                TunnelStatus {
+                    tunnel: 1,
+                    ..Default::default()
+                },
+                TunnelStatus {
+                    tunnel: 2,
+                    running: true,
+                    room_code: Some("ABCD".to_string()),
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn routes_pick_the_selected_tab_unless_told_otherwise() {
+        let (tx, rx) = unbounded();
+        let status = two_tunnels();
+        assert_eq!(respond("GET", "/room", &status, &tx), (200, r#"{"room_code":"ABCD","tunnel":2}"#.to_string()));
+        assert_eq!(respond("GET", "/room?tunnel=1", &status, &tx).0, 404);
+        assert_eq!(respond("POST", "/start?tunnel=1", &status, &tx).0, 202);
+        assert_eq!(respond("POST", "/stop", &status, &tx).0, 202);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [AdminCommand::Start(0), AdminCommand::Stop(1)]);
+
+        assert_eq!(respond("GET", "/start", &status, &tx).0, 405);
+        assert_eq!(respond("POST", "/stop?tunnel=3", &status, &tx).0, 404);
+        assert_eq!(respond("POST", "/stop?tunnel=0", &status, &tx).0, 400);
+        assert_eq!(respond("GET", "/metrics", &status, &tx).0, 404);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn browsers_and_strangers_are_refused() {
+        let headers = |pairs: &[(&str, &'static str)]| -> Vec<(String, &'static str)> {
+            pairs.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+        };
+        let ok = headers(&[("host", "127.0.0.1:9470"), ("authorization", "Bearer secret")]);
+        assert_eq!(authorize(&ok, 9470, "secret"), Ok(()));
+        let localhost = headers(&[("host", "localhost:9470"), ("authorization", "Bearer secret")]);
+        assert_eq!(authorize(&localhost, 9470, "secret"), Ok(()));
+
+        // DNS rebinding : le navigateur envoie le nom de domaine de la page
+        let rebound = headers(&[("host", "evil.example:9470"), ("authorization", "Bearer secret")]);
+        assert_eq!(authorize(&rebound, 9470, "secret").unwrap_err().0, 403);
+        let other_port = headers(&[("host", "127.0.0.1:80"), ("authorization", "Bearer secret")]);
+        assert_eq!(authorize(&other_port, 9470, "secret").unwrap_err().0, 403);
+        assert_eq!(authorize(&headers(&[("authorization", "Bearer secret")]), 9470, "secret").unwrap_err().0, 403);
+        // CSRF : même avec le bon `Host`, une requête de navigateur porte un `Origin`
+        let csrf = headers(&[("host", "127.0.0.1:9470"), ("origin", "null"), ("authorization", "Bearer secret")]);
+        assert_eq!(authorize(&csrf, 9470, "secret").unwrap_err().0, 403);
+
+        let anonymous = headers(&[("host", "127.0.0.1:9470")]);
+        assert_eq!(authorize(&anonymous, 9470, "secret").unwrap_err().0, 401);
+        let wrong = headers(&[("host", "127.0.0.1:9470"), ("authorization", "Bearer guess")]);
+        assert_eq!(authorize(&wrong, 9470, "secret").unwrap_err().0, 401);
+    }
+
+    #[tokio::test]
+    async fn start_over_http_wakes_the_app() {
+        assert!(AdminServer::start("0.0.0.0:0", String::new(), || {}).is_err());
+
+        let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = probe.local_addr().unwrap();
+        drop(probe);
+        let (woken_tx, woken) = unbounded();
+        let server = AdminServer::start(&addr.to_string(), "secret".to_string(), move || {
+            let _ = woken_tx.send(());
+        })
+        .unwrap();
+        server.update(two_tunnels());
+
+        let request = |head: String| async move {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(head.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let host = format!("Host: 127.0.0.1:{}", addr.port());
+        let refused = request(format!("POST /start?tunnel=1 HTTP/1.1\r\n{}\r\n\r\n", host)).await;
+        assert!(refused.starts_with("HTTP/1.1 401 Unauthorized"));
+        let csrf = format!("POST /start HTTP/1.1\r\n{}\r\nOrigin: http://evil.example\r\n\r\n", host);
+        assert!(request(csrf).await.starts_with("HTTP/1.1 403 Forbidden"));
+        assert!(server.commands().next().is_none());
+
+        let authorized = format!("POST /start?tunnel=1 HTTP/1.1\r\n{}\r\nAuthorization: Bearer secret\r\n\r\n", host);
+        let response = request(authorized).await;
+        assert!(response.starts_with("HTTP/1.1 202 Accepted"));
+        assert!(woken.recv_timeout(Duration::from_secs(1)).is_ok());
+        assert_eq!(server.commands().collect::<Vec<_>>(), [AdminCommand::Start(0)]);
+    }
+}
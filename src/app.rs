@@ -0,0 +1,903 @@
+//! La fenêtre : réglages globaux, barre d'onglets et historique, et les services qui vivent
+//! avec elle (métriques, API d'admin, instance unique, icône, raccourci global).
+
+use crossbeam_channel::{Receiver, Sender, bounded};
+use eframe::egui;
+use std::time::{Duration, Instant};
+use troudever_core::logging::LogLevel;
+use troudever_core::stats;
+use troudever_core::upstream::ProxyKind;
+use troudever_core::{EVENT_CHANNEL_CAPACITY, ProxyConfig, ProxyEvent, TunnelId};
+
+use crate::admin::{AdminCommand, AdminServer, AdminStatus, TunnelStatus};
+use crate::cli::LaunchOptions;
+use crate::config::{SCALE_RANGE, Settings, Theme};
+use crate::history::History;
+use crate::hotkey::Hotkey;
+use crate::i18n::{Language, message, tr, tr_args};
+use crate::instance::InstanceServer;
+use crate::logs::{JsonlFile, LogEntry, LogRetention};
+use crate::metrics::{MetricsServer, SharedMetrics};
+use crate::selftest::SelfTest;
+use crate::start_metrics;
+use crate::tab::{TunnelTab, desktop_notify};
+use crate::tray::{Tray, TrayCommand, TrayState};
+use crate::{admin, i18n, token, tray};
+
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+pub struct TrouDeVerApp {
+    pub settings: Settings,
+    log_file: JsonlFile,
+    log_file_input: String,
+    log_file_error: Option<String>,
+    metrics: SharedMetrics,
+    metrics_server: Option<MetricsServer>,
+    metrics_input: String,
+    metrics_error: Option<String>,
+    admin: Option<AdminServer>,
+    admin_input: String,
+    admin_error: Option<String>,
+    // Reçoit les options des lancements suivants
+    instance: Option<InstanceServer>,
+    history: History,
+    // Le panneau d'historique remplace l'onglet sélectionné
+    show_history: bool,
+    tunnels: Vec<TunnelTab>,
+    selected: usize,
+    next_id: TunnelId,
+
+    // Message éphémère en bas de la fenêtre, et depuis quand il est affiché
+    toast: Option<(String, Instant)>,
+    self_test: SelfTest,
+
+    tray: Option<Tray>,
+    hotkey: Option<Hotkey>,
+    hotkey_input: String,
+    hotkey_error: Option<String>,
+    // Fenêtre cachée dans la zone de notification
+    pub hidden: bool,
+    // Dernier zoom vu, pour garder celui des raccourcis Ctrl+/Ctrl-
+    zoom: f32,
+
+    // Communication
+    rx_event: Receiver<(TunnelId, ProxyEvent)>,
+    tx_event: Sender<(TunnelId, ProxyEvent)>,
+}
+
+impl TrouDeVerApp {
+    pub fn new(settings: Settings, instance: Option<(std::net::TcpListener, String)>, ctx: &egui::Context) -> Self {
+        // Borné : si l'UI rame, le moteur jette logs et stats plutôt que de gonfler la mémoire
+        let (tx, rx) = bounded(EVENT_CHANNEL_CAPACITY);
+        let mut log_file = JsonlFile::default();
+        log_file.set_max_bytes(settings.log_file_max_bytes());
+        let log_file_error = log_file.set_path(&settings.log_file).err().map(|e| e.to_string());
+        let mut tunnels: Vec<TunnelTab> = settings
+            .tunnels
+            .iter()
+            .enumerate()
+            .map(|(id, config)| TunnelTab::new(id, config.clone(), settings.log_retention, tx.clone()))
+            .collect();
+        if tunnels.is_empty() {
+            tunnels.push(TunnelTab::new(0, ProxyConfig::default(), settings.log_retention, tx.clone()));
+        }
+        let metrics = SharedMetrics::default();
+        let (metrics_server, metrics_error) = match start_metrics(&settings.metrics_addr, &metrics) {
+            Ok(server) => (server, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let (hotkey, hotkey_error) = register_hotkey(&settings.hotkey, ctx);
+        let (admin, admin_error) = match start_admin(&settings.admin_addr, ctx) {
+            Ok(server) => (server, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let wake = ctx.clone();
+        let instance = instance.and_then(|(listener, secret)| {
+            InstanceServer::start(listener, secret, move || wake.request_repaint()).ok()
+        });
+        Self {
+            log_file_input: settings.log_file.display().to_string(),
+            metrics_input: settings.metrics_addr.clone(),
+            metrics,
+            metrics_server,
+            metrics_error,
+            admin,
+            admin_input: settings.admin_addr.clone(),
+            admin_error,
+            instance,
+            history: History::load(),
+            show_history: false,
+            next_id: tunnels.len(),
+            log_file,
+            log_file_error,
+            tunnels,
+            selected: 0,
+            toast: None,
+            self_test: SelfTest::Idle,
+            tray: settings.tray_enabled().then(|| Tray::spawn(ctx)),
+            hotkey,
+            hotkey_input: settings.hotkey.clone(),
+            hotkey_error,
+            hidden: settings.starts_hidden(),
+            zoom: settings.appearance.scale(),
+            settings,
+            rx_event: rx,
+            tx_event: tx,
+        }
+    }
+}
+
+impl eframe::App for TrouDeVerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.follow_zoom(ctx);
+        self.drain_events(ctx);
+
+        let configs_before = self.tunnel_configs();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading(tr("app.heading"));
+            egui::CollapsingHeader::new(tr("settings.title"))
+                .id_salt("settings")
+                .show(ui, |ui| self.show_settings_panel(ui));
+            ui.separator();
+            self.show_tab_bar(ui);
+
+            if self.show_history {
+                self.show_history_panel(ui);
+            } else {
+                let profiles_before = self.settings.profiles.clone();
+                self.tunnels[self.selected].show(ui, &mut self.settings);
+                if self.settings.profiles != profiles_before {
+                    self.save_settings();
+                }
+            }
+        });
+
+        // Comme pour les réglages globaux : sauvegardé dès que ça change
+        let configs = self.tunnel_configs();
+        if configs != configs_before {
+            self.settings.tunnels = configs;
+            self.save_settings();
+        }
+
+        self.show_toast(ctx);
+        self.poll_hotkey(ctx);
+        self.poll_instance(ctx);
+        self.update_admin();
+        self.update_tray(ctx);
+        self.track_window(ctx);
+        ctx.request_repaint();
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_settings();
+    }
+}
+
+impl TrouDeVerApp {
+    /// Événements des tunnels, puis ce que les onglets demandent à l'app (presse-papier, historique).
+    fn drain_events(&mut self, ctx: &egui::Context) {
+        let rooms_before = self.settings.rooms.clone();
+        while let Ok((id, event)) = self.rx_event.try_recv() {
+            if let Ok(mut metrics) = self.metrics.lock() {
+                metrics.observe(id, &event);
+            }
+            // Un tunnel supprimé peut encore avoir des événements en vol : on les ignore
+            if let Some(tunnel) = self.tunnels.iter_mut().find(|t| t.id == id) {
+                match event {
+                    ProxyEvent::Log(record) if record.level > self.settings.log_level => {}
+                    ProxyEvent::Log(record) => {
+                        let entry = LogEntry::now(record);
+                        if let Err(e) = self.log_file.append(&tunnel.name(), &entry) {
+                            self.log_file_error = Some(e.to_string());
+                        }
+                        tunnel.push_log(entry);
+                    }
+                    event => tunnel.handle_event(event, &mut self.settings),
+                }
+            }
+        }
+        if self.settings.rooms != rooms_before {
+            self.save_settings();
+        }
+
+        let mut history_changed = false;
+        for tunnel in &mut self.tunnels {
+            if let Some(code) = tunnel.copy_request.take() {
+                ctx.copy_text(code.clone());
+                self.toast = Some((tr_args("toast.copied", &[("code", &code)]), Instant::now()));
+            }
+            if let Some(record) = tunnel.finished_session.take() {
+                self.history.push(record);
+                history_changed = true;
+            }
+        }
+        if history_changed {
+            self.save_history();
+        }
+    }
+
+    /// Réglages de l'app, enregistrés dès qu'ils changent.
+    fn show_settings_panel(&mut self, ui: &mut egui::Ui) {
+        let before = self.settings.clone();
+        self.show_general_settings(ui);
+        self.show_appearance_settings(ui);
+        self.show_log_settings(ui);
+        self.show_service_settings(ui);
+        self.show_advanced_settings(ui);
+        self.apply_settings(ui.ctx(), &before);
+    }
+
+    /// Notifications, room codes, hooks, icône et langue.
+    fn show_general_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.settings.notifications, tr("settings.notifications"));
+            ui.add_enabled_ui(self.settings.notifications, |ui| {
+                let notify = &mut self.settings.notify;
+                ui.checkbox(&mut notify.connected, tr("settings.notify_connected"));
+                ui.checkbox(&mut notify.disconnected, tr("settings.notify_lost"));
+                ui.checkbox(&mut notify.room_code, tr("settings.notify_room"));
+            });
+        });
+        ui.checkbox(&mut self.settings.auto_copy_room, tr("settings.auto_copy"));
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.recent_rooms"));
+            if ui.add(egui::DragValue::new(&mut self.settings.rooms.max).range(0..=100)).changed() {
+                self.settings.rooms.trim();
+            }
+        })
+        .response
+        .on_hover_text(tr("settings.recent_rooms_hint"));
+        egui::CollapsingHeader::new(tr("hooks.title")).id_salt("hooks").show(ui, |ui| {
+            let hooks = &mut self.settings.hooks;
+            for (label, command) in [
+                ("hooks.room_code", &mut hooks.room_code),
+                ("hooks.connected", &mut hooks.connected),
+                ("hooks.disconnected", &mut hooks.disconnected),
+            ] {
+                ui.horizontal(|ui| {
+                    ui.label(tr(label));
+                    ui.add(egui::TextEdit::singleline(command).hint_text(tr("hooks.none")));
+                })
+                .response
+                .on_hover_text(tr("hooks.hint"));
+            }
+        });
+        ui.horizontal(|ui| {
+            let tray = egui::Checkbox::new(&mut self.settings.tray_icon, tr("settings.tray"));
+            ui.add_enabled(tray::SUPPORTED, tray);
+            ui.add_enabled(
+                self.settings.tray_enabled(),
+                egui::Checkbox::new(&mut self.settings.start_minimized, tr("settings.start_minimized")),
+            );
+        })
+        .response
+        .on_hover_text(tr(if tray::SUPPORTED { "settings.tray_hint" } else { "settings.tray_unsupported" }));
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.language"));
+            egui::ComboBox::from_id_salt("language")
+                .selected_text(self.settings.language.label())
+                .show_ui(ui, |ui| {
+                    for language in Language::ALL {
+                        ui.selectable_value(&mut self.settings.language, language, language.label());
+                    }
+                });
+        });
+    }
+
+    fn show_appearance_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("appearance.title")).id_salt("appearance").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr("settings.theme"));
+                for theme in Theme::ALL {
+                    ui.radio_value(&mut self.settings.theme, theme, message(theme.label()));
+                }
+            });
+            let appearance = &mut self.settings.appearance;
+            ui.horizontal(|ui| {
+                ui.label(tr("appearance.scale"));
+                let slider = egui::Slider::new(&mut appearance.scale, SCALE_RANGE)
+                    .clamping(egui::SliderClamping::Edits)
+                    .step_by(0.05)
+                    .custom_formatter(|scale, _| format!("{:.0}%", scale * 100.0))
+                    .custom_parser(|text| {
+                        text.trim_end_matches('%').trim().parse::<f64>().ok().map(|p| p / 100.0)
+                    });
+                let slider = ui.add(slider);
+                // Zoomer pendant le glissement déplacerait le curseur sous la souris
+                if slider.drag_stopped() || (slider.changed() && !slider.dragged()) {
+                    ui.ctx().set_zoom_factor(appearance.scale());
+                }
+            })
+            .response
+            .on_hover_text(tr("appearance.scale_hint"));
+            ui.checkbox(&mut appearance.always_on_top, tr("appearance.always_on_top"));
+        });
+    }
+
+    fn show_log_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.log_level"));
+            egui::ComboBox::from_id_salt("log_level")
+                .selected_text(message(self.settings.log_level.label()))
+                .show_ui(ui, |ui| {
+                    for level in LogLevel::ALL {
+                        ui.selectable_value(&mut self.settings.log_level, level, message(level.label()));
+                    }
+                });
+        })
+        .response
+        .on_hover_text(tr("settings.log_level_hint"));
+        ui.checkbox(&mut self.settings.keep_logs, tr("settings.keep_logs"))
+            .on_hover_text(tr("settings.keep_logs_hint"));
+        let retention = &mut self.settings.log_retention;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.log_lines"));
+            let lines = egui::DragValue::new(&mut retention.max_lines).range(LogRetention::MAX_LINES_RANGE);
+            changed |= ui.add(lines.speed(100)).changed();
+        })
+        .response
+        .on_hover_text(tr("settings.log_lines_hint"));
+        changed |= ui
+            .checkbox(&mut retention.keep_problems, tr("settings.keep_problems"))
+            .on_hover_text(tr("settings.keep_problems_hint"))
+            .changed();
+        if changed {
+            for tunnel in &mut self.tunnels {
+                tunnel.set_log_retention(*retention);
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.log_file"));
+            // Appliqué à la perte de focus, pour ne pas créer un fichier par frappe
+            let input = egui::TextEdit::singleline(&mut self.log_file_input)
+                .hint_text(tr("common.disabled"))
+                .desired_width(160.0);
+            if ui.add(input).lost_focus() {
+                self.settings.log_file = self.log_file_input.trim().into();
+            }
+            if ui.button("…").on_hover_text(tr("common.browse")).clicked()
+                && let Some(path) = rfd::FileDialog::new().set_file_name("troudever.jsonl").save_file()
+            {
+                self.log_file_input = path.display().to_string();
+                self.settings.log_file = path;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.rotate"));
+            ui.add(egui::DragValue::new(&mut self.settings.log_file_max_mb).range(0..=10_000).suffix(" MB"))
+                .on_hover_text(tr("settings.rotate_hint"));
+        });
+        if let Some(e) = &self.log_file_error {
+            let error = tr_args("settings.log_file_error", &[("error", &message(e))]);
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    /// Métriques, API d'admin et raccourci global : relancés à la perte de focus du champ.
+    fn show_service_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.metrics"));
+            let input = egui::TextEdit::singleline(&mut self.metrics_input)
+                .hint_text(tr("settings.metrics_none"))
+                .desired_width(160.0);
+            if ui.add(input).lost_focus() && self.metrics_input.trim() != self.settings.metrics_addr {
+                self.settings.metrics_addr = self.metrics_input.trim().to_string();
+                // L'ancien serveur libère le port avant que le nouveau ne le prenne
+                self.metrics_server = None;
+                match start_metrics(&self.settings.metrics_addr, &self.metrics) {
+                    Ok(server) => {
+                        self.metrics_server = server;
+                        self.metrics_error = None;
+                    }
+                    Err(e) => self.metrics_error = Some(e.to_string()),
+                }
+            }
+        })
+        .response
+        .on_hover_text(tr("settings.metrics_hint"));
+        if let Some(e) = &self.metrics_error {
+            let error = tr_args("settings.metrics_error", &[("error", &message(e))]);
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.admin"));
+            let input = egui::TextEdit::singleline(&mut self.admin_input)
+                .hint_text(tr("settings.admin_none"))
+                .desired_width(160.0);
+            if ui.add(input).lost_focus() && self.admin_input.trim() != self.settings.admin_addr {
+                self.settings.admin_addr = self.admin_input.trim().to_string();
+                self.admin = None;
+                match start_admin(&self.settings.admin_addr, ui.ctx()) {
+                    Ok(server) => {
+                        self.admin = server;
+                        self.admin_error = None;
+                    }
+                    Err(e) => self.admin_error = Some(e.to_string()),
+                }
+            }
+        })
+        .response
+        .on_hover_text(tr("settings.admin_hint"));
+        if let Some(e) = &self.admin_error {
+            let error = tr_args("settings.admin_error", &[("error", &message(e))]);
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        ui.horizontal(|ui| {
+            ui.label(tr("settings.hotkey"));
+            let input = egui::TextEdit::singleline(&mut self.hotkey_input)
+                .hint_text(tr("settings.hotkey_none"))
+                .desired_width(160.0);
+            if ui.add(input).lost_focus() && self.hotkey_input.trim() != self.settings.hotkey {
+                self.settings.hotkey = self.hotkey_input.trim().to_string();
+                // L'ancien raccourci est libéré avant d'enregistrer le nouveau
+                drop(self.hotkey.take());
+                (self.hotkey, self.hotkey_error) = register_hotkey(&self.settings.hotkey, ui.ctx());
+            }
+        })
+        .response
+        .on_hover_text(tr("settings.hotkey_hint"));
+        if let Some(e) = &self.hotkey_error {
+            let error = tr_args("settings.hotkey_error", &[("error", &message(e))]);
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+
+    fn show_advanced_settings(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("settings.advanced")).id_salt("advanced").show(ui, |ui| {
+            let proxy = &mut self.settings.upstream_proxy;
+            ui.horizontal(|ui| {
+                ui.label(tr("upstream.kind"));
+                egui::ComboBox::from_id_salt("upstream_kind")
+                    .selected_text(message(proxy.kind.label()))
+                    .show_ui(ui, |ui| {
+                        for kind in ProxyKind::ALL {
+                            ui.selectable_value(&mut proxy.kind, kind, message(kind.label()));
+                        }
+                    });
+            });
+            if proxy.kind == ProxyKind::None {
+                ui.weak(tr("upstream.env_hint"));
+            }
+            ui.add_enabled_ui(proxy.kind != ProxyKind::None, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr("upstream.address"));
+                    ui.add(egui::TextEdit::singleline(&mut proxy.address).hint_text("127.0.0.1:1080"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label(tr("upstream.user"));
+                    ui.add(egui::TextEdit::singleline(&mut proxy.username).desired_width(90.0));
+                    ui.label(tr("upstream.password"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut proxy.password)
+                            .password(true)
+                            .desired_width(90.0),
+                    );
+                });
+            });
+            ui.horizontal(|ui| {
+                let running = self.self_test.is_running();
+                if ui
+                    .add_enabled(!running, egui::Button::new(tr("selftest.button")))
+                    .on_hover_text(tr("selftest.hint"))
+                    .clicked()
+                {
+                    self.self_test = SelfTest::start(ui.ctx().clone());
+                }
+                if running {
+                    ui.spinner();
+                }
+            });
+            self.self_test.show(ui);
+        });
+    }
+
+    /// Ce qui ne suit pas tout seul un réglage modifié (fichier de logs, icône, langue, fenêtre).
+    fn apply_settings(&mut self, ctx: &egui::Context, before: &Settings) {
+        if self.settings.log_file_max_mb != before.log_file_max_mb {
+            self.log_file.set_max_bytes(self.settings.log_file_max_bytes());
+        }
+        if self.settings.log_file != before.log_file {
+            self.log_file_error = self.log_file.set_path(&self.settings.log_file).err().map(|e| e.to_string());
+        }
+        if self.settings.tray_icon != before.tray_icon {
+            self.tray = self.settings.tray_enabled().then(|| Tray::spawn(ctx));
+        }
+        if self.settings.language != before.language {
+            i18n::set_language(self.settings.language);
+        }
+        if self.settings.appearance.always_on_top != before.appearance.always_on_top {
+            let level = self.settings.appearance.window_level();
+            ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(level));
+        }
+        if self.settings.theme != before.theme {
+            ctx.set_theme(self.settings.theme);
+        }
+        if self.settings != *before {
+            self.save_settings();
+        }
+    }
+
+    fn show_tab_bar(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        ui.horizontal_wrapped(|ui| {
+            for (index, tunnel) in self.tunnels.iter().enumerate() {
+                let label = if tunnel.is_running {
+                    format!("● {}", tunnel.label())
+                } else {
+                    tunnel.label()
+                };
+                if ui
+                    .selectable_label(!self.show_history && self.selected == index, label)
+                    .on_hover_text(tunnel.summary())
+                    .clicked()
+                {
+                    self.selected = index;
+                    self.show_history = false;
+                }
+            }
+            if ui.button("+").on_hover_text(tr("tabs.add")).clicked() {
+                let retention = self.settings.log_retention;
+                let tab = TunnelTab::new(self.next_id, ProxyConfig::default(), retention, self.tx_event.clone());
+                self.tunnels.push(tab);
+                self.next_id += 1;
+                self.selected = self.tunnels.len() - 1;
+                self.show_history = false;
+            }
+            if self.tunnels.len() > 1
+                && !self.show_history
+                && ui.button("🗑").on_hover_text(tr("tabs.remove")).clicked()
+            {
+                remove = Some(self.selected);
+            }
+            ui.separator();
+            if ui.selectable_label(self.show_history, tr("tabs.history")).clicked() {
+                self.show_history = !self.show_history;
+            }
+        });
+        ui.separator();
+
+        if let Some(index) = remove {
+            let mut tunnel = self.tunnels.remove(index);
+            tunnel.stop_proxy();
+            self.selected = self.selected.min(self.tunnels.len() - 1);
+        }
+    }
+
+    /// egui applique les raccourcis de zoom en fin de frame : on les retrouve à la suivante.
+    fn follow_zoom(&mut self, ctx: &egui::Context) {
+        let zoom = ctx.zoom_factor();
+        if zoom == self.zoom {
+            return;
+        }
+        self.zoom = zoom;
+        if self.settings.appearance.scale != zoom {
+            self.settings.appearance.scale = zoom;
+            self.save_settings();
+        }
+    }
+
+    /// Relevée à chaque frame, sauvée à la fermeture. Réduite, maximisée ou cachée, la fenêtre
+    /// garde la place qu'elle avait avant, où elle reviendra.
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let (inner, outer, minimized, maximized) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (viewport.inner_rect, viewport.outer_rect, viewport.minimized, viewport.maximized)
+        });
+        let window = &mut self.settings.window;
+        window.hidden = self.hidden;
+        if self.hidden {
+            return;
+        }
+        window.minimized = minimized == Some(true);
+        if window.minimized {
+            return;
+        }
+        window.maximized = maximized == Some(true);
+        if window.maximized {
+            return;
+        }
+        // egui donne des points au zoom courant
+        let zoom = ctx.zoom_factor();
+        if let Some(inner) = inner {
+            window.size = Some((inner.size() * zoom).into());
+        }
+        if let Some(outer) = outer {
+            window.position = Some((outer.min.to_vec2() * zoom).into());
+        }
+    }
+
+    fn poll_hotkey(&mut self, ctx: &egui::Context) {
+        let presses = self.hotkey.as_ref().map_or(0, Hotkey::presses);
+        for _ in 0..presses {
+            let tunnel = &mut self.tunnels[self.selected];
+            if tunnel.is_running {
+                tunnel.stop_proxy();
+            } else {
+                tunnel.start_proxy(&self.settings);
+            }
+            let key = if tunnel.is_running { "toast.started" } else { "toast.stopped" };
+            let toast = tr_args(key, &[("tunnel", &tunnel.label())]);
+            // Sans la fenêtre sous les yeux, seule une notification le dit (l'icône suit d'elle-même)
+            if self.settings.notifications && (self.hidden || !ctx.input(|i| i.focused)) {
+                let body = tr("notify.hotkey").to_string();
+                desktop_notify(toast.clone(), body, tunnel.events.clone());
+            }
+            self.toast = Some((toast, Instant::now()));
+        }
+    }
+
+    fn poll_instance(&mut self, ctx: &egui::Context) {
+        let launches: Vec<LaunchOptions> = self.instance.iter().flat_map(InstanceServer::launches).collect();
+        for launch in launches {
+            self.show_window(ctx);
+            self.show_history = false;
+            self.selected = 0;
+            self.apply_launch(launch);
+        }
+    }
+
+    fn update_admin(&mut self) {
+        let Some(admin) = &self.admin else {
+            return;
+        };
+        let commands: Vec<AdminCommand> = admin.commands().collect();
+        for command in commands {
+            match command {
+                AdminCommand::Start(index) => {
+                    if let Some(tunnel) = self.tunnels.get_mut(index).filter(|t| !t.is_running) {
+                        tunnel.start_proxy(&self.settings);
+                    }
+                }
+                AdminCommand::Stop(index) => {
+                    if let Some(tunnel) = self.tunnels.get_mut(index) {
+                        tunnel.stop_proxy();
+                    }
+                }
+            }
+        }
+        admin.update(AdminStatus {
+            selected: self.selected,
+            tunnels: self
+                .tunnels
+                .iter()
+                .map(|tunnel| TunnelStatus {
+                    tunnel: tunnel.id + 1,
+                    running: tunnel.is_running,
+                    status: tunnel.status_msg.clone(),
+                    error: tunnel.error,
+                    peers: tunnel.peers.clone(),
+                    room_code: tunnel.room_history.last().map(|(_, code)| code.clone()),
+                })
+                .collect(),
+        });
+    }
+
+    fn update_tray(&mut self, ctx: &egui::Context) {
+        let Some(tray) = &self.tray else {
+            return;
+        };
+        let commands: Vec<TrayCommand> = tray.commands().collect();
+        for command in commands {
+            self.apply_tray_command(ctx, command);
+        }
+
+        let state = self.tray_state();
+        let Some(tray) = &mut self.tray else {
+            return;
+        };
+        if let Err(e) = tray.update(state) {
+            self.tray = None;
+            let entry = LogEntry::app(LogLevel::Warn, format!("Tray icon unavailable: {}", e));
+            self.tunnels[self.selected].push_log(entry);
+            // Sans icône, une fenêtre cachée serait perdue
+            if self.hidden {
+                self.show_window(ctx);
+            }
+            return;
+        }
+        if tray.is_shown() && !self.hidden && ctx.input(|i| i.viewport().minimized) == Some(true) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+            self.hidden = true;
+        }
+    }
+
+    fn tray_state(&self) -> TrayState {
+        let tunnel = &self.tunnels[self.selected];
+        TrayState {
+            tunnel: tunnel.label(),
+            running: tunnel.is_running,
+            status: message(&tunnel.status_msg).into_owned(),
+            room_code: tunnel.room_history.last().map(|(_, code)| code.clone()),
+            connected: self.tunnels.iter().filter(|t| t.is_running).count(),
+            total: self.tunnels.len(),
+        }
+    }
+
+    fn apply_tray_command(&mut self, ctx: &egui::Context, command: TrayCommand) {
+        let tunnel = &mut self.tunnels[self.selected];
+        match command {
+            TrayCommand::ShowWindow => self.show_window(ctx),
+            TrayCommand::Connect => {
+                if !tunnel.is_running {
+                    tunnel.start_proxy(&self.settings);
+                }
+            }
+            TrayCommand::Disconnect => tunnel.stop_proxy(),
+            TrayCommand::CopyRoomCode => {
+                if let Some((_, code)) = tunnel.room_history.last() {
+                    ctx.copy_text(code.clone());
+                }
+            }
+            TrayCommand::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+        }
+    }
+
+    fn show_toast(&mut self, ctx: &egui::Context) {
+        let Some((text, since)) = &self.toast else {
+            return;
+        };
+        if since.elapsed() > TOAST_DURATION {
+            self.toast = None;
+            return;
+        }
+        egui::Area::new(egui::Id::new("toast"))
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -20.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(text.as_str()));
+            });
+    }
+
+    fn show_window(&mut self, ctx: &egui::Context) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        self.hidden = false;
+    }
+
+    fn tunnel_configs(&self) -> Vec<ProxyConfig> {
+        self.tunnels.iter().map(|tunnel| tunnel.config.clone()).collect()
+    }
+
+    /// Au démarrage, ou quand un second lancement passe ses options : seul le premier onglet est
+    /// concerné, et il garde sa configuration tant qu'il tourne.
+    pub fn apply_launch(&mut self, launch: LaunchOptions) {
+        let tunnel = &mut self.tunnels[0];
+        let mut config = tunnel.config.clone();
+        let mut profile_name = None;
+        if let Some(name) = launch.profile {
+            match self.settings.profile(&name) {
+                Some(profile) => {
+                    config = profile.config.clone();
+                    profile_name = Some(name);
+                }
+                None => tunnel.push_log(LogEntry::app(LogLevel::Warn, format!("No profile named {}", name))),
+            }
+        }
+        if let Some(ws_url) = launch.ws_url {
+            config.ws_url = ws_url;
+        }
+        if let Some(tcp_addr) = launch.tcp_addr {
+            config.tcp_addr = tcp_addr;
+        }
+        if config != tunnel.config || profile_name.is_some() {
+            if tunnel.is_running {
+                let warning = "Tunnel running: the new launch options are ignored".to_string();
+                tunnel.push_log(LogEntry::app(LogLevel::Warn, warning));
+            } else {
+                tunnel.config = config;
+                tunnel.profile_name = profile_name.unwrap_or_default();
+            }
+        }
+        if launch.autoconnect && !tunnel.is_running {
+            tunnel.start_proxy(&self.settings);
+        }
+    }
+
+    fn show_history_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading(tr("history.title"));
+            if ui
+                .add_enabled(!self.history.sessions.is_empty(), egui::Button::new(tr("common.clear")))
+                .clicked()
+            {
+                self.history.sessions.clear();
+                self.save_history();
+            }
+        });
+        if self.history.sessions.is_empty() {
+            ui.weak(tr("history.empty"));
+            return;
+        }
+        let mut reconnect = None;
+        egui::ScrollArea::vertical().id_salt("history").show(ui, |ui| {
+            for (index, session) in self.history.sessions.iter().enumerate().rev() {
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong(format!(
+                            "{} → {}",
+                            session.started.format("%Y-%m-%d %H:%M:%S"),
+                            session.ended.format("%H:%M:%S")
+                        ));
+                        if let Some(room) = &session.room_code {
+                            ui.label(tr_args("history.room", &[("code", room)]));
+                        }
+                        if ui
+                            .small_button(tr("history.reconnect"))
+                            .on_hover_text(tr("history.reconnect_hint"))
+                            .clicked()
+                        {
+                            reconnect = Some(index);
+                        }
+                    });
+                    ui.label(format!("{} ⇄ {}", session.config.ws_url, session.config.tcp_addr));
+                    let mut details = format!(
+                        "↑ {} ↓ {}",
+                        stats::format_bytes(session.bytes_up as f64),
+                        stats::format_bytes(session.bytes_down as f64)
+                    );
+                    if !session.peers.is_empty() {
+                        details.push_str(&tr_args("history.peers", &[("peers", &session.peers.join(", "))]));
+                    }
+                    ui.label(details);
+                    if !session.faults.is_empty() {
+                        ui.colored_label(ui.visuals().warn_fg_color, message(&session.faults.summary()));
+                    }
+                    ui.weak(message(&session.end_reason));
+                });
+            }
+        });
+        if let Some(index) = reconnect {
+            let session = self.history.sessions[index].clone();
+            let retention = self.settings.log_retention;
+            let mut tunnel = TunnelTab::new(self.next_id, session.config, retention, self.tx_event.clone());
+            self.next_id += 1;
+            // Même room qu'à l'époque : elle est redemandée au relais
+            if let Some(room) = session.room_code {
+                tunnel.push_room_code(room);
+            }
+            tunnel.start_proxy(&self.settings);
+            self.tunnels.push(tunnel);
+            self.selected = self.tunnels.len() - 1;
+            self.show_history = false;
+        }
+    }
+
+    fn save_history(&mut self) {
+        if let Err(e) = self.history.save() {
+            let entry = LogEntry::app(LogLevel::Error, format!("Failed to save session history: {}", e));
+            self.tunnels[self.selected].push_log(entry);
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save() {
+            let entry = LogEntry::app(LogLevel::Error, format!("Failed to save settings: {}", e));
+            self.tunnels[self.selected].push_log(entry);
+        }
+    }
+}
+
+/// Spec vide : pas de raccourci, et pas d'erreur.
+fn register_hotkey(spec: &str, ctx: &egui::Context) -> (Option<Hotkey>, Option<String>) {
+    if spec.trim().is_empty() {
+        return (None, None);
+    }
+    match Hotkey::register(spec, ctx) {
+        Ok(hotkey) => (Some(hotkey), None),
+        Err(e) => (None, Some(e)),
+    }
+}
+
+/// Comme les métriques : `Ok(None)` quand l'API est désactivée. Un nouveau jeton à chaque
+/// démarrage, écrit avant d'écouter : sans lui, l'API ne servirait à personne.
+fn start_admin(addr: &str, ctx: &egui::Context) -> std::io::Result<Option<AdminServer>> {
+    if addr.trim().is_empty() {
+        return Ok(None);
+    }
+    let secret = token::generate()?;
+    token::write(admin::TOKEN_FILE, &secret)?;
+    let ctx = ctx.clone();
+    AdminServer::start(addr.trim(), secret, move || ctx.request_repaint()).map(Some)
+}
+
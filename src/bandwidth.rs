@@ -0,0 +1,132 @@
+//! Débit montant et descendant de la dernière minute, tracé à la main avec le painter d'egui :
+//! un creux à zéro en pleine partie, c'est un gel, un pic, une rafale.
+
+use eframe::egui::{self, Color32, Pos2, Sense, Shape, Stroke, Vec2};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use troudever_core::stats::{Stats, format_rate};
+
+use crate::i18n::tr_args;
+
+pub const WINDOW: Duration = Duration::from_secs(60);
+const HEIGHT: f32 = 60.0;
+const UP_COLOR: Color32 = Color32::from_rgb(90, 160, 255);
+const DOWN_COLOR: Color32 = Color32::from_rgb(80, 200, 120);
+/// Échelle minimale : un filet de keep-alive ne doit pas remplir le graphe
+const MIN_SCALE: f64 = 1024.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+    at: Instant,
+    up: f64,
+    down: f64,
+}
+
+/// Un point par instantané de stats (une fois par seconde), plus vieux en premier.
+#[derive(Default)]
+pub struct Bandwidth {
+    samples: VecDeque<Sample>,
+}
+
+impl Bandwidth {
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn record(&mut self, at: Instant, stats: &Stats) {
+        self.samples.push_back(Sample {
+            at,
+            up: stats.up_rate,
+            down: stats.down_rate,
+        });
+        while self.samples.front().is_some_and(|sample| at.duration_since(sample.at) > WINDOW) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Plus haut débit de la fenêtre, dans un sens ou l'autre.
+    pub fn peak(&self) -> f64 {
+        self.samples.iter().map(|sample| sample.up.max(sample.down)).fold(0.0, f64::max)
+    }
+
+    pub fn show(&self, ui: &mut egui::Ui, now: Instant) {
+        let size = Vec2::new(ui.available_width(), HEIGHT);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+        let visuals = ui.visuals();
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+        let scale = self.peak().max(MIN_SCALE);
+        let point = |at: Instant, rate: f64| {
+            let age = now.saturating_duration_since(at).as_secs_f32() / WINDOW.as_secs_f32();
+            Pos2::new(rect.right() - age * rect.width(), rect.bottom() - (rate / scale) as f32 * rect.height())
+        };
+        let up = self.samples.iter().map(|sample| point(sample.at, sample.up)).collect();
+        let down = self.samples.iter().map(|sample| point(sample.at, sample.down)).collect();
+        painter.add(Shape::line(up, Stroke::new(1.5, UP_COLOR)));
+        painter.add(Shape::line(down, Stroke::new(1.5, DOWN_COLOR)));
+        let peak = tr_args("bandwidth.peak", &[("rate", &format_rate(scale))]);
+        painter.text(
+            rect.left_top() + Vec2::new(4.0, 2.0),
+            egui::Align2::LEFT_TOP,
+            peak,
+            egui::FontId::proportional(10.0),
+            visuals.weak_text_color(),
+        );
+        let legend = rect.right_top() + Vec2::new(-4.0, 2.0);
+        let font = egui::FontId::proportional(10.0);
+        let down = painter.text(legend, egui::Align2::RIGHT_TOP, "↓", font.clone(), DOWN_COLOR);
+        painter.text(down.left_top() - Vec2::new(4.0, 0.0), egui::Align2::RIGHT_TOP, "↑", font, UP_COLOR);
+
+        // Survol : les deux débits de l'instantané le plus proche
+        let hovered = response.hover_pos().and_then(|pos| {
+            let age = WINDOW.mul_f32(((rect.right() - pos.x) / rect.width()).clamp(0.0, 1.0));
+            let at = now.checked_sub(age)?;
+            self.samples.iter().min_by_key(|sample| sample.at.max(at) - sample.at.min(at))
+        });
+        if let Some(sample) = hovered {
+            let x = point(sample.at, 0.0).x;
+            painter.vline(x, rect.y_range(), Stroke::new(1.0, visuals.weak_text_color()));
+            let ago = now.saturating_duration_since(sample.at).as_secs();
+            response.on_hover_text(tr_args(
+                "bandwidth.hover",
+                &[("up", &format_rate(sample.up)), ("down", &format_rate(sample.down)), ("ago", &ago)],
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(up: f64, down: f64) -> Stats {
+        Stats {
+            up_rate: up,
+            down_rate: down,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_the_last_minute_is_kept() {
+        let t0 = Instant::now();
+        let mut bandwidth = Bandwidth::default();
+        bandwidth.record(t0, &stats(50_000.0, 10.0));
+        for secs in 1..=60 {
+            bandwidth.record(t0 + Duration::from_secs(secs), &stats(100.0, 2_000.0));
+        }
+        assert_eq!(bandwidth.samples.len(), 61);
+        assert_eq!(bandwidth.peak(), 50_000.0);
+        // Le pic sort de la fenêtre
+        bandwidth.record(t0 + Duration::from_secs(61), &stats(0.0, 0.0));
+        assert_eq!(bandwidth.samples.len(), 61);
+        assert_eq!(bandwidth.peak(), 2_000.0);
+        bandwidth.clear();
+        assert!(bandwidth.is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+
+/// Valeurs de lancement venant de la ligne de commande et de l'environnement.
+///
+/// Priorité : CLI > env > config sauvegardée > défauts. Les deux derniers sont gérés par l'app,
+/// ici on ne garde que ce qui a été explicitement fourni.
+#[derive(Parser, Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+#[command(name = "troudever", version, about = "WebSocket <-> TCP proxy", long_about = None)]
+pub struct LaunchOptions {
+    /// WebSocket gateway URL [env: TROUDEVER_WS_URL]
+    #[arg(long = "ws-url", visible_alias = "ws", value_name = "URL")]
+    pub ws_url: Option<String>,
+    /// TCP server address [env: TROUDEVER_TCP_ADDR]
+    #[arg(long = "tcp-addr", visible_alias = "tcp", value_name = "HOST:PORT")]
+    pub tcp_addr: Option<String>,
+    /// Load a saved profile into the first tunnel (--ws-url and --tcp-addr still override it)
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Start the first tunnel right away
+    #[arg(long = "auto-start", visible_aliases = ["autoconnect", "connect"])]
+    pub autoconnect: bool,
+    /// Run without a window and print logs to stdout (implies --auto-start)
+    #[arg(long)]
+    pub headless: bool,
+    /// Run the built-in self-test against in-process echo servers, then exit (status 1 on failure)
+    #[arg(long = "self-test")]
+    pub self_test: bool,
+    /// Open a new window even if one is already running, instead of handing it these options
+    #[arg(long = "new-instance")]
+    pub new_instance: bool,
+}
+
+impl LaunchOptions {
+    pub fn from_env() -> Result<Self, clap::Error> {
+        Self::parse_with(std::env::args_os(), |key| std::env::var(key).ok())
+    }
+
+    // L'env est lu à la main plutôt que via `clap/env`, pour pouvoir le simuler en test
+    fn parse_with<I, T>(args: I, env: impl Fn(&str) -> Option<String>) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut options = Self::try_parse_from(args)?;
+        let env = |key| env(key).filter(|v| !v.is_empty());
+        options.ws_url = options.ws_url.or_else(|| env("TROUDEVER_WS_URL"));
+        options.tcp_addr = options.tcp_addr.or_else(|| env("TROUDEVER_TCP_ADDR"));
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("troudever").chain(list.iter().copied()).map(String::from).collect()
+    }
+
+    #[test]
+    fn cli_overrides_env() {
+        let env = |key: &str| match key {
+            "TROUDEVER_WS_URL" => Some("ws://env:1".to_string()),
+            "TROUDEVER_TCP_ADDR" => Some("10.0.0.1:9000".to_string()),
+            _ => None,
+        };
+        let options = LaunchOptions::parse_with(args(&["--ws", "ws://cli:2", "--autoconnect"]), env).unwrap();
+        assert_eq!(options.ws_url.as_deref(), Some("ws://cli:2"));
+        assert_eq!(options.tcp_addr.as_deref(), Some("10.0.0.1:9000"));
+        assert!(options.autoconnect);
+    }
+
+    #[test]
+    fn inline_values_and_empty_env() {
+        let env = |key: &str| (key == "TROUDEVER_WS_URL").then(String::new);
+        let options = LaunchOptions::parse_with(args(&["--tcp=127.0.0.1:7000"]), env).unwrap();
+        assert_eq!(options.ws_url, None);
+        assert_eq!(options.tcp_addr.as_deref(), Some("127.0.0.1:7000"));
+        assert!(!options.autoconnect);
+    }
+
+    #[test]
+    fn headless_long_names() {
+        let options = LaunchOptions::parse_with(
+            args(&["--headless", "--ws-url", "wss://relay", "--tcp-addr", "127.0.0.1:7000", "--auto-start"]),
+            |_| None,
+        )
+        .unwrap();
+        assert!(options.headless && options.autoconnect);
+        assert_eq!(options.ws_url.as_deref(), Some("wss://relay"));
+        assert_eq!(options.tcp_addr.as_deref(), Some("127.0.0.1:7000"));
+    }
+
+    #[test]
+    fn desktop_shortcut_form() {
+        let options = LaunchOptions::parse_with(
+            args(&["--ws", "wss://relay.example", "--tcp", "127.0.0.1:9000", "--connect"]),
+            |_| None,
+        )
+        .unwrap();
+        assert!(options.autoconnect && !options.headless);
+        assert_eq!(options.ws_url.as_deref(), Some("wss://relay.example"));
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert!(LaunchOptions::parse_with(args(&["--ws"]), |_| None).is_err());
+        assert!(LaunchOptions::parse_with(args(&["--port", "1"]), |_| None).is_err());
+    }
+
+    #[test]
+    fn self_test_flag() {
+        let options = LaunchOptions::parse_with(args(&["--self-test"]), |_| None).unwrap();
+        assert!(options.self_test && !options.headless);
+    }
+
+    #[test]
+    fn profile_and_new_instance() {
+        let options =
+            LaunchOptions::parse_with(args(&["--profile", "dev", "--connect", "--new-instance"]), |_| None).unwrap();
+        assert_eq!(options.profile.as_deref(), Some("dev"));
+        assert!(options.autoconnect && options.new_instance);
+    }
+}
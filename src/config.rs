@@ -0,0 +1,399 @@
+use chrono::{DateTime, Local};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use troudever_core::ProxyConfig;
+use troudever_core::logging::LogLevel;
+use troudever_core::upstream::UpstreamProxy;
+
+use crate::hooks::Hooks;
+use crate::i18n::Language;
+use crate::logs::LogRetention;
+
+/// Réglages globaux de l'app, sauvegardés en JSON dans le dossier de config de la plateforme.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub notifications: bool,
+    /// Détail de `notifications`, par type d'événement
+    pub notify: NotifyOn,
+    /// Commandes lancées sur les événements des tunnels, vides = aucune
+    pub hooks: Hooks,
+    /// Copie chaque nouveau room code dans le presse-papier
+    pub auto_copy_room: bool,
+    /// Room codes déjà vus, pour rejoindre celle d'hier
+    pub rooms: RoomHistory,
+    /// Icône dans la zone de notification ; la fenêtre minimisée s'y cache
+    pub tray_icon: bool,
+    pub start_minimized: bool,
+    pub theme: Theme,
+    pub appearance: Appearance,
+    /// Fenêtre telle qu'à la dernière fermeture
+    pub window: WindowState,
+    /// Langue de l'interface ; logs, historique et API restent en anglais
+    pub language: Language,
+    pub upstream_proxy: UpstreamProxy,
+    /// Logs plus bavards que ce niveau ignorés, à l'écran comme dans le fichier
+    pub log_level: LogLevel,
+    /// Lignes gardées à l'écran par onglet
+    pub log_retention: LogRetention,
+    /// Fichier JSONL alimenté en continu, vide = désactivé
+    pub log_file: PathBuf,
+    /// Rotation du fichier de logs à cette taille (Mo), 0 = jamais
+    pub log_file_max_mb: u64,
+    /// Garde les logs d'un onglet d'une connexion à l'autre, pour comparer les tentatives
+    pub keep_logs: bool,
+    /// Raccourci global (`ctrl+shift+F9`) qui lance ou arrête l'onglet sélectionné, vide = aucun
+    pub hotkey: String,
+    /// Adresse d'écoute de l'endpoint Prometheus (`127.0.0.1:9464`), vide = désactivé
+    pub metrics_addr: String,
+    /// API HTTP de contrôle (`127.0.0.1:9470`), loopback uniquement, vide = désactivée
+    pub admin_addr: String,
+    /// Un par onglet, dans l'ordre
+    pub tunnels: Vec<ProxyConfig>,
+    pub profiles: Vec<Profile>,
+}
+
+/// Événements qui déclenchent une notification. Par défaut ceux d'avant le réglage : la room
+/// et la perte du tunnel.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct NotifyOn {
+    pub connected: bool,
+    pub disconnected: bool,
+    pub room_code: bool,
+}
+
+impl Default for NotifyOn {
+    fn default() -> Self {
+        Self {
+            connected: false,
+            disconnected: true,
+            room_code: true,
+        }
+    }
+}
+
+/// Room code donné par un relais, gardé d'un lancement à l'autre.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecentRoom {
+    pub code: String,
+    pub seen: DateTime<Local>,
+    /// `tcp_addr` du tunnel qui l'a reçu : un code ne vaut que sur son relais
+    pub relay: String,
+}
+
+/// Plus récent en dernier, tous relais confondus.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RoomHistory {
+    /// Codes gardés au plus, 0 = aucun
+    pub max: usize,
+    pub rooms: Vec<RecentRoom>,
+}
+
+impl Default for RoomHistory {
+    fn default() -> Self {
+        Self {
+            max: 10,
+            rooms: vec![],
+        }
+    }
+}
+
+impl RoomHistory {
+    /// Un code déjà connu sur ce relais remonte en tête avec sa nouvelle date.
+    pub fn remember(&mut self, relay: &str, code: &str, seen: DateTime<Local>) {
+        self.rooms.retain(|room| room.relay != relay || room.code != code);
+        self.rooms.push(RecentRoom {
+            code: code.to_string(),
+            seen,
+            relay: relay.to_string(),
+        });
+        self.trim();
+    }
+
+    pub fn trim(&mut self) {
+        let excess = self.rooms.len().saturating_sub(self.max);
+        self.rooms.drain(..excess);
+    }
+
+    /// Ceux d'un relais, du plus récent au plus ancien.
+    pub fn for_relay<'a>(&'a self, relay: &'a str) -> impl Iterator<Item = &'a RecentRoom> {
+        self.rooms.iter().rev().filter(move |room| room.relay == relay)
+    }
+}
+
+/// Zoom de toute l'interface, texte compris (egui : `zoom_factor`).
+pub const SCALE_RANGE: RangeInclusive<f32> = 0.5..=3.0;
+
+/// Taille de l'interface et place de la fenêtre ; le thème a son propre champ, plus ancien.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Appearance {
+    pub scale: f32,
+    pub always_on_top: bool,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            always_on_top: false,
+        }
+    }
+}
+
+impl Appearance {
+    /// Un fichier édité à la main ne doit pas rendre la fenêtre illisible
+    pub fn scale(&self) -> f32 {
+        self.scale.clamp(*SCALE_RANGE.start(), *SCALE_RANGE.end())
+    }
+
+    pub fn window_level(&self) -> egui::WindowLevel {
+        if self.always_on_top { egui::WindowLevel::AlwaysOnTop } else { egui::WindowLevel::Normal }
+    }
+}
+
+/// Taille par défaut, avant la première fermeture
+const WINDOW_SIZE: [f32; 2] = [400.0, 550.0];
+/// Un fichier édité à la main ne doit pas rouvrir une fenêtre minuscule
+const MIN_WINDOW_SIZE: f32 = 200.0;
+
+/// Place et état de la fenêtre, pour la rouvrir là où on l'a laissée. En points à zoom 1,
+/// comme les attend `ViewportBuilder`.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct WindowState {
+    /// Coin haut gauche, décorations comprises ; jamais connu sous Wayland
+    pub position: Option<[f32; 2]>,
+    /// Taille du contenu de la fenêtre ni maximisée ni réduite
+    pub size: Option<[f32; 2]>,
+    pub maximized: bool,
+    pub minimized: bool,
+    /// Cachée dans la zone de notification
+    pub hidden: bool,
+}
+
+impl WindowState {
+    pub fn viewport(&self, builder: egui::ViewportBuilder) -> egui::ViewportBuilder {
+        let size = self.size.unwrap_or(WINDOW_SIZE).map(|side| side.max(MIN_WINDOW_SIZE));
+        let builder = builder.with_inner_size(size).with_maximized(self.maximized);
+        match self.position {
+            Some(position) => builder.with_position(position),
+            None => builder,
+        }
+    }
+}
+
+/// Config de tunnel nommée, à recharger dans n'importe quel onglet (relais de dev, de prod...).
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub config: ProxyConfig,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+pub enum Theme {
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Dark, Theme::Light];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::System => "System",
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+}
+
+impl From<Theme> for egui::ThemePreference {
+    fn from(theme: Theme) -> Self {
+        match theme {
+            Theme::System => egui::ThemePreference::System,
+            Theme::Dark => egui::ThemePreference::Dark,
+            Theme::Light => egui::ThemePreference::Light,
+        }
+    }
+}
+
+impl Settings {
+    /// Fenêtre cachée au lancement, réglage ou état de la dernière fermeture : seulement avec
+    /// une icône pour la rouvrir.
+    pub fn starts_hidden(&self) -> bool {
+        self.tray_enabled() && (self.start_minimized || self.window.hidden)
+    }
+
+    pub fn tray_enabled(&self) -> bool {
+        self.tray_icon && crate::tray::SUPPORTED
+    }
+
+    pub fn notify_connected(&self) -> bool {
+        self.notifications && self.notify.connected
+    }
+
+    pub fn notify_disconnected(&self) -> bool {
+        self.notifications && self.notify.disconnected
+    }
+
+    pub fn notify_room_code(&self) -> bool {
+        self.notifications && self.notify.room_code
+    }
+
+    pub fn log_file_max_bytes(&self) -> u64 {
+        self.log_file_max_mb.saturating_mul(1_000_000)
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Remplace le profil du même nom s'il existe.
+    pub fn save_profile(&mut self, name: &str, config: ProxyConfig) {
+        let profile = Profile {
+            name: name.to_string(),
+            config,
+        };
+        match self.profiles.iter_mut().find(|profile| profile.name == name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    pub fn delete_profile(&mut self, name: &str) {
+        self.profiles.retain(|profile| profile.name != name);
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("troudever").join("settings.json"))
+    }
+
+    /// Fichier absent ou illisible : on repart des valeurs par défaut.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use troudever_core::config::DEFAULT_READ_BUFFER;
+
+    #[test]
+    fn older_settings_load_and_tunnels_roundtrip() {
+        let settings: Settings = serde_json::from_str(r#"{"notifications":true}"#).unwrap();
+        assert!(settings.notify_room_code() && settings.notify_disconnected());
+        assert!(!settings.notify_connected());
+        assert!(settings.tunnels.is_empty());
+        assert_eq!(settings.appearance.scale(), 1.0);
+        let huge: Appearance = serde_json::from_str(r#"{"scale":40.0}"#).unwrap();
+        assert_eq!(huge.scale(), *SCALE_RANGE.end());
+
+        let mut tunnel = ProxyConfig {
+            ws_url: "wss://relay.example".to_string(),
+            max_retries: 5,
+            ..Default::default()
+        };
+        tunnel.upstream.password = "secret".to_string();
+        let json = serde_json::to_string(&Settings {
+            tunnels: vec![tunnel],
+            ..Default::default()
+        })
+        .unwrap();
+        // Le proxy amont n'est stocké qu'une fois, dans les réglages globaux
+        assert!(!json.contains("secret"));
+
+        let loaded: Settings = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.tunnels[0].ws_url, "wss://relay.example");
+        assert_eq!(loaded.tunnels[0].max_retries, 5);
+        assert_eq!(loaded.tunnels[0].read_buffer_size, DEFAULT_READ_BUFFER);
+    }
+
+    #[test]
+    fn profiles_are_replaced_by_name() {
+        let mut settings = Settings::default();
+        let dev = ProxyConfig {
+            ws_url: "ws://localhost:4455".to_string(),
+            ..Default::default()
+        };
+        settings.save_profile("dev", dev.clone());
+        settings.save_profile("prod", ProxyConfig::default());
+        settings.save_profile(
+            "dev",
+            ProxyConfig {
+                ws_url: "ws://127.0.0.1:4456".to_string(),
+                ..dev
+            },
+        );
+        assert_eq!(settings.profiles.len(), 2);
+        assert_eq!(settings.profile("dev").unwrap().config.ws_url, "ws://127.0.0.1:4456");
+
+        settings.delete_profile("dev");
+        assert!(settings.profile("dev").is_none());
+        assert_eq!(settings.profiles[0].name, "prod");
+    }
+
+    #[test]
+    fn window_reopens_as_it_was_left() {
+        let mut settings = Settings::default();
+        settings.window.hidden = true;
+        // Sans icône, une fenêtre cachée ne se rouvrirait pas
+        assert!(!settings.starts_hidden());
+        settings.tray_icon = true;
+        assert_eq!(settings.starts_hidden(), crate::tray::SUPPORTED);
+
+        let tiny = WindowState {
+            size: Some([50.0, 900.0]),
+            ..Default::default()
+        };
+        let builder = tiny.viewport(egui::ViewportBuilder::default());
+        assert_eq!(builder.inner_size, Some(egui::vec2(MIN_WINDOW_SIZE, 900.0)));
+        assert_eq!(builder.position, None);
+        let builder = WindowState::default().viewport(egui::ViewportBuilder::default());
+        assert_eq!(builder.inner_size, Some(WINDOW_SIZE.into()));
+    }
+
+    #[test]
+    fn recent_rooms_are_kept_per_relay_and_capped() {
+        let mut rooms = RoomHistory {
+            max: 3,
+            ..Default::default()
+        };
+        let now = Local::now();
+        for code in ["A", "B", "C"] {
+            rooms.remember("relay:9000", code, now);
+        }
+        rooms.remember("other:9000", "A", now);
+        // Revu : remonte au lieu d'apparaître deux fois
+        rooms.remember("relay:9000", "B", now);
+        let codes: Vec<&str> = rooms.for_relay("relay:9000").map(|room| room.code.as_str()).collect();
+        assert_eq!(codes, ["B", "C"]);
+        assert_eq!(rooms.for_relay("other:9000").count(), 1);
+
+        rooms.max = 0;
+        rooms.trim();
+        assert!(rooms.rooms.is_empty());
+    }
+}
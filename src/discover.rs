@@ -0,0 +1,80 @@
+//! Bouton "Discover" du serveur TCP : la recherche mDNS/SSDP du moteur tourne en tâche de
+//! fond, l'onglet la relève à chaque frame et affiche la liste jusqu'au choix.
+
+use eframe::egui;
+use std::io;
+use tokio::sync::oneshot;
+use troudever_core::discovery::{self, Server};
+
+use crate::i18n::{tr, tr_args};
+
+#[derive(Default)]
+pub enum Discovery {
+    #[default]
+    Idle,
+    Searching(oneshot::Receiver<io::Result<Vec<Server>>>),
+    Found(Result<Vec<Server>, String>),
+}
+
+impl Discovery {
+    pub fn start(ctx: egui::Context) -> Self {
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = tx.send(discovery::discover(discovery::DISCOVERY_WAIT).await);
+            ctx.request_repaint();
+        });
+        Discovery::Searching(rx)
+    }
+
+    pub fn is_searching(&self) -> bool {
+        matches!(self, Discovery::Searching(_))
+    }
+
+    fn poll(&mut self) {
+        let Discovery::Searching(rx) = self else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok(result) => *self = Discovery::Found(result.map_err(|e| e.to_string())),
+            Err(oneshot::error::TryRecvError::Empty) => {}
+            Err(oneshot::error::TryRecvError::Closed) => {
+                *self = Discovery::Found(Err("discovery task ended".to_string()));
+            }
+        }
+    }
+
+    /// La liste des serveurs trouvés ; rend l'adresse choisie, et la liste se referme.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        self.poll();
+        let Discovery::Found(result) = self else {
+            return None;
+        };
+        let mut picked = None;
+        let mut close = false;
+        ui.group(|ui| {
+            match result {
+                Ok(servers) if servers.is_empty() => {
+                    ui.label(tr("discover.none"));
+                }
+                Ok(servers) => {
+                    ui.label(tr("discover.pick"));
+                    for server in servers.iter() {
+                        let text = format!("{}  {}  ({})", server.name, server.addr, server.via.label());
+                        if ui.selectable_label(false, text).clicked() {
+                            picked = Some(server.addr.clone());
+                        }
+                    }
+                }
+                Err(e) => {
+                    let text = tr_args("discover.failed", &[("error", e)]);
+                    ui.colored_label(ui.visuals().error_fg_color, text);
+                }
+            }
+            close = ui.small_button(tr("discover.close")).clicked();
+        });
+        if picked.is_some() || close {
+            *self = Discovery::Idle;
+        }
+        picked
+    }
+}
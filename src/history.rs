@@ -0,0 +1,141 @@
+//! Historique des sessions terminées, sauvegardé à côté des réglages : de quoi retrouver
+//! quand un tunnel a tourné, avec qui, et le relancer à l'identique.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use troudever_core::stats::{Faults, Totals};
+use troudever_core::{ProxyConfig, ProxyEvent};
+
+// Au-delà, les plus anciennes sessions sont oubliées
+const MAX_SESSIONS: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SessionRecord {
+    pub started: DateTime<Local>,
+    pub ended: DateTime<Local>,
+    /// Pairs annoncés par le relais pendant la session
+    pub peers: Vec<String>,
+    pub room_code: Option<String>,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    /// Messages perdus, écritures ratées... cumulés sur la session
+    #[serde(default)]
+    pub faults: Faults,
+    pub end_reason: String,
+    pub config: ProxyConfig,
+}
+
+/// Session en cours d'un onglet, alimentée par ses événements.
+pub struct Session {
+    started: DateTime<Local>,
+    config: ProxyConfig,
+    peers: Vec<String>,
+    totals: Totals,
+}
+
+impl Session {
+    pub fn start(config: ProxyConfig) -> Self {
+        Self {
+            started: Local::now(),
+            config,
+            peers: vec![],
+            totals: Totals::default(),
+        }
+    }
+
+    pub fn observe(&mut self, event: &ProxyEvent) {
+        match event {
+            ProxyEvent::Stats(stats) => self.totals.observe(stats),
+            ProxyEvent::PeerJoined(peer) if !self.peers.contains(peer) => self.peers.push(peer.clone()),
+            _ => {}
+        }
+    }
+
+    pub fn finish(self, room_code: Option<String>, end_reason: String) -> SessionRecord {
+        SessionRecord {
+            started: self.started,
+            ended: Local::now(),
+            peers: self.peers,
+            room_code,
+            bytes_up: self.totals.up.bytes,
+            bytes_down: self.totals.down.bytes,
+            faults: self.totals.faults,
+            end_reason,
+            config: self.config,
+        }
+    }
+}
+
+/// Plus récente en dernier, comme dans le fichier.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct History {
+    pub sessions: Vec<SessionRecord>,
+}
+
+impl History {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("troudever").join("history.json"))
+    }
+
+    /// Fichier absent ou illisible : historique vide.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn push(&mut self, record: SessionRecord) {
+        self.sessions.push(record);
+        let excess = self.sessions.len().saturating_sub(MAX_SESSIONS);
+        self.sessions.drain(..excess);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use troudever_core::stats::{Counters, Stats};
+
+    #[test]
+    fn session_keeps_peers_and_totals() {
+        let mut session = Session::start(ProxyConfig::default());
+        let stats = |bytes| {
+            ProxyEvent::Stats(Stats {
+                down: Counters { bytes, messages: 1 },
+                ..Default::default()
+            })
+        };
+        session.observe(&ProxyEvent::PeerJoined("alice".to_string()));
+        session.observe(&ProxyEvent::PeerJoined("alice".to_string()));
+        session.observe(&stats(300));
+        session.observe(&stats(50));
+        let record = session.finish(Some("ABCD".to_string()), "TCP server closed connection".to_string());
+        assert_eq!(record.peers, ["alice"]);
+        assert_eq!(record.bytes_down, 350);
+        assert!(record.faults.is_empty());
+        assert_eq!(record.room_code.as_deref(), Some("ABCD"));
+
+        let mut history = History::default();
+        for _ in 0..MAX_SESSIONS + 5 {
+            history.push(record.clone());
+        }
+        assert_eq!(history.sessions.len(), MAX_SESSIONS);
+        let json = serde_json::to_string(&history).unwrap();
+        let loaded: History = serde_json::from_str(&json).unwrap();
+        assert!(loaded.sessions[0] == record);
+    }
+}
@@ -0,0 +1,171 @@
+//! Commandes lancées sur les événements d'un tunnel (room code reçu, tunnel prêt, tunnel perdu),
+//! via le shell de la plateforme. Les détails passent en variables d'environnement `TROUDEVER_*`,
+//! pour qu'un script poste le room code sur un chat sans parser quoi que ce soit.
+
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use troudever_core::ProxyConfig;
+
+/// Une commande par événement, vide = rien à lancer.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Hooks {
+    pub room_code: String,
+    pub connected: String,
+    /// Côté perdu (le tunnel se reconnecte) ou tunnel arrêté
+    pub disconnected: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HookEvent {
+    RoomCode,
+    Connected,
+    Disconnected,
+}
+
+impl HookEvent {
+    /// Valeur de `TROUDEVER_EVENT`, stable pour les scripts
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::RoomCode => "room_code",
+            HookEvent::Connected => "connected",
+            HookEvent::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// Tunnel monté ou non : `connected` et `disconnected` ne partent qu'au changement, pas à chaque
+/// tentative de reconnexion ni au `Stopped` d'un tunnel déjà tombé.
+#[derive(Default)]
+pub struct Link {
+    up: bool,
+}
+
+impl Link {
+    /// Vrai si le tunnel vient de monter.
+    pub fn connected(&mut self) -> bool {
+        !std::mem::replace(&mut self.up, true)
+    }
+
+    /// Vrai si le tunnel était monté.
+    pub fn lost(&mut self) -> bool {
+        std::mem::replace(&mut self.up, false)
+    }
+}
+
+/// Ce que le script reçoit de l'événement.
+pub struct HookDetails<'a> {
+    /// Nom stable du tunnel (`Tunnel 1`), celui des logs
+    pub tunnel: &'a str,
+    pub config: &'a ProxyConfig,
+    /// Dernier room code connu, le nouveau pour `room_code`
+    pub room_code: Option<&'a str>,
+    /// Pourquoi le tunnel est tombé, pour `disconnected`
+    pub reason: Option<&'a str>,
+}
+
+impl Hooks {
+    fn command(&self, event: HookEvent) -> &str {
+        match event {
+            HookEvent::RoomCode => &self.room_code,
+            HookEvent::Connected => &self.connected,
+            HookEvent::Disconnected => &self.disconnected,
+        }
+    }
+
+    /// Lance la commande sans l'attendre ; `report` reçoit l'échec éventuel (lancement
+    /// impossible, code de sortie non nul) depuis un thread à part.
+    pub fn run(&self, event: HookEvent, details: &HookDetails, report: impl FnOnce(String) + Send + 'static) {
+        let command = self.command(event).trim();
+        if command.is_empty() {
+            return;
+        }
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        shell.arg(command).envs(env(event, details)).stdin(Stdio::null());
+        let mut child = match shell.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                report(format!("Cannot run the {} hook: {}", event.name(), e));
+                return;
+            }
+        };
+        // Attendre le processus évite un zombie ; un thread suffit, les événements sont rares
+        std::thread::spawn(move || match child.wait() {
+            Ok(status) if status.success() => {}
+            Ok(status) => report(format!("The {} hook failed: {}", event.name(), status)),
+            Err(e) => report(format!("The {} hook failed: {}", event.name(), e)),
+        });
+    }
+}
+
+fn env(event: HookEvent, details: &HookDetails) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("TROUDEVER_EVENT", event.name().to_string()),
+        ("TROUDEVER_TUNNEL", details.tunnel.to_string()),
+        ("TROUDEVER_WS_URL", details.config.ws_url.clone()),
+        ("TROUDEVER_TCP_ADDR", details.config.tcp_addr.clone()),
+    ];
+    if let Some(code) = details.room_code {
+        env.push(("TROUDEVER_ROOM_CODE", code.to_string()));
+    }
+    if let Some(reason) = details.reason {
+        env.push(("TROUDEVER_REASON", reason.to_string()));
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn details_become_environment_variables() {
+        let config = ProxyConfig {
+            ws_url: "wss://relay.example/ws".to_string(),
+            tcp_addr: "127.0.0.1:7777".to_string(),
+            ..Default::default()
+        };
+        let details = HookDetails {
+            tunnel: "Tunnel 2",
+            config: &config,
+            room_code: Some("ABCD"),
+            reason: None,
+        };
+        let env = env(HookEvent::RoomCode, &details);
+        assert_eq!(
+            env,
+            [
+                ("TROUDEVER_EVENT", "room_code".to_string()),
+                ("TROUDEVER_TUNNEL", "Tunnel 2".to_string()),
+                ("TROUDEVER_WS_URL", "wss://relay.example/ws".to_string()),
+                ("TROUDEVER_TCP_ADDR", "127.0.0.1:7777".to_string()),
+                ("TROUDEVER_ROOM_CODE", "ABCD".to_string()),
+            ]
+        );
+        // Pas de commande : rien n'est lancé, rien n'est signalé
+        Hooks::default().run(HookEvent::Disconnected, &details, |e| panic!("{}", e));
+    }
+
+    #[test]
+    fn a_drop_fires_disconnected_once() {
+        let mut link = Link::default();
+        // Des essais ratés avant la première connexion : rien n'était monté
+        assert!(!link.lost());
+        assert!(!link.lost());
+        assert!(link.connected());
+        assert!(!link.connected());
+        // Une série de retries puis l'arrêt : une seule chute
+        let fired = (0..5).filter(|_| link.lost()).count() + usize::from(link.lost());
+        assert_eq!(fired, 1);
+        assert!(link.connected());
+        assert!(link.lost());
+    }
+}
@@ -0,0 +1,76 @@
+//! Raccourci clavier global qui lance ou arrête l'onglet sélectionné, même fenêtre cachée ou
+//! sans focus. Sous Linux, `global-hotkey` passe par X11 : rien sous Wayland pur.
+
+use crossbeam_channel::{Receiver, unbounded};
+use eframe::egui;
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use std::sync::OnceLock;
+
+// Le handler de `global-hotkey` ne s'installe qu'une fois par processus : il sert tous les
+// raccourcis enregistrés ensuite
+static PRESSES: OnceLock<Receiver<u32>> = OnceLock::new();
+
+fn presses(ctx: &egui::Context) -> &'static Receiver<u32> {
+    PRESSES.get_or_init(|| {
+        let (tx, rx) = unbounded();
+        let ctx = ctx.clone();
+        GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+            if event.state() == HotKeyState::Pressed {
+                let _ = tx.send(event.id());
+                // La fenêtre cachée ne redessine plus d'elle-même
+                ctx.request_repaint();
+            }
+        }));
+        rx
+    })
+}
+
+/// `ctrl+shift+F9`, `alt+KeyT`... Modificateurs d'abord, une seule touche.
+pub fn parse(spec: &str) -> Result<HotKey, String> {
+    spec.trim().parse().map_err(|e: global_hotkey::hotkey::HotKeyParseError| e.to_string())
+}
+
+/// Enregistré tant qu'on le garde.
+pub struct Hotkey {
+    manager: GlobalHotKeyManager,
+    hotkey: HotKey,
+    presses: &'static Receiver<u32>,
+}
+
+impl Hotkey {
+    pub fn register(spec: &str, ctx: &egui::Context) -> Result<Self, String> {
+        let hotkey = parse(spec)?;
+        let manager = GlobalHotKeyManager::new().map_err(|e| e.to_string())?;
+        manager.register(hotkey).map_err(|e| e.to_string())?;
+        Ok(Self {
+            manager,
+            hotkey,
+            presses: presses(ctx),
+        })
+    }
+
+    /// Appuis depuis le dernier appel.
+    pub fn presses(&self) -> usize {
+        self.presses.try_iter().filter(|&id| id == self.hotkey.id()).count()
+    }
+}
+
+impl Drop for Hotkey {
+    fn drop(&mut self) {
+        let _ = self.manager.unregister(self.hotkey);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_parse_with_modifiers_first() {
+        assert_eq!(parse(" ctrl+shift+F9 ").unwrap(), parse("Control+Shift+F9").unwrap());
+        assert!(parse("alt+KeyT").is_ok());
+        assert!(parse("F9+ctrl").is_err());
+        assert!(parse("").is_err());
+    }
+}
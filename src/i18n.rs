@@ -0,0 +1,206 @@
+//! Traductions de l'interface, chargées depuis `locales/*.json` (embarqués dans le binaire).
+//!
+//! Deux tables par langue :
+//! - `ui` : libellés, boutons et bulles d'aide de l'app, par clé ;
+//! - `messages` : ce qui arrive en anglais du moteur ou de l'app (statuts, logs, libellés
+//!   d'options), traduit seulement à l'affichage. `{}` y remplace une partie variable.
+//!
+//! Le texte d'origine reste en anglais partout ailleurs : fichier de logs, historique, API
+//! d'admin et mode headless, pour que les scripts n'aient pas à connaître la langue choisie.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum Language {
+    #[default]
+    English,
+    French,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::French];
+
+    /// Toujours dans la langue elle-même : c'est ce qu'on cherche quand on ne lit pas l'autre
+    pub fn label(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::French => "Français",
+        }
+    }
+
+    fn resource(self) -> &'static str {
+        match self {
+            Language::English => include_str!("../locales/en.json"),
+            Language::French => include_str!("../locales/fr.json"),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Resource {
+    ui: HashMap<String, String>,
+    messages: HashMap<String, String>,
+}
+
+/// Un message anglais à trous et sa traduction.
+struct Pattern {
+    // Texte entre les `{}`, dans l'ordre ; un de plus que de trous
+    parts: Vec<String>,
+    translation: String,
+}
+
+impl Pattern {
+    /// Les parties variables de `text`, s'il a cette forme.
+    fn captures<'a>(&self, text: &'a str) -> Option<Vec<&'a str>> {
+        let (first, rest) = self.parts.split_first()?;
+        let (last, middle) = rest.split_last()?;
+        let mut remaining = text.strip_prefix(first.as_str())?;
+        let mut captures = Vec::with_capacity(rest.len());
+        for part in middle {
+            let at = remaining.find(part.as_str())?;
+            captures.push(&remaining[..at]);
+            remaining = &remaining[at + part.len()..];
+        }
+        captures.push(remaining.strip_suffix(last.as_str())?);
+        Some(captures)
+    }
+}
+
+struct Catalog {
+    ui: HashMap<String, String>,
+    exact: HashMap<String, String>,
+    // Les plus précis d'abord : "{} lost. {}" ne doit pas passer avant un message plus long
+    patterns: Vec<Pattern>,
+}
+
+impl Catalog {
+    fn parse(json: &str) -> Self {
+        // Fichiers embarqués, vérifiés par les tests : une erreur ici est un bug de build
+        let resource: Resource = serde_json::from_str(json).expect("invalid locale file");
+        let mut exact = HashMap::new();
+        let mut patterns = vec![];
+        for (english, translation) in resource.messages {
+            if !english.contains("{}") {
+                exact.insert(english, translation);
+                continue;
+            }
+            let parts: Vec<String> = english.split("{}").map(str::to_string).collect();
+            // Sans texte fixe, tout correspondrait
+            if parts.iter().all(String::is_empty) {
+                continue;
+            }
+            patterns.push(Pattern { parts, translation });
+        }
+        patterns.sort_by_key(|pattern| std::cmp::Reverse(pattern.parts.iter().map(String::len).sum::<usize>()));
+        Self {
+            ui: resource.ui,
+            exact,
+            patterns,
+        }
+    }
+
+    fn message<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if let Some(translation) = self.exact.get(text) {
+            return Cow::Owned(translation.clone());
+        }
+        for pattern in &self.patterns {
+            let Some(captures) = pattern.captures(text) else {
+                continue;
+            };
+            // Les trous contiennent souvent un autre message : "TCP lost. Reconnecting (2)..."
+            let mut captures = captures.into_iter().map(|capture| self.message(capture));
+            let mut pieces = pattern.translation.split("{}");
+            let mut translated = pieces.next().unwrap_or_default().to_string();
+            for piece in pieces {
+                translated.push_str(&captures.next().unwrap_or_default());
+                translated.push_str(piece);
+            }
+            return Cow::Owned(translated);
+        }
+        Cow::Borrowed(text)
+    }
+}
+
+static LANGUAGE: AtomicUsize = AtomicUsize::new(0);
+static CATALOGS: OnceLock<Vec<Catalog>> = OnceLock::new();
+
+fn catalogs() -> &'static [Catalog] {
+    CATALOGS.get_or_init(|| Language::ALL.iter().map(|language| Catalog::parse(language.resource())).collect())
+}
+
+fn current() -> &'static Catalog {
+    &catalogs()[LANGUAGE.load(Ordering::Relaxed)]
+}
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as usize, Ordering::Relaxed);
+}
+
+/// Libellé de l'interface. Une clé absente retombe sur l'anglais, puis sur la clé elle-même.
+pub fn tr(key: &'static str) -> &'static str {
+    current()
+        .ui
+        .get(key)
+        .or_else(|| catalogs()[Language::English as usize].ui.get(key))
+        .map_or(key, String::as_str)
+}
+
+/// Comme [`tr`], avec les `{nom}` du libellé remplacés.
+pub fn tr_args(key: &'static str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut text = tr(key).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// Un texte anglais venu du moteur ou de l'app, traduit s'il est connu, tel quel sinon.
+pub fn message(text: &str) -> Cow<'_, str> {
+    current().message(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(text: &str) -> Vec<&str> {
+        text.split('{').skip(1).filter_map(|rest| rest.split_once('}')).map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn every_language_has_every_label() {
+        let resources: Vec<Resource> =
+            Language::ALL.iter().map(|l| serde_json::from_str(l.resource()).unwrap()).collect();
+        let english = &resources[Language::English as usize];
+        for (language, resource) in Language::ALL.iter().zip(&resources) {
+            for (key, text) in &english.ui {
+                let translated = resource.ui.get(key).unwrap_or_else(|| panic!("{:?} lacks {}", language, key));
+                assert_eq!(names(translated), names(text), "{:?} {}", language, key);
+            }
+            assert_eq!(resource.ui.len(), english.ui.len(), "{:?} has unknown labels", language);
+            for (english, translated) in &resource.messages {
+                assert_eq!(english.matches("{}").count(), translated.matches("{}").count(), "{}", english);
+            }
+        }
+    }
+
+    #[test]
+    fn messages_translate_with_their_variable_parts() {
+        let french = Catalog::parse(Language::French.resource());
+        assert_eq!(french.message("Waiting for TCP client..."), "En attente d'un client TCP...");
+        assert_eq!(french.message("TCP lost. Reconnecting (3)..."), "TCP perdu. Reconnexion (3)...");
+        let stopped = french.message("Stopped: Auth failed: bad token");
+        assert_eq!(stopped, "Arrêté : Échec de l'authentification : bad token");
+        // Inconnu : rendu tel quel
+        assert_eq!(french.message("os error 111"), "os error 111");
+
+        let english = Catalog::parse(Language::English.resource());
+        assert_eq!(english.message("TCP lost. Reconnecting (3)..."), "TCP lost. Reconnecting (3)...");
+    }
+}
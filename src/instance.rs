@@ -0,0 +1,165 @@
+//! Une seule fenêtre à la fois : la première instance écoute sur un port de loopback fixe, et un
+//! second lancement lui passe ses options (profil, `--connect`...) au lieu d'ouvrir un deuxième
+//! proxy vers le même serveur TCP. Le premier remet alors sa fenêtre au premier plan.
+//!
+//! Le port est ouvert à tous les comptes de la machine : chaque lancement commence par le jeton
+//! que la première instance a laissé dans le dossier de réglages, que seul son utilisateur peut lire.
+
+use crossbeam_channel::{Receiver, unbounded};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+
+use crate::cli::LaunchOptions;
+use crate::token;
+
+pub const INSTANCE_ADDR: &str = "127.0.0.1:47470";
+pub const TOKEN_FILE: &str = "instance-token";
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(2);
+/// Réponse de la première instance, pour ne pas confondre avec un autre service sur le port
+const ACK: &str = "troudever ok";
+
+pub enum Claim {
+    /// Personne d'autre : le port est à nous jusqu'au démarrage du serveur
+    First(TcpListener),
+    /// Une instance tourne déjà et a reçu nos options
+    Forwarded,
+}
+
+/// Prend le port, ou passe `launch` à l'instance qui l'a déjà, `token` en tête. L'erreur vient
+/// d'un port pris par autre chose qu'une instance à nous : on démarre quand même, sans garde.
+pub fn claim(addr: &str, token: &str, launch: &LaunchOptions) -> io::Result<Claim> {
+    match TcpListener::bind(addr) {
+        Ok(listener) => Ok(Claim::First(listener)),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => forward(addr, token, launch).map(|()| Claim::Forwarded),
+        Err(e) => Err(e),
+    }
+}
+
+/// Le jeton sur une ligne, une ligne JSON avec les options, puis l'accusé de réception. Un
+/// mauvais jeton n'a pas de réponse : autant qu'un autre programme sur le port.
+pub fn forward(addr: &str, token: &str, launch: &LaunchOptions) -> io::Result<()> {
+    let addr: SocketAddr = addr.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stream = TcpStream::connect_timeout(&addr, FORWARD_TIMEOUT)?;
+    stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+    stream.set_write_timeout(Some(FORWARD_TIMEOUT))?;
+    let line = format!("{}\n{}\n", token, serde_json::to_string(launch)?);
+    stream.write_all(line.as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.trim_end() != ACK {
+        let message = format!("{} is used by another program or another user", addr);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(())
+}
+
+/// Serveur arrêté quand on le lâche.
+pub struct InstanceServer {
+    task: JoinHandle<()>,
+    launches: Receiver<LaunchOptions>,
+}
+
+impl InstanceServer {
+    /// `token` est celui écrit dans `TOKEN_FILE` ; `wake` réveille l'app quand un lancement
+    /// arrive, fenêtre cachée comprise.
+    pub fn start(listener: TcpListener, token: String, wake: impl Fn() + Send + Sync + 'static) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let (tx, launches) = unbounded();
+        let (token, wake) = (Arc::new(token), Arc::new(wake));
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let (tx, token, wake) = (tx.clone(), token.clone(), wake.clone());
+                tokio::spawn(async move {
+                    let (read, mut write) = socket.into_split();
+                    let mut read = tokio::io::BufReader::new(read);
+                    let (mut given, mut line) = (String::new(), String::new());
+                    let lines = async {
+                        read.read_line(&mut given).await?;
+                        read.read_line(&mut line).await
+                    };
+                    if !matches!(tokio::time::timeout(FORWARD_TIMEOUT, lines).await, Ok(Ok(_))) {
+                        return;
+                    }
+                    // Rien sur le canal avant le jeton : un autre compte ne pilote pas notre fenêtre
+                    if !token::matches(&token, given.trim_end()) {
+                        return;
+                    }
+                    let Ok(launch) = serde_json::from_str(&line) else {
+                        return;
+                    };
+                    let _ = tx.send(launch);
+                    wake();
+                    let _ = write.write_all(format!("{}\n", ACK).as_bytes()).await;
+                });
+            }
+        });
+        Ok(Self { task, launches })
+    }
+
+    /// Lancements reçus depuis le dernier appel.
+    pub fn launches(&self) -> impl Iterator<Item = LaunchOptions> + '_ {
+        self.launches.try_iter()
+    }
+}
+
+impl Drop for InstanceServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn second_launch_reaches_the_first_instance() {
+        let Claim::First(listener) = claim("127.0.0.1:0", "", &LaunchOptions::default()).unwrap() else {
+            panic!("port 0 is never taken");
+        };
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = InstanceServer::start(listener, "secret".to_string(), || {}).unwrap();
+        let launch = LaunchOptions {
+            profile: Some("dev".to_string()),
+            autoconnect: true,
+            ..Default::default()
+        };
+        let sent = launch.clone();
+        let (to, again) = (addr.clone(), launch.clone());
+        tokio::task::spawn_blocking(move || forward(&to, "secret", &sent)).await.unwrap().unwrap();
+        assert_eq!(server.launches().collect::<Vec<_>>(), [launch]);
+        // Le port est pris par l'instance : un nouveau lancement lui est passé
+        let outcome = tokio::task::spawn_blocking(move || claim(&addr, "secret", &again)).await.unwrap().unwrap();
+        assert!(matches!(outcome, Claim::Forwarded));
+    }
+
+    #[tokio::test]
+    async fn launches_without_the_token_are_ignored() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = InstanceServer::start(listener, "secret".to_string(), || {}).unwrap();
+        for token in ["", "secreT", "secret2"] {
+            let to = addr.clone();
+            let result = tokio::task::spawn_blocking(move || forward(&to, token, &LaunchOptions::default()));
+            assert_eq!(result.await.unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        assert_eq!(server.launches().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn other_programs_on_the_port_are_not_an_instance() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+        });
+        let result = tokio::task::spawn_blocking(move || forward(&addr, "", &LaunchOptions::default())).await.unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,362 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use troudever_core::logging::{LogLevel, LogRecord};
+
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub record: LogRecord,
+}
+
+impl LogEntry {
+    pub fn now(record: LogRecord) -> Self {
+        Self {
+            time: Local::now(),
+            record,
+        }
+    }
+
+    /// Un message de l'application elle-même, pas du moteur.
+    pub fn app(level: LogLevel, msg: String) -> Self {
+        Self::now(LogRecord::new(level, "app", msg))
+    }
+
+    pub fn text_line(&self) -> String {
+        format!(
+            "[{}] {:<5} {}",
+            self.time.format("%Y-%m-%d %H:%M:%S"),
+            self.record.level.label().to_uppercase(),
+            self.record
+        )
+    }
+
+    /// Un enregistrement JSONL : `{"ts", "level", "tunnel", "target", "msg", "fields"}`.
+    pub fn json_line(&self, tunnel: &str) -> String {
+        let fields: serde_json::Map<String, serde_json::Value> = self
+            .record
+            .fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone().into()))
+            .collect();
+        serde_json::json!({
+            "ts": self.time.to_rfc3339(),
+            "level": self.record.level.label(),
+            "tunnel": tunnel,
+            "target": self.record.target,
+            "msg": self.record.message,
+            "fields": fields,
+        })
+        .to_string()
+    }
+}
+
+/// Ce que montre la vue des logs : un texte à chercher, et les niveaux cochés.
+pub struct LogFilter {
+    pub text: String,
+    /// Indexé comme `LogLevel::ALL`
+    pub levels: [bool; 4],
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            levels: [true; 4],
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn shows(&mut self, level: LogLevel) -> &mut bool {
+        &mut self.levels[level as usize]
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.text.trim().is_empty() || self.levels.contains(&false)
+    }
+
+    /// Recherche insensible à la casse ; `needle` est le texte déjà passé en minuscules.
+    pub fn matches(&self, entry: &LogEntry, needle: &str) -> bool {
+        self.levels[entry.record.level as usize]
+            && (needle.is_empty() || entry.record.to_string().to_lowercase().contains(needle))
+    }
+
+    pub fn needle(&self) -> String {
+        self.text.trim().to_lowercase()
+    }
+}
+
+/// Lignes gardées par onglet. Au-delà, les plus vieilles infos et debug partent d'abord :
+/// l'erreur qui a fait tomber le tunnel reste là quand on vient la chercher. Le fichier JSONL,
+/// lui, garde tout.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct LogRetention {
+    pub max_lines: usize,
+    /// Erreurs et warnings ne partent qu'une fois seuls à dépasser la limite
+    pub keep_problems: bool,
+}
+
+impl Default for LogRetention {
+    fn default() -> Self {
+        Self {
+            max_lines: 10_000,
+            keep_problems: true,
+        }
+    }
+}
+
+impl LogRetention {
+    pub const MAX_LINES_RANGE: std::ops::RangeInclusive<usize> = 100..=1_000_000;
+
+    pub fn trim(&self, logs: &mut VecDeque<LogEntry>) {
+        let mut excess = logs.len().saturating_sub(self.max_lines);
+        if excess == 0 {
+            return;
+        }
+        if self.keep_problems {
+            logs.retain(|entry| {
+                let evict = excess > 0 && entry.record.level > LogLevel::Warn;
+                if evict {
+                    excess -= 1;
+                }
+                !evict
+            });
+        }
+        logs.drain(..excess);
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Jsonl,
+}
+
+impl LogFormat {
+    pub const ALL: [LogFormat; 2] = [LogFormat::Text, LogFormat::Jsonl];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogFormat::Text => "Text",
+            LogFormat::Jsonl => "JSONL",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Text => "log",
+            LogFormat::Jsonl => "jsonl",
+        }
+    }
+
+    pub fn render<'a>(self, tunnel: &str, entries: impl IntoIterator<Item = &'a LogEntry>) -> String {
+        let mut out = String::new();
+        for entry in entries {
+            match self {
+                LogFormat::Text => out.push_str(&entry.text_line()),
+                LogFormat::Jsonl => out.push_str(&entry.json_line(tunnel)),
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Fichiers gardés après rotation : `x.jsonl.1` (le plus récent) à `x.jsonl.3`
+const ROTATED_FILES: usize = 3;
+
+/// Fichier JSONL alimenté au fil de l'eau par tous les tunnels.
+#[derive(Default)]
+pub struct JsonlFile {
+    path: PathBuf,
+    file: Option<File>,
+    written: u64,
+    /// Taille à partir de laquelle on passe au fichier suivant, 0 = jamais
+    max_bytes: u64,
+}
+
+impl JsonlFile {
+    /// Ouvre (ou rouvre) le fichier si le chemin a changé ; chemin vide = désactivé.
+    pub fn set_path(&mut self, path: &Path) -> io::Result<()> {
+        if self.path == path && (self.file.is_some() || path.as_os_str().is_empty()) {
+            return Ok(());
+        }
+        self.path = path.to_path_buf();
+        self.file = None;
+        if !path.as_os_str().is_empty() {
+            self.open()?;
+        }
+        Ok(())
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = max_bytes;
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        for index in (1..ROTATED_FILES).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                std::fs::rename(from, self.rotated(index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1))?;
+        self.open()
+    }
+
+    pub fn append(&mut self, tunnel: &str, entry: &LogEntry) -> io::Result<()> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        let line = entry.json_line(tunnel) + "\n";
+        if self.max_bytes > 0 && self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        // Une ligne = un write + flush, pour ne rien perdre si l'app plante
+        file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_line_has_expected_fields() {
+        let record = LogRecord::new(LogLevel::Error, "tcp", "TCP write error \"x\"").field("peer", "127.0.0.1:4000");
+        let entry = LogEntry::now(record);
+        let value: serde_json::Value = serde_json::from_str(&entry.json_line("Tunnel 1")).unwrap();
+        assert_eq!(value["level"], "error");
+        assert_eq!(value["tunnel"], "Tunnel 1");
+        assert_eq!(value["target"], "tcp");
+        assert_eq!(value["msg"], "TCP write error \"x\"");
+        assert_eq!(value["fields"]["peer"], "127.0.0.1:4000");
+        assert!(DateTime::parse_from_rfc3339(value["ts"].as_str().unwrap()).is_ok());
+        assert!(entry.text_line().ends_with("] ERROR TCP write error \"x\" peer=127.0.0.1:4000"));
+    }
+
+    #[test]
+    fn filter_combines_text_and_levels() {
+        let entries = [
+            LogEntry::now(LogRecord::new(LogLevel::Info, "tcp", "[OK] TCP Connected")),
+            LogEntry::now(LogRecord::new(LogLevel::Warn, "ws", "WebSocket lost: reset")),
+            LogEntry::now(LogRecord::new(LogLevel::Error, "tcp", "TCP connection failed").field("addr", "a:1")),
+        ];
+
+        let mut filter = LogFilter::default();
+        assert!(!filter.is_active());
+        filter.text = " tcp ".to_string();
+        let needle = filter.needle();
+        let shown: Vec<_> = entries.iter().filter(|entry| filter.matches(entry, &needle)).collect();
+        assert_eq!(shown.len(), 2);
+        // Les champs comptent dans la recherche
+        assert!(filter.matches(&entries[2], "addr=a:1"));
+
+        *filter.shows(LogLevel::Error) = false;
+        assert!(filter.is_active());
+        assert!(filter.matches(&entries[0], &needle));
+        assert!(!filter.matches(&entries[2], &needle));
+    }
+
+    #[test]
+    fn retention_evicts_chatter_before_problems() {
+        let entry = |level, message: &str| LogEntry::now(LogRecord::new(level, "app", message.to_string()));
+        let mut logs: VecDeque<LogEntry> = [
+            entry(LogLevel::Error, "relay refused"),
+            entry(LogLevel::Info, "info 1"),
+            entry(LogLevel::Warn, "ws lost"),
+            entry(LogLevel::Debug, "debug 1"),
+            entry(LogLevel::Info, "info 2"),
+        ]
+        .into();
+        let messages = |logs: &VecDeque<LogEntry>| logs.iter().map(|e| e.record.message.clone()).collect::<Vec<_>>();
+        let mut retention = LogRetention {
+            max_lines: 3,
+            keep_problems: true,
+        };
+        retention.trim(&mut logs);
+        assert_eq!(messages(&logs), ["relay refused", "ws lost", "info 2"]);
+        // Que des problèmes au-delà : les plus vieux partent quand même
+        retention.max_lines = 1;
+        retention.trim(&mut logs);
+        assert_eq!(messages(&logs), ["ws lost"]);
+
+        logs.extend([entry(LogLevel::Info, "info 3"), entry(LogLevel::Info, "info 4")]);
+        retention.keep_problems = false;
+        retention.max_lines = 2;
+        retention.trim(&mut logs);
+        assert_eq!(messages(&logs), ["info 3", "info 4"]);
+    }
+
+    #[test]
+    fn jsonl_file_appends_one_record_per_line() {
+        let path = std::env::temp_dir().join(format!("troudever-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut sink = JsonlFile::default();
+        sink.set_path(&path).unwrap();
+        sink.append("A", &LogEntry::app(LogLevel::Info, "one".to_string())).unwrap();
+        sink.append("B", &LogEntry::app(LogLevel::Info, "two".to_string())).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("\"tunnel\":\"B\""));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn jsonl_file_rotates_by_size() {
+        let path = std::env::temp_dir().join(format!("troudever-rotate-{}.jsonl", std::process::id()));
+        let mut sink = JsonlFile::default();
+        let cleanup = |sink: &JsonlFile| {
+            let _ = std::fs::remove_file(&sink.path);
+            for index in 1..=ROTATED_FILES + 1 {
+                let _ = std::fs::remove_file(sink.rotated(index));
+            }
+        };
+        sink.path = path.clone();
+        cleanup(&sink);
+
+        sink.set_path(&path).unwrap();
+        let line_len = LogEntry::app(LogLevel::Info, "0".to_string()).json_line("A").len() as u64 + 1;
+        // Deux lignes par fichier
+        sink.set_max_bytes(line_len * 2);
+        for i in 0..9 {
+            sink.append("A", &LogEntry::app(LogLevel::Info, i.to_string())).unwrap();
+        }
+
+        let lines = |path: &Path| std::fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        for index in 1..=ROTATED_FILES {
+            assert_eq!(lines(&sink.rotated(index)), 2);
+        }
+        assert!(!sink.rotated(ROTATED_FILES + 1).exists());
+        assert!(std::fs::read_to_string(sink.rotated(1)).unwrap().contains("\"msg\":\"7\""));
+        cleanup(&sink);
+    }
+}
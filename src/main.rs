@@ -1,336 +1,225 @@
-use crossbeam_channel::{Receiver, Sender, unbounded};
+use app::TrouDeVerApp;
+use cli::LaunchOptions;
+use config::Settings;
 use eframe::egui;
-use futures_util::{SinkExt, StreamExt};
-use serde_json::Value;
-use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-use url::Url;
-
-enum ProxyEvent {
-    Log(String),
-    Status(String),
-    RoomCode(String),
-    Stopped,
-}
-
-struct TrouDeVerApp {
-    // Configuration
-    ws_url: String,
-    tcp_addr: String,
-    auto_reconnect: bool, // Nouvelle option UI
-
-    // State
-    is_running: bool,
-    room_number: Option<String>,
-    status_msg: String,
-    logs: Vec<String>,
-
-    // Communication
-    rx_event: Receiver<ProxyEvent>,
-    tx_event: Sender<ProxyEvent>,
-    proxy_abort: Option<tokio::task::AbortHandle>,
-}
-
-impl Default for TrouDeVerApp {
-    fn default() -> Self {
-        let (tx, rx) = unbounded();
-        Self {
-            ws_url: "ws://localhost:4455".to_owned(),
-            tcp_addr: "127.0.0.1:9000".to_owned(),
-            auto_reconnect: true,
-            is_running: false,
-            room_number: None,
-            status_msg: "Ready".to_owned(),
-            logs: vec![],
-            rx_event: rx,
-            tx_event: tx,
-            proxy_abort: None,
+use hooks::{HookDetails, HookEvent, Link};
+use instance::{Claim, INSTANCE_ADDR};
+use logs::{JsonlFile, LogEntry};
+use metrics::{MetricsServer, SharedMetrics};
+use troudever_core::selftest::SELF_TEST_TIMEOUT;
+use troudever_core::{ProxyEvent, SHUTDOWN_GRACE, Tunnel};
+
+mod admin;
+mod app;
+mod bandwidth;
+mod cli;
+mod config;
+mod discover;
+mod history;
+mod hooks;
+mod hotkey;
+mod i18n;
+mod instance;
+mod logs;
+mod metrics;
+mod qr;
+mod selftest;
+mod tab;
+mod timeline;
+mod token;
+mod tray;
+
+// Lisible sur les deux thèmes
+const TARGET_UP_COLOR: egui::Color32 = egui::Color32::from_rgb(0, 170, 0);
+
+/// Sans fenêtre : un seul tunnel, logs et room code sur stdout, Ctrl+C pour fermer proprement.
+async fn run_headless(launch: LaunchOptions) {
+    let settings = Settings::load();
+    let mut config = settings.tunnels.first().cloned().unwrap_or_default();
+    if let Some(name) = &launch.profile {
+        match settings.profile(name) {
+            Some(profile) => config = profile.config.clone(),
+            None => {
+                eprintln!("troudever: no profile named {}", name);
+                std::process::exit(2);
+            }
         }
     }
-}
+    if let Some(ws_url) = launch.ws_url {
+        config.ws_url = ws_url;
+    }
+    if let Some(tcp_addr) = launch.tcp_addr {
+        config.tcp_addr = tcp_addr;
+    }
+    config.upstream = settings.upstream_proxy.clone();
 
-impl eframe::App for TrouDeVerApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Handle network events
-        while let Ok(event) = self.rx_event.try_recv() {
-            match event {
-                ProxyEvent::Log(msg) => {
-                    let time = chrono::Local::now().format("%H:%M:%S");
-                    self.logs.push(format!("[{}] {}", time, msg));
-                    if self.logs.len() > 50 {
-                        self.logs.remove(0);
-                    }
-                }
-                ProxyEvent::RoomCode(code) => self.room_number = Some(code),
-                ProxyEvent::Status(msg) => self.status_msg = msg,
-                ProxyEvent::Stopped => {
-                    self.is_running = false;
-                    self.status_msg = "Stopped".to_string();
-                    self.proxy_abort = None;
-                }
-            }
+    let metrics = SharedMetrics::default();
+    let _metrics_server = match start_metrics(&settings.metrics_addr, &metrics) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("troudever: cannot serve metrics on {}: {}", settings.metrics_addr, e);
+            None
         }
+    };
 
-        // Draw UI
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("TrouDeVer - Proxy");
-            ui.separator();
-
-            ui.add_enabled_ui(!self.is_running, |ui| {
-                ui.horizontal(|ui| {
-                    ui.label("WebSocket URL:");
-                    ui.text_edit_singleline(&mut self.ws_url);
-                });
-                ui.horizontal(|ui| {
-                    ui.label("TCP Server:");
-                    ui.text_edit_singleline(&mut self.tcp_addr);
-                });
-                ui.checkbox(&mut self.auto_reconnect, "Auto Reconnect");
-            });
-
-            ui.add_space(10.0);
+    let hook_config = config.clone();
+    let mut tunnel = Tunnel::builder(config).start();
+    let rx = tunnel.events().cloned().expect("tunnel owns its event channel");
 
-            if self.is_running {
-                if ui.button("[ STOP ]").clicked() {
-                    self.stop_proxy();
+    // Le channel crossbeam est bloquant : on l'imprime depuis un thread à part
+    let printer = std::thread::spawn(move || {
+        let mut log_file = JsonlFile::default();
+        log_file.set_max_bytes(settings.log_file_max_bytes());
+        if let Err(e) = log_file.set_path(&settings.log_file) {
+            eprintln!("troudever: cannot open {}: {}", settings.log_file.display(), e);
+        }
+        let mut room: Option<String> = None;
+        let mut link = Link::default();
+        let run_hook = |event, room: &Option<String>, reason: Option<&str>| {
+            let details = HookDetails {
+                tunnel: "Tunnel 1",
+                config: &hook_config,
+                room_code: room.as_deref(),
+                reason,
+            };
+            settings.hooks.run(event, &details, |e| eprintln!("troudever: {}", e));
+        };
+        for (id, event) in rx {
+            if let Ok(mut metrics) = metrics.lock() {
+                metrics.observe(id, &event);
+            }
+            match event {
+                ProxyEvent::Log(record) if record.level > settings.log_level => {}
+                ProxyEvent::Log(record) => {
+                    let entry = LogEntry::now(record);
+                    println!("{}", entry.text_line());
+                    let _ = log_file.append(&format!("Tunnel {}", id + 1), &entry);
                 }
-            } else {
-                if ui.button("[ CONNECT ]").clicked() {
-                    self.start_proxy();
+                ProxyEvent::Status(status) => println!("status: {}", status),
+                ProxyEvent::Error(error) | ProxyEvent::RelayError(error) => println!("error: {}", error),
+                ProxyEvent::Kicked(reason) => println!("kicked: {}", reason),
+                ProxyEvent::PeerJoined(peer) => println!("peer joined: {}", peer),
+                ProxyEvent::PeerLeft(peer) => println!("peer left: {}", peer),
+                ProxyEvent::RoomCode(code) => {
+                    println!("room: {}", code);
+                    room = Some(code);
+                    run_hook(HookEvent::RoomCode, &room, None);
                 }
-            }
-
-            ui.label(format!("Status: {}", self.status_msg));
-            if let Some(code) = &self.room_number {
-                ui.add_space(10.0);
-                ui.heading(format!("ROOM CODE : {}", code));
-                if ui.button("Copier").clicked() {
-                    ui.ctx().copy_text(code.to_string());
+                ProxyEvent::Joined(code) => {
+                    println!("room: {}", code);
+                    room = Some(code);
                 }
-            }
-            ui.separator();
-
-            ui.heading("Logs");
-            egui::ScrollArea::vertical()
-                .stick_to_bottom(true)
-                .show(ui, |ui| {
-                    for log in &self.logs {
-                        ui.label(log);
+                ProxyEvent::JoinFailed(reason) => println!("join failed: {}", reason),
+                ProxyEvent::Reconnecting(side) => {
+                    println!("reconnecting: {} lost", side);
+                    if link.lost() {
+                        run_hook(HookEvent::Disconnected, &room, Some(&format!("{} lost", side)));
                     }
-                });
-        });
-
-        ctx.request_repaint();
-    }
-}
-
-impl TrouDeVerApp {
-    fn start_proxy(&mut self) {
-        self.is_running = true;
-        self.status_msg = "Starting...".to_string();
-        self.logs.clear();
-
-        let ws_url = self.ws_url.clone();
-        let tcp_addr = self.tcp_addr.clone();
-        let tx = self.tx_event.clone();
-        let auto_reconnect = self.auto_reconnect;
-        
-        let initial_room = self.room_number.clone();
-
-        let handle = tokio::spawn(async move {
-            let mut current_room = initial_room;
-
-            loop {
-                let last_room = run_proxy_logic(
-                    ws_url.clone(), 
-                    tcp_addr.clone(), 
-                    tx.clone(), 
-                    current_room.clone()
-                ).await;
-
-                if last_room.is_some() {
-                    current_room = last_room;
                 }
-
-                if !auto_reconnect {
+                ProxyEvent::Target(status) => match status.error {
+                    None => println!("target {}: connected", status.index + 1),
+                    Some(error) => println!("target {}: {}", status.index + 1, error),
+                },
+                ProxyEvent::Connected if link.connected() => run_hook(HookEvent::Connected, &room, None),
+                ProxyEvent::Connected => {}
+                ProxyEvent::Latency(_) | ProxyEvent::Stats(_) | ProxyEvent::Frame(_) => {}
+                ProxyEvent::Stopped => {
+                    if link.lost() {
+                        run_hook(HookEvent::Disconnected, &room, Some("Stopped"));
+                    }
                     break;
                 }
-                
-                let _ = tx.send(ProxyEvent::Status("Connection lost. Retrying in 3s...".to_string()));
-                let _ = tx.send(ProxyEvent::Log("Reconnecting in 3 seconds...".to_string()));
-                
-                tokio::time::sleep(Duration::from_secs(3)).await;
             }
+        }
+    });
 
-            let _ = tx.send(ProxyEvent::Stopped);
-        });
-
-        self.proxy_abort = Some(handle.abort_handle());
+    let finished = tokio::select! {
+        _ = tunnel.wait() => true,
+        _ = tokio::signal::ctrl_c() => false,
+    };
+    if !finished {
+        tunnel.shutdown(SHUTDOWN_GRACE).await;
     }
+    let _ = printer.join();
+}
 
-    fn stop_proxy(&mut self) {
-        if let Some(handle) = &self.proxy_abort {
-            handle.abort();
-            self.logs.push("Stopped by user.".to_string());
-        }
-        self.proxy_abort = None;
-        self.is_running = false;
-        self.status_msg = "Stopped".to_string();
+/// `Ok(None)` quand l'endpoint est désactivé (adresse vide).
+fn start_metrics(addr: &str, metrics: &SharedMetrics) -> std::io::Result<Option<MetricsServer>> {
+    if addr.trim().is_empty() {
+        return Ok(None);
     }
+    MetricsServer::start(addr.trim(), metrics.clone()).map(Some)
 }
 
-async fn run_proxy_logic(
-    ws_url: String, 
-    tcp_addr: String, 
-    tx: Sender<ProxyEvent>,
-    room_code: Option<String>
-) -> Option<String> {
-    
-    let _ = tx.send(ProxyEvent::Log(format!("Connecting to WebSocket at {}...", ws_url)));
-    let url = match Url::parse(&ws_url) {
-        Ok(u) => u,
-        Err(e) => {
-            let _ = tx.send(ProxyEvent::Log(format!("Invalid URL: {}", e)));
-            return room_code;
-        }
-    };
-
-    let ws_stream = match connect_async(url.as_str()).await {
-        Ok((ws, _)) => ws,
-        Err(e) => {
-            let _ = tx.send(ProxyEvent::Log(format!("WebSocket failed: {}", e)));
-            return room_code;
-        }
-    };
-    let _ = tx.send(ProxyEvent::Log("[OK] WebSocket Connected".to_string()));
-
-    let _ = tx.send(ProxyEvent::Log(format!("Connecting to TCP Server at {}...", tcp_addr)));
-    let mut tcp_stream = match TcpStream::connect(&tcp_addr).await {
-        Ok(s) => {
-            if let Err(e) = s.set_nodelay(true) {
-                let _ = tx.send(ProxyEvent::Log(format!("Warning: Failed to set TCP_NODELAY: {}", e)));
-            }
-            s
-        }
-        Err(e) => {
-            let _ = tx.send(ProxyEvent::Log(format!("TCP connection failed: {}", e)));
-            return room_code;
-        }
-    };
+/// Écrit avant que le serveur n'écoute, comme pour l'API d'admin.
+fn instance_token() -> std::io::Result<String> {
+    let secret = token::generate()?;
+    token::write(instance::TOKEN_FILE, &secret)?;
+    Ok(secret)
+}
 
-    if let Some(code) = &room_code {
-        let _ = tx.send(ProxyEvent::Log(format!("Attempting to resume session for Room: {}", code)));
-        let handshake_json = serde_json::json!({
-            "request_room": code
-        });
-        let handshake_bytes = handshake_json.to_string().into_bytes();
-        let len = handshake_bytes.len() as u32;
-        
-        if tcp_stream.write_all(&len.to_be_bytes()).await.is_err() || 
-           tcp_stream.write_all(&handshake_bytes).await.is_err() {
-            let _ = tx.send(ProxyEvent::Log("Failed to send Handshake".to_string()));
-             return room_code;
-        }
+#[tokio::main]
+async fn main() -> eframe::Result<()> {
+    let launch = LaunchOptions::from_env().unwrap_or_else(|e| e.exit());
+    if launch.self_test {
+        let report = troudever_core::selftest::run(SELF_TEST_TIMEOUT).await;
+        println!("{}", report);
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+    if launch.headless {
+        run_headless(launch).await;
+        return Ok(());
     }
 
-    let _ = tx.send(ProxyEvent::Log("[OK] TCP Connected. Tunnel active.".to_string()));
-    let _ = tx.send(ProxyEvent::Status("Connected (Active)".to_string()));
-
-    let (mut ws_write, mut ws_read) = ws_stream.split();
-    let (mut tcp_read, mut tcp_write) = tcp_stream.into_split();
-    let mut tcp_buffer = vec![0u8; 1_048_576];
-
-    let mut current_known_room = room_code;
-
-    loop {
-        tokio::select! {
-            // WebSocket -> TCP
-            Some(msg) = ws_read.next() => {
-                match msg {
-                    Ok(message) => {
-                        let data = message.into_data();
-                        if !data.is_empty() {
-                            let len = data.len() as u32;
-                            let len_bytes = len.to_be_bytes();
-
-                            if tcp_write.write_all(&len_bytes).await.is_err() || 
-                               tcp_write.write_all(&data).await.is_err() {
-                                let _ = tx.send(ProxyEvent::Log("TCP write error".to_string()));
-                                break;
-                            }
-                            let _ = tcp_write.flush().await;
-                        }
-                    },
-                    Err(e) => {
-                        let _ = tx.send(ProxyEvent::Log(format!("WebSocket read error: {}", e)));
-                        break;
-                    }
+    // Le mode headless et le self-test n'ouvrent pas de fenêtre : pas de garde pour eux
+    let instance = if launch.new_instance {
+        None
+    } else {
+        // Jeton de l'instance en cours s'il y en a une ; sinon il est remplacé juste après
+        let secret = token::read(instance::TOKEN_FILE).unwrap_or_default();
+        match instance::claim(INSTANCE_ADDR, &secret, &launch) {
+            Ok(Claim::First(listener)) => match instance_token() {
+                Ok(secret) => Some((listener, secret)),
+                Err(e) => {
+                    eprintln!("troudever: cannot write the instance token: {}", e);
+                    None
                 }
+            },
+            Ok(Claim::Forwarded) => {
+                println!("troudever: already running, options passed to the open window");
+                return Ok(());
             }
-
-            result = tcp_read.read(&mut tcp_buffer) => {
-                match result {
-                    Ok(0) => {
-                        let _ = tx.send(ProxyEvent::Log("TCP server closed connection".to_string()));
-                        break;
-                    }
-                    Ok(n) => {
-                        let data_chunk = &tcp_buffer[0..n];
-                        
-                        let stream = serde_json::Deserializer::from_slice(&data_chunk).into_iter::<Value>();
-                        let mut forward_message = true;
-                        
-                        for json in stream {
-                            if let Ok(value) = json {
-                                if let Some(_) = value.get("internal") {
-                                    forward_message = false;
-                                    if let Some(code) = value.get("room").and_then(|v| v.as_str()){
-                                        let code_str = code.to_string();
-                                        let _ = tx.send(ProxyEvent::RoomCode(code_str.clone()));
-                                        let _ = tx.send(ProxyEvent::Log(format!("Room ID confirmed: {}", code_str)));
-                                        current_known_room = Some(code_str);
-                                    }
-                                }
-                            }
-                        }
-
-                        if forward_message {
-                            let ws_message = match std::str::from_utf8(data_chunk) {
-                                Ok(text) => Message::Text(text.to_string().into()),
-                                Err(_) => Message::Binary(data_chunk.to_vec().into()),
-                            };
-
-                            if let Err(e) = ws_write.send(ws_message).await {
-                                let _ = tx.send(ProxyEvent::Log(format!("WebSocket send error: {}", e)));
-                                break;
-                            }
-                            let _ = ws_write.flush();
-                        }
-                    }
-                    Err(e) => {
-                        let _ = tx.send(ProxyEvent::Log(format!("TCP read error: {}", e)));
-                        break;
-                    }
-                }
+            Err(e) => {
+                eprintln!("troudever: cannot check for another instance on {}: {}", INSTANCE_ADDR, e);
+                None
             }
         }
-    }
-    
-    current_known_room
-}
+    };
 
-#[tokio::main]
-async fn main() -> eframe::Result<()> {
+    let settings = Settings::load();
+    i18n::set_language(settings.language);
     let options = eframe::NativeOptions {
-        viewport: eframe::egui::ViewportBuilder::default().with_inner_size([400.0, 550.0]),
+        viewport: settings
+            .window
+            .viewport(eframe::egui::ViewportBuilder::default())
+            .with_visible(!settings.starts_hidden())
+            .with_window_level(settings.appearance.window_level()),
         ..Default::default()
     };
 
     eframe::run_native(
         "TrouDeVer",
         options,
-        Box::new(|_cc| Ok(Box::new(TrouDeVerApp::default()))),
+        Box::new(|cc| {
+            let mut app = TrouDeVerApp::new(settings, instance, &cc.egui_ctx);
+            cc.egui_ctx.set_theme(app.settings.theme);
+            cc.egui_ctx.set_zoom_factor(app.settings.appearance.scale());
+            // Pas d'option de démarrage pour ça : réduite dès la première frame
+            if app.settings.window.minimized && !app.hidden {
+                cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+            }
+            app.apply_launch(launch);
+            Ok(Box::new(app))
+        }),
     )
-}
\ No newline at end of file
+}
@@ -0,0 +1,237 @@
+//! Endpoint Prometheus optionnel : compteurs et jauges par tunnel, tenus à partir des
+//! événements que reçoit le frontend, servis au format texte sur `GET /metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use troudever_core::stats::Totals;
+use troudever_core::{ProxyEvent, TunnelId};
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+#[derive(Default)]
+struct TunnelMetrics {
+    totals: Totals,
+    reconnects: u64,
+    errors: u64,
+    // Déjà connecté depuis le lancement : le `Connected` suivant est une reconnexion
+    started: bool,
+    connected: bool,
+    latency: Option<Duration>,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    tunnels: BTreeMap<TunnelId, TunnelMetrics>,
+}
+
+impl Metrics {
+    pub fn observe(&mut self, id: TunnelId, event: &ProxyEvent) {
+        let tunnel = self.tunnels.entry(id).or_default();
+        match event {
+            ProxyEvent::Stats(stats) => tunnel.totals.observe(stats),
+            ProxyEvent::Connected => {
+                if std::mem::replace(&mut tunnel.started, true) {
+                    tunnel.reconnects += 1;
+                }
+                tunnel.connected = true;
+            }
+            ProxyEvent::Error(_) | ProxyEvent::RelayError(_) | ProxyEvent::Kicked(_) | ProxyEvent::JoinFailed(_) => {
+                tunnel.errors += 1
+            }
+            ProxyEvent::Latency(rtt) => tunnel.latency = Some(*rtt),
+            ProxyEvent::Stopped => {
+                tunnel.started = false;
+                tunnel.connected = false;
+                tunnel.latency = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Format d'exposition texte de Prometheus.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, values: &mut dyn Iterator<Item = (String, String)>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in values {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        };
+        let label = |id: &TunnelId| format!("tunnel=\"{}\"", id + 1);
+        let tunnels = &self.tunnels;
+        family(
+            "troudever_bytes_total",
+            "counter",
+            "Bytes tunneled, up = towards the WebSocket",
+            &mut tunnels.iter().flat_map(|(id, t)| {
+                [
+                    (format!("{},direction=\"up\"", label(id)), t.totals.up.bytes.to_string()),
+                    (format!("{},direction=\"down\"", label(id)), t.totals.down.bytes.to_string()),
+                ]
+            }),
+        );
+        family(
+            "troudever_messages_total",
+            "counter",
+            "Messages tunneled, up = towards the WebSocket",
+            &mut tunnels.iter().flat_map(|(id, t)| {
+                [
+                    (format!("{},direction=\"up\"", label(id)), t.totals.up.messages.to_string()),
+                    (format!("{},direction=\"down\"", label(id)), t.totals.down.messages.to_string()),
+                ]
+            }),
+        );
+        family(
+            "troudever_reconnects_total",
+            "counter",
+            "Times the tunnel came back after losing a side",
+            &mut tunnels.iter().map(|(id, t)| (label(id), t.reconnects.to_string())),
+        );
+        family(
+            "troudever_errors_total",
+            "counter",
+            "Errors reported by the tunnel or the relay",
+            &mut tunnels.iter().map(|(id, t)| (label(id), t.errors.to_string())),
+        );
+        family(
+            "troudever_message_faults_total",
+            "counter",
+            "Messages dropped, failed writes, TCP messages sent as binary and partial TCP writes",
+            &mut tunnels.iter().flat_map(|(id, t)| {
+                let faults = &t.totals.faults;
+                [
+                    ("dropped", faults.dropped),
+                    ("write_error", faults.write_errors),
+                    ("binary_fallback", faults.binary_fallbacks),
+                    ("partial_write", faults.partial_writes),
+                ]
+                .map(|(kind, count)| (format!("{},kind=\"{}\"", label(id), kind), count.to_string()))
+            }),
+        );
+        family(
+            "troudever_connected",
+            "gauge",
+            "1 while both sides of the tunnel are connected",
+            &mut tunnels.iter().map(|(id, t)| (label(id), u8::from(t.connected).to_string())),
+        );
+        family(
+            "troudever_latency_seconds",
+            "gauge",
+            "Last WebSocket round-trip time",
+            &mut tunnels
+                .iter()
+                .filter_map(|(id, t)| Some((label(id), t.latency?.as_secs_f64().to_string()))),
+        );
+        out
+    }
+}
+
+/// Serveur HTTP minimal, arrêté quand on le lâche.
+pub struct MetricsServer {
+    task: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Le bind est synchrone : une adresse prise ou invalide remonte tout de suite.
+    pub fn start(addr: &str, metrics: SharedMetrics) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, metrics.clone()));
+            }
+        });
+        Ok(Self { task })
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(mut socket: TcpStream, metrics: SharedMetrics) {
+    // Une requête de scrape tient largement dans un buffer : on ne lit que la ligne de requête
+    let mut request = [0u8; 1024];
+    let Ok(Ok(n)) = tokio::time::timeout(Duration::from_secs(5), socket.read(&mut request)).await else {
+        return;
+    };
+    let request = String::from_utf8_lossy(&request[..n]);
+    let response = if request.starts_with("GET /metrics ") || request.starts_with("GET /metrics?") {
+        let body = metrics.lock().map(|metrics| metrics.render()).unwrap_or_default();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    let _ = socket.write_all(response.as_bytes()).await;
+    let _ = socket.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use troudever_core::stats::{Counters, Stats};
+
+    fn stats(up_bytes: u64, up_messages: u64) -> ProxyEvent {
+        ProxyEvent::Stats(Stats {
+            up: Counters {
+                bytes: up_bytes,
+                messages: up_messages,
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn counters_survive_new_sessions() {
+        let mut metrics = Metrics::default();
+        metrics.observe(0, &ProxyEvent::Connected);
+        metrics.observe(0, &stats(100, 2));
+        metrics.observe(0, &stats(150, 3));
+        // Reconnexion : le moteur repart de zéro
+        metrics.observe(0, &ProxyEvent::Connected);
+        metrics.observe(0, &stats(40, 1));
+        metrics.observe(0, &ProxyEvent::Latency(Duration::from_millis(25)));
+
+        let text = metrics.render();
+        assert!(text.contains("troudever_bytes_total{tunnel=\"1\",direction=\"up\"} 190\n"));
+        assert!(text.contains("troudever_messages_total{tunnel=\"1\",direction=\"up\"} 4\n"));
+        assert!(text.contains("troudever_reconnects_total{tunnel=\"1\"} 1\n"));
+        assert!(text.contains("troudever_connected{tunnel=\"1\"} 1\n"));
+        assert!(text.contains("troudever_message_faults_total{tunnel=\"1\",kind=\"dropped\"} 0\n"));
+        assert!(text.contains("troudever_latency_seconds{tunnel=\"1\"} 0.025\n"));
+
+        metrics.observe(0, &ProxyEvent::Stopped);
+        assert!(metrics.render().contains("troudever_connected{tunnel=\"1\"} 0\n"));
+    }
+
+    #[tokio::test]
+    async fn scrape_over_http() {
+        let metrics = SharedMetrics::default();
+        metrics.lock().unwrap().observe(0, &ProxyEvent::Connected);
+        let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = probe.local_addr().unwrap();
+        drop(probe);
+        let _server = MetricsServer::start(&addr.to_string(), metrics).unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("troudever_connected{tunnel=\"1\"} 1"));
+    }
+}
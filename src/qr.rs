@@ -0,0 +1,46 @@
+//! QR code du room code, à scanner depuis un téléphone plutôt que de le dicter.
+
+use eframe::egui;
+use qrcode::{Color, QrCode};
+
+// Marge blanche autour du code, en modules : les lecteurs en ont besoin pour le repérer
+const QUIET_ZONE: usize = 2;
+const MODULE_SIZE: f32 = 4.0;
+
+/// Modules du code (vrai = foncé), ligne par ligne, et leur nombre par côté.
+fn modules(text: &str) -> Option<(usize, Vec<bool>)> {
+    let code = QrCode::new(text.as_bytes()).ok()?;
+    let dark = code.to_colors().into_iter().map(|color| color == Color::Dark).collect();
+    Some((code.width(), dark))
+}
+
+/// Dessiné en noir sur blanc quel que soit le thème : beaucoup de lecteurs ne lisent pas l'inverse.
+pub fn show(ui: &mut egui::Ui, text: &str) {
+    let Some((width, dark)) = modules(text) else {
+        return;
+    };
+    let side = (width + 2 * QUIET_ZONE) as f32 * MODULE_SIZE;
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(side, side), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::WHITE);
+    for (index, _) in dark.iter().enumerate().filter(|(_, dark)| **dark) {
+        let x = (index % width + QUIET_ZONE) as f32 * MODULE_SIZE;
+        let y = (index / width + QUIET_ZONE) as f32 * MODULE_SIZE;
+        let module = egui::Rect::from_min_size(rect.min + egui::vec2(x, y), egui::vec2(MODULE_SIZE, MODULE_SIZE));
+        painter.rect_filled(module, 0.0, egui::Color32::BLACK);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn room_code_fits_a_small_code() {
+        let (width, dark) = modules("ABCD").unwrap();
+        // Version 1 : 21 modules par côté, coin haut gauche foncé (motif de repérage)
+        assert_eq!(width, 21);
+        assert_eq!(dark.len(), 21 * 21);
+        assert!(dark[0]);
+    }
+}
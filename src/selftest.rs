@@ -0,0 +1,81 @@
+//! Bouton "Run self-test" des réglages : le test du moteur tourne en tâche de fond, comme la
+//! découverte, et son rapport reste affiché jusqu'à ce qu'on le ferme.
+
+use eframe::egui;
+use tokio::sync::oneshot;
+use troudever_core::selftest::{self, Report, SELF_TEST_TIMEOUT};
+
+use crate::TARGET_UP_COLOR;
+use crate::i18n::{message, tr, tr_args};
+
+#[derive(Default)]
+pub enum SelfTest {
+    #[default]
+    Idle,
+    Running(oneshot::Receiver<Report>),
+    Done(Report),
+}
+
+impl SelfTest {
+    pub fn start(ctx: egui::Context) -> Self {
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = tx.send(selftest::run(SELF_TEST_TIMEOUT).await);
+            ctx.request_repaint();
+        });
+        SelfTest::Running(rx)
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self, SelfTest::Running(_))
+    }
+
+    fn poll(&mut self) {
+        let SelfTest::Running(rx) = self else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok(report) => *self = SelfTest::Done(report),
+            Err(oneshot::error::TryRecvError::Empty) => {}
+            // Tâche tuée : rapport vide, donc en échec
+            Err(oneshot::error::TryRecvError::Closed) => *self = SelfTest::Done(Report::default()),
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        self.poll();
+        let SelfTest::Done(report) = self else {
+            return;
+        };
+        let mut close = false;
+        ui.group(|ui| {
+            for check in &report.checks {
+                match &check.result {
+                    Ok(took) => {
+                        let text = tr_args(
+                            "selftest.passed_check",
+                            &[("check", &message(check.name)), ("ms", &took.as_millis())],
+                        );
+                        ui.colored_label(TARGET_UP_COLOR, text);
+                    }
+                    Err(e) => {
+                        let text = tr_args(
+                            "selftest.failed_check",
+                            &[("check", &message(check.name)), ("error", &message(e))],
+                        );
+                        ui.colored_label(ui.visuals().error_fg_color, text);
+                    }
+                }
+            }
+            if report.passed() {
+                ui.label(tr("selftest.passed"));
+            } else {
+                ui.label(tr("selftest.failed"));
+            }
+            close = ui.small_button(tr("selftest.close")).clicked();
+        });
+        if close {
+            *self = SelfTest::Idle;
+        }
+    }
+}
@@ -0,0 +1,1194 @@
+//! Un onglet : la config d'un tunnel, ce qu'il montre de sa session, et le tunnel lui-même
+//! quand il tourne. L'app ne fait que lui passer les événements et lui prêter les réglages.
+
+use chrono::{DateTime, Local};
+use crossbeam_channel::Sender;
+use eframe::egui;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use troudever_core::chaos::{Conditions, NetworkConditions};
+use troudever_core::config::READ_BUFFER_RANGE;
+use troudever_core::fanout::{Routing, TargetStatus};
+use troudever_core::inspect::{self, FrameLog};
+use troudever_core::logging::{LogLevel, LogRecord};
+use troudever_core::record;
+use troudever_core::stats::{self, LatencyStats, Stats};
+use troudever_core::tunnel::{PauseMode, PausedDirections, TcpFraming, TcpMode, WsMode};
+use troudever_core::{EventSender, MISSED_PONGS_LIMIT, ProxyConfig, ProxyEvent, SHUTDOWN_GRACE, Tunnel, TunnelId};
+
+use crate::bandwidth::Bandwidth;
+use crate::config::Settings;
+use crate::discover::Discovery;
+use crate::history::{Session, SessionRecord};
+use crate::hooks::{HookDetails, HookEvent, Link};
+use crate::i18n::{message, tr, tr_args};
+use crate::logs::{LogEntry, LogFilter, LogFormat, LogRetention};
+use crate::timeline::{Timeline, format_uptime};
+use crate::{TARGET_UP_COLOR, qr};
+
+/// Un onglet : la config éditable, l'état affiché, et le tunnel quand il tourne.
+pub struct TunnelTab {
+    pub id: TunnelId,
+    pub config: ProxyConfig,
+
+    // State
+    pub is_running: bool,
+    pub room_history: Vec<(DateTime<Local>, String)>,
+    pub status_msg: String,
+    timeline: Timeline,
+    // Le statut courant est une erreur (auth refusée...)
+    pub error: bool,
+    latency: LatencyStats,
+    // Annoncés par le relais (peer_joined / peer_left)
+    pub peers: Vec<String>,
+    // Dernier état connu de chaque cible de fan-out, par index
+    targets: Vec<TargetStatus>,
+    stats: Option<Stats>,
+    bandwidth: Bandwidth,
+    logs: VecDeque<LogEntry>,
+    log_retention: LogRetention,
+    log_filter: LogFilter,
+    log_format: LogFormat,
+    frames: FrameLog,
+    inspect_view: inspect::View,
+    // Profil chargé ou nom à sauvegarder
+    pub profile_name: String,
+    // Room code à copier par l'app, qui seule a accès au presse-papier
+    pub copy_request: Option<String>,
+    // Room d'un autre à rejoindre, saisie une fois connecté
+    join_code: String,
+    // Bouton "Discover" du serveur TCP
+    discovery: Discovery,
+    // Session lancée par CONNECT, et celle qui vient de finir en attendant que l'app l'archive
+    session: Option<Session>,
+    pub finished_session: Option<SessionRecord>,
+
+    tx_event: Sender<(TunnelId, ProxyEvent)>,
+    pub events: EventSender,
+    // `None` une fois STOP demandé, en attendant `Stopped`
+    proxy: Option<Tunnel>,
+    paused: bool,
+    // Sens suspendus un à un, en plus de la pause générale
+    held: PausedDirections,
+    // Pour ne lancer les hooks de connexion qu'au changement d'état
+    link: Link,
+}
+
+impl TunnelTab {
+    pub fn new(
+        id: TunnelId,
+        config: ProxyConfig,
+        log_retention: LogRetention,
+        tx_event: Sender<(TunnelId, ProxyEvent)>,
+    ) -> Self {
+        Self {
+            id,
+            config,
+            is_running: false,
+            room_history: vec![],
+            status_msg: "Ready".to_owned(),
+            timeline: Timeline::default(),
+            error: false,
+            latency: LatencyStats::default(),
+            peers: vec![],
+            targets: vec![],
+            stats: None,
+            bandwidth: Bandwidth::default(),
+            logs: VecDeque::new(),
+            log_retention,
+            log_filter: LogFilter::default(),
+            log_format: LogFormat::default(),
+            frames: FrameLog::default(),
+            inspect_view: inspect::View::default(),
+            profile_name: String::new(),
+            copy_request: None,
+            join_code: String::new(),
+            discovery: Discovery::Idle,
+            session: None,
+            finished_session: None,
+            events: EventSender::new(id, tx_event.clone()),
+            tx_event,
+            proxy: None,
+            paused: false,
+            held: PausedDirections::default(),
+            link: Link::default(),
+        }
+    }
+
+    /// Nom stable, celui des fichiers de logs.
+    pub fn name(&self) -> String {
+        format!("Tunnel {}", self.id + 1)
+    }
+
+    /// Nom affiché, dans la langue choisie.
+    pub fn label(&self) -> String {
+        tr_args("tabs.tunnel", &[("number", &(self.id + 1))])
+    }
+
+    /// Survol de l'onglet : de quoi distinguer les tunnels sans les ouvrir.
+    pub fn summary(&self) -> String {
+        let status = message(&self.status_msg);
+        let mut summary = format!("{} ⇄ {}\n{}", self.config.ws_url, self.config.tcp_addr, status);
+        if let Some((_, room)) = self.room_history.last() {
+            summary.push('\n');
+            summary.push_str(&tr_args("tab.room", &[("code", room)]));
+        }
+        summary
+    }
+
+    pub fn handle_event(&mut self, event: ProxyEvent, settings: &mut Settings) {
+        if let Some(session) = &mut self.session {
+            session.observe(&event);
+        }
+        self.timeline.observe(Local::now(), &event);
+        match event {
+            ProxyEvent::Log(record) => self.push_log(LogEntry::now(record)),
+            ProxyEvent::RoomCode(code) => {
+                if !self.push_room_code(code.clone()) {
+                    return;
+                }
+                settings.rooms.remember(&self.config.tcp_addr, &code, Local::now());
+                if settings.notify_room_code() {
+                    let summary = tr_args("notify.room_code", &[("code", &code)]);
+                    desktop_notify(summary, self.label(), self.events.clone());
+                }
+                if settings.auto_copy_room {
+                    self.copy_request = Some(code);
+                }
+                self.run_hook(settings, HookEvent::RoomCode, None);
+            }
+            ProxyEvent::Status(msg) => {
+                self.status_msg = msg;
+                self.error = false;
+            }
+            ProxyEvent::Error(msg) => {
+                self.status_msg = msg;
+                self.error = true;
+            }
+            ProxyEvent::RelayError(message) => {
+                self.status_msg = format!("Relay error: {}", message);
+                self.error = true;
+            }
+            ProxyEvent::Kicked(reason) => {
+                self.status_msg = format!("Kicked by the relay: {}", reason);
+                self.error = true;
+            }
+            ProxyEvent::Joined(code) => {
+                // La room rejointe devient celle du tunnel (QR, historique, reprise)
+                if self.push_room_code(code.clone()) {
+                    settings.rooms.remember(&self.config.tcp_addr, &code, Local::now());
+                }
+                self.status_msg = format!("Joined room {}", code);
+                self.error = false;
+            }
+            ProxyEvent::JoinFailed(reason) => {
+                self.status_msg = format!("Join failed: {}", reason);
+                self.error = true;
+            }
+            ProxyEvent::PeerJoined(peer) => {
+                if !self.peers.contains(&peer) {
+                    self.peers.push(peer);
+                }
+            }
+            ProxyEvent::PeerLeft(peer) => self.peers.retain(|known| *known != peer),
+            ProxyEvent::Connected => {
+                if settings.notify_connected() {
+                    desktop_notify(tr("notify.connected").to_string(), self.label(), self.events.clone());
+                }
+                if self.link.connected() {
+                    self.run_hook(settings, HookEvent::Connected, None);
+                }
+            }
+            ProxyEvent::Reconnecting(side) => {
+                if self.link.lost() {
+                    self.run_hook(settings, HookEvent::Disconnected, Some(&format!("{} lost", side)));
+                }
+            }
+            ProxyEvent::Target(status) => match self.targets.iter_mut().find(|known| known.index == status.index) {
+                Some(known) => *known = status,
+                None => self.targets.push(status),
+            },
+            ProxyEvent::Latency(rtt) => self.latency.record(rtt),
+            ProxyEvent::Stats(stats) => {
+                self.bandwidth.record(Instant::now(), &stats);
+                self.stats = Some(stats);
+            }
+            ProxyEvent::Frame(frame) => self.frames.push(frame),
+            ProxyEvent::Stopped => {
+                // Un STOP utilisateur a déjà consommé `proxy`
+                let unexpected = self.is_running && self.proxy.is_some();
+                if unexpected && settings.notify_disconnected() {
+                    desktop_notify(tr("notify.disconnected").to_string(), self.label(), self.events.clone());
+                }
+                let reason = if self.error {
+                    self.status_msg.clone()
+                } else if !unexpected {
+                    "Stopped by user".to_string()
+                } else {
+                    self.logs.back().map_or_else(|| "Stopped".to_string(), |entry| entry.record.message.clone())
+                };
+                if self.link.lost() {
+                    self.run_hook(settings, HookEvent::Disconnected, Some(&reason));
+                }
+                if let Some(session) = self.session.take() {
+                    let room = self.room_history.last().map(|(_, code)| code.clone());
+                    self.finished_session = Some(session.finish(room, reason));
+                }
+                self.is_running = false;
+                if self.error {
+                    self.status_msg = format!("Stopped: {}", self.status_msg);
+                } else {
+                    self.status_msg = "Stopped".to_string();
+                }
+                self.proxy = None;
+                self.paused = false;
+                self.held = PausedDirections::default();
+                self.peers.clear();
+                self.targets.clear();
+            }
+        }
+    }
+
+    /// Un échec du script finit dans les logs de l'onglet, comme celui d'une notification.
+    fn run_hook(&self, settings: &Settings, event: HookEvent, reason: Option<&str>) {
+        let name = self.name();
+        let details = HookDetails {
+            tunnel: &name,
+            config: &self.config,
+            room_code: self.room_history.last().map(|(_, code)| code.as_str()),
+            reason,
+        };
+        let events = self.events.clone();
+        settings.hooks.run(event, &details, move |e| events.log(LogLevel::Warn, "app", e));
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, settings: &mut Settings) {
+        ui.add_enabled_ui(!self.is_running, |ui| {
+            self.show_profiles(ui, settings);
+            self.show_endpoints(ui);
+            self.show_connection_options(ui);
+            self.show_timeouts(ui);
+            self.show_fanout(ui);
+            self.show_control(ui);
+            self.show_handshake(ui);
+            self.show_auth(ui);
+            self.show_tls(ui);
+            self.show_encoding(ui);
+            self.show_capture_options(ui);
+        });
+        self.show_live_options(ui);
+
+        ui.add_space(10.0);
+
+        self.show_run_buttons(ui, settings);
+        self.show_status(ui);
+        self.show_timeline(ui);
+        self.show_room(ui, settings);
+        ui.separator();
+
+        if self.config.inspect || !self.frames.is_empty() {
+            self.show_inspector(ui);
+        }
+        self.show_logs(ui);
+    }
+
+    /// Modes et adresses des deux côtés ; figés pendant que le tunnel tourne, comme le reste de la config.
+    fn show_endpoints(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for mode in WsMode::ALL {
+                ui.radio_value(&mut self.config.ws_mode, mode, message(mode.label()));
+            }
+        });
+        ui.horizontal(|ui| {
+            match self.config.ws_mode {
+                WsMode::Connect => ui.label(tr("tunnel.ws_url")),
+                WsMode::Listen => ui
+                    .label(tr("tunnel.ws_listen"))
+                    .on_hover_text(tr("tunnel.ws_listen_hint")),
+            };
+            ui.text_edit_singleline(&mut self.config.ws_url);
+        });
+        ui.horizontal(|ui| {
+            for mode in TcpMode::ALL {
+                ui.radio_value(&mut self.config.tcp_mode, mode, message(mode.label()));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(match self.config.tcp_mode {
+                TcpMode::Connect => tr("tunnel.tcp_server"),
+                TcpMode::Listen | TcpMode::Multiplex => tr("tunnel.tcp_listen"),
+                TcpMode::Udp => tr("tunnel.udp_listen"),
+                TcpMode::Socks5 => tr("tunnel.socks_listen"),
+            });
+            ui.text_edit_singleline(&mut self.config.tcp_addr)
+                .on_hover_text(tr("tunnel.tcp_addr_hint"));
+            // On ne découvre que ce qu'on va composer
+            if self.config.tcp_mode == TcpMode::Connect {
+                let searching = self.discovery.is_searching();
+                let button = ui.add_enabled(!searching, egui::Button::new(tr("discover.button")));
+                if button.on_hover_text(tr("discover.hint")).clicked() {
+                    self.discovery = Discovery::start(ui.ctx().clone());
+                }
+                if searching {
+                    ui.spinner();
+                }
+            }
+        });
+        if let Some(addr) = self.discovery.show(ui) {
+            self.config.tcp_addr = addr;
+        }
+        // Un datagramme est déjà un message ; le multiplexage transporte les octets tels quels
+        let streamed = matches!(self.config.tcp_mode, TcpMode::Multiplex | TcpMode::Socks5);
+        ui.add_enabled_ui(self.config.tcp_mode != TcpMode::Udp && !streamed, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr("tunnel.framing"));
+                egui::ComboBox::from_id_salt(("tcp_framing", self.id))
+                    .selected_text(message(self.config.tcp_framing.label()))
+                    .show_ui(ui, |ui| {
+                        for framing in TcpFraming::ALL {
+                            ui.selectable_value(&mut self.config.tcp_framing, framing, message(framing.label()));
+                        }
+                    });
+            })
+            .response
+            .on_hover_text(tr("tunnel.framing_hint"));
+        });
+    }
+
+    fn show_connection_options(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.config.auto_reconnect, tr("tunnel.auto_reconnect"));
+            ui.add_enabled_ui(self.config.auto_reconnect, |ui| {
+                ui.label(tr("tunnel.max_retries"));
+                ui.add(egui::DragValue::new(&mut self.config.max_retries).range(0..=1000))
+                    .on_hover_text(tr("tunnel.max_retries_hint"));
+            });
+        });
+        ui.horizontal(|ui| {
+            let buffer = &mut self.config.reconnect_buffer;
+            ui.label(tr("tunnel.reconnect_buffer"));
+            ui.add(egui::DragValue::new(&mut buffer.max_messages).range(0..=100_000).suffix(" msg"));
+            ui.add(egui::DragValue::new(&mut buffer.max_kb).range(1..=262_144).suffix(" KB"));
+        })
+        .response
+        .on_hover_text(tr("tunnel.reconnect_buffer_hint"));
+        ui.horizontal(|ui| {
+            ui.label(tr("tunnel.ping"));
+            ui.add(egui::DragValue::new(&mut self.config.ping_interval_secs).range(0..=300).suffix(" s"));
+        })
+        .response
+        .on_hover_text(tr_args("tunnel.ping_hint", &[("pings", &MISSED_PONGS_LIMIT)]));
+        ui.horizontal(|ui| {
+            ui.label(tr("tunnel.read_buffer"));
+            ui.add(
+                egui::DragValue::new(&mut self.config.read_buffer_size)
+                    .range(READ_BUFFER_RANGE)
+                    .speed(1024)
+                    .suffix(tr("unit.bytes")),
+            );
+        })
+        .response
+        .on_hover_text(tr("tunnel.read_buffer_hint"));
+    }
+
+    fn show_timeouts(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("timeouts.title"))
+            .id_salt(("timeouts", self.id))
+            .show(ui, |ui| {
+                let timeouts = &mut self.config.timeouts;
+                ui.horizontal(|ui| {
+                    ui.label(tr("timeouts.connect"));
+                    ui.add(egui::DragValue::new(&mut timeouts.connect_secs).range(1..=300).suffix(" s"));
+                })
+                .response
+                .on_hover_text(tr("timeouts.connect_hint"));
+                ui.horizontal(|ui| {
+                    ui.label(tr("timeouts.read"));
+                    ui.add(egui::DragValue::new(&mut timeouts.read_secs).range(0..=3600).suffix(" s"));
+                })
+                .response
+                .on_hover_text(tr("timeouts.read_hint"));
+                ui.horizontal(|ui| {
+                    ui.label(tr("timeouts.idle"));
+                    ui.add(egui::DragValue::new(&mut timeouts.idle_secs).range(0..=86400).suffix(" s"));
+                })
+                .response
+                .on_hover_text(tr("timeouts.idle_hint"));
+                ui.horizontal(|ui| {
+                    ui.label(tr("timeouts.watchdog"));
+                    ui.add(egui::DragValue::new(&mut timeouts.watchdog_secs).range(0..=3600).suffix(" s"));
+                })
+                .response
+                .on_hover_text(tr("timeouts.watchdog_hint"));
+                ui.horizontal(|ui| {
+                    ui.label(tr("timeouts.keepalive"));
+                    ui.add(egui::DragValue::new(&mut timeouts.keepalive_secs).range(0..=7200).suffix(" s"));
+                })
+                .response
+                .on_hover_text(tr("timeouts.keepalive_hint"));
+            });
+    }
+
+    fn show_fanout(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("fanout.title"))
+            .id_salt(("fanout", self.id))
+            .show(ui, |ui| {
+                let fanout = &mut self.config.fanout;
+                ui.label(tr("fanout.intro"));
+                let mut removed = None;
+                for (index, addr) in fanout.targets.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}.", index + 1));
+                        ui.add(egui::TextEdit::singleline(addr).hint_text("127.0.0.1:9100"));
+                        if ui.small_button("✖").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed {
+                    fanout.targets.remove(index);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button(tr("fanout.add")).clicked() {
+                        fanout.targets.push(String::new());
+                    }
+                    for routing in Routing::ALL {
+                        ui.radio_value(&mut fanout.routing, routing, message(routing.label()));
+                    }
+                })
+                .response
+                .on_hover_text(tr("fanout.routing_hint"));
+            });
+    }
+
+    fn show_control(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("control.title"))
+            .id_salt(("control", self.id))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr("control.marker"));
+                    ui.add(egui::TextEdit::singleline(&mut self.config.control.marker).hint_text("internal"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label(tr("control.room_pointer"));
+                    ui.add(egui::TextEdit::singleline(&mut self.config.control.room_pointer).hint_text("/room"));
+                });
+                if let Err(e) = self.config.control.validate() {
+                    ui.colored_label(ui.visuals().error_fg_color, message(&e));
+                }
+            });
+    }
+
+    fn show_handshake(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("handshake.title"))
+            .id_salt(("handshake", self.id))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr("handshake.subprotocol"));
+                    let none = tr("handshake.subprotocol_none");
+                    ui.add(egui::TextEdit::singleline(&mut self.config.ws_subprotocol).hint_text(none));
+                })
+                .response
+                .on_hover_text(tr("handshake.subprotocol_hint"));
+                ui.checkbox(&mut self.config.negotiate_version, tr("handshake.negotiate_version"))
+                    .on_hover_text(tr("handshake.negotiate_version_hint"));
+                let mut removed = None;
+                for (index, (name, value)) in self.config.ws_headers.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let name = egui::TextEdit::singleline(name).hint_text(tr("handshake.header"));
+                        ui.add(name.desired_width(140.0));
+                        ui.add(egui::TextEdit::singleline(value).hint_text(tr("handshake.value")).password(true));
+                        if ui.small_button("✖").on_hover_text(tr("handshake.remove")).clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed {
+                    self.config.ws_headers.remove(index);
+                }
+                if ui.button(tr("handshake.add")).clicked() {
+                    self.config.ws_headers.push((String::new(), String::new()));
+                }
+            });
+    }
+
+    fn show_auth(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("auth.title"))
+            .id_salt(("auth", self.id))
+            .show(ui, |ui| {
+                let auth = &mut self.config.auth;
+                ui.checkbox(&mut auth.enabled, tr("auth.enabled"))
+                    .on_hover_text(tr("auth.enabled_hint"));
+                ui.add_enabled_ui(auth.enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(tr("auth.token"));
+                        ui.add(egui::TextEdit::singleline(&mut auth.token).password(true));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(tr("auth.client_name"));
+                        ui.text_edit_singleline(&mut auth.client_name);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(tr("auth.protocol_version"));
+                        ui.add(egui::DragValue::new(&mut auth.protocol_version));
+                    });
+                });
+            });
+    }
+
+    fn show_tls(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("tls.title"))
+            .id_salt(("tls", self.id))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr("tls.ca_file"));
+                    let mut ca_file = self.config.tls.ca_file.display().to_string();
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut ca_file).hint_text(tr("tls.ca_file_none")))
+                        .changed()
+                    {
+                        self.config.tls.ca_file = ca_file.into();
+                    }
+                    if ui.button("…").on_hover_text(tr("common.browse")).clicked()
+                        && let Some(path) = rfd::FileDialog::new().add_filter("PEM", &["pem", "crt"]).pick_file()
+                    {
+                        self.config.tls.ca_file = path;
+                    }
+                });
+                ui.checkbox(&mut self.config.tls.accept_invalid_certs, tr("tls.accept_invalid"))
+                    .on_hover_text(tr("tls.accept_invalid_hint"));
+            });
+    }
+
+    /// Compression, découpage et chiffrement de bout en bout.
+    fn show_encoding(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.config.compress, tr("tunnel.compress"))
+            .on_hover_text(tr("tunnel.compress_hint"));
+        ui.horizontal(|ui| {
+            ui.label(tr("tunnel.max_frame"));
+            ui.add(
+                egui::DragValue::new(&mut self.config.max_frame_size)
+                    .range(0..=*READ_BUFFER_RANGE.end())
+                    .speed(1024)
+                    .suffix(tr("unit.bytes")),
+            );
+        })
+        .response
+        .on_hover_text(tr("tunnel.max_frame_hint"));
+        egui::CollapsingHeader::new(tr("e2e.title"))
+            .id_salt(("e2e", self.id))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr("e2e.key"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.config.e2e_key)
+                            .password(true)
+                            .hint_text(tr("common.disabled")),
+                    );
+                })
+                .response
+                .on_hover_text(tr("e2e.key_hint"));
+            });
+        let encoded = !self.config.e2e_key.is_empty() || self.config.compress || self.config.max_frame_size > 0;
+        if encoded && matches!(self.config.tcp_mode, TcpMode::Udp | TcpMode::Multiplex | TcpMode::Socks5) {
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                tr("tunnel.encoding_modes"),
+            );
+        }
+    }
+
+    /// Inspecteur, enregistrement et comportement de la pause.
+    fn show_capture_options(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.config.inspect, tr("inspect.enabled"));
+            ui.add_enabled(
+                self.config.inspect,
+                egui::DragValue::new(&mut self.config.inspect_max_bytes)
+                    .range(16..=65536)
+                    .prefix(tr("inspect.max"))
+                    .suffix(tr("unit.bytes")),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label(tr("record.file"));
+            // Le chemin est celui de la config : appliqué seulement au prochain lancement
+            let mut record_file = self.config.record_file.display().to_string();
+            if ui
+                .add(egui::TextEdit::singleline(&mut record_file).hint_text(tr("common.disabled")))
+                .changed()
+            {
+                self.config.record_file = record_file.trim().into();
+            }
+            if ui.button("…").on_hover_text(tr("common.browse")).clicked()
+                && let Some(path) = rfd::FileDialog::new().set_file_name("recording.jsonl").save_file()
+            {
+                self.config.record_file = path;
+            }
+        })
+        .response
+        .on_hover_text(tr("record.file_hint"));
+        ui.horizontal(|ui| {
+            ui.label(tr("tunnel.pause_mode"));
+            for mode in PauseMode::ALL {
+                ui.radio_value(&mut self.config.pause_mode, mode, message(mode.label()));
+            }
+        })
+        .response
+        .on_hover_text(tr("tunnel.pause_mode_hint"));
+    }
+
+    fn show_live_options(&mut self, ui: &mut egui::Ui) {
+        // Réglable pendant que le tunnel tourne, pour simuler un lien lent
+        let rates = ui.horizontal(|ui| {
+            ui.label(tr("rates.ws_to_tcp"));
+            let ws_to_tcp = ui.add(egui::DragValue::new(&mut self.config.ws_to_tcp_rate).suffix(" B/s"));
+            ui.label(tr("rates.tcp_to_ws"));
+            let tcp_to_ws = ui.add(egui::DragValue::new(&mut self.config.tcp_to_ws_rate).suffix(" B/s"));
+            ws_to_tcp.changed() || tcp_to_ws.changed()
+        });
+        rates.response.on_hover_text(tr("rates.hint"));
+        if rates.inner && let Some(tunnel) = &self.proxy {
+            tunnel.set_rates(self.config.ws_to_tcp_rate, self.config.tcp_to_ws_rate);
+        }
+        if self.show_network_conditions(ui) && let Some(tunnel) = &self.proxy {
+            tunnel.set_network_conditions(self.config.network);
+        }
+    }
+
+    fn show_run_buttons(&mut self, ui: &mut egui::Ui, settings: &Settings) {
+        if self.is_running {
+            let stopping = self.proxy.is_none();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!stopping, egui::Button::new(tr("run.stop"))).clicked() {
+                    self.stop_proxy();
+                }
+                let label = if self.paused { tr("run.resume") } else { tr("run.pause") };
+                if ui.add_enabled(!stopping, egui::Button::new(label)).clicked() {
+                    self.set_paused(!self.paused);
+                }
+                ui.separator();
+                // Pas de sens à part en UDP ni en multiplexé
+                let directed = !stopping && matches!(self.config.tcp_mode, TcpMode::Connect | TcpMode::Listen);
+                let mut held = self.held;
+                ui.add_enabled_ui(directed, |ui| {
+                    ui.toggle_value(&mut held.ws_to_tcp, tr("run.pause_ws_to_tcp"))
+                        .on_hover_text(tr("run.pause_direction_hint"));
+                    ui.toggle_value(&mut held.tcp_to_ws, tr("run.pause_tcp_to_ws"))
+                        .on_hover_text(tr("run.pause_direction_hint"));
+                });
+                if held != self.held {
+                    self.set_held(held);
+                }
+            });
+        } else {
+            let valid = self.config.control.validate().is_ok();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(valid, egui::Button::new(tr("run.connect"))).clicked() {
+                    self.start_proxy(settings);
+                }
+                if ui
+                    .button(tr("run.replay"))
+                    .on_hover_text(tr("run.replay_hint"))
+                    .clicked()
+                    && let Some(path) =
+                        rfd::FileDialog::new().add_filter(tr("run.replay_filter"), &["jsonl"]).pick_file()
+                {
+                    self.start_replay(&path, settings);
+                }
+            });
+        }
+    }
+
+    /// Statut, latence, trafic, débit, pairs et cibles de fan-out.
+    fn show_status(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let status = tr_args("status.line", &[("status", &message(&self.status_msg))]);
+            if self.error {
+                ui.colored_label(ui.visuals().error_fg_color, status);
+            } else {
+                ui.label(status);
+            }
+            if let Some(uptime) = self.timeline.uptime(Local::now()) {
+                ui.label(tr_args("status.uptime", &[("uptime", &format_uptime(uptime))]))
+                    .on_hover_text(tr("status.uptime_hint"));
+                ui.ctx().request_repaint_after(Duration::from_secs(1));
+            }
+            if self.is_running {
+                ui.label(message(&self.latency.summary()));
+                if !self.config.e2e_key.is_empty() {
+                    ui.label(tr("status.encrypted")).on_hover_text(tr("status.encrypted_hint"));
+                }
+            }
+        });
+        if self.is_running
+            && let Some(stats) = &self.stats
+        {
+            ui.label(message(&stats.summary())).on_hover_text(tr_args(
+                "status.traffic_hint",
+                &[
+                    ("up", &stats::format_bytes(stats.up.bytes as f64)),
+                    ("up_messages", &stats.up.messages),
+                    ("down", &stats::format_bytes(stats.down.bytes as f64)),
+                    ("down_messages", &stats.down.messages),
+                ],
+            ));
+            if !stats.faults.is_empty() {
+                ui.colored_label(ui.visuals().warn_fg_color, message(&stats.faults.summary()))
+                    .on_hover_text(tr("status.faults_hint"));
+            }
+        }
+        if self.is_running && !self.bandwidth.is_empty() {
+            egui::CollapsingHeader::new(tr("bandwidth.title"))
+                .id_salt(("bandwidth", self.id))
+                .default_open(true)
+                .show(ui, |ui| self.bandwidth.show(ui, Instant::now()));
+        }
+        if !self.peers.is_empty() {
+            ui.label(tr_args("status.peers", &[("peers", &self.peers.join(", "))]));
+        }
+        if self.is_running && !self.targets.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(tr("status.targets"));
+                for (index, addr) in self.config.fanout.targets.iter().enumerate() {
+                    let status = self.targets.iter().find(|status| status.index == index);
+                    let (color, hover) = match status {
+                        Some(status) if status.connected => (TARGET_UP_COLOR, tr("status.target_up").to_string()),
+                        Some(status) => {
+                            let error = message(status.error.as_deref().unwrap_or_default()).into_owned();
+                            (ui.visuals().error_fg_color, error)
+                        }
+                        None => (ui.visuals().weak_text_color(), tr("status.target_connecting").to_string()),
+                    };
+                    ui.colored_label(color, format!("● {}", addr)).on_hover_text(hover);
+                }
+            });
+        }
+    }
+
+    fn show_room(&mut self, ui: &mut egui::Ui, settings: &Settings) {
+        if self.is_running && matches!(self.config.tcp_mode, TcpMode::Connect | TcpMode::Listen) {
+            ui.horizontal(|ui| {
+                ui.label(tr("room.join_label"));
+                ui.text_edit_singleline(&mut self.join_code);
+                let code = self.join_code.trim();
+                if ui.add_enabled(!code.is_empty(), egui::Button::new(tr("room.join"))).clicked()
+                    && let Some(tunnel) = &self.proxy
+                {
+                    tunnel.join_room(code);
+                    self.status_msg = format!("Joining room {}...", code);
+                    self.error = false;
+                }
+            });
+        }
+        if let Some((_, code)) = self.room_history.last() {
+            ui.add_space(10.0);
+            ui.heading(tr_args("room.heading", &[("code", code)]));
+            ui.horizontal(|ui| {
+                if ui.button(tr("room.copy")).clicked() {
+                    ui.ctx().copy_text(code.to_string());
+                }
+                let relayed = self.is_running && matches!(self.config.tcp_mode, TcpMode::Connect | TcpMode::Listen);
+                if ui
+                    .add_enabled(relayed, egui::Button::new(tr("room.new")))
+                    .on_hover_text(tr("room.new_hint"))
+                    .clicked()
+                    && let Some(tunnel) = &self.proxy
+                {
+                    // Pas de statut d'attente : le nouveau code remplace l'ancien dès qu'il arrive
+                    tunnel.new_room();
+                }
+            });
+            qr::show(ui, code);
+        }
+        self.show_recent_rooms(ui, settings);
+    }
+
+    fn show_logs(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading(tr("logs.title"));
+            egui::ComboBox::from_id_salt(("log_format", self.id))
+                .selected_text(message(self.log_format.label()))
+                .show_ui(ui, |ui| {
+                    for format in LogFormat::ALL {
+                        ui.selectable_value(&mut self.log_format, format, message(format.label()));
+                    }
+                });
+            if ui.button(tr("logs.save")).clicked() {
+                self.save_logs();
+            }
+            if ui.button(tr("logs.copy_all")).on_hover_text(tr("logs.copy_all_hint")).clicked() {
+                ui.ctx().copy_text(self.log_format.render(&self.name(), &self.logs));
+            }
+            if ui.add_enabled(!self.logs.is_empty(), egui::Button::new(tr("common.clear"))).clicked() {
+                self.logs.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.log_filter.text)
+                    .hint_text(tr("logs.filter"))
+                    .desired_width(180.0),
+            );
+            for level in LogLevel::ALL {
+                let shown = self.log_filter.shows(level);
+                if ui.selectable_label(*shown, message(level.label())).clicked() {
+                    *shown = !*shown;
+                }
+            }
+            if self.log_filter.is_active() && ui.small_button("✖").on_hover_text(tr("logs.clear_filter")).clicked() {
+                self.log_filter = LogFilter::default();
+            }
+        });
+        // Seules les lignes visibles sont dessinées : des milliers d'entrées restent fluides
+        let needle = self.log_filter.needle();
+        let shown: Vec<&LogEntry> = self.logs.iter().filter(|log| self.log_filter.matches(log, &needle)).collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical()
+            .id_salt(("logs", self.id))
+            .auto_shrink([false, true])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                for log in &shown[rows] {
+                    let text = format!("[{}] {}", log.time.format("%H:%M:%S"), translate_record(&log.record));
+                    let mut text = egui::RichText::new(text);
+                    match log.record.level {
+                        LogLevel::Info => {}
+                        LogLevel::Debug => text = text.weak(),
+                        LogLevel::Warn => text = text.color(ui.visuals().warn_fg_color),
+                        LogLevel::Error => text = text.color(ui.visuals().error_fg_color),
+                    }
+                    // Une ligne par entrée, sinon la hauteur de ligne ne tient plus
+                    ui.add(egui::Label::new(text).truncate());
+                }
+            });
+    }
+
+    pub fn push_log(&mut self, entry: LogEntry) {
+        self.logs.push_back(entry);
+        self.log_retention.trim(&mut self.logs);
+    }
+
+    pub fn set_log_retention(&mut self, retention: LogRetention) {
+        self.log_retention = retention;
+        retention.trim(&mut self.logs);
+    }
+
+    /// Repart de zéro, ou marque le début de la session si les logs sont gardés.
+    fn begin_logs(&mut self, keep: bool) {
+        if !keep {
+            self.logs.clear();
+        } else if !self.logs.is_empty() {
+            self.push_log(LogEntry::app(LogLevel::Info, "--- New session ---".to_string()));
+        }
+    }
+
+    fn save_logs(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(format!("troudever-tunnel-{}.{}", self.id + 1, self.log_format.extension()))
+            .save_file()
+        else {
+            return;
+        };
+        let content = self.log_format.render(&self.name(), &self.logs);
+        if let Err(e) = std::fs::write(&path, content) {
+            self.push_log(LogEntry::app(LogLevel::Error, format!("Failed to save logs: {}", e)));
+        }
+    }
+
+    fn show_profiles(&mut self, ui: &mut egui::Ui, settings: &mut Settings) {
+        ui.horizontal(|ui| {
+            ui.label(tr("profile.label"));
+            egui::ComboBox::from_id_salt(("profile", self.id))
+                .selected_text(self.profile_name.as_str())
+                .show_ui(ui, |ui| {
+                    for profile in &settings.profiles {
+                        if ui.selectable_label(profile.name == self.profile_name, &profile.name).clicked() {
+                            self.profile_name = profile.name.clone();
+                            self.config = profile.config.clone();
+                        }
+                    }
+                });
+            ui.add(
+                egui::TextEdit::singleline(&mut self.profile_name)
+                    .hint_text(tr("profile.name"))
+                    .desired_width(90.0),
+            );
+            let name = self.profile_name.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new(tr("profile.save")))
+                .on_hover_text(tr("profile.save_hint"))
+                .clicked()
+            {
+                settings.save_profile(&name, self.config.clone());
+            }
+            if ui.add_enabled(settings.profile(&name).is_some(), egui::Button::new(tr("profile.delete"))).clicked() {
+                settings.delete_profile(&name);
+            }
+        });
+    }
+
+    /// Retourne `true` si un réglage a changé.
+    fn show_network_conditions(&mut self, ui: &mut egui::Ui) -> bool {
+        type Field<T> = fn(&mut Conditions) -> &mut T;
+        const DELAYS: [(&str, Field<u64>); 2] =
+            [("network.delay", |c| &mut c.delay_ms), ("network.jitter", |c| &mut c.jitter_ms)];
+        const ODDS: [(&str, Field<f64>); 4] = [
+            ("network.drop", |c| &mut c.drop),
+            ("network.duplicate", |c| &mut c.duplicate),
+            ("network.reorder", |c| &mut c.reorder),
+            ("network.corrupt", |c| &mut c.corrupt),
+        ];
+        let id = self.id;
+        let network = &mut self.config.network;
+        let title = if network.is_active() { tr("network.title_active") } else { tr("network.title") };
+        let mut changed = false;
+        egui::CollapsingHeader::new(title)
+            .id_salt(("network", id))
+            .show(ui, |ui| {
+                ui.label(tr("network.intro"));
+                egui::Grid::new(("network_grid", id)).num_columns(3).show(ui, |ui| {
+                    ui.label("");
+                    ui.label(message(inspect::Direction::WsToTcp.arrow()));
+                    ui.label(message(inspect::Direction::TcpToWs.arrow()));
+                    ui.end_row();
+                    for (key, field) in DELAYS {
+                        ui.label(tr(key));
+                        for conditions in [&mut network.ws_to_tcp, &mut network.tcp_to_ws] {
+                            let value = egui::DragValue::new(field(conditions)).range(0..=10_000).suffix(" ms");
+                            changed |= ui.add(value).changed();
+                        }
+                        ui.end_row();
+                    }
+                    for (key, field) in ODDS {
+                        ui.label(tr(key));
+                        for conditions in [&mut network.ws_to_tcp, &mut network.tcp_to_ws] {
+                            let odds = egui::Slider::new(field(conditions), 0.0..=1.0)
+                                .custom_formatter(|p, _| format!("{:.0}%", p * 100.0))
+                                .custom_parser(|text| {
+                                    text.trim_end_matches('%').trim().parse::<f64>().ok().map(|p| p / 100.0)
+                                });
+                            changed |= ui.add(odds).changed();
+                        }
+                        ui.end_row();
+                    }
+                });
+                if ui.add_enabled(network.is_active(), egui::Button::new(tr("network.reset"))).clicked() {
+                    *network = NetworkConditions::default();
+                    changed = true;
+                }
+            });
+        changed
+    }
+
+    fn show_timeline(&self, ui: &mut egui::Ui) {
+        if self.timeline.transitions().next().is_none() {
+            return;
+        }
+        egui::CollapsingHeader::new(tr("timeline.title"))
+            .id_salt(("timeline", self.id))
+            .show(ui, |ui| {
+                // Plus récente en haut : c'est elle qu'on vient chercher
+                for transition in self.timeline.transitions().rev() {
+                    let state = message(transition.state.label());
+                    let mut line = format!("{}  {}", transition.at.format("%H:%M:%S"), state);
+                    if let Some(detail) = &transition.detail {
+                        line.push_str(&format!(" — {}", message(detail)));
+                    }
+                    ui.label(line);
+                }
+            });
+    }
+
+    fn show_inspector(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr("inspect.title"))
+            .id_salt(("inspect", self.id))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for view in inspect::View::ALL {
+                        ui.radio_value(&mut self.inspect_view, view, message(view.label()));
+                    }
+                    let label = if self.frames.is_paused() { tr("inspect.resume") } else { tr("inspect.pause") };
+                    if ui.button(label).clicked() {
+                        self.frames.set_paused(!self.frames.is_paused());
+                    }
+                    if ui.button(tr("common.clear")).clicked() {
+                        self.frames.clear();
+                    }
+                })
+                .response
+                .on_hover_text(tr_args("inspect.hint", &[("count", &inspect::FRAMES_PER_DIRECTION)]));
+                egui::ScrollArea::vertical()
+                    .id_salt(("frames", self.id))
+                    .max_height(200.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for frame in self.frames.iter() {
+                            let truncated = if frame.len > frame.bytes.len() { tr("inspect.truncated") } else { "" };
+                            ui.label(format!(
+                                "[{}] {} {}{}{}",
+                                frame.time.format("%H:%M:%S%.3f"),
+                                message(frame.dir.arrow()),
+                                frame.len,
+                                tr("unit.bytes"),
+                                truncated
+                            ));
+                            ui.label(egui::RichText::new(frame.render(self.inspect_view)).monospace());
+                        }
+                    });
+            });
+    }
+
+    pub fn start_proxy(&mut self, settings: &Settings) {
+        self.is_running = true;
+        self.status_msg = "Starting...".to_string();
+        self.error = false;
+        self.latency = LatencyStats::default();
+        self.stats = None;
+        self.bandwidth.clear();
+        self.begin_logs(settings.keep_logs);
+        self.targets.clear();
+        self.timeline.start(Local::now());
+
+        let mut config = self.config.clone();
+        config.upstream = settings.upstream_proxy.clone();
+        self.paused = false;
+        self.held = PausedDirections::default();
+
+        let initial_room = self.room_history.last().map(|(_, code)| code.clone());
+        self.session = Some(Session::start(self.config.clone()));
+        let tunnel = Tunnel::builder(config)
+            .id(self.id)
+            .room_code(initial_room)
+            .events(self.tx_event.clone())
+            .start();
+        self.proxy = Some(tunnel);
+    }
+
+    fn start_replay(&mut self, path: &std::path::Path, settings: &Settings) {
+        let frames = match record::load(path) {
+            Ok(frames) => frames,
+            Err(e) => {
+                self.status_msg = format!("Cannot load {}: {}", path.display(), e);
+                self.error = true;
+                return;
+            }
+        };
+        self.is_running = true;
+        self.status_msg = "Starting replay...".to_string();
+        self.error = false;
+        self.begin_logs(settings.keep_logs);
+        self.timeline.start(Local::now());
+        self.paused = false;
+        self.held = PausedDirections::default();
+        let tunnel = Tunnel::builder(self.config.clone())
+            .id(self.id)
+            .events(self.tx_event.clone())
+            .replay(frames);
+        self.proxy = Some(tunnel);
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if let Some(tunnel) = &self.proxy {
+            tunnel.set_paused(paused);
+            self.paused = paused;
+        }
+    }
+
+    fn set_held(&mut self, held: PausedDirections) {
+        if let Some(tunnel) = &self.proxy {
+            tunnel.set_paused_directions(held);
+            self.held = held;
+        }
+    }
+
+    /// Codes déjà vus sur ce relais, ce lancement ou un précédent, hors code courant.
+    fn show_recent_rooms(&mut self, ui: &mut egui::Ui, settings: &Settings) {
+        let current = self.room_history.last().map(|(_, code)| code.as_str());
+        let recent: Vec<_> = settings
+            .rooms
+            .for_relay(&self.config.tcp_addr)
+            .filter(|room| Some(room.code.as_str()) != current)
+            .cloned()
+            .collect();
+        if recent.is_empty() {
+            return;
+        }
+        let relayed = matches!(self.config.tcp_mode, TcpMode::Connect | TcpMode::Listen);
+        let mut rejoin = None;
+        ui.menu_button(tr_args("room.previous", &[("count", &recent.len())]), |ui| {
+            for room in &recent {
+                ui.horizontal(|ui| {
+                    ui.label(format!("[{}] {}", room.seen.format("%Y-%m-%d %H:%M"), room.code));
+                    if ui.small_button(tr("room.copy")).clicked() {
+                        ui.ctx().copy_text(room.code.clone());
+                    }
+                    if ui
+                        .add_enabled(relayed, egui::Button::new(tr("room.rejoin")).small())
+                        .on_hover_text(tr("room.rejoin_hint"))
+                        .clicked()
+                    {
+                        rejoin = Some(room.code.clone());
+                    }
+                });
+            }
+        });
+        let Some(code) = rejoin else {
+            return;
+        };
+        match &self.proxy {
+            Some(tunnel) if self.is_running => {
+                tunnel.join_room(&code);
+                self.status_msg = format!("Joining room {}...", code);
+                self.error = false;
+            }
+            // Arrêté : le tunnel démarre en demandant cette room, comme après une coupure
+            _ if !self.is_running => {
+                self.push_room_code(code);
+                self.start_proxy(settings);
+            }
+            _ => {}
+        }
+    }
+
+    /// Retourne `false` si le code est identique au précédent.
+    pub fn push_room_code(&mut self, code: String) -> bool {
+        // Pas de doublon consécutif : un resume renvoie souvent le même code
+        if self.room_history.last().is_some_and(|(_, last)| *last == code) {
+            return false;
+        }
+        self.room_history.push((Local::now(), code));
+        true
+    }
+
+    pub fn stop_proxy(&mut self) {
+        let Some(tunnel) = self.proxy.take() else {
+            return;
+        };
+        self.events.log(LogLevel::Info, "app", "Stopped by user.");
+        self.status_msg = "Stopping...".to_string();
+        // Abort au-delà du délai si la fermeture propre traîne (connexion en cours, écriture bloquée...)
+        tokio::spawn(tunnel.shutdown(SHUTDOWN_GRACE));
+    }
+}
+
+/// La ligne d'un log telle qu'affichée : message traduit, champs `clé=valeur` intacts.
+fn translate_record(record: &LogRecord) -> String {
+    let text = record.to_string();
+    format!("{}{}", message(&record.message), &text[record.message.len()..])
+}
+
+// D-Bus peut bloquer (ou être absent) : on notifie hors du thread UI et on se contente
+// d'un log si ça échoue.
+pub fn desktop_notify(summary: String, body: String, tx: EventSender) {
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = notify_rust::Notification::new()
+            .appname("TrouDeVer")
+            .summary(&summary)
+            .body(&body)
+            .show()
+        {
+            tx.log(LogLevel::Warn, "app", format!("Notification unavailable: {}", e));
+        }
+    });
+}
@@ -0,0 +1,139 @@
+//! États successifs d'un onglet (connexion, actif, reconnexion, arrêté), horodatés : de quoi
+//! dater une coupure en pleine partie, et la durée depuis le dernier retour à l'état actif.
+
+use chrono::{DateTime, Local, TimeDelta};
+use std::collections::VecDeque;
+use troudever_core::ProxyEvent;
+
+// Au-delà, les plus vieilles transitions sont oubliées
+const MAX_TRANSITIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TunnelState {
+    Connecting,
+    Active,
+    Reconnecting,
+    Stopped,
+}
+
+impl TunnelState {
+    pub fn label(self) -> &'static str {
+        match self {
+            TunnelState::Connecting => "Connecting",
+            TunnelState::Active => "Active",
+            TunnelState::Reconnecting => "Reconnecting",
+            TunnelState::Stopped => "Stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub at: DateTime<Local>,
+    pub state: TunnelState,
+    /// Ce qui l'a provoquée, quand on le sait (côté perdu, erreur)
+    pub detail: Option<String>,
+}
+
+/// Plus récente en dernier. Survit à l'arrêt : on la consulte justement après une coupure.
+#[derive(Default)]
+pub struct Timeline {
+    transitions: VecDeque<Transition>,
+    // Dernière erreur vue, cause probable de l'arrêt qui suivra
+    last_error: Option<String>,
+}
+
+impl Timeline {
+    /// Nouvelle session : l'historique repart de zéro.
+    pub fn start(&mut self, at: DateTime<Local>) {
+        self.transitions.clear();
+        self.last_error = None;
+        self.push(at, TunnelState::Connecting, None);
+    }
+
+    pub fn observe(&mut self, at: DateTime<Local>, event: &ProxyEvent) {
+        match event {
+            ProxyEvent::Connected => {
+                self.last_error = None;
+                self.push(at, TunnelState::Active, None);
+            }
+            ProxyEvent::Reconnecting(side) => self.push(at, TunnelState::Reconnecting, Some(format!("{} lost", side))),
+            ProxyEvent::Stopped => {
+                let detail = self.last_error.take();
+                self.push(at, TunnelState::Stopped, detail);
+            }
+            ProxyEvent::Error(message) | ProxyEvent::Kicked(message) => self.last_error = Some(message.clone()),
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, at: DateTime<Local>, state: TunnelState, detail: Option<String>) {
+        // Pas de doublon consécutif : seuls les changements d'état comptent
+        if self.transitions.back().is_some_and(|last| last.state == state) {
+            return;
+        }
+        self.transitions.push_back(Transition { at, state, detail });
+        if self.transitions.len() > MAX_TRANSITIONS {
+            self.transitions.pop_front();
+        }
+    }
+
+    pub fn transitions(&self) -> impl DoubleEndedIterator<Item = &Transition> {
+        self.transitions.iter()
+    }
+
+    /// Depuis le dernier passage à l'état actif, tant qu'on y est.
+    pub fn uptime(&self, now: DateTime<Local>) -> Option<TimeDelta> {
+        self.transitions
+            .back()
+            .filter(|last| last.state == TunnelState::Active)
+            .map(|last| now - last.at)
+    }
+}
+
+/// "42s", "12m 03s", "2h 05m".
+pub fn format_uptime(uptime: TimeDelta) -> String {
+    let secs = uptime.num_seconds().max(0);
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions_and_uptime_follow_the_events() {
+        let t0 = Local::now();
+        let at = |secs| t0 + TimeDelta::seconds(secs);
+        let mut timeline = Timeline::default();
+        timeline.start(t0);
+        timeline.observe(at(2), &ProxyEvent::Connected);
+        assert_eq!(timeline.uptime(at(62)), Some(TimeDelta::seconds(60)));
+
+        timeline.observe(at(100), &ProxyEvent::Reconnecting("TCP".to_string()));
+        assert_eq!(timeline.uptime(at(101)), None);
+        timeline.observe(at(103), &ProxyEvent::Connected);
+        timeline.observe(at(103), &ProxyEvent::Connected);
+        timeline.observe(at(200), &ProxyEvent::Error("Relay refused the session".to_string()));
+        timeline.observe(at(200), &ProxyEvent::Stopped);
+
+        let states: Vec<_> = timeline.transitions().map(|t| t.state).collect();
+        use TunnelState::*;
+        assert_eq!(states, [Connecting, Active, Reconnecting, Active, Stopped]);
+        let details: Vec<_> = timeline.transitions().map(|t| t.detail.as_deref()).collect();
+        assert_eq!(details[2], Some("TCP lost"));
+        assert_eq!(details[4], Some("Relay refused the session"));
+        assert_eq!(timeline.uptime(at(300)), None);
+    }
+
+    #[test]
+    fn uptime_reads_at_a_glance() {
+        assert_eq!(format_uptime(TimeDelta::seconds(42)), "42s");
+        assert_eq!(format_uptime(TimeDelta::seconds(12 * 60 + 3)), "12m 03s");
+        assert_eq!(format_uptime(TimeDelta::seconds(2 * 3600 + 5 * 60 + 9)), "2h 05m");
+    }
+}
@@ -0,0 +1,69 @@
+//! Jetons d'accès locaux, tirés au hasard à chaque lancement et rangés à côté de `settings.json`,
+//! lisibles par le seul utilisateur : un autre compte ou une page web ne peut pas les deviner.
+//! Sert à l'API d'admin et au passage des options d'un second lancement.
+
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub fn path(name: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("troudever").join(name))
+}
+
+fn required_path(name: &str) -> io::Result<PathBuf> {
+    path(name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))
+}
+
+/// 32 octets aléatoires, en hexadécimal.
+pub fn generate() -> io::Result<String> {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| io::Error::other("no randomness available"))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Écrase le jeton précédent ; sous Unix le fichier est en 0600.
+pub fn write(name: &str, token: &str) -> io::Result<PathBuf> {
+    let path = required_path(name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` ne vaut qu'à la création : un ancien fichier trop ouvert est resserré
+        if path.exists() {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(&path)?.write_all(token.as_bytes())?;
+    Ok(path)
+}
+
+/// Jeton laissé par une autre instance, sans le saut de ligne qu'un éditeur aurait ajouté.
+pub fn read(name: &str) -> io::Result<String> {
+    Ok(std::fs::read_to_string(required_path(name)?)?.trim().to_string())
+}
+
+/// Comparaison en temps constant, pour ne rien apprendre du jeton en chronométrant les refus.
+pub fn matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_and_compared_whole() {
+        let token = generate().unwrap();
+        assert_eq!(token.len(), 64);
+        assert_ne!(generate().unwrap(), token);
+        assert!(matches(&token, &token.clone()));
+        assert!(!matches(&token, &token[..63]));
+        assert!(!matches(&token, ""));
+        assert!(!matches("abcd", "abce"));
+    }
+}
@@ -0,0 +1,260 @@
+//! Icône dans la zone de notification : état des tunnels, et un menu pour piloter l'onglet
+//! sélectionné sans rouvrir la fenêtre. Sous Linux via StatusNotifierItem (D-Bus, sans GTK) ;
+//! pas encore d'implémentation pour les autres plateformes.
+
+use crossbeam_channel::{Receiver, unbounded};
+use eframe::egui;
+
+use crate::i18n::tr_args;
+
+/// Faux hors Linux : les réglages de l'icône y sont grisés, même repris d'un `settings.json` Linux.
+pub const SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// Action choisie dans le menu, appliquée par l'app à la frame suivante.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayCommand {
+    ShowWindow,
+    Connect,
+    Disconnect,
+    CopyRoomCode,
+    Quit,
+}
+
+/// Ce que le menu affiche, recalculé à chaque frame. Textes déjà traduits.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrayState {
+    /// Nom de l'onglet sélectionné, cible de Connect/Disconnect
+    pub tunnel: String,
+    pub running: bool,
+    pub status: String,
+    pub room_code: Option<String>,
+    pub connected: usize,
+    pub total: usize,
+}
+
+impl TrayState {
+    /// "1/2 tunnels connected"
+    pub fn summary(&self) -> String {
+        tr_args("tray.summary", &[("connected", &self.connected), ("total", &self.total)])
+    }
+}
+
+/// L'icône une fois lancée. `update` ne touche au bus que si l'état a changé.
+pub struct Tray {
+    commands: Receiver<TrayCommand>,
+    state: TrayState,
+    #[cfg(target_os = "linux")]
+    handle: sni::Pending,
+    #[cfg(not(target_os = "linux"))]
+    unsupported_reported: bool,
+}
+
+impl Tray {
+    /// Le lancement est asynchrone : une erreur (pas de D-Bus, pas d'hôte de notification)
+    /// remonte plus tard par `update`.
+    pub fn spawn(ctx: &egui::Context) -> Self {
+        let (tx, commands) = unbounded();
+        #[cfg(not(target_os = "linux"))]
+        let _ = (ctx, tx);
+        Self {
+            commands,
+            state: TrayState::default(),
+            #[cfg(target_os = "linux")]
+            handle: sni::Pending::spawn(ctx.clone(), tx),
+            #[cfg(not(target_os = "linux"))]
+            unsupported_reported: false,
+        }
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = TrayCommand> + '_ {
+        self.commands.try_iter()
+    }
+
+    /// `Err` une seule fois, si l'icône n'a pas pu être affichée.
+    pub fn update(&mut self, state: TrayState) -> Result<(), String> {
+        #[cfg(target_os = "linux")]
+        {
+            // L'état poussé pendant le lancement est perdu : on le renvoie une fois l'icône visible
+            let shown_now = self.handle.poll()?;
+            if shown_now || state != self.state {
+                self.handle.update(state.clone());
+            }
+        }
+        self.state = state;
+        #[cfg(not(target_os = "linux"))]
+        if !std::mem::replace(&mut self.unsupported_reported, true) {
+            return Err("tray icons are only supported on Linux for now".to_string());
+        }
+        Ok(())
+    }
+
+    /// Vrai une fois que l'icône est visible : on peut cacher la fenêtre sans la perdre.
+    pub fn is_shown(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.handle.is_shown();
+        #[cfg(not(target_os = "linux"))]
+        false
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        self.handle.shutdown();
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sni {
+    use super::{TrayCommand, TrayState};
+    use crate::i18n::{tr, tr_args};
+    use crossbeam_channel::Sender;
+    use eframe::egui;
+    use ksni::TrayMethods;
+    use tokio::sync::oneshot;
+
+    pub struct Icon {
+        state: TrayState,
+        commands: Sender<TrayCommand>,
+        // Réveille l'UI : sans repaint la commande attendrait la prochaine frame
+        ctx: egui::Context,
+    }
+
+    impl Icon {
+        fn send(&self, command: TrayCommand) {
+            let _ = self.commands.send(command);
+            self.ctx.request_repaint();
+        }
+
+        fn item(label: String, enabled: bool, command: TrayCommand) -> ksni::MenuItem<Self> {
+            ksni::menu::StandardItem {
+                label,
+                enabled,
+                activate: Box::new(move |icon: &mut Self| icon.send(command)),
+                ..Default::default()
+            }
+            .into()
+        }
+    }
+
+    impl ksni::Tray for Icon {
+        fn id(&self) -> String {
+            "troudever".into()
+        }
+
+        fn title(&self) -> String {
+            "TrouDeVer".into()
+        }
+
+        fn icon_name(&self) -> String {
+            if self.state.connected > 0 { "network-transmit-receive" } else { "network-offline" }.into()
+        }
+
+        fn tool_tip(&self) -> ksni::ToolTip {
+            ksni::ToolTip {
+                title: format!("TrouDeVer - {}", self.state.summary()),
+                description: format!("{}: {}", self.state.tunnel, self.state.status),
+                ..Default::default()
+            }
+        }
+
+        fn activate(&mut self, _x: i32, _y: i32) {
+            self.send(TrayCommand::ShowWindow);
+        }
+
+        fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+            let state = &self.state;
+            let copy = match &state.room_code {
+                Some(code) => tr_args("tray.copy_code", &[("code", code)]),
+                None => tr("tray.copy").to_string(),
+            };
+            let tunnel: &[(&str, &dyn std::fmt::Display)] = &[("tunnel", &state.tunnel)];
+            vec![
+                Self::item(tr("tray.show").to_string(), true, TrayCommand::ShowWindow),
+                ksni::MenuItem::Separator,
+                Self::item(tr_args("tray.connect", tunnel), !state.running, TrayCommand::Connect),
+                Self::item(tr_args("tray.disconnect", tunnel), state.running, TrayCommand::Disconnect),
+                Self::item(copy, state.room_code.is_some(), TrayCommand::CopyRoomCode),
+                ksni::MenuItem::Separator,
+                Self::item(tr("tray.quit").to_string(), true, TrayCommand::Quit),
+            ]
+        }
+    }
+
+    pub enum Pending {
+        Starting(oneshot::Receiver<Result<ksni::Handle<Icon>, String>>),
+        Shown(ksni::Handle<Icon>),
+        Failed,
+    }
+
+    impl Pending {
+        pub fn spawn(ctx: egui::Context, commands: Sender<TrayCommand>) -> Self {
+            let (tx, rx) = oneshot::channel();
+            let icon = Icon {
+                state: TrayState::default(),
+                commands,
+                ctx,
+            };
+            tokio::spawn(async move {
+                let _ = tx.send(icon.spawn().await.map_err(|e| e.to_string()));
+            });
+            Pending::Starting(rx)
+        }
+
+        /// `Ok(true)` à la frame où l'icône devient visible.
+        pub fn poll(&mut self) -> Result<bool, String> {
+            let Pending::Starting(rx) = self else {
+                return Ok(false);
+            };
+            match rx.try_recv() {
+                Ok(Ok(handle)) => {
+                    *self = Pending::Shown(handle);
+                    return Ok(true);
+                }
+                Ok(Err(e)) => {
+                    *self = Pending::Failed;
+                    return Err(e);
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {}
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    *self = Pending::Failed;
+                    return Err("tray task ended".to_string());
+                }
+            }
+            Ok(false)
+        }
+
+        pub fn update(&self, state: TrayState) {
+            if let Pending::Shown(handle) = self {
+                let handle = handle.clone();
+                tokio::spawn(async move { handle.update(|icon: &mut Icon| icon.state = state).await });
+            }
+        }
+
+        pub fn is_shown(&self) -> bool {
+            matches!(self, Pending::Shown(handle) if !handle.is_closed())
+        }
+
+        pub fn shutdown(&self) {
+            if let Pending::Shown(handle) = self {
+                // Attendre n'a pas d'intérêt : le service s'arrête avec lui
+                drop(handle.shutdown());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_counts_connected_tunnels() {
+        let state = TrayState {
+            connected: 1,
+            total: 2,
+            ..Default::default()
+        };
+        assert_eq!(state.summary(), "1/2 tunnels connected");
+    }
+}
@@ -0,0 +1,145 @@
+//! Authentification auprès du relais : un message JSON envoyé juste après l'upgrade
+//! WebSocket, puis on attend l'accusé avant d'activer le tunnel.
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Error, protocol::Message};
+
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub enabled: bool,
+    // Stocké en clair dans settings.json, comme le mot de passe du proxy amont
+    pub token: String,
+    pub client_name: String,
+    pub protocol_version: u32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            token: String::new(),
+            client_name: "troudever".to_owned(),
+            protocol_version: 1,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// `{"type":"auth","token":"...","client":"troudever","version":1}`
+    pub fn message(&self) -> String {
+        serde_json::json!({
+            "type": "auth",
+            "token": self.token,
+            "client": self.client_name,
+            "version": self.protocol_version,
+        })
+        .to_string()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    /// Le relais a dit non : inutile de réessayer avec le même token.
+    Rejected(String),
+    /// Pas de réponse, connexion perdue... : une nouvelle tentative peut passer.
+    Failed(String),
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::Rejected(reason) => write!(f, "rejected by relay: {}", reason),
+            AuthError::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// Réponse du relais : `Some(Ok)` pour `{"type":"auth_ok"}` ou `{"ok":true}`, `Some(Err)` pour
+/// `{"type":"auth_error"}` ou `{"ok":false}` (raison dans `error`, `reason` ou `message`),
+/// `None` pour tout le reste.
+pub fn parse_reply(text: &str) -> Option<Result<(), String>> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    let accepted = match (value.get("type").and_then(Value::as_str), value.get("ok").and_then(Value::as_bool)) {
+        (Some("auth_ok"), _) | (_, Some(true)) => true,
+        (Some("auth_error"), _) | (_, Some(false)) => false,
+        _ => return None,
+    };
+    if accepted {
+        return Some(Ok(()));
+    }
+    let reason = ["error", "reason", "message"]
+        .iter()
+        .find_map(|key| value.get(key).and_then(Value::as_str))
+        .unwrap_or("no reason given");
+    Some(Err(reason.to_string()))
+}
+
+/// Envoie le handshake et attend l'accusé (au plus `timeout`). Les messages sans rapport
+/// reçus entre-temps sont ignorés : ils arrivent avant que le tunnel existe.
+pub async fn authenticate<S>(ws: &mut S, config: &AuthConfig, timeout: Duration) -> Result<(), AuthError>
+where
+    S: Sink<Message, Error = Error> + Stream<Item = Result<Message, Error>> + Unpin,
+{
+    ws.send(Message::Text(config.message().into()))
+        .await
+        .map_err(|e| AuthError::Failed(format!("cannot send handshake: {}", e)))?;
+
+    let wait_ack = async {
+        while let Some(message) = ws.next().await {
+            match message {
+                Ok(Message::Text(text)) => {
+                    if let Some(reply) = parse_reply(&text) {
+                        return reply.map_err(AuthError::Rejected);
+                    }
+                }
+                Ok(Message::Close(frame)) => {
+                    return Err(AuthError::Rejected(crate::tunnel::describe_close(frame.as_ref())));
+                }
+                Ok(_) => {}
+                Err(e) => return Err(AuthError::Failed(e.to_string())),
+            }
+        }
+        Err(AuthError::Failed("connection closed before the ack".to_string()))
+    };
+    tokio::time::timeout(timeout, wait_ack)
+        .await
+        .unwrap_or_else(|_| Err(AuthError::Failed(format!("no ack within {}s", timeout.as_secs()))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_message_fields() {
+        let config = AuthConfig {
+            enabled: true,
+            token: "s3cret".to_string(),
+            ..Default::default()
+        };
+        let value: Value = serde_json::from_str(&config.message()).unwrap();
+        assert_eq!(value["type"], "auth");
+        assert_eq!(value["token"], "s3cret");
+        assert_eq!(value["client"], "troudever");
+        assert_eq!(value["version"], 1);
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(parse_reply(r#"{"type":"auth_ok","room":"ABCD"}"#), Some(Ok(())));
+        assert_eq!(parse_reply(r#"{"ok":true}"#), Some(Ok(())));
+        assert_eq!(
+            parse_reply(r#"{"type":"auth_error","reason":"bad token"}"#),
+            Some(Err("bad token".to_string()))
+        );
+        assert_eq!(parse_reply(r#"{"ok":false}"#), Some(Err("no reason given".to_string())));
+        assert_eq!(parse_reply(r#"{"event":"hello"}"#), None);
+        assert_eq!(parse_reply("not json"), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Délais de reconnexion : la première tentative part tout de suite (un simple rebond se
+/// rattrape souvent), puis 1s, 2s, 4s... plafonné à 30s.
+pub struct Backoff {
+    attempt: u32,
+    // 0 = illimité
+    max_retries: u32,
+}
+
+impl Backoff {
+    pub fn new(max_retries: u32) -> Self {
+        Self { attempt: 0, max_retries }
+    }
+
+    /// Délai avant la prochaine tentative, `None` une fois les tentatives épuisées.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.max_retries != 0 && self.attempt >= self.max_retries {
+            return None;
+        }
+        self.attempt += 1;
+        if self.attempt == 1 {
+            return Some(Duration::ZERO);
+        }
+        let factor = 1u32.checked_shl(self.attempt - 2).unwrap_or(u32::MAX);
+        Some(BASE_DELAY.saturating_mul(factor).min(MAX_DELAY))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// "Reconnecting 2/5..." pour la barre de statut.
+    pub fn status(&self) -> String {
+        match self.max_retries {
+            0 => format!("Reconnecting ({})...", self.attempt),
+            max => format!("Reconnecting {}/{}...", self.attempt, max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let mut backoff = Backoff::new(0);
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay().unwrap().as_secs()).collect();
+        assert_eq!(delays, vec![0, 1, 2, 4, 8, 16, 30, 30]);
+        for _ in 0..100 {
+            assert_eq!(backoff.next_delay(), Some(MAX_DELAY));
+        }
+    }
+
+    #[test]
+    fn retry_count_is_enforced_and_reset() {
+        let mut backoff = Backoff::new(2);
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert_eq!(backoff.status(), "Reconnecting 2/2...");
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::ZERO));
+        assert_eq!(backoff.attempts(), 1);
+    }
+}
@@ -0,0 +1,240 @@
+//! Conditions réseau simulées, pour éprouver le netcode d'un jeu : retard, pertes, doublons,
+//! désordre et corruption, tirés au hasard message par message et réglés pour chaque sens.
+//! Modes Connect et Listen seulement ; modifiables pendant que le tunnel tourne.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+// Retard en plus d'un message remis dans le désordre : de quoi laisser passer les suivants
+const REORDER_HOLD: Duration = Duration::from_millis(50);
+
+/// Réglages d'un sens. Probabilités entre 0 et 1.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Conditions {
+    pub delay_ms: u64,
+    /// Retard aléatoire en plus, entre 0 et cette valeur ; l'ordre est conservé
+    pub jitter_ms: u64,
+    pub drop: f64,
+    pub duplicate: f64,
+    pub reorder: f64,
+    /// Un octet du payload au hasard est inversé
+    pub corrupt: f64,
+}
+
+impl Conditions {
+    pub fn is_active(&self) -> bool {
+        *self != Conditions::default()
+    }
+
+    /// "delay 100±20 ms, drop 5%", ou "none".
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        if self.delay_ms > 0 || self.jitter_ms > 0 {
+            parts.push(format!("delay {}±{} ms", self.delay_ms, self.jitter_ms));
+        }
+        for (name, p) in [
+            ("drop", self.drop),
+            ("duplicate", self.duplicate),
+            ("reorder", self.reorder),
+            ("corrupt", self.corrupt),
+        ] {
+            if p > 0.0 {
+                parts.push(format!("{} {}%", name, (p * 1000.0).round() / 10.0));
+            }
+        }
+        if parts.is_empty() { "none".to_string() } else { parts.join(", ") }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct NetworkConditions {
+    pub ws_to_tcp: Conditions,
+    pub tcp_to_ws: Conditions,
+}
+
+impl NetworkConditions {
+    pub fn is_active(&self) -> bool {
+        self.ws_to_tcp.is_active() || self.tcp_to_ws.is_active()
+    }
+}
+
+/// xorshift64* : largement assez bon pour tirer des pertes, sans dépendance en plus.
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        // Les clés de `RandomState` sont tirées au hasard, par processus puis par instance
+        let seed = RandomState::new().build_hasher().finish();
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        // 53 bits : un flottant uniforme dans [0, 1)
+        let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        p > 0.0 && unit < p
+    }
+
+    /// Entre 0 et `max` inclus.
+    fn up_to(&mut self, max: u64) -> u64 {
+        if max == 0 { 0 } else { self.next() % (max + 1) }
+    }
+}
+
+/// Un sens du tunnel : les messages y attendent leur heure de sortie.
+pub(crate) struct Chaos<T> {
+    conditions: Conditions,
+    rng: Rng,
+    // Triés par échéance
+    held: VecDeque<(Instant, T)>,
+    // Échéance du dernier message remis dans l'ordre : le jitter ne doit pas doubler
+    last_due: Option<Instant>,
+}
+
+impl<T: Clone> Chaos<T> {
+    pub(crate) fn new(conditions: Conditions) -> Self {
+        Self {
+            conditions,
+            rng: Rng::new(),
+            held: VecDeque::new(),
+            last_due: None,
+        }
+    }
+
+    /// Les messages déjà retenus gardent leur échéance.
+    pub(crate) fn set_conditions(&mut self, conditions: Conditions) {
+        self.conditions = conditions;
+    }
+
+    /// Inactif, les messages ne passent pas par la file du tout.
+    pub(crate) fn is_active(&self) -> bool {
+        self.conditions.is_active()
+    }
+
+    /// Une copie abîmée du payload, ou `None` s'il passe intact.
+    pub(crate) fn corrupt(&mut self, payload: &[u8]) -> Option<Vec<u8>> {
+        if payload.is_empty() || !self.rng.chance(self.conditions.corrupt) {
+            return None;
+        }
+        let mut damaged = payload.to_vec();
+        let index = self.rng.up_to(payload.len() as u64 - 1) as usize;
+        damaged[index] ^= 0xff;
+        Some(damaged)
+    }
+
+    /// Retient le message ; renvoie le nombre d'exemplaires qui partiront (0 = perdu).
+    pub(crate) fn admit(&mut self, item: T, now: Instant) -> usize {
+        if self.rng.chance(self.conditions.drop) {
+            return 0;
+        }
+        let copies = 1 + usize::from(self.rng.chance(self.conditions.duplicate));
+        for _ in 0..copies {
+            let jitter = self.rng.up_to(self.conditions.jitter_ms);
+            let mut due = now + Duration::from_millis(self.conditions.delay_ms + jitter);
+            if self.rng.chance(self.conditions.reorder) {
+                due += REORDER_HOLD + Duration::from_millis(self.conditions.jitter_ms);
+            } else {
+                due = due.max(self.last_due.unwrap_or(due));
+                self.last_due = Some(due);
+            }
+            let index = self.held.partition_point(|(held, _)| *held <= due);
+            self.held.insert(index, (due, item.clone()));
+        }
+        copies
+    }
+
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.held.front().map(|(due, _)| *due)
+    }
+
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Option<T> {
+        if self.next_due()? > now {
+            return None;
+        }
+        self.held.pop_front().map(|(_, item)| item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(chaos: &mut Chaos<u32>, now: Instant) -> Vec<u32> {
+        std::iter::from_fn(|| chaos.pop_due(now)).collect()
+    }
+
+    #[test]
+    fn delay_and_jitter_keep_the_order() {
+        let start = Instant::now();
+        let mut chaos = Chaos::new(Conditions {
+            delay_ms: 100,
+            jitter_ms: 40,
+            ..Default::default()
+        });
+        for n in 0..50 {
+            assert_eq!(chaos.admit(n, start + Duration::from_millis(n as u64)), 1);
+        }
+        assert!(chaos.next_due().unwrap() >= start + Duration::from_millis(100));
+        assert!(drain(&mut chaos, start + Duration::from_millis(99)).is_empty());
+        assert_eq!(drain(&mut chaos, start + Duration::from_secs(1)), (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn certain_outcomes() {
+        let now = Instant::now();
+        let mut dropping = Chaos::new(Conditions {
+            drop: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(dropping.admit(1, now), 0);
+        assert_eq!(dropping.next_due(), None);
+
+        let mut doubling = Chaos::new(Conditions {
+            duplicate: 1.0,
+            ..Default::default()
+        });
+        doubling.admit(7, now);
+        assert_eq!(drain(&mut doubling, now), [7, 7]);
+
+        // Le premier est retenu, les suivants passent devant
+        let mut shuffling = Chaos::new(Conditions {
+            reorder: 1.0,
+            ..Default::default()
+        });
+        shuffling.admit(1, now);
+        shuffling.set_conditions(Conditions::default());
+        shuffling.admit(2, now);
+        assert_eq!(drain(&mut shuffling, now + Duration::from_secs(1)), [2, 1]);
+
+        let mut corrupting: Chaos<u32> = Chaos::new(Conditions {
+            corrupt: 1.0,
+            ..Default::default()
+        });
+        let damaged = corrupting.corrupt(b"abcd").unwrap();
+        assert_eq!(damaged.iter().zip(b"abcd").filter(|(a, b)| a != b).count(), 1);
+        assert!(Chaos::<u32>::new(Conditions::default()).corrupt(b"abcd").is_none());
+    }
+
+    #[test]
+    fn describe_lists_what_is_on() {
+        assert_eq!(Conditions::default().describe(), "none");
+        let conditions = Conditions {
+            delay_ms: 100,
+            jitter_ms: 20,
+            drop: 0.05,
+            ..Default::default()
+        };
+        assert_eq!(conditions.describe(), "delay 100±20 ms, drop 5%");
+    }
+}
@@ -0,0 +1,122 @@
+//! Découpage des gros messages côté WebSocket, pour les relais qui refusent les frames au-delà
+//! d'une taille. Actif, chaque message devient une ou plusieurs frames binaires
+//! `[flag][morceau]` d'au plus `max_frame` octets : `1` si la suite arrive, `0` pour le
+//! dernier morceau (ou un message qui tenait d'un coup). À activer des deux côtés.
+
+use std::fmt;
+
+const LAST: u8 = 0;
+const MORE: u8 = 1;
+
+/// En dessous, l'en-tête pèserait trop lourd face au morceau
+pub const MIN_FRAME: usize = 256;
+
+#[derive(Debug, PartialEq)]
+pub enum ChunkError {
+    Empty,
+    UnknownFlag(u8),
+    TooLarge(usize),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::Empty => write!(f, "empty chunk"),
+            ChunkError::UnknownFlag(flag) => write!(f, "unknown chunk flag {}", flag),
+            ChunkError::TooLarge(max) => write!(f, "reassembled message exceeds {} bytes", max),
+        }
+    }
+}
+
+/// Les frames d'un message, dans l'ordre. `max_frame` est ramené à [`MIN_FRAME`] au besoin.
+pub fn split(payload: &[u8], max_frame: usize) -> Vec<Vec<u8>> {
+    let piece = max_frame.max(MIN_FRAME) - 1;
+    let mut pieces = payload.chunks(piece).peekable();
+    if pieces.peek().is_none() {
+        return vec![vec![LAST]];
+    }
+    let mut frames = vec![];
+    while let Some(data) = pieces.next() {
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push(if pieces.peek().is_some() { MORE } else { LAST });
+        frame.extend_from_slice(data);
+        frames.push(frame);
+    }
+    frames
+}
+
+/// Recolle les morceaux d'un WebSocket. À vider quand il se reconnecte : un message coupé
+/// en route ne se termine jamais.
+pub struct Reassembler {
+    pending: Vec<u8>,
+    max_len: usize,
+}
+
+impl Reassembler {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            max_len,
+        }
+    }
+
+    /// Le message complet au dernier morceau, `None` avant. Sur erreur, ce qui était en cours
+    /// est perdu.
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, ChunkError> {
+        let result = self.accept(frame);
+        if result.is_err() {
+            self.clear();
+        }
+        result
+    }
+
+    fn accept(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, ChunkError> {
+        let (&flag, data) = frame.split_first().ok_or(ChunkError::Empty)?;
+        if flag != LAST && flag != MORE {
+            return Err(ChunkError::UnknownFlag(flag));
+        }
+        if self.pending.len() + data.len() > self.max_len {
+            return Err(ChunkError::TooLarge(self.max_len));
+        }
+        self.pending.extend_from_slice(data);
+        Ok((flag == LAST).then(|| std::mem::take(&mut self.pending)))
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_message_is_split_and_reassembled() {
+        let state: Vec<u8> = (0..1000u32).map(|n| n as u8).collect();
+        let frames = split(&state, 300);
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|frame| frame.len() <= 300));
+        assert_eq!(frames.iter().map(|frame| frame[0]).collect::<Vec<_>>(), [MORE, MORE, MORE, LAST]);
+
+        let mut reassembler = Reassembler::new(4096);
+        let mut outputs = frames.iter().map(|frame| reassembler.push(frame).unwrap());
+        assert_eq!(outputs.by_ref().take(3).flatten().count(), 0);
+        assert_eq!(outputs.next().unwrap(), Some(state));
+
+        // Un petit message tient dans une frame ; un vide aussi
+        assert_eq!(split(b"hi", 300), [b"\0hi".to_vec()]);
+        assert_eq!(reassembler.push(&split(b"", 300)[0]), Ok(Some(vec![])));
+    }
+
+    #[test]
+    fn bad_chunks_reset_the_message() {
+        let mut reassembler = Reassembler::new(4);
+        assert_eq!(reassembler.push(b"\x01abc"), Ok(None));
+        assert_eq!(reassembler.push(b"\x00de"), Err(ChunkError::TooLarge(4)));
+        // Le morceau en trop a emporté le début : on repart de zéro
+        assert_eq!(reassembler.push(b"\x00de"), Ok(Some(b"de".to_vec())));
+        assert_eq!(reassembler.push(b"\x07x"), Err(ChunkError::UnknownFlag(7)));
+        assert_eq!(reassembler.push(b""), Err(ChunkError::Empty));
+    }
+}
@@ -0,0 +1,91 @@
+//! Compression des payloads côté WebSocket, faute de permessage-deflate (voir `handshake`).
+//! Chaque message devient une frame binaire `[flag][données]` : `0` tel quel, `1` deflate.
+//! Les petits messages, et ceux que deflate n'arrive pas à réduire, partent tels quels.
+
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::fmt;
+use std::io::{Read, Write};
+
+const RAW: u8 = 0;
+const DEFLATE: u8 = 1;
+
+// En dessous, l'en-tête deflate coûte plus qu'il ne rapporte
+const MIN_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum CompressError {
+    Empty,
+    UnknownFlag(u8),
+    Corrupt(String),
+    // Protection contre les bombes de décompression
+    TooLarge(usize),
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::Empty => write!(f, "empty compressed frame"),
+            CompressError::UnknownFlag(flag) => write!(f, "unknown compression flag {}", flag),
+            CompressError::Corrupt(e) => write!(f, "corrupt compressed frame: {}", e),
+            CompressError::TooLarge(max) => write!(f, "decompressed message exceeds {} bytes", max),
+        }
+    }
+}
+
+pub fn pack(payload: &[u8]) -> Vec<u8> {
+    if payload.len() >= MIN_LEN {
+        let mut encoder = DeflateEncoder::new(vec![DEFLATE], Compression::fast());
+        if encoder.write_all(payload).is_ok()
+            && let Ok(frame) = encoder.finish()
+            && frame.len() <= payload.len()
+        {
+            return frame;
+        }
+    }
+    let mut frame = Vec::with_capacity(payload.len() + 1);
+    frame.push(RAW);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub fn unpack(frame: &[u8], max_len: usize) -> Result<Vec<u8>, CompressError> {
+    let (&flag, data) = frame.split_first().ok_or(CompressError::Empty)?;
+    match flag {
+        RAW => Ok(data.to_vec()),
+        DEFLATE => {
+            let mut payload = Vec::new();
+            DeflateDecoder::new(data)
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut payload)
+                .map_err(|e| CompressError::Corrupt(e.to_string()))?;
+            if payload.len() > max_len {
+                return Err(CompressError::TooLarge(max_len));
+            }
+            Ok(payload)
+        }
+        flag => Err(CompressError::UnknownFlag(flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_state_shrinks_and_roundtrips() {
+        let state = br#"{"players":[{"x":1,"y":2},{"x":1,"y":2},{"x":1,"y":2},{"x":1,"y":2},{"x":1,"y":2}]}"#;
+        let frame = pack(state);
+        assert_eq!(frame[0], DEFLATE);
+        assert!(frame.len() < state.len());
+        assert_eq!(unpack(&frame, 1024).unwrap(), state);
+        assert_eq!(unpack(&frame, 10), Err(CompressError::TooLarge(10)));
+
+        // Trop court pour valoir la peine
+        assert_eq!(pack(b"hi"), b"\0hi");
+        assert_eq!(unpack(b"\0hi", 1024).unwrap(), b"hi");
+        assert_eq!(unpack(b"\x07hi", 1024), Err(CompressError::UnknownFlag(7)));
+        assert_eq!(unpack(b"", 1024), Err(CompressError::Empty));
+    }
+}
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::auth::AuthConfig;
+use crate::chaos::NetworkConditions;
+use crate::control::ControlRules;
+use crate::fanout::FanoutConfig;
+use crate::tls::TlsOptions;
+use crate::tunnel::{PauseMode, TcpFraming, TcpMode, WsMode};
+use crate::upstream::UpstreamProxy;
+
+pub const DEFAULT_READ_BUFFER: usize = 1_048_576;
+pub const READ_BUFFER_RANGE: std::ops::RangeInclusive<usize> = 1024..=16 * 1_048_576;
+
+/// Réglages d'un tunnel. Sérialisables pour que le frontend les sauvegarde, sauf le proxy
+/// amont qu'il fournit à chaque lancement.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ProxyConfig {
+    pub ws_url: String,
+    pub ws_mode: WsMode,
+    // Ajoutés à la requête d'upgrade (`Authorization`...), dans l'ordre
+    pub ws_headers: Vec<(String, String)>,
+    // `Sec-WebSocket-Protocol` demandé, vide = aucun
+    pub ws_subprotocol: String,
+    pub tcp_addr: String,
+    pub tcp_mode: TcpMode,
+    pub tcp_framing: TcpFraming,
+    // Cibles TCP qui reçoivent aussi le trafic du WebSocket (voir `fanout`)
+    pub fanout: FanoutConfig,
+    pub auto_reconnect: bool,
+    // Tentatives par coupure, 0 = illimité
+    pub max_retries: u32,
+    pub reconnect_buffer: ReconnectBuffer,
+    // Ping WebSocket de keepalive, 0 = désactivé
+    pub ping_interval_secs: u64,
+    // Octets par seconde, 0 = illimité
+    pub ws_to_tcp_rate: u64,
+    pub tcp_to_ws_rate: u64,
+    // Sort des messages d'un sens suspendu depuis l'interface
+    pub pause_mode: PauseMode,
+    // Retard, pertes, doublons... simulés (voir `chaos`), comme les débits réglables en cours de route
+    pub network: NetworkConditions,
+    pub read_buffer_size: usize,
+    pub control: ControlRules,
+    // Annonce au relais nos versions du protocole juste après l'upgrade (voir `version`)
+    pub negotiate_version: bool,
+    // Handshake envoyé au relais avant d'activer le tunnel
+    pub auth: AuthConfig,
+    // Rempli par le frontend au lancement (réglage global dans l'app)
+    #[serde(skip)]
+    pub upstream: UpstreamProxy,
+    pub tls: TlsOptions,
+    // Copie chaque frame vers l'inspecteur : désactivé par défaut, ça coûte une allocation par message
+    pub inspect: bool,
+    pub inspect_max_bytes: usize,
+    // Enregistrement JSONL du trafic (voir `record`), vide = désactivé
+    pub record_file: PathBuf,
+    // Clé partagée du chiffrement de bout en bout (voir `crypto`), vide = désactivé
+    pub e2e_key: String,
+    // Compression des payloads (voir `compress`), à activer des deux côtés
+    pub compress: bool,
+    // Taille maximale d'une frame WebSocket, les messages plus gros sont découpés (voir `chunk`).
+    // 0 = désactivé ; à régler des deux côtés
+    pub max_frame_size: usize,
+    pub timeouts: Timeouts,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            ws_url: "ws://localhost:4455".to_owned(),
+            ws_mode: WsMode::Connect,
+            ws_headers: Vec::new(),
+            ws_subprotocol: String::new(),
+            tcp_addr: "127.0.0.1:9000".to_owned(),
+            tcp_mode: TcpMode::Connect,
+            tcp_framing: TcpFraming::Relay,
+            fanout: FanoutConfig::default(),
+            auto_reconnect: true,
+            max_retries: 0,
+            reconnect_buffer: ReconnectBuffer::default(),
+            ping_interval_secs: 5,
+            ws_to_tcp_rate: 0,
+            tcp_to_ws_rate: 0,
+            pause_mode: PauseMode::Hold,
+            network: NetworkConditions::default(),
+            read_buffer_size: DEFAULT_READ_BUFFER,
+            control: ControlRules::default(),
+            negotiate_version: false,
+            auth: AuthConfig::default(),
+            upstream: UpstreamProxy::default(),
+            tls: TlsOptions::default(),
+            inspect: false,
+            inspect_max_bytes: crate::inspect::DEFAULT_MAX_BYTES,
+            record_file: PathBuf::new(),
+            e2e_key: String::new(),
+            compress: false,
+            max_frame_size: 0,
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+/// File des messages arrivés pendant qu'un côté se reconnecte, rejouée dans l'ordre à son
+/// retour. Pleine, elle perd les plus vieux. Modes Connect et Listen.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct ReconnectBuffer {
+    // 0 = pas de file : tout ce qui arrive pendant la coupure est perdu
+    pub max_messages: usize,
+    pub max_kb: usize,
+}
+
+impl Default for ReconnectBuffer {
+    fn default() -> Self {
+        Self {
+            max_messages: 1024,
+            max_kb: 4096,
+        }
+    }
+}
+
+impl ReconnectBuffer {
+    pub fn max_bytes(&self) -> usize {
+        self.max_kb.saturating_mul(1024)
+    }
+}
+
+/// En secondes. Lecture, inactivité et chien de garde ne concernent que les modes Connect et Listen.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct Timeouts {
+    // Connexion TCP, ou ouverture du WebSocket (proxy amont, TLS et upgrade compris)
+    pub connect_secs: u64,
+    // Rien reçu d'un côté depuis : il est considéré perdu et reconnecté. 0 = désactivé
+    pub read_secs: u64,
+    // Aucun payload dans un sens ni dans l'autre : la session est fermée. 0 = désactivé
+    pub idle_secs: u64,
+    // Ça circulait, les deux côtés sont là, et plus aucun payload depuis : le tunnel est coincé,
+    // la session est fermée puis rétablie. 0 = désactivé
+    pub watchdog_secs: u64,
+    // SO_KEEPALIVE : l'OS sonde une connexion TCP muette au-delà de ce délai. 0 = désactivé
+    pub keepalive_secs: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect_secs: 10,
+            read_secs: 0,
+            idle_secs: 0,
+            watchdog_secs: 0,
+            keepalive_secs: 0,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect_secs.max(1))
+    }
+
+    pub fn read(&self) -> Option<Duration> {
+        (self.read_secs > 0).then(|| Duration::from_secs(self.read_secs))
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        (self.idle_secs > 0).then(|| Duration::from_secs(self.idle_secs))
+    }
+
+    pub fn watchdog(&self) -> Option<Duration> {
+        (self.watchdog_secs > 0).then(|| Duration::from_secs(self.watchdog_secs))
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        (self.keepalive_secs > 0).then(|| Duration::from_secs(self.keepalive_secs))
+    }
+}
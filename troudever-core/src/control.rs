@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Règles pour reconnaître les messages de contrôle du serveur relais (à ne pas forwarder)
+/// et en extraire le code de room.
+///
+/// Champs vides = comportement historique : marqueur `internal`, code dans `room`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ControlRules {
+    /// Nom de champ de premier niveau, ou JSON pointer (`/meta/kind`)
+    pub marker: String,
+    /// JSON pointer vers le code de room (`/meta/session/code`)
+    pub room_pointer: String,
+}
+
+/// Ce que demande un message de contrôle, d'après son champ `type`. Sans `type`, c'est
+/// l'annonce de room historique.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlMessage {
+    Room(String),
+    /// `{"type":"error","message":...}`
+    Error(String),
+    /// `{"type":"peer_joined","peer":...}`
+    PeerJoined(String),
+    PeerLeft(String),
+    /// `{"type":"kick","reason":...}` : le relais ne veut plus de nous
+    Kick(String),
+    /// Réponse à une demande de join : `{"type":"join","ok":true,"room":...}`
+    Joined(String),
+    /// `{"type":"join","ok":false,"reason":...}`
+    JoinFailed(String),
+    /// Type inconnu de cette version, juste loggé
+    Unknown(String),
+}
+
+fn text_field(value: &Value, field: &str) -> String {
+    value.get(field).and_then(Value::as_str).unwrap_or_default().to_string()
+}
+
+impl ControlRules {
+    pub fn is_control(&self, value: &Value) -> bool {
+        let marker = self.marker.trim();
+        if marker.is_empty() {
+            value.get("internal").is_some()
+        } else if marker.starts_with('/') {
+            value.pointer(marker).is_some()
+        } else {
+            value.get(marker).is_some()
+        }
+    }
+
+    pub fn room_code<'a>(&self, value: &'a Value) -> Option<&'a str> {
+        let pointer = self.room_pointer.trim();
+        let code = if pointer.is_empty() {
+            value.get("room")
+        } else {
+            value.pointer(pointer)
+        };
+        code.and_then(Value::as_str)
+    }
+
+    /// `None` pour une annonce de room sans code.
+    pub fn message(&self, value: &Value) -> Option<ControlMessage> {
+        let message = match value.get("type").and_then(Value::as_str) {
+            None | Some("room") => ControlMessage::Room(self.room_code(value)?.to_string()),
+            Some("error") => ControlMessage::Error(text_field(value, "message")),
+            Some("peer_joined") => ControlMessage::PeerJoined(text_field(value, "peer")),
+            Some("peer_left") => ControlMessage::PeerLeft(text_field(value, "peer")),
+            Some("kick") => ControlMessage::Kick(text_field(value, "reason")),
+            Some("join") if value.get("ok").and_then(Value::as_bool) == Some(true) => {
+                ControlMessage::Joined(self.room_code(value)?.to_string())
+            }
+            Some("join") => ControlMessage::JoinFailed(text_field(value, "reason")),
+            Some(other) => ControlMessage::Unknown(other.to_string()),
+        };
+        Some(message)
+    }
+
+    /// Erreur lisible si un des champs n'est pas un JSON pointer valide (RFC 6901).
+    pub fn validate(&self) -> Result<(), String> {
+        let marker = self.marker.trim();
+        if marker.starts_with('/') {
+            validate_pointer(marker).map_err(|e| format!("Marker: {}", e))?;
+        }
+        let pointer = self.room_pointer.trim();
+        if !pointer.is_empty() {
+            validate_pointer(pointer).map_err(|e| format!("Room code path: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_pointer(pointer: &str) -> Result<(), String> {
+    if !pointer.starts_with('/') {
+        return Err("a JSON pointer must start with '/'".to_string());
+    }
+    let mut chars = pointer.chars();
+    while let Some(c) = chars.next() {
+        if c == '~' && !matches!(chars.next(), Some('0' | '1')) {
+            return Err("'~' must be escaped as '~0' or '~1'".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn blank_rules_keep_internal_room_behavior() {
+        let rules = ControlRules::default();
+        let value = json!({"internal": true, "room": "ABCD"});
+        assert!(rules.is_control(&value));
+        assert_eq!(rules.room_code(&value), Some("ABCD"));
+        assert!(!rules.is_control(&json!({"room": "ABCD"})));
+    }
+
+    #[test]
+    fn nested_pointer_and_marker() {
+        let rules = ControlRules {
+            marker: "/meta/control".to_string(),
+            room_pointer: "/meta/session/code".to_string(),
+        };
+        let value = json!({"meta": {"control": 1, "session": {"code": "XYZ"}}});
+        assert!(rules.is_control(&value));
+        assert_eq!(rules.room_code(&value), Some("XYZ"));
+        assert!(!rules.is_control(&json!({"meta": {"session": {"code": "XYZ"}}})));
+    }
+
+    #[test]
+    fn typed_messages_are_dispatched() {
+        let rules = ControlRules::default();
+        let message = |value| rules.message(&value);
+        assert_eq!(message(json!({"internal": 1, "room": "R"})), Some(ControlMessage::Room("R".to_string())));
+        assert_eq!(message(json!({"internal": 1, "type": "room"})), None);
+        assert_eq!(
+            message(json!({"internal": 1, "type": "error", "message": "room full"})),
+            Some(ControlMessage::Error("room full".to_string()))
+        );
+        assert_eq!(
+            message(json!({"internal": 1, "type": "peer_joined", "peer": "bob"})),
+            Some(ControlMessage::PeerJoined("bob".to_string()))
+        );
+        assert_eq!(message(json!({"internal": 1, "type": "kick"})), Some(ControlMessage::Kick(String::new())));
+        assert_eq!(
+            message(json!({"internal": 1, "type": "join", "ok": true, "room": "R2"})),
+            Some(ControlMessage::Joined("R2".to_string()))
+        );
+        assert_eq!(
+            message(json!({"internal": 1, "type": "join", "ok": false, "reason": "no such room"})),
+            Some(ControlMessage::JoinFailed("no such room".to_string()))
+        );
+        assert_eq!(
+            message(json!({"internal": 1, "type": "shrug"})),
+            Some(ControlMessage::Unknown("shrug".to_string()))
+        );
+    }
+
+    #[test]
+    fn plain_field_marker() {
+        let rules = ControlRules {
+            marker: "relay".to_string(),
+            ..Default::default()
+        };
+        assert!(rules.is_control(&json!({"relay": "hello", "room": "R1"})));
+        assert!(!rules.is_control(&json!({"internal": true})));
+    }
+
+    #[test]
+    fn invalid_pointers_are_rejected() {
+        let missing_slash = ControlRules {
+            room_pointer: "meta/code".to_string(),
+            ..Default::default()
+        };
+        assert!(missing_slash.validate().is_err());
+
+        let bad_escape = ControlRules {
+            marker: "/a~2b".to_string(),
+            ..Default::default()
+        };
+        assert!(bad_escape.validate().is_err());
+
+        let ok = ControlRules {
+            marker: "internal".to_string(),
+            room_pointer: "/a~1b/c~0d".to_string(),
+        };
+        assert!(ok.validate().is_ok());
+    }
+}
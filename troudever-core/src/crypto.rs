@@ -0,0 +1,348 @@
+//! Chiffrement de bout en bout des payloads, pour que le relais ne voie passer que du bruit.
+//! Les deux pairs saisissent la même clé ; chaque message devient une frame binaire
+//! `[version=2][émetteur 8 octets][compteur 8 octets][nonce 24 octets][XChaCha20-Poly1305]`.
+//!
+//! Le nonce de 24 octets est tiré au hasard à chaque message : la clé ne change pas d'une
+//! session à l'autre, mais une collision reste hors de portée (de l'ordre de 2^80 messages),
+//! donc pas de limite pratique par clé. `ring` n'a que le ChaCha20 à nonce de 12 octets : la
+//! sous-clé XChaCha est dérivée ici par HChaCha20.
+//!
+//! L'émetteur (tiré au hasard par session) et son compteur font partie des données
+//! authentifiées : le relais ne peut ni rejouer une frame (compteur déjà vu pour cet émetteur),
+//! ni renvoyer à un tunnel ses propres frames (émetteur = nous). Les deux pairs ont le même rôle,
+//! c'est l'émetteur qui tient lieu de sens.
+//!
+//! Les compteurs sont suivis sur une fenêtre glissante de `WINDOW` frames, comme en IPsec : une
+//! frame en retard (relais ou chaos qui réordonne) passe tant qu'elle est dans la fenêtre et pas
+//! encore vue. Un émetteur n'est jamais oublié pendant la session, sinon il pourrait rejouer :
+//! une fois `MAX_SENDERS` atteints, les nouveaux sont refusés.
+
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroU32;
+
+const VERSION: u8 = 2;
+const XNONCE_LEN: usize = 24;
+/// Version, émetteur et compteur : en clair, mais authentifiés
+const HEADER_LEN: usize = 1 + 8 + 8;
+pub const OVERHEAD: usize = HEADER_LEN + XNONCE_LEN + 16;
+// Émetteurs suivis par session ; au-delà, les nouveaux sont refusés
+const MAX_SENDERS: usize = 64;
+// Retard maximal accepté, en frames : un bit par compteur sous le plus grand vu
+const WINDOW: u64 = u64::BITS as u64;
+
+// Sel fixe : les deux pairs doivent dériver la même clé sans rien échanger
+const SALT: &[u8] = b"troudever e2e v1";
+const ITERATIONS: NonZeroU32 = NonZeroU32::new(100_000).unwrap();
+
+#[derive(Debug, PartialEq)]
+pub enum CryptoError {
+    TooShort(usize),
+    UnknownVersion(u8),
+    // Mauvaise clé ou frame altérée, `ring` ne distingue pas
+    Rejected,
+    /// Compteur déjà vu pour cet émetteur, ou trop en retard pour la fenêtre
+    Replayed(u64),
+    /// Nouvel émetteur alors que `MAX_SENDERS` sont déjà suivis
+    TooManySenders,
+    /// Une de nos propres frames, renvoyée par le relais
+    Reflected,
+    Random,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::TooShort(len) => write!(f, "encrypted frame too short ({} bytes)", len),
+            CryptoError::UnknownVersion(version) => write!(f, "unknown encryption version {}", version),
+            CryptoError::Rejected => write!(f, "decryption failed (wrong key?)"),
+            CryptoError::Replayed(counter) => write!(f, "replayed frame (counter {} already seen or too old)", counter),
+            CryptoError::TooManySenders => write!(f, "too many senders in this session ({} already known)", MAX_SENDERS),
+            CryptoError::Reflected => write!(f, "our own frame came back"),
+            CryptoError::Random => write!(f, "no randomness available for the nonce"),
+        }
+    }
+}
+
+/// Plus grand compteur vu pour un émetteur ; le bit `n` marque `highest - n` comme vu.
+#[derive(Debug)]
+struct ReplayWindow {
+    highest: u64,
+    bits: u64,
+}
+
+impl ReplayWindow {
+    fn new(counter: u64) -> Self {
+        Self { highest: counter, bits: 1 }
+    }
+
+    /// `false` si le compteur est déjà vu ou sorti de la fenêtre ; sinon il est marqué.
+    fn accept(&mut self, counter: u64) -> bool {
+        if counter > self.highest {
+            let shift = counter - self.highest;
+            self.bits = if shift >= WINDOW { 0 } else { self.bits << shift };
+            self.bits |= 1;
+            self.highest = counter;
+            return true;
+        }
+        let age = self.highest - counter;
+        if age >= WINDOW || self.bits & (1 << age) != 0 {
+            return false;
+        }
+        self.bits |= 1 << age;
+        true
+    }
+}
+
+pub struct Cipher {
+    key: [u8; 32],
+    rng: SystemRandom,
+    sender: u64,
+    sent: u64,
+    seen: HashMap<u64, ReplayWindow>,
+}
+
+impl Cipher {
+    /// La dérivation (PBKDF2) prend quelques dizaines de ms : à faire une fois par session.
+    pub fn new(passphrase: &str) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, ITERATIONS, SALT, passphrase.as_bytes(), &mut key);
+        let rng = SystemRandom::new();
+        let mut sender = [0u8; 8];
+        // Sans hasard, `seal` échouera de toute façon
+        let _ = rng.fill(&mut sender);
+        Self {
+            key,
+            rng,
+            sender: u64::from_be_bytes(sender),
+            sent: 0,
+            seen: HashMap::new(),
+        }
+    }
+
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let mut nonce = [0u8; XNONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| CryptoError::Random)?;
+        self.sent += 1;
+        let mut frame = Vec::with_capacity(OVERHEAD + plaintext.len());
+        frame.push(VERSION);
+        frame.extend_from_slice(&self.sender.to_be_bytes());
+        frame.extend_from_slice(&self.sent.to_be_bytes());
+        let header: [u8; HEADER_LEN] = frame[..].try_into().unwrap();
+        frame.extend_from_slice(&nonce);
+        let mut sealed = plaintext.to_vec();
+        let (key, nonce) = self.subkey(&nonce);
+        key.seal_in_place_append_tag(nonce, Aad::from(header), &mut sealed)
+            .map_err(|_| CryptoError::Rejected)?;
+        frame.extend_from_slice(&sealed);
+        Ok(frame)
+    }
+
+    pub fn open(&mut self, frame: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if frame.len() < OVERHEAD {
+            return Err(CryptoError::TooShort(frame.len()));
+        }
+        if frame[0] != VERSION {
+            return Err(CryptoError::UnknownVersion(frame[0]));
+        }
+        let header: [u8; HEADER_LEN] = frame[..HEADER_LEN].try_into().unwrap();
+        let sender = u64::from_be_bytes(header[1..9].try_into().unwrap());
+        let counter = u64::from_be_bytes(header[9..].try_into().unwrap());
+        let nonce: [u8; XNONCE_LEN] = frame[HEADER_LEN..HEADER_LEN + XNONCE_LEN].try_into().unwrap();
+        let mut data = frame[HEADER_LEN + XNONCE_LEN..].to_vec();
+        let (key, nonce) = self.subkey(&nonce);
+        // Vérifié avant le compteur : une frame forgée ne doit rien changer à l'état
+        let len = key
+            .open_in_place(nonce, Aad::from(header), &mut data)
+            .map_err(|_| CryptoError::Rejected)?
+            .len();
+        if sender == self.sender {
+            return Err(CryptoError::Reflected);
+        }
+        let known = self.seen.len();
+        match self.seen.get_mut(&sender) {
+            Some(window) => {
+                if !window.accept(counter) {
+                    return Err(CryptoError::Replayed(counter));
+                }
+            }
+            None if known >= MAX_SENDERS => return Err(CryptoError::TooManySenders),
+            None => {
+                self.seen.insert(sender, ReplayWindow::new(counter));
+            }
+        }
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// XChaCha20-Poly1305 : sous-clé tirée des 16 premiers octets du nonce, les 8 derniers
+    /// font le nonce ChaCha20 (précédés de 4 zéros).
+    fn subkey(&self, nonce: &[u8; XNONCE_LEN]) -> (LessSafeKey, Nonce) {
+        let key = hchacha20(&self.key, nonce[..16].try_into().unwrap());
+        let key = LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).expect("32-byte key"));
+        let mut short = [0u8; NONCE_LEN];
+        short[4..].copy_from_slice(&nonce[16..]);
+        (key, Nonce::assume_unique_for_key(short))
+    }
+}
+
+/// HChaCha20 (draft-irtf-cfrg-xchacha, 2.2) : les 20 tours de ChaCha20 sans l'addition finale,
+/// dont on garde les mots 0 à 3 et 12 à 15.
+fn hchacha20(key: &[u8; 32], input: &[u8; 16]) -> [u8; 32] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for (word, bytes) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    for (word, bytes) in state[12..].iter_mut().zip(input.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    for _ in 0..10 {
+        for [a, b, c, d] in [[0, 4, 8, 12], [1, 5, 9, 13], [2, 6, 10, 14], [3, 7, 11, 15]] {
+            quarter_round(&mut state, a, b, c, d);
+        }
+        for [a, b, c, d] in [[0, 5, 10, 15], [1, 6, 11, 12], [2, 7, 8, 13], [3, 4, 9, 14]] {
+            quarter_round(&mut state, a, b, c, d);
+        }
+    }
+    let mut out = [0u8; 32];
+    for (bytes, word) in out.chunks_exact_mut(4).zip(state[..4].iter().chain(&state[12..])) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn sealed_frames_open_with_the_same_key_only() {
+        let mut alice = Cipher::new("correct horse");
+        let mut bob = Cipher::new("correct horse");
+        let frame = alice.seal(b"score=3").unwrap();
+        assert_eq!(frame.len(), OVERHEAD + 7);
+        assert_eq!(frame[0], VERSION);
+        assert_eq!(bob.open(&frame).unwrap(), b"score=3");
+        // Nonce aléatoire : deux envois du même message diffèrent
+        let again = alice.seal(b"score=3").unwrap();
+        assert_ne!(again[HEADER_LEN..], frame[HEADER_LEN..]);
+
+        assert_eq!(Cipher::new("battery staple").open(&again), Err(CryptoError::Rejected));
+        let mut tampered = again.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(bob.open(&tampered), Err(CryptoError::Rejected));
+        assert_eq!(bob.open(&again[..10]), Err(CryptoError::TooShort(10)));
+        // L'en-tête est authentifié : changer le compteur casse la frame
+        let mut bumped = again.clone();
+        bumped[HEADER_LEN - 1] ^= 0x40;
+        assert_eq!(bob.open(&bumped), Err(CryptoError::Rejected));
+        assert_eq!(bob.open(&again).unwrap(), b"score=3");
+    }
+
+    #[test]
+    fn replayed_and_reflected_frames_are_rejected() {
+        let mut alice = Cipher::new("correct horse");
+        let mut bob = Cipher::new("correct horse");
+        let first = alice.seal(b"move=1").unwrap();
+        let second = alice.seal(b"move=2").unwrap();
+        assert_eq!(bob.open(&first).unwrap(), b"move=1");
+        assert_eq!(bob.open(&first), Err(CryptoError::Replayed(1)));
+        assert_eq!(bob.open(&second).unwrap(), b"move=2");
+        // Déjà vue, même une fois dépassée
+        assert_eq!(bob.open(&first), Err(CryptoError::Replayed(1)));
+
+        // Le relais renvoie à Alice ce qu'elle a envoyé
+        assert_eq!(alice.open(&second), Err(CryptoError::Reflected));
+        // Une nouvelle session d'Alice repart de 1 sous un autre émetteur
+        let mut restarted = Cipher::new("correct horse");
+        assert_eq!(bob.open(&restarted.seal(b"move=1").unwrap()).unwrap(), b"move=1");
+        // Et Bob, dans l'autre sens, a son propre compteur
+        assert_eq!(alice.open(&bob.seal(b"ack").unwrap()).unwrap(), b"ack");
+    }
+
+    #[test]
+    fn reordered_frames_inside_the_window_are_accepted() {
+        let mut alice = Cipher::new("correct horse");
+        let mut bob = Cipher::new("correct horse");
+        let frames: Vec<_> = (0..WINDOW + 2).map(|_| alice.seal(b"tick").unwrap()).collect();
+        assert_eq!(bob.open(&frames[2]).unwrap(), b"tick");
+        assert_eq!(bob.open(&frames[0]).unwrap(), b"tick");
+        assert_eq!(bob.open(&frames[1]).unwrap(), b"tick");
+        assert_eq!(bob.open(&frames[1]), Err(CryptoError::Replayed(2)));
+        // Le compteur 66 sort 1 et 2 de la fenêtre ; 4, jamais vu, y est encore
+        assert_eq!(bob.open(&frames[WINDOW as usize + 1]).unwrap(), b"tick");
+        assert_eq!(bob.open(&frames[3]).unwrap(), b"tick");
+        assert_eq!(bob.open(&frames[2]), Err(CryptoError::Replayed(3)));
+        assert_eq!(bob.open(&frames[0]), Err(CryptoError::Replayed(1)));
+        // Un grand saut vide la fenêtre sans rien accepter deux fois
+        let mut window = ReplayWindow::new(1);
+        assert!(window.accept(1_000));
+        assert!(!window.accept(1_000));
+        assert!(window.accept(999));
+        assert!(!window.accept(1));
+    }
+
+    #[test]
+    fn senders_are_never_forgotten() {
+        let mut bob = Cipher::new("correct horse");
+        let mut alice = Cipher::new("correct horse");
+        let first = alice.sender;
+        let replay = alice.seal(b"hi").unwrap();
+        bob.open(&replay).unwrap();
+        // Autant de sessions d'Alice, sans refaire la dérivation à chaque fois
+        for sender in 1..MAX_SENDERS as u64 {
+            alice.sender = first.wrapping_add(sender);
+            bob.open(&alice.seal(b"hi").unwrap()).unwrap();
+        }
+        assert_eq!(bob.seen.len(), MAX_SENDERS);
+        alice.sender = first.wrapping_add(MAX_SENDERS as u64);
+        assert_eq!(bob.open(&alice.seal(b"hi").unwrap()), Err(CryptoError::TooManySenders));
+        // Le premier émetteur est toujours suivi : sa frame ne se rejoue pas
+        assert_eq!(bob.open(&replay), Err(CryptoError::Replayed(1)));
+        assert_eq!(bob.seen.len(), MAX_SENDERS);
+    }
+
+    #[test]
+    fn xchacha20_poly1305_matches_the_draft_vector() {
+        let mut cipher = Cipher::new("");
+        cipher.key = std::array::from_fn(|i| 0x80 + i as u8);
+        let nonce: [u8; XNONCE_LEN] = std::array::from_fn(|i| 0x40 + i as u8);
+        let text = "Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, \
+                    sunscreen would be it.";
+        let mut data = text.as_bytes().to_vec();
+        let (key, short) = cipher.subkey(&nonce);
+        let aad = [0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7];
+        key.seal_in_place_append_tag(short, Aad::from(aad), &mut data).unwrap();
+        assert_eq!(hex(&data[..16]), "bd6d179d3e83d43b9576579493c0e939");
+        assert_eq!(hex(&data[data.len() - 16..]), "c0875924c1c7987947deafd8780acf49");
+    }
+
+    #[test]
+    fn hchacha20_matches_the_draft_vector() {
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let input = [0, 0, 0, 9, 0, 0, 0, 0x4a, 0, 0, 0, 0, 0x31, 0x41, 0x59, 0x27];
+        assert_eq!(
+            hex(&hchacha20(&key, &input)),
+            "82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! Recherche des serveurs de jeu sur le réseau local, pour remplir `tcp_addr` sans taper
+//! d'adresse. Deux annonces sont écoutées en même temps :
+//! - mDNS / DNS-SD : le service [`MDNS_SERVICE`], adresse et port tirés du SRV ;
+//! - SSDP : le type [`SSDP_TARGET`], dont le `LOCATION` donne directement `hôte:port`.
+//!
+//! Les requêtes partent d'un port éphémère : les répondeurs renvoient alors en unicast, sans
+//! rejoindre de groupe multicast ni disputer le port 5353 à l'OS.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use url::Url;
+
+/// Type DNS-SD qu'un serveur de jeu annonce pour être trouvé
+pub const MDNS_SERVICE: &str = "_troudever._tcp.local";
+/// `ST` SSDP équivalent, pour les serveurs qui parlent déjà UPnP
+pub const SSDP_TARGET: &str = "urn:troudever:service:game:1";
+/// Temps laissé aux réponses
+pub const DISCOVERY_WAIT: Duration = Duration::from_secs(2);
+
+const MDNS_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+const SSDP_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+// Au-delà, un nom compressé qui boucle
+const MAX_POINTERS: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Protocol {
+    Mdns,
+    Ssdp,
+}
+
+impl Protocol {
+    pub fn label(self) -> &'static str {
+        match self {
+            Protocol::Mdns => "mDNS",
+            Protocol::Ssdp => "SSDP",
+        }
+    }
+}
+
+/// Un serveur qui a répondu.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Server {
+    /// Nom annoncé (instance DNS-SD, en-tête `SERVER`), sinon l'adresse
+    pub name: String,
+    /// Au format de `tcp_addr`
+    pub addr: String,
+    pub via: Protocol,
+}
+
+/// Interroge le réseau pendant `wait` et rend les serveurs trouvés, triés par nom. Une erreur
+/// seulement si aucune des deux recherches n'a pu partir.
+pub async fn discover(wait: Duration) -> io::Result<Vec<Server>> {
+    let (mdns, ssdp) = tokio::join!(browse_mdns(wait), search_ssdp(wait));
+    let mut servers = match (mdns, ssdp) {
+        (Err(e), Err(_)) => return Err(e),
+        (mdns, ssdp) => [mdns, ssdp].into_iter().flat_map(Result::unwrap_or_default).collect::<Vec<_>>(),
+    };
+    // Un serveur qui annonce les deux protocoles, ou répond deux fois, n'apparaît qu'une fois
+    servers.sort_by(|a, b| a.addr.cmp(&b.addr));
+    servers.dedup_by(|a, b| a.addr == b.addr);
+    servers.sort_by_cached_key(|server| server.name.to_lowercase());
+    Ok(servers)
+}
+
+async fn browse_mdns(wait: Duration) -> io::Result<Vec<Server>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.send_to(&mdns_query(MDNS_SERVICE), MDNS_GROUP).await?;
+    Ok(collect(&socket, wait, |packet, from| parse_mdns(packet, MDNS_SERVICE, from.ip())).await)
+}
+
+async fn search_ssdp(wait: Duration) -> io::Result<Vec<Server>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: {}\r\n\r\n",
+        SSDP_GROUP, SSDP_TARGET
+    );
+    socket.send_to(request.as_bytes(), SSDP_GROUP).await?;
+    Ok(collect(&socket, wait, |packet, _| parse_ssdp(&String::from_utf8_lossy(packet)).into_iter().collect()).await)
+}
+
+/// Lit les réponses jusqu'à l'échéance.
+async fn collect<F>(socket: &UdpSocket, wait: Duration, mut parse: F) -> Vec<Server>
+where
+    F: FnMut(&[u8], SocketAddr) -> Vec<Server>,
+{
+    let deadline = tokio::time::Instant::now() + wait;
+    let mut buf = vec![0u8; 9000];
+    let mut servers = vec![];
+    while let Ok(Ok((len, from))) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        servers.extend(parse(&buf[..len], from));
+    }
+    servers
+}
+
+fn encode_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+/// Question PTR, bit QU levé : réponse unicast demandée.
+fn mdns_query(service: &str) -> Vec<u8> {
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    encode_name(&mut packet, service);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&0x8001u16.to_be_bytes());
+    packet
+}
+
+fn read_u16(packet: &[u8], at: usize) -> Option<u16> {
+    packet.get(at..at + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Nom DNS à `pos`, compression comprise ; rend aussi la position juste après lui.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+    for _ in 0..=MAX_POINTERS {
+        loop {
+            let len = *packet.get(pos)? as usize;
+            if len & 0xc0 == 0xc0 {
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+                break;
+            }
+            if len == 0 {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            labels.push(String::from_utf8_lossy(packet.get(pos + 1..pos + 1 + len)?).into_owned());
+            pos += 1 + len;
+        }
+    }
+    None
+}
+
+#[derive(Default)]
+struct Records {
+    // Service -> instance
+    ptr: Vec<(String, String)>,
+    // Instance -> (hôte, port)
+    srv: HashMap<String, (String, u16)>,
+    a: HashMap<String, Ipv4Addr>,
+}
+
+fn records(packet: &[u8]) -> Option<Records> {
+    let questions = read_u16(packet, 4)?;
+    let answers = (6..12).step_by(2).map(|at| read_u16(packet, at).map(usize::from)).sum::<Option<usize>>()?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(packet, pos)?.1 + 4;
+    }
+    let mut records = Records::default();
+    for _ in 0..answers {
+        let (name, end) = read_name(packet, pos)?;
+        let kind = read_u16(packet, end)?;
+        let data = end + 10;
+        let len = read_u16(packet, end + 8)? as usize;
+        let rdata = packet.get(data..data + len)?;
+        // Les noms DNS ne tiennent pas compte de la casse
+        let name = name.to_lowercase();
+        match kind {
+            TYPE_PTR => records.ptr.push((name, read_name(packet, data)?.0)),
+            TYPE_SRV if len > 6 => {
+                let target = read_name(packet, data + 6)?.0.to_lowercase();
+                records.srv.insert(name, (target, read_u16(rdata, 4)?));
+            }
+            TYPE_A if len == 4 => {
+                records.a.insert(name, Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]));
+            }
+            _ => {}
+        }
+        pos = data + len;
+    }
+    Some(records)
+}
+
+/// Les instances de `service` d'une réponse mDNS. Les répondeurs joignent SRV et A au PTR ;
+/// sans SRV l'instance est ignorée, sans A on prend l'adresse qui a répondu.
+fn parse_mdns(packet: &[u8], service: &str, from: IpAddr) -> Vec<Server> {
+    let Some(records) = records(packet) else {
+        return vec![];
+    };
+    let service = service.to_lowercase();
+    let suffix = format!(".{}", service);
+    records
+        .ptr
+        .iter()
+        .filter(|(name, _)| *name == service)
+        .filter_map(|(_, instance)| {
+            let (host, port) = records.srv.get(&instance.to_lowercase())?;
+            let ip = records.a.get(host).map_or(from, |ip| IpAddr::V4(*ip));
+            // "Arena._troudever._tcp.local" s'affiche "Arena"
+            let name = if instance.to_lowercase().ends_with(&suffix) {
+                &instance[..instance.len() - suffix.len()]
+            } else {
+                instance
+            };
+            Some(Server {
+                name: name.to_string(),
+                addr: SocketAddr::new(ip, *port).to_string(),
+                via: Protocol::Mdns,
+            })
+        })
+        .collect()
+}
+
+/// Réponse à un M-SEARCH. `LOCATION` doit porter un port explicite, celui du serveur de jeu :
+/// `tcp://192.168.1.20:9000`.
+fn parse_ssdp(response: &str) -> Option<Server> {
+    let mut lines = response.lines();
+    if !lines.next()?.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+    let headers: HashMap<String, &str> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim()))
+        .collect();
+    if headers.get("st").is_some_and(|target| *target != SSDP_TARGET) {
+        return None;
+    }
+    let location = Url::parse(headers.get("location")?).ok()?;
+    let addr = format!("{}:{}", location.host()?, location.port()?);
+    let name = headers.get("server").or(headers.get("usn")).map_or(addr.clone(), |name| name.to_string());
+    Some(Server {
+        name,
+        addr,
+        via: Protocol::Ssdp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(packet: &mut Vec<u8>, name: &[u8], kind: u16, rdata: &[u8]) {
+        packet.extend_from_slice(name);
+        packet.extend_from_slice(&kind.to_be_bytes());
+        packet.extend_from_slice(&[0, 1, 0, 0, 0x11, 0x94]);
+        packet.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        packet.extend_from_slice(rdata);
+    }
+
+    #[test]
+    fn mdns_answer_gives_name_and_address() {
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 3, 0, 0, 0, 0];
+        let mut service = vec![];
+        encode_name(&mut service, MDNS_SERVICE);
+        let mut instance = vec![];
+        encode_name(&mut instance, "Arena");
+        // "Arena" puis un pointeur vers le nom du service, au début du premier enregistrement
+        instance.pop();
+        instance.extend_from_slice(&[0xc0, 12]);
+        record(&mut packet, &service, TYPE_PTR, &instance);
+
+        let mut host = vec![];
+        encode_name(&mut host, "arena-pc.local");
+        let mut srv = vec![0, 0, 0, 0, 0x23, 0x28];
+        srv.extend_from_slice(&host);
+        // Le nom de l'instance pointe dans la donnée du PTR
+        let instance_at = 12 + service.len() + 10;
+        record(&mut packet, &[0xc0, instance_at as u8], TYPE_SRV, &srv);
+        record(&mut packet, &host, TYPE_A, &[192, 168, 1, 20]);
+
+        let from = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let servers = parse_mdns(&packet, MDNS_SERVICE, from);
+        assert_eq!(servers, [Server {
+            name: "Arena".to_string(),
+            addr: "192.168.1.20:9000".to_string(),
+            via: Protocol::Mdns,
+        }]);
+
+        // Un autre service ne compte pas ; un paquet tronqué non plus
+        assert!(parse_mdns(&packet, "_other._tcp.local", from).is_empty());
+        assert!(parse_mdns(&packet[..packet.len() - 3], MDNS_SERVICE, from).is_empty());
+    }
+
+    #[test]
+    fn compression_loops_are_rejected() {
+        let packet = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc0, 12];
+        assert_eq!(read_name(&packet, 12), None);
+    }
+
+    #[test]
+    fn ssdp_location_is_the_game_address() {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nST: {}\r\nLOCATION: tcp://192.168.1.30:7777\r\nSERVER: Kart Lobby\r\n\r\n",
+            SSDP_TARGET
+        );
+        assert_eq!(parse_ssdp(&response), Some(Server {
+            name: "Kart Lobby".to_string(),
+            addr: "192.168.1.30:7777".to_string(),
+            via: Protocol::Ssdp,
+        }));
+        // Un autre type d'appareil, ou sans port : ignoré
+        assert_eq!(parse_ssdp("HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\nLOCATION: tcp://h:1\r\n"), None);
+        assert_eq!(parse_ssdp("HTTP/1.1 200 OK\r\nLOCATION: http://192.168.1.1/desc.xml\r\n"), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Cibles TCP en plus de `tcp_addr` (spectateur, service d'enregistrement...) : ce qui arrive du
+//! WebSocket leur est copié, à toutes ou à une seule d'après un octet de routage en tête du
+//! message. Seul le serveur principal répond au WebSocket ; ce que renvoient les autres cibles
+//! est lu puis jeté. Modes Connect et Listen.
+//!
+//! Chaque cible a sa propre tâche et sa propre file : une cible lente ou tombée perd des
+//! messages, elle ne ralentit jamais la partie.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::backoff::Backoff;
+use crate::local;
+use crate::logging::{LogLevel, LogRecord};
+use crate::proxy::{EventSender, ProxyEvent};
+
+// Messages en attente par cible avant d'en perdre
+const TARGET_QUEUE: usize = 256;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Le backoff repart de zéro : une cible qui refuse tout de suite ne doit pas tourner à vide
+const MIN_RETRY: Duration = Duration::from_millis(500);
+/// Octet de routage qui vise toutes les cibles, serveur principal compris.
+pub const ROUTE_ALL: u8 = 0xff;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum Routing {
+    /// Chaque message part vers le serveur principal et toutes les cibles
+    #[default]
+    Broadcast,
+    /// Le premier octet choisit : 0 le serveur principal, n la cible n, `ROUTE_ALL` tout le monde.
+    /// Il est retiré avant l'envoi.
+    Keyed,
+}
+
+impl Routing {
+    pub const ALL: [Routing; 2] = [Routing::Broadcast, Routing::Keyed];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Routing::Broadcast => "Copy to all",
+            Routing::Keyed => "Routing byte",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct FanoutConfig {
+    /// Adresses au même format que `tcp_addr`
+    pub targets: Vec<String>,
+    pub routing: Routing,
+}
+
+impl FanoutConfig {
+    pub fn is_active(&self) -> bool {
+        !self.targets.is_empty()
+    }
+}
+
+/// État d'une cible, remonté en [`ProxyEvent::Target`] à chaque changement.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TargetStatus {
+    /// Position dans `targets`, à partir de 0
+    pub index: usize,
+    pub connected: bool,
+    /// Pourquoi elle est tombée, ou pourquoi elle ne répond pas
+    pub error: Option<String>,
+}
+
+/// Où va un message venu du WebSocket.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Route {
+    /// Serveur principal, et les cibles si `also_targets`
+    Primary { also_targets: bool },
+    Target(usize),
+    /// Octet de routage hors des cibles configurées
+    Unknown(u8),
+}
+
+/// Décide de la route ; renvoie aussi la longueur de l'en-tête à retirer (l'octet de routage).
+pub(crate) fn route(config: &FanoutConfig, data: &[u8]) -> (Route, usize) {
+    if !config.is_active() {
+        return (Route::Primary { also_targets: false }, 0);
+    }
+    let Some(&key) = data.first().filter(|_| config.routing == Routing::Keyed) else {
+        return (Route::Primary { also_targets: config.routing == Routing::Broadcast }, 0);
+    };
+    let route = match key {
+        0 => Route::Primary { also_targets: false },
+        ROUTE_ALL => Route::Primary { also_targets: true },
+        n if (n as usize) <= config.targets.len() => Route::Target(n as usize - 1),
+        n => Route::Unknown(n),
+    };
+    (route, 1)
+}
+
+struct Target {
+    addr: String,
+    queue: mpsc::Sender<Vec<u8>>,
+    // Déjà signalée en retard : un seul warning par rafale de pertes
+    lagging: bool,
+}
+
+/// Les cibles d'une session, arrêtées quand on la lâche.
+pub(crate) struct Fanout {
+    targets: Vec<Target>,
+    _tasks: JoinSet<()>,
+}
+
+impl Fanout {
+    pub(crate) fn start(config: &FanoutConfig, keepalive: Option<Duration>, tx: &EventSender) -> Self {
+        let mut tasks = JoinSet::new();
+        let targets = config
+            .targets
+            .iter()
+            .enumerate()
+            .map(|(index, addr)| {
+                let (queue, rx) = mpsc::channel(TARGET_QUEUE);
+                tasks.spawn(run_target(index, addr.clone(), keepalive, rx, tx.clone()));
+                Target {
+                    addr: addr.clone(),
+                    queue,
+                    lagging: false,
+                }
+            })
+            .collect();
+        Self { targets, _tasks: tasks }
+    }
+
+    /// Copie un message déjà encadré vers une cible.
+    pub(crate) fn send(&mut self, index: usize, frame: &[u8], tx: &EventSender) {
+        let Some(target) = self.targets.get_mut(index) else {
+            return;
+        };
+        match target.queue.try_send(frame.to_vec()) {
+            Ok(()) => target.lagging = false,
+            Err(_) if !std::mem::replace(&mut target.lagging, true) => {
+                let record = LogRecord::new(LogLevel::Warn, "fanout", "Target not keeping up, dropping messages")
+                    .field("target", &target.addr);
+                let _ = tx.send(ProxyEvent::Log(record));
+            }
+            Err(_) => {}
+        }
+    }
+
+    pub(crate) fn send_all(&mut self, frame: &[u8], tx: &EventSender) {
+        for index in 0..self.targets.len() {
+            self.send(index, frame, tx);
+        }
+    }
+}
+
+fn report(tx: &EventSender, index: usize, connected: bool, error: Option<String>) {
+    let _ = tx.send(ProxyEvent::Target(TargetStatus { index, connected, error }));
+}
+
+/// Connecte la cible, lui écrit ce qui arrive, et recommence quand elle tombe.
+async fn run_target(
+    index: usize,
+    addr: String,
+    keepalive: Option<Duration>,
+    mut queue: mpsc::Receiver<Vec<u8>>,
+    tx: EventSender,
+) {
+    let mut backoff = Backoff::new(0);
+    let mut sink = [0u8; 4096];
+    loop {
+        let connect = tokio::time::timeout(CONNECT_TIMEOUT, local::connect(&addr, keepalive))
+            .await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out")));
+        let error = match connect {
+            Ok((mut read, mut write)) => {
+                backoff.reset();
+                // Ce qui s'est accumulé pendant la coupure n'a plus de sens pour la cible
+                while queue.try_recv().is_ok() {}
+                let record = LogRecord::new(LogLevel::Info, "fanout", "Target connected").field("target", &addr);
+                let _ = tx.send(ProxyEvent::Log(record));
+                report(&tx, index, true, None);
+                loop {
+                    tokio::select! {
+                        frame = queue.recv() => {
+                            let Some(frame) = frame else { return };
+                            if let Err(e) = write.write_all(&frame).await {
+                                break format!("write error: {}", e);
+                            }
+                        }
+                        read = read.read(&mut sink) => match read {
+                            Ok(0) => break "closed by the target".to_string(),
+                            Ok(_) => {}
+                            Err(e) => break format!("read error: {}", e),
+                        },
+                    }
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+        let record = LogRecord::new(LogLevel::Warn, "fanout", format!("Target unavailable: {}", error))
+            .field("target", &addr);
+        let _ = tx.send(ProxyEvent::Log(record));
+        report(&tx, index, false, Some(error));
+        // Cible injoignable : la file est vidée au fil de l'eau pour que personne n'attende
+        let pause = tokio::time::sleep(backoff.next_delay().unwrap_or_default().max(MIN_RETRY));
+        tokio::pin!(pause);
+        loop {
+            tokio::select! {
+                _ = &mut pause => break,
+                frame = queue.recv() => if frame.is_none() { return },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyed(targets: usize) -> FanoutConfig {
+        FanoutConfig {
+            targets: (0..targets).map(|n| format!("127.0.0.1:{}", 9100 + n)).collect(),
+            routing: Routing::Keyed,
+        }
+    }
+
+    #[test]
+    fn routing_byte_picks_and_is_stripped() {
+        assert_eq!(route(&keyed(2), &[2, b'h', b'i']), (Route::Target(1), 1));
+        assert_eq!(route(&keyed(2), &[0, b'x']), (Route::Primary { also_targets: false }, 1));
+        assert_eq!(route(&keyed(2), &[ROUTE_ALL, 1]), (Route::Primary { also_targets: true }, 1));
+        assert_eq!(route(&keyed(2), &[3, 1]), (Route::Unknown(3), 1));
+
+        // Sans cible, ou en copie, le message passe intact
+        assert_eq!(route(&FanoutConfig::default(), &[2, b'h']), (Route::Primary { also_targets: false }, 0));
+        let broadcast = FanoutConfig {
+            routing: Routing::Broadcast,
+            ..keyed(1)
+        };
+        assert_eq!(route(&broadcast, &[2, b'h']), (Route::Primary { also_targets: true }, 0));
+    }
+}
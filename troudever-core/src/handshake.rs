@@ -0,0 +1,100 @@
+//! Construction de la requête d'upgrade WebSocket. Tout ce qui touche à la requête
+//! (headers, extensions) passe par ici, qu'on sorte en direct ou via un proxy amont ;
+//! la config TLS est dans `tls`.
+
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::{Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use url::Url;
+
+use crate::config::ProxyConfig;
+
+/// Pas de permessage-deflate : tungstenite 0.28 refuse toute frame avec RSV1
+/// (`NonZeroReservedBits`) et ne sait pas décompresser. Proposer l'extension casserait la
+/// session dès le premier message compressé de la gateway.
+///
+/// Les headers de la config s'ajoutent à ceux de l'upgrade (et remplacent un homonyme) ;
+/// tungstenite vérifie lui-même que le serveur accepte le sous-protocole demandé.
+pub fn request(url: &Url, config: &ProxyConfig) -> Result<Request, String> {
+    let mut request = url.as_str().into_client_request().map_err(|e| e.to_string())?;
+    let headers = request.headers_mut();
+    for (name, value) in &config.ws_headers {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        // La valeur peut être un secret (Authorization) : seul le nom apparaît dans l'erreur
+        let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name '{}'", name))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| format!("invalid value for header '{}'", name))?;
+        headers.insert(header, value);
+    }
+    let subprotocol = config.ws_subprotocol.trim();
+    if !subprotocol.is_empty() {
+        let value = HeaderValue::from_str(subprotocol).map_err(|_| format!("invalid subprotocol '{}'", subprotocol))?;
+        headers.insert("Sec-WebSocket-Protocol", value);
+    }
+    Ok(request)
+}
+
+/// Sous-protocole retenu par le serveur, pour le log.
+pub fn negotiated_subprotocol(response: &Response) -> Option<&str> {
+    response
+        .headers()
+        .get("Sec-WebSocket-Protocol")
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Extensions acceptées par le serveur, pour le log.
+pub fn negotiated_extensions(response: &Response) -> Option<&str> {
+    response
+        .headers()
+        .get("Sec-WebSocket-Extensions")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_targets_url_without_extensions() {
+        let url = Url::parse("wss://gateway.example:8443/socket?x=1").unwrap();
+        let request = request(&url, &ProxyConfig::default()).unwrap();
+        assert_eq!(request.uri(), "wss://gateway.example:8443/socket?x=1");
+        assert_eq!(request.headers()["Host"], "gateway.example:8443");
+        assert!(request.headers().get("Sec-WebSocket-Extensions").is_none());
+        assert!(request.headers().get("Sec-WebSocket-Protocol").is_none());
+    }
+
+    #[test]
+    fn custom_headers_and_subprotocol() {
+        let url = Url::parse("wss://gateway.example/socket").unwrap();
+        let mut config = ProxyConfig {
+            ws_headers: vec![
+                ("Authorization".to_string(), "Bearer s3cret".to_string()),
+                (" ".to_string(), "ignored".to_string()),
+            ],
+            ws_subprotocol: "game.v2".to_string(),
+            ..Default::default()
+        };
+        let upgrade = request(&url, &config).unwrap();
+        assert_eq!(upgrade.headers()["Authorization"], "Bearer s3cret");
+        assert_eq!(upgrade.headers()["Sec-WebSocket-Protocol"], "game.v2");
+
+        config.ws_headers = vec![("X-Token".to_string(), "a\r\nInjected: 1".to_string())];
+        assert_eq!(request(&url, &config).unwrap_err(), "invalid value for header 'X-Token'");
+        config.ws_headers = vec![("Bad Name".to_string(), "1".to_string())];
+        assert_eq!(request(&url, &config).unwrap_err(), "invalid header name 'Bad Name'");
+    }
+
+    #[test]
+    fn extensions_are_read_from_response() {
+        let mut response = Response::new(None);
+        response
+            .headers_mut()
+            .insert("Sec-WebSocket-Extensions", "permessage-deflate".parse().unwrap());
+        assert_eq!(negotiated_extensions(&response), Some("permessage-deflate"));
+        assert_eq!(negotiated_extensions(&Response::new(None)), None);
+    }
+}
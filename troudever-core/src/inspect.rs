@@ -0,0 +1,197 @@
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+
+pub const DEFAULT_MAX_BYTES: usize = 256;
+
+/// Frames gardées par direction
+pub const FRAMES_PER_DIRECTION: usize = 50;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    WsToTcp,
+    TcpToWs,
+}
+
+impl Direction {
+    pub fn arrow(self) -> &'static str {
+        match self {
+            Direction::WsToTcp => "WS → TCP",
+            Direction::TcpToWs => "TCP → WS",
+        }
+    }
+}
+
+/// Copie (tronquée) d'une frame tunnelée, pour l'inspecteur.
+pub struct CapturedFrame {
+    pub time: DateTime<Local>,
+    pub dir: Direction,
+    pub bytes: Vec<u8>,
+    /// Taille réelle avant troncature
+    pub len: usize,
+    pub is_text: bool,
+}
+
+impl CapturedFrame {
+    pub fn new(dir: Direction, data: &[u8], max_bytes: usize, is_text: bool) -> Self {
+        Self {
+            time: Local::now(),
+            dir,
+            bytes: data[..data.len().min(max_bytes)].to_vec(),
+            len: data.len(),
+            is_text,
+        }
+    }
+}
+
+/// Comment afficher le contenu d'une frame dans l'inspecteur.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum View {
+    /// JSON indenté si ça parse, sinon comme `Text`
+    #[default]
+    Json,
+    /// UTF-8, sinon hexa
+    Text,
+    Hex,
+}
+
+impl View {
+    pub const ALL: [View; 3] = [View::Json, View::Text, View::Hex];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            View::Json => "JSON",
+            View::Text => "Text",
+            View::Hex => "Hex",
+        }
+    }
+}
+
+impl CapturedFrame {
+    pub fn render(&self, view: View) -> String {
+        if view == View::Json
+            && let Ok(value) = serde_json::from_slice::<serde_json::Value>(&self.bytes)
+        {
+            return serde_json::to_string_pretty(&value).unwrap_or_default();
+        }
+        if view == View::Hex {
+            return hex_dump(&self.bytes);
+        }
+        // Une frame texte tronquée peut couper un caractère : lossy plutôt que hexa
+        match std::str::from_utf8(&self.bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) if self.is_text => String::from_utf8_lossy(&self.bytes).into_owned(),
+            Err(_) => hex_dump(&self.bytes),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FrameLog {
+    frames: VecDeque<CapturedFrame>,
+    paused: bool,
+}
+
+impl FrameLog {
+    /// En pause les nouvelles frames sont ignorées, celles déjà capturées restent.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn push(&mut self, frame: CapturedFrame) {
+        if self.paused {
+            return;
+        }
+        let dir = frame.dir;
+        self.frames.push_back(frame);
+        if self.frames.iter().filter(|f| f.dir == dir).count() > FRAMES_PER_DIRECTION
+            && let Some(oldest) = self.frames.iter().position(|f| f.dir == dir)
+        {
+            self.frames.remove(oldest);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CapturedFrame> {
+        self.frames.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Dump classique : offset, 16 octets en hexa, puis la colonne ASCII.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        if line > 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("{:04x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_dump_layout() {
+        let dump = hex_dump(b"Hello, world!\n\x00\x01ABC");
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "0000  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a 00 01  |Hello, world!...|"
+        );
+        assert_eq!(lines[1], format!("0010  {:<47}  |ABC|", "41 42 43"));
+        assert_eq!(hex_dump(b""), "");
+    }
+
+    #[test]
+    fn frames_are_truncated_and_bounded_per_direction() {
+        let frame = CapturedFrame::new(Direction::TcpToWs, &[7u8; 1000], 16, false);
+        assert_eq!(frame.bytes.len(), 16);
+        assert_eq!(frame.len, 1000);
+
+        let mut log = FrameLog::default();
+        log.push(CapturedFrame::new(Direction::WsToTcp, b"keep", 16, true));
+        for _ in 0..FRAMES_PER_DIRECTION + 10 {
+            log.push(CapturedFrame::new(Direction::TcpToWs, b"x", 16, true));
+        }
+        assert_eq!(log.iter().filter(|f| f.dir == Direction::TcpToWs).count(), FRAMES_PER_DIRECTION);
+        assert_eq!(log.iter().next().unwrap().bytes, b"keep");
+
+        log.set_paused(true);
+        log.push(CapturedFrame::new(Direction::WsToTcp, b"ignored", 16, true));
+        assert_eq!(log.iter().filter(|f| f.dir == Direction::WsToTcp).count(), 1);
+    }
+
+    #[test]
+    fn views() {
+        let json = CapturedFrame::new(Direction::TcpToWs, br#"{"room":"ABCD"}"#, 256, false);
+        assert_eq!(json.render(View::Json), "{\n  \"room\": \"ABCD\"\n}");
+        assert_eq!(json.render(View::Text), r#"{"room":"ABCD"}"#);
+
+        // JSON tronqué : on retombe sur le texte brut
+        let cut = CapturedFrame::new(Direction::TcpToWs, br#"{"room":"AB"#, 256, true);
+        assert_eq!(cut.render(View::Json), r#"{"room":"AB"#);
+
+        let binary = CapturedFrame::new(Direction::WsToTcp, &[0xff, 0x00], 256, false);
+        assert_eq!(binary.render(View::Text), hex_dump(&[0xff, 0x00]));
+        assert_eq!(json.render(View::Hex), hex_dump(br#"{"room":"ABCD"}"#));
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Au-delà, les pings les plus anciens sont considérés comme perdus
+const MAX_PENDING: usize = 8;
+
+/// Corrèle les pings envoyés avec les pongs reçus via leur payload (un compteur sur 8 octets).
+#[derive(Default)]
+pub struct PingTracker {
+    next_seq: u64,
+    pending: VecDeque<(u64, Instant)>,
+}
+
+impl PingTracker {
+    /// Retourne le payload du prochain ping et note son heure d'envoi.
+    pub fn next_ping(&mut self, now: Instant) -> Vec<u8> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((seq, now));
+        seq.to_be_bytes().to_vec()
+    }
+
+    /// Pings envoyés depuis le dernier pong reçu.
+    pub fn unanswered(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Retourne le RTT si le pong correspond à un ping en attente, `None` sinon
+    /// (pong inconnu, déjà reçu, ou payload qui ne vient pas de nous).
+    pub fn on_pong(&mut self, payload: &[u8], now: Instant) -> Option<Duration> {
+        let seq = u64::from_be_bytes(payload.try_into().ok()?);
+        let index = self.pending.iter().position(|(s, _)| *s == seq)?;
+        let (_, sent_at) = self.pending[index];
+        // Les pings plus anciens n'auront plus de réponse utile
+        self.pending.drain(..=index);
+        Some(now.saturating_duration_since(sent_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_pong_gives_rtt() {
+        let start = Instant::now();
+        let mut tracker = PingTracker::default();
+        let payload = tracker.next_ping(start);
+
+        let rtt = tracker.on_pong(&payload, start + Duration::from_millis(42));
+        assert_eq!(rtt, Some(Duration::from_millis(42)));
+        // Un doublon ne donne pas une deuxième mesure
+        assert_eq!(tracker.on_pong(&payload, start + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn late_pong_for_older_ping_is_ignored() {
+        let start = Instant::now();
+        let mut tracker = PingTracker::default();
+        let first = tracker.next_ping(start);
+        let second = tracker.next_ping(start + Duration::from_secs(5));
+
+        assert_eq!(tracker.unanswered(), 2);
+        let rtt = tracker.on_pong(&second, start + Duration::from_millis(5010));
+        assert_eq!(rtt, Some(Duration::from_millis(10)));
+        assert_eq!(tracker.unanswered(), 0);
+        assert_eq!(tracker.on_pong(&first, start + Duration::from_millis(5020)), None);
+    }
+
+    #[test]
+    fn foreign_payload_is_ignored() {
+        let mut tracker = PingTracker::default();
+        tracker.next_ping(Instant::now());
+        assert_eq!(tracker.on_pong(b"hello", Instant::now()), None);
+    }
+}
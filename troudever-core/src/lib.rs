@@ -0,0 +1,275 @@
+//! Moteur de TrouDeVer : un tunnel WebSocket <-> TCP avec reconnexion, contrôle du relais
+//! et statistiques, sans interface. Le binaire egui n'en est qu'un frontend.
+//!
+//! ```no_run
+//! # async fn demo() {
+//! use troudever_core::{ProxyConfig, ProxyEvent, Tunnel};
+//!
+//! let tunnel = Tunnel::builder(ProxyConfig::default()).start();
+//! let events = tunnel.events().unwrap().clone();
+//! std::thread::spawn(move || {
+//!     for (_, event) in events {
+//!         if let ProxyEvent::Stopped = event {
+//!             break;
+//!         }
+//!     }
+//! });
+//! tunnel.shutdown(troudever_core::SHUTDOWN_GRACE).await;
+//! # }
+//! ```
+
+pub mod auth;
+mod backoff;
+pub mod chaos;
+pub mod chunk;
+pub mod compress;
+pub mod config;
+pub mod control;
+pub mod crypto;
+pub mod discovery;
+pub mod fanout;
+mod handshake;
+pub mod inspect;
+mod latency;
+mod local;
+pub mod logging;
+pub mod mux;
+mod proxy;
+pub mod record;
+pub mod selftest;
+pub mod socks;
+pub mod stats;
+mod throttle;
+pub mod tls;
+pub mod tunnel;
+pub mod udp;
+pub mod upstream;
+pub mod version;
+
+pub use config::ProxyConfig;
+pub use proxy::{EVENT_CHANNEL_CAPACITY, EventSender, MISSED_PONGS_LIMIT, ProxyEvent, TunnelId};
+
+use crossbeam_channel::{Receiver, Sender, bounded};
+use logging::LogLevel;
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Délai laissé à la fermeture propre avant d'abort la tâche
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// Prépare un [`Tunnel`] : config, id, room à reprendre et canal d'événements.
+pub struct TunnelBuilder {
+    config: ProxyConfig,
+    id: TunnelId,
+    room_code: Option<String>,
+    events: Option<Sender<(TunnelId, ProxyEvent)>>,
+}
+
+impl TunnelBuilder {
+    /// Recopié sur chaque événement ; 0 par défaut.
+    pub fn id(mut self, id: TunnelId) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Room à redemander au serveur relais dès la première connexion TCP.
+    pub fn room_code(mut self, code: Option<String>) -> Self {
+        self.room_code = code;
+        self
+    }
+
+    /// Canal partagé (plusieurs tunnels, un seul consommateur), borné de préférence : voir
+    /// [`EventSender`]. Sans lui, le tunnel crée le sien, exposé par [`Tunnel::events`].
+    pub fn events(mut self, tx: Sender<(TunnelId, ProxyEvent)>) -> Self {
+        self.events = Some(tx);
+        self
+    }
+
+    /// Lance le tunnel sur le runtime tokio courant.
+    pub fn start(self) -> Tunnel {
+        self.launch(proxy::run_tunnel)
+    }
+
+    /// Rejoue les frames WS → TCP d'un enregistrement vers le serveur TCP (ou le premier client
+    /// en mode écoute), à leur rythme d'origine, sans WebSocket. Le `Tunnel` rendu s'arrête
+    /// comme un autre, et de lui-même à la fin du rejeu.
+    pub fn replay(self, frames: Vec<record::RecordedFrame>) -> Tunnel {
+        self.launch(|config, events, _, signals| proxy::run_replay(config, frames, events, signals))
+    }
+
+    fn launch<F, Fut>(self, run: F) -> Tunnel
+    where
+        F: FnOnce(ProxyConfig, EventSender, Option<String>, proxy::Signals) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, rx) = match self.events {
+            Some(tx) => (tx, None),
+            None => {
+                let (tx, rx) = bounded(EVENT_CHANNEL_CAPACITY);
+                (tx, Some(rx))
+            }
+        };
+        let events = EventSender::new(self.id, tx);
+        let rates = (self.config.ws_to_tcp_rate, self.config.tcp_to_ws_rate);
+        let (signals, signals_rx) = proxy::signals(rates, self.config.network);
+        let task = tokio::spawn(run(self.config, events.clone(), self.room_code, signals_rx));
+        Tunnel {
+            events,
+            rx,
+            signals,
+            task,
+        }
+    }
+}
+
+/// Un tunnel lancé. Il enchaîne les sessions (et les reconnexions) jusqu'à [`Tunnel::stop`]
+/// ou l'abandon des reconnexions, puis envoie [`ProxyEvent::Stopped`].
+pub struct Tunnel {
+    events: EventSender,
+    rx: Option<Receiver<(TunnelId, ProxyEvent)>>,
+    signals: proxy::SignalSenders,
+    task: JoinHandle<()>,
+}
+
+impl Tunnel {
+    pub fn builder(config: ProxyConfig) -> TunnelBuilder {
+        TunnelBuilder {
+            config,
+            id: 0,
+            room_code: None,
+            events: None,
+        }
+    }
+
+    pub fn id(&self) -> TunnelId {
+        self.events.tunnel_id()
+    }
+
+    /// Les événements du tunnel, sauf si un canal a été fourni au builder.
+    pub fn events(&self) -> Option<&Receiver<(TunnelId, ProxyEvent)>> {
+        self.rx.as_ref()
+    }
+
+    /// Demande un arrêt propre : Close WebSocket, fin de flux TCP, puis `Stopped`.
+    pub fn stop(&self) {
+        let _ = self.signals.shutdown.send(true);
+    }
+
+    /// En pause, plus rien n'est lu d'aucun côté ; les connexions restent ouvertes.
+    pub fn set_paused(&self, paused: bool) {
+        let _ = self.signals.pause.send(paused);
+    }
+
+    /// Suspend un sens sans l'autre, en plus de [`Tunnel::set_paused`] ; `pause_mode` dit si ses
+    /// messages attendent ou sont jetés. Modes Connect et Listen seulement.
+    pub fn set_paused_directions(&self, directions: tunnel::PausedDirections) {
+        let _ = self.signals.directions.send(directions);
+    }
+
+    /// Change les limites de débit (octets par seconde, 0 = illimité) sans couper le tunnel.
+    /// Sans effet sur les modes UDP et multiplexé, qui ne sont pas bridés.
+    pub fn set_rates(&self, ws_to_tcp: u64, tcp_to_ws: u64) {
+        let _ = self.signals.rates.send((ws_to_tcp, tcp_to_ws));
+    }
+
+    /// Change les conditions réseau simulées (voir [`chaos`]) sans couper le tunnel. Modes
+    /// Connect et Listen seulement.
+    pub fn set_network_conditions(&self, network: chaos::NetworkConditions) {
+        let _ = self.signals.network.send(network);
+    }
+
+    /// Demande au relais de rejoindre une room existante ; la réponse arrive en
+    /// [`ProxyEvent::Joined`] ou [`ProxyEvent::JoinFailed`]. Modes TCP seulement.
+    pub fn join_room(&self, code: &str) {
+        let _ = self.signals.relay.send(proxy::RelayRequest::Join(code.to_string()));
+    }
+
+    /// Demande au relais un nouveau room code pour la session en cours (un code qui a fuité,
+    /// par exemple) ; il arrive en [`ProxyEvent::RoomCode`]. Modes TCP seulement.
+    pub fn new_room(&self) {
+        let _ = self.signals.relay.send(proxy::RelayRequest::NewRoom);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.task.abort_handle()
+    }
+
+    /// Attend que le tunnel s'arrête de lui-même (reconnexions abandonnées, initiale ratée...).
+    pub async fn wait(&mut self) {
+        let _ = (&mut self.task).await;
+    }
+
+    /// `stop`, puis attend au plus `grace` avant d'abort la tâche. Dans ce cas `Stopped` est
+    /// envoyé ici et la fonction retourne `false`.
+    pub async fn shutdown(mut self, grace: Duration) -> bool {
+        if self.task.is_finished() {
+            return true;
+        }
+        self.stop();
+        if tokio::time::timeout(grace, &mut self.task).await.is_ok() {
+            return true;
+        }
+        self.task.abort();
+        self.events.log(LogLevel::Warn, "tunnel", "Graceful shutdown timed out, task aborted");
+        let _ = self.events.send(ProxyEvent::Stopped);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stop_ends_with_stopped_event() {
+        // Rien n'écoute : le tunnel retente jusqu'à ce qu'on l'arrête
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        drop(listener);
+
+        let tunnel = Tunnel::builder(config).id(7).start();
+        let events = tunnel.events().unwrap().clone();
+        assert!(tunnel.shutdown(SHUTDOWN_GRACE).await);
+        let last = events.try_iter().last().unwrap();
+        assert_eq!(last.0, 7);
+        assert!(matches!(last.1, ProxyEvent::Stopped));
+    }
+
+    #[tokio::test]
+    async fn stalled_frontend_does_not_block_shutdown() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        drop(listener);
+        // Canal déjà plein, que personne ne lit pendant l'arrêt
+        let (tx, rx) = bounded(1);
+        tx.send((0, ProxyEvent::Connected)).unwrap();
+
+        let tunnel = Tunnel::builder(config).events(tx).start();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let shutdown = tokio::time::timeout(Duration::from_secs(5), tunnel.shutdown(SHUTDOWN_GRACE)).await;
+        assert!(shutdown.unwrap());
+
+        // Le frontend reprend : ce qui attendait arrive, `Stopped` en dernier
+        let mut last = None;
+        for _ in 0..100 {
+            while let Ok((_, event)) = rx.try_recv() {
+                last = Some(event);
+            }
+            if matches!(last, Some(ProxyEvent::Stopped)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(matches!(last, Some(ProxyEvent::Stopped)));
+    }
+}
@@ -0,0 +1,301 @@
+//! Côté local du tunnel, choisi d'après la syntaxe de `tcp_addr` : `unix:///chemin.sock` pour
+//! un socket Unix, `\\.\pipe\nom` (ou `pipe://nom`) pour un named pipe Windows, TCP sinon.
+//! Le reste du proxy ne voit que des moitiés lecture/écriture. En TCP, un nom qui résout vers
+//! plusieurs adresses est tenté façon happy eyeballs (RFC 8305), IPv6 et IPv4 en alternance.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+pub(crate) type LocalRead = Box<dyn AsyncRead + Send + Unpin>;
+pub(crate) type LocalWrite = Box<dyn AsyncWrite + Send + Unpin>;
+
+// Délai avant de lancer l'adresse suivante sans abandonner celle en cours
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum LocalAddr<'a> {
+    Tcp(&'a str),
+    Unix(&'a str),
+    Pipe(String),
+}
+
+impl<'a> LocalAddr<'a> {
+    pub(crate) fn parse(addr: &'a str) -> Self {
+        let addr = addr.trim();
+        if let Some(path) = addr.strip_prefix("unix://") {
+            LocalAddr::Unix(path)
+        } else if let Some(name) = addr.strip_prefix("pipe://") {
+            LocalAddr::Pipe(format!(r"\\.\pipe\{}", name))
+        } else if addr.starts_with(r"\\.\pipe\") {
+            LocalAddr::Pipe(addr.to_string())
+        } else {
+            LocalAddr::Tcp(addr)
+        }
+    }
+}
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} are not supported on this platform", what))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// `host:port` ou `[ipv6]:port`. Une IPv6 sans crochets est ambiguë : refusée plutôt que devinée.
+pub(crate) fn split_host_port(addr: &str) -> io::Result<(&str, u16)> {
+    let (host, port) = match addr.strip_prefix('[') {
+        Some(rest) => rest.split_once("]:").ok_or_else(|| invalid("expected [address]:port"))?,
+        None => {
+            let (host, port) = addr.rsplit_once(':').ok_or_else(|| invalid("expected host:port"))?;
+            if host.contains(':') {
+                return Err(invalid("IPv6 addresses need brackets, e.g. [::1]:9000"));
+            }
+            (host, port)
+        }
+    };
+    let port = port.parse().map_err(|_| invalid("invalid port"))?;
+    Ok((host, port))
+}
+
+/// Alterne les familles en gardant l'ordre du résolveur, en commençant par celle qu'il préfère.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (first, second): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|a| a.is_ipv6() == first_v6);
+    let mut second = second.into_iter();
+    let mut out = Vec::new();
+    for addr in first {
+        out.push(addr);
+        out.extend(second.next());
+    }
+    out.extend(second);
+    out
+}
+
+/// Une tentative par adresse, la suivante partant après `ATTEMPT_DELAY` ou dès un échec ; la
+/// première qui aboutit gagne, les autres sont annulées. Toutes en échec : la dernière erreur.
+async fn race(addrs: Vec<SocketAddr>) -> io::Result<TcpStream> {
+    let mut pending = addrs.into_iter();
+    let mut attempts = JoinSet::new();
+    let start = |attempts: &mut JoinSet<io::Result<TcpStream>>, addr: SocketAddr| {
+        attempts.spawn(async move {
+            TcpStream::connect(addr).await.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", addr, e)))
+        });
+    };
+    let Some(addr) = pending.next() else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no address found"));
+    };
+    start(&mut attempts, addr);
+    let mut last_error = None;
+    loop {
+        tokio::select! {
+            done = attempts.join_next() => match done {
+                Some(Ok(Ok(stream))) => return Ok(stream),
+                Some(Ok(Err(e))) => {
+                    last_error = Some(e);
+                    if let Some(addr) = pending.next() {
+                        start(&mut attempts, addr);
+                    }
+                }
+                Some(Err(e)) => last_error = Some(io::Error::other(e)),
+                None => return Err(last_error.unwrap_or_else(|| io::Error::other("no address reachable"))),
+            },
+            _ = tokio::time::sleep(ATTEMPT_DELAY), if !pending.as_slice().is_empty() => {
+                if let Some(addr) = pending.next() {
+                    start(&mut attempts, addr);
+                }
+            }
+        }
+    }
+}
+
+async fn connect_tcp(addr: &str) -> io::Result<TcpStream> {
+    let (host, port) = split_host_port(addr)?;
+    let addrs = tokio::net::lookup_host((host, port)).await?.collect();
+    race(interleave(addrs)).await
+}
+
+/// Active SO_KEEPALIVE : un pair disparu sans fermer (câble débranché, NAT qui a oublié la
+/// session) fait échouer la lecture au lieu de la laisser muette pour toujours.
+pub(crate) fn set_keepalive(stream: &TcpStream, idle: Duration) {
+    let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+    // Trois sondes espacées d'un tiers du délai : le pair est déclaré mort vers deux fois `idle`
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    let keepalive = keepalive.with_interval((idle / 3).max(Duration::from_secs(1))).with_retries(3);
+    // Best effort, comme TCP_NODELAY : le délai d'inactivité applicatif reste là en secours
+    let _ = socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive);
+}
+
+fn tcp_halves(stream: TcpStream, keepalive: Option<Duration>) -> (LocalRead, LocalWrite) {
+    // Best effort : sans TCP_NODELAY on perd un peu de latence, pas la connexion
+    let _ = stream.set_nodelay(true);
+    if let Some(idle) = keepalive {
+        set_keepalive(&stream, idle);
+    }
+    let (read, write) = stream.into_split();
+    (Box::new(read), Box::new(write))
+}
+
+/// Compose l'adresse locale. `keepalive` ne concerne que TCP.
+pub(crate) async fn connect(addr: &str, keepalive: Option<Duration>) -> io::Result<(LocalRead, LocalWrite)> {
+    match LocalAddr::parse(addr) {
+        LocalAddr::Tcp(addr) => connect_tcp(addr).await.map(|stream| tcp_halves(stream, keepalive)),
+        #[cfg(unix)]
+        LocalAddr::Unix(path) => {
+            let (read, write) = tokio::net::UnixStream::connect(path).await?.into_split();
+            Ok((Box::new(read), Box::new(write)))
+        }
+        #[cfg(not(unix))]
+        LocalAddr::Unix(_) => Err(unsupported("Unix sockets")),
+        #[cfg(windows)]
+        LocalAddr::Pipe(name) => {
+            let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(name)?;
+            let (read, write) = tokio::io::split(pipe);
+            Ok((Box::new(read), Box::new(write)))
+        }
+        #[cfg(not(windows))]
+        LocalAddr::Pipe(_) => Err(unsupported("Named pipes")),
+    }
+}
+
+/// Écoute du mode `Listen`, un client à la fois.
+pub(crate) enum LocalListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+    // Une instance de pipe ne sert qu'un client : on en recrée une à chaque accept
+    #[cfg(windows)]
+    Pipe(String),
+}
+
+impl LocalListener {
+    pub(crate) async fn bind(addr: &str) -> io::Result<Self> {
+        match LocalAddr::parse(addr) {
+            LocalAddr::Tcp(addr) => TcpListener::bind(addr).await.map(LocalListener::Tcp),
+            #[cfg(unix)]
+            LocalAddr::Unix(path) => {
+                use std::os::unix::fs::FileTypeExt;
+                // Un socket laissé par une session précédente bloquerait le bind ; un vrai fichier, non
+                if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                    std::fs::remove_file(path)?;
+                }
+                tokio::net::UnixListener::bind(path).map(LocalListener::Unix)
+            }
+            #[cfg(not(unix))]
+            LocalAddr::Unix(_) => Err(unsupported("Unix sockets")),
+            #[cfg(windows)]
+            LocalAddr::Pipe(name) => {
+                // Vérifie tout de suite que le nom est libre
+                tokio::net::windows::named_pipe::ServerOptions::new().first_pipe_instance(true).create(&name)?;
+                Ok(LocalListener::Pipe(name))
+            }
+            #[cfg(not(windows))]
+            LocalAddr::Pipe(_) => Err(unsupported("Named pipes")),
+        }
+    }
+
+    /// Attend le prochain client ; renvoie aussi de quoi l'identifier dans les logs.
+    pub(crate) async fn accept(&self, keepalive: Option<Duration>) -> io::Result<(LocalRead, LocalWrite, String)> {
+        match self {
+            LocalListener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                let (read, write) = tcp_halves(stream, keepalive);
+                Ok((read, write, peer.to_string()))
+            }
+            #[cfg(unix)]
+            LocalListener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let (read, write) = stream.into_split();
+                Ok((Box::new(read), Box::new(write), "Unix socket".to_string()))
+            }
+            #[cfg(windows)]
+            LocalListener::Pipe(name) => {
+                let server = tokio::net::windows::named_pipe::ServerOptions::new().create(name)?;
+                server.connect().await?;
+                let (read, write) = tokio::io::split(server);
+                Ok((Box::new(read), Box::new(write), "named pipe".to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_syntax_picks_the_transport() {
+        assert_eq!(LocalAddr::parse("127.0.0.1:7777"), LocalAddr::Tcp("127.0.0.1:7777"));
+        assert_eq!(LocalAddr::parse("unix:///run/game.sock"), LocalAddr::Unix("/run/game.sock"));
+        assert_eq!(LocalAddr::parse("pipe://game"), LocalAddr::Pipe(r"\\.\pipe\game".to_string()));
+        assert_eq!(LocalAddr::parse(r"\\.\pipe\game"), LocalAddr::Pipe(r"\\.\pipe\game".to_string()));
+    }
+
+    #[test]
+    fn bracketed_ipv6_and_interleaved_families() {
+        assert_eq!(split_host_port("[::1]:9000").unwrap(), ("::1", 9000));
+        assert_eq!(split_host_port("game.example:80").unwrap(), ("game.example", 80));
+        assert!(split_host_port("::1:9000").is_err());
+        assert!(split_host_port("[::1]").is_err());
+        assert!(split_host_port("localhost:http").is_err());
+
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let order: Vec<String> = interleave(addrs).iter().map(|a| a.ip().to_string()).collect();
+        assert_eq!(order, ["::1", "10.0.0.1", "::2", "::3"]);
+    }
+
+    #[tokio::test]
+    async fn dead_first_address_falls_through_to_the_next() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = listener.local_addr().unwrap();
+        // Port libéré aussitôt : la tentative y est refusée
+        let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let stream = race(vec![dead, live]).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), live);
+
+        let error = race(vec![dead]).await.unwrap_err();
+        assert!(error.to_string().starts_with(&dead.to_string()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn keepalive_probes_after_the_configured_delay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let socket = socket2::SockRef::from(&stream);
+        assert!(!socket.keepalive().unwrap());
+
+        set_keepalive(&stream, Duration::from_secs(30));
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.tcp_keepalive_time().unwrap(), Duration::from_secs(30));
+        assert_eq!(socket.tcp_keepalive_interval().unwrap(), Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn listen_replaces_a_stale_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path = std::env::temp_dir().join(format!("troudever-local-{}.sock", std::process::id()));
+        let addr = format!("unix://{}", path.display());
+        // Socket d'une session précédente, jamais nettoyé
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = LocalListener::bind(&addr).await.unwrap();
+
+        let (_, mut client) = connect(&addr, None).await.unwrap();
+        let (mut read, _write, peer) = listener.accept(None).await.unwrap();
+        assert_eq!(peer, "Unix socket");
+        client.write_all(b"hi").await.unwrap();
+        let mut buf = [0u8; 2];
+        read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hi");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,110 @@
+//! Logs structurés du moteur : niveau, origine (`ws`, `tcp`, `relay`...), message et champs.
+//! Chaque log part en [`crate::ProxyEvent::Log`] et en événement `tracing` (cible
+//! `troudever_core`), pour qu'un programme qui embarque le moteur les récupère avec son
+//! propre subscriber.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const TARGET: &str = "troudever_core";
+
+/// Du plus grave au plus bavard : `level <= seuil` se lit « assez important pour être gardé ».
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    /// Partie du tunnel qui parle : `ws`, `tcp`, `relay`, `udp`, `mux`, `fanout`, `record` ou `tunnel`
+    pub target: &'static str,
+    pub message: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl LogRecord {
+    pub fn new(level: LogLevel, target: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            target,
+            message: message.into(),
+            fields: vec![],
+        }
+    }
+
+    pub fn field(mut self, key: &'static str, value: impl fmt::Display) -> Self {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+
+    /// Émet le log côté `tracing` ; sans subscriber installé, ça ne coûte presque rien.
+    pub(crate) fn trace(&self, tunnel: usize) {
+        let (target, fields) = (self.target, FieldsText(&self.fields));
+        let message = &self.message;
+        // Le niveau d'un événement `tracing` doit être connu à la compilation
+        match self.level {
+            LogLevel::Error => tracing::error!(target: TARGET, tunnel, source = target, %fields, "{}", message),
+            LogLevel::Warn => tracing::warn!(target: TARGET, tunnel, source = target, %fields, "{}", message),
+            LogLevel::Info => tracing::info!(target: TARGET, tunnel, source = target, %fields, "{}", message),
+            LogLevel::Debug => tracing::debug!(target: TARGET, tunnel, source = target, %fields, "{}", message),
+        }
+    }
+}
+
+/// Le message suivi des champs, `clé=valeur`.
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.fields.is_empty() {
+            write!(f, " {}", FieldsText(&self.fields))?;
+        }
+        Ok(())
+    }
+}
+
+struct FieldsText<'a>(&'a [(&'static str, String)]);
+
+impl fmt::Display for FieldsText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (key, value)) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_sort_by_verbosity_and_fields_follow_the_message() {
+        assert!(LogLevel::Error < LogLevel::Warn && LogLevel::Info < LogLevel::Debug);
+        assert_eq!(LogLevel::default(), LogLevel::Info);
+
+        let record = LogRecord::new(LogLevel::Info, "mux", "Stream opened").field("stream", 3).field("peer", "a:1");
+        assert_eq!(record.to_string(), "Stream opened stream=3 peer=a:1");
+        assert_eq!(LogRecord::new(LogLevel::Warn, "tcp", "TCP lost").to_string(), "TCP lost");
+    }
+}
@@ -0,0 +1,132 @@
+//! Mode multiplexé : plusieurs clients TCP locaux partagent le même WebSocket. Chaque frame
+//! binaire commence par `[type][stream u32 big-endian]` ; `Open` et `Close` encadrent la vie
+//! d'un stream, `Data` porte ses octets. En mode SOCKS5, l'ouverture porte en plus la
+//! destination (`Connect`) : même type, suivi de `host:port` en UTF-8.
+
+use std::fmt;
+
+pub const HEADER_LEN: usize = 5;
+
+const OPEN: u8 = 1;
+const DATA: u8 = 2;
+const CLOSE: u8 = 3;
+
+/// Numéro attribué par TrouDeVer à chaque client accepté, jamais celui d'un stream encore ouvert.
+pub type StreamId = u32;
+
+/// Prochain id libre à partir de `next`, 0 exclu : après un tour complet, seuls les ids fermés
+/// resservent. `None` quand `open` streams occupent déjà tout l'espace.
+pub fn allocate_stream_id(next: &mut StreamId, open: usize, in_use: impl Fn(StreamId) -> bool) -> Option<StreamId> {
+    if open >= StreamId::MAX as usize {
+        return None;
+    }
+    loop {
+        let id = *next;
+        *next = id.checked_add(1).unwrap_or(1);
+        if id != 0 && !in_use(id) {
+            return Some(id);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MuxFrame<'a> {
+    Open(StreamId),
+    /// Ouverture vers une destination choisie par le client (`host:port`)
+    Connect(StreamId, &'a str),
+    Data(StreamId, &'a [u8]),
+    Close(StreamId),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MuxFrameError {
+    TooShort(usize),
+    UnknownType(u8),
+    InvalidTarget,
+}
+
+impl fmt::Display for MuxFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MuxFrameError::TooShort(len) => write!(f, "multiplexed frame too short ({} bytes)", len),
+            MuxFrameError::UnknownType(kind) => write!(f, "unknown multiplexed frame type {}", kind),
+            MuxFrameError::InvalidTarget => write!(f, "stream destination is not UTF-8"),
+        }
+    }
+}
+
+impl MuxFrame<'_> {
+    pub fn stream(&self) -> StreamId {
+        match self {
+            MuxFrame::Open(id) | MuxFrame::Connect(id, _) | MuxFrame::Data(id, _) | MuxFrame::Close(id) => *id,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let (kind, payload): (u8, &[u8]) = match self {
+            MuxFrame::Open(_) => (OPEN, &[]),
+            MuxFrame::Connect(_, target) => (OPEN, target.as_bytes()),
+            MuxFrame::Data(_, payload) => (DATA, payload),
+            MuxFrame::Close(_) => (CLOSE, &[]),
+        };
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.push(kind);
+        frame.extend_from_slice(&self.stream().to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    pub fn decode(frame: &[u8]) -> Result<MuxFrame<'_>, MuxFrameError> {
+        if frame.len() < HEADER_LEN {
+            return Err(MuxFrameError::TooShort(frame.len()));
+        }
+        let id = StreamId::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]);
+        match frame[0] {
+            OPEN if frame.len() == HEADER_LEN => Ok(MuxFrame::Open(id)),
+            OPEN => match std::str::from_utf8(&frame[HEADER_LEN..]) {
+                Ok(target) => Ok(MuxFrame::Connect(id, target)),
+                Err(_) => Err(MuxFrameError::InvalidTarget),
+            },
+            DATA => Ok(MuxFrame::Data(id, &frame[HEADER_LEN..])),
+            CLOSE => Ok(MuxFrame::Close(id)),
+            kind => Err(MuxFrameError::UnknownType(kind)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_roundtrip() {
+        let data = MuxFrame::Data(258, b"hello").encode();
+        assert_eq!(&data[..HEADER_LEN], &[DATA, 0, 0, 1, 2]);
+        assert_eq!(MuxFrame::decode(&data), Ok(MuxFrame::Data(258, &b"hello"[..])));
+        assert_eq!(MuxFrame::decode(&MuxFrame::Open(7).encode()), Ok(MuxFrame::Open(7)));
+        assert_eq!(MuxFrame::decode(&MuxFrame::Close(7).encode()), Ok(MuxFrame::Close(7)));
+        let connect = MuxFrame::Connect(3, "example.com:443").encode();
+        assert_eq!(&connect[..HEADER_LEN], &[OPEN, 0, 0, 0, 3]);
+        assert_eq!(MuxFrame::decode(&connect), Ok(MuxFrame::Connect(3, "example.com:443")));
+        assert_eq!(MuxFrame::decode(&[OPEN, 0, 0, 0, 3, 0xff]), Err(MuxFrameError::InvalidTarget));
+        assert_eq!(MuxFrame::decode(&[DATA, 0, 0]), Err(MuxFrameError::TooShort(3)));
+        assert_eq!(MuxFrame::decode(&[9, 0, 0, 0, 1]), Err(MuxFrameError::UnknownType(9)));
+    }
+
+    #[test]
+    fn wrapped_ids_skip_open_streams() {
+        let open = [StreamId::MAX, 1, 2];
+        let mut next = StreamId::MAX - 1;
+        let in_use = |id| open.contains(&id);
+        assert_eq!(allocate_stream_id(&mut next, open.len(), in_use), Some(StreamId::MAX - 1));
+        assert_eq!(allocate_stream_id(&mut next, open.len(), in_use), Some(3));
+        assert_eq!(next, 4);
+    }
+
+    #[test]
+    fn exhausted_id_space_refuses_new_streams() {
+        let mut next = 1;
+        assert_eq!(allocate_stream_id(&mut next, StreamId::MAX as usize, |_| true), None);
+        assert_eq!(next, 1);
+    }
+}
@@ -0,0 +1,3687 @@
+use bytes::Bytes;
+use crossbeam_channel::{SendError, Sender, TrySendError};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, accept_async, client_async_tls_with_config, connect_async_tls_with_config,
+    tungstenite::{self, protocol::Message},
+};
+use url::Url;
+
+use crate::auth;
+use crate::backoff::Backoff;
+use crate::chaos::{Chaos, NetworkConditions};
+use crate::chunk::{self, Reassembler};
+use crate::config::{ProxyConfig, READ_BUFFER_RANGE};
+use crate::compress;
+use crate::control::ControlMessage;
+use crate::crypto::Cipher;
+use crate::fanout::{self, Fanout, Route, TargetStatus};
+use crate::handshake;
+use crate::inspect::{CapturedFrame, Direction};
+use crate::latency::PingTracker;
+use crate::logging::{LogLevel, LogRecord};
+use crate::local::{self, LocalListener, LocalRead, LocalWrite};
+use crate::mux::{self, MuxFrame, StreamId};
+use crate::record::{RecordedFrame, Recorder};
+use crate::socks;
+use crate::stats::{Stats, StatsMeter};
+use crate::throttle::{self, Rates, TokenBucket};
+use crate::tunnel::{self, Backlog, FrameDecoder, PauseMode, PausedDirections, TcpMode, WsMode};
+use crate::udp;
+use crate::upstream::UpstreamProxy;
+use crate::version;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsWrite = SplitSink<WsStream, Message>;
+type WsRead = SplitStream<WsStream>;
+
+// Pings sans pong avant de considérer le WebSocket comme mort
+pub const MISSED_PONGS_LIMIT: usize = 3;
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// Capacité conseillée pour le canal d'événements (c'est celle du canal créé par `Tunnel`).
+pub const EVENT_CHANNEL_CAPACITY: usize = 4096;
+// Rythme auquel les événements en attente retentent leur chance sur un canal plein
+const PENDING_RETRY: Duration = Duration::from_millis(20);
+/// Identifiant choisi par l'appelant, recopié sur chaque événement du tunnel.
+pub type TunnelId = usize;
+
+/// Ce qu'un tunnel remonte à son frontend. `Stopped` est toujours le dernier.
+pub enum ProxyEvent {
+    Log(LogRecord),
+    Status(String),
+    /// Comme `Status`, mais reste affiché en erreur jusqu'au prochain statut (même après l'arrêt)
+    Error(String),
+    RoomCode(String),
+    /// Messages de contrôle typés du relais (voir `control::ControlMessage`)
+    RelayError(String),
+    PeerJoined(String),
+    PeerLeft(String),
+    /// Le relais a mis fin à la session : pas de reconnexion
+    Kicked(String),
+    /// Réponses du relais à [`crate::Tunnel::join_room`]
+    Joined(String),
+    JoinFailed(String),
+    /// Les deux côtés sont branchés : début de session, ou retour après une reconnexion
+    Connected,
+    /// Un côté (`WebSocket` ou `TCP`) est tombé et une nouvelle tentative est prévue
+    Reconnecting(String),
+    /// Une cible de fan-out (voir `fanout`) vient de se connecter ou de tomber
+    Target(TargetStatus),
+    Latency(Duration),
+    Stats(Stats),
+    Frame(CapturedFrame),
+    Stopped,
+}
+
+impl ProxyEvent {
+    /// Peut être perdu quand le frontend ne suit plus : un autre du même genre suivra. Les
+    /// warnings et erreurs, eux, passent toujours : le debug ne doit pas les noyer.
+    fn is_lossy(&self) -> bool {
+        match self {
+            ProxyEvent::Log(record) => record.level > LogLevel::Warn,
+            ProxyEvent::Latency(_) | ProxyEvent::Stats(_) | ProxyEvent::Frame(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Envoie les événements d'un tunnel, étiquetés avec son id, sur un canal éventuellement
+/// partagé entre plusieurs tunnels.
+///
+/// `send` ne bloque jamais : il est appelé depuis les tâches du runtime, et un frontend figé
+/// ne doit pas figer tous les tunnels avec lui (arrêt compris). Sur un canal borné plein, les
+/// événements « lossy » sont jetés et comptés ; les autres attendent dans une file propre au
+/// tunnel, dans l'ordre, qu'une tâche de fond reverse dès que le canal a de la place. Si le
+/// frontend ne suit vraiment plus, cette file aussi est bornée : les plus vieux partent, et
+/// un seul « Frontend lagging: N events dropped » les résume.
+#[derive(Clone)]
+pub struct EventSender {
+    tunnel_id: TunnelId,
+    tx: Sender<(TunnelId, ProxyEvent)>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    dropped: AtomicUsize,
+    pending: Mutex<VecDeque<ProxyEvent>>,
+    // Une tâche vide déjà `pending` ; lu et écrit sous le verrou de `pending`
+    flushing: AtomicBool,
+}
+
+impl EventSender {
+    pub fn new(tunnel_id: TunnelId, tx: Sender<(TunnelId, ProxyEvent)>) -> Self {
+        Self {
+            tunnel_id,
+            tx,
+            shared: Arc::default(),
+        }
+    }
+
+    pub fn tunnel_id(&self) -> TunnelId {
+        self.tunnel_id
+    }
+
+    pub fn send(&self, event: ProxyEvent) -> Result<(), SendError<(TunnelId, ProxyEvent)>> {
+        if let ProxyEvent::Log(record) = &event {
+            record.trace(self.tunnel_id);
+        }
+        let mut pending = self.shared.pending.lock().unwrap_or_else(|e| e.into_inner());
+        // Ce qui attend déjà passe d'abord, pour garder l'ordre
+        if self.drain(&mut pending).is_err() {
+            return Err(SendError((self.tunnel_id, event)));
+        }
+        if !pending.is_empty() {
+            self.hold(&mut pending, event);
+            return Ok(());
+        }
+        match self.tx.try_send((self.tunnel_id, event)) {
+            Ok(()) => {
+                drop(pending);
+                self.report_dropped();
+                Ok(())
+            }
+            Err(TrySendError::Full((_, event))) => {
+                self.hold(&mut pending, event);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(event)) => Err(SendError(event)),
+        }
+    }
+
+    /// Raccourci pour un log sans champs ; comme pour tout log, un frontend parti n'est pas une erreur.
+    pub fn log(&self, level: LogLevel, target: &'static str, message: impl Into<String>) {
+        let _ = self.send(ProxyEvent::Log(LogRecord::new(level, target, message)));
+    }
+
+    /// Canal plein : un événement lossy est perdu, un autre attend son tour.
+    fn hold(&self, pending: &mut VecDeque<ProxyEvent>, event: ProxyEvent) {
+        if event.is_lossy() {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        pending.push_back(event);
+        if pending.len() > EVENT_CHANNEL_CAPACITY {
+            pending.pop_front();
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        if !self.shared.flushing.swap(true, Ordering::Relaxed) {
+            self.spawn_flush();
+        }
+    }
+
+    /// Vide la file dans le canal tant qu'il a de la place. L'erreur dit que le frontend est parti.
+    fn drain(&self, pending: &mut VecDeque<ProxyEvent>) -> Result<(), SendError<(TunnelId, ProxyEvent)>> {
+        while let Some(event) = pending.pop_front() {
+            match self.tx.try_send((self.tunnel_id, event)) {
+                Ok(()) => {}
+                Err(TrySendError::Full((_, event))) => {
+                    pending.push_front(event);
+                    return Ok(());
+                }
+                Err(TrySendError::Disconnected(event)) => {
+                    pending.clear();
+                    return Err(SendError(event));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sans runtime (appel depuis un thread à part), la file attend le prochain `send`
+    fn spawn_flush(&self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            self.shared.flushing.store(false, Ordering::Relaxed);
+            return;
+        };
+        let sender = self.clone();
+        runtime.spawn(async move {
+            loop {
+                tokio::time::sleep(PENDING_RETRY).await;
+                let mut pending = sender.shared.pending.lock().unwrap_or_else(|e| e.into_inner());
+                let gone = sender.drain(&mut pending).is_err();
+                if gone || pending.is_empty() {
+                    sender.shared.flushing.store(false, Ordering::Relaxed);
+                    drop(pending);
+                    sender.report_dropped();
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Signale les pertes dès que le canal a de nouveau de la place.
+    fn report_dropped(&self) {
+        let dropped = self.shared.dropped.swap(0, Ordering::Relaxed);
+        if dropped == 0 {
+            return;
+        }
+        let message = format!("Frontend lagging: {} events dropped", dropped);
+        let log = ProxyEvent::Log(LogRecord::new(LogLevel::Warn, "tunnel", message));
+        if self.tx.try_send((self.tunnel_id, log)).is_err() {
+            self.shared.dropped.fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Ce que le frontend envoie à un tunnel lancé, côté moteur. `Tunnel` garde les émetteurs.
+pub(crate) struct Signals {
+    pub shutdown: watch::Receiver<bool>,
+    pub pause: watch::Receiver<bool>,
+    pub directions: watch::Receiver<PausedDirections>,
+    pub rates: watch::Receiver<Rates>,
+    pub network: watch::Receiver<NetworkConditions>,
+    /// Demandes au relais, envoyées dès que le TCP est là
+    pub relay: mpsc::UnboundedReceiver<RelayRequest>,
+}
+
+pub(crate) struct SignalSenders {
+    pub shutdown: watch::Sender<bool>,
+    pub pause: watch::Sender<bool>,
+    pub directions: watch::Sender<PausedDirections>,
+    pub rates: watch::Sender<Rates>,
+    pub network: watch::Sender<NetworkConditions>,
+    pub relay: mpsc::UnboundedSender<RelayRequest>,
+}
+
+pub(crate) enum RelayRequest {
+    Join(String),
+    NewRoom,
+}
+
+pub(crate) fn signals(rates: Rates, network: NetworkConditions) -> (SignalSenders, Signals) {
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    let (pause, pause_rx) = watch::channel(false);
+    let (directions, directions_rx) = watch::channel(PausedDirections::default());
+    let (rates, rates_rx) = watch::channel(rates);
+    let (network, network_rx) = watch::channel(network);
+    let (relay, relay_rx) = mpsc::unbounded_channel();
+    let senders = SignalSenders {
+        shutdown,
+        pause,
+        directions,
+        rates,
+        network,
+        relay,
+    };
+    let signals = Signals {
+        shutdown: shutdown_rx,
+        pause: pause_rx,
+        directions: directions_rx,
+        rates: rates_rx,
+        network: network_rx,
+        relay: relay_rx,
+    };
+    (senders, signals)
+}
+
+/// Enchaîne les sessions jusqu'à l'arrêt (ou l'abandon des reconnexions), puis envoie `Stopped`.
+pub(crate) async fn run_tunnel(
+    config: ProxyConfig,
+    tx: EventSender,
+    mut current_room: Option<String>,
+    mut signals: Signals,
+) {
+    // Hors des modes TCP simples la clé serait ignorée : mieux vaut ne rien envoyer en clair
+    let encoded = !config.e2e_key.is_empty() || config.compress || config.max_frame_size > 0;
+    if encoded && matches!(config.tcp_mode, TcpMode::Udp | TcpMode::Multiplex | TcpMode::Socks5) {
+        let _ = tx.send(ProxyEvent::Error(
+            "End-to-end encryption, compression and chunking need the Connect or Listen mode".to_string(),
+        ));
+        let _ = tx.send(ProxyEvent::Stopped);
+        return;
+    }
+    let mut backoff = Backoff::new(config.max_retries);
+    // Un seul fichier pour tout le tunnel, reconnexions comprises
+    let mut recorder = None;
+    if !config.record_file.as_os_str().is_empty() {
+        match Recorder::create(&config.record_file) {
+            Ok(file) => {
+                tx.log(LogLevel::Info, "record", format!("Recording traffic to {}", config.record_file.display()));
+                recorder = Some(file);
+            }
+            Err(e) => {
+                tx.log(LogLevel::Error, "record", format!("Cannot record to {}: {}", config.record_file.display(), e));
+            }
+        }
+    }
+
+    loop {
+        let end = run_proxy_logic(
+            &config,
+            tx.clone(),
+            current_room.clone(),
+            &mut signals,
+            &mut recorder,
+        )
+        .await;
+        if let Some(Err(e)) = recorder.as_mut().map(Recorder::flush) {
+            tx.log(LogLevel::Error, "record", format!("Recording error: {}", e));
+        }
+
+        if end.room_code.is_some() {
+            current_room = end.room_code;
+        }
+        // Une session qui a tourné n'est pas un échec de plus dans la même série : sans ça,
+        // chaque relance du chien de garde userait une des `max_retries`
+        if end.established {
+            backoff.reset();
+        }
+
+        let retry = end.retry && (config.auto_reconnect || end.restart);
+        if !retry || is_shutdown(&signals.shutdown) {
+            break;
+        }
+        let Some(delay) = next_retry(&mut backoff, "Connection", &tx) else {
+            break;
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = signals.shutdown.changed() => break,
+        }
+    }
+
+    let _ = tx.send(ProxyEvent::Stopped);
+}
+
+/// Ce que le pair doit appliquer à l'identique aux payloads : compression puis chiffrement
+/// à l'envoi, l'inverse à la réception. Les deux sortent toujours en frames binaires.
+struct PayloadCodec {
+    cipher: Option<Cipher>,
+    compress: bool,
+}
+
+impl PayloadCodec {
+    fn new(config: &ProxyConfig) -> Self {
+        Self {
+            cipher: (!config.e2e_key.is_empty()).then(|| Cipher::new(&config.e2e_key)),
+            compress: config.compress,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.cipher.is_some() || self.compress
+    }
+
+    fn encode(&mut self, payload: &[u8]) -> Result<Vec<u8>, String> {
+        let packed = if self.compress { compress::pack(payload) } else { payload.to_vec() };
+        match &mut self.cipher {
+            Some(cipher) => cipher.seal(&packed).map_err(|e| e.to_string()),
+            None => Ok(packed),
+        }
+    }
+
+    fn decode(&mut self, is_text: bool, frame: &[u8]) -> Result<Vec<u8>, String> {
+        // Du texte ne peut pas venir d'un pair qui applique les mêmes réglages
+        if is_text {
+            return Err("text frame, expected an encoded binary one".to_string());
+        }
+        let opened = match &mut self.cipher {
+            Some(cipher) => cipher.open(frame).map_err(|e| e.to_string())?,
+            None => frame.to_vec(),
+        };
+        if self.compress {
+            compress::unpack(&opened, *READ_BUFFER_RANGE.end()).map_err(|e| e.to_string())
+        } else {
+            Ok(opened)
+        }
+    }
+}
+
+/// Envoie un message de données, découpé en frames d'au plus `max_frame` octets si le
+/// découpage est actif (voir `chunk`). Le pair recolle avant tout le reste.
+async fn send_ws(writer: &mut WsWrite, message: Message, max_frame: usize) -> Result<(), tungstenite::Error> {
+    match message {
+        Message::Binary(payload) if max_frame > 0 => {
+            for frame in chunk::split(&payload, max_frame) {
+                writer.feed(Message::Binary(frame.into())).await?;
+            }
+            writer.flush().await
+        }
+        message => writer.send(message).await,
+    }
+}
+
+fn active_status(paused: bool) -> &'static str {
+    if paused { "Paused" } else { "Connected (Active)" }
+}
+
+/// Modes Connect et Listen, où un seul sens peut aussi être suspendu.
+fn session_status(paused: bool, held: PausedDirections) -> String {
+    match held.describe() {
+        Some(directions) if !paused => format!("Connected ({} paused)", directions),
+        _ => active_status(paused).to_string(),
+    }
+}
+
+fn is_shutdown(shutdown: &watch::Receiver<bool>) -> bool {
+    // Un émetteur disparu (tunnel supprimé, app fermée) vaut demande d'arrêt
+    *shutdown.borrow() || shutdown.has_changed().is_err()
+}
+
+async fn open_websocket(config: &ProxyConfig, url: &Url, tx: &EventSender) -> Option<WsStream> {
+    let request = match handshake::request(url, config) {
+        Ok(request) => request,
+        Err(e) => {
+            tx.log(LogLevel::Error, "ws", format!("Invalid WebSocket request: {}", e));
+            return None;
+        }
+    };
+
+    let connector = if url.scheme() == "wss" {
+        match config.tls.connector() {
+            Ok(connector) => Some(connector),
+            Err(e) => {
+                tx.log(LogLevel::Error, "ws", format!("TLS setup failed: {}", e));
+                return None;
+            }
+        }
+    } else {
+        None
+    };
+    if connector.is_some() && config.tls.accept_invalid_certs {
+        tx.log(LogLevel::Warn, "ws", "Warning: TLS certificate validation is disabled");
+    }
+
+    // Le réglage de l'app prime ; sinon les variables d'environnement habituelles
+    let host = url.host_str().unwrap_or_default();
+    let from_env = if config.upstream.is_enabled() {
+        None
+    } else {
+        UpstreamProxy::from_env(url.scheme(), host, |key| std::env::var(key).ok())
+    };
+    let (upstream, source) = match &from_env {
+        Some((proxy, var)) => (proxy, *var),
+        None => (&config.upstream, "settings"),
+    };
+
+    let result = if upstream.is_enabled() {
+        let port = url.port_or_known_default().unwrap_or(80);
+        tx.log(LogLevel::Info, "ws", format!(
+            "Using {} proxy at {} (from {})",
+            upstream.kind.label(),
+            upstream.address,
+            source
+        ));
+        let stream = match upstream.connect(host, port).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                tx.log(LogLevel::Error, "ws", format!("Upstream proxy handshake failed: {}", e));
+                return None;
+            }
+        };
+        client_async_tls_with_config(request, stream, None, connector).await
+    } else {
+        connect_async_tls_with_config(request, None, false, connector).await
+    };
+
+    match result {
+        Ok((ws, response)) => {
+            if let Some(extensions) = handshake::negotiated_extensions(&response) {
+                tx.log(LogLevel::Debug, "ws", format!("Server extensions: {}", extensions));
+            }
+            if let Some(protocol) = handshake::negotiated_subprotocol(&response) {
+                tx.log(LogLevel::Debug, "ws", format!("Subprotocol: {}", protocol));
+            }
+            Some(ws)
+        }
+        Err(e) => {
+            tx.log(LogLevel::Error, "ws", format!("WebSocket failed: {}", e));
+            None
+        }
+    }
+}
+
+fn log_dropped<T>(tx: &EventSender, meter: &mut StatsMeter, backlog: &mut Backlog<T>, side: &str) {
+    // Sans file, chaque message serait un warning : le total part au retour du côté tombé
+    if !backlog.is_enabled() {
+        return;
+    }
+    let dropped = backlog.take_dropped();
+    meter.record_dropped(dropped);
+    if dropped > 0 {
+        tx.log(LogLevel::Warn, "tunnel", format!("{} backlog full, dropped {} message(s)", side, dropped));
+    }
+}
+
+/// Un message qui ne peut pas passer : un warning, et une perte de plus au compteur.
+fn drop_message(tx: &EventSender, meter: &mut StatsMeter, target: &'static str, reason: String) {
+    tx.log(LogLevel::Warn, target, reason);
+    meter.record_dropped(1);
+}
+
+/// `write_all` qui dit, en cas d'erreur, combien d'octets de la frame sont déjà partis.
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> Result<(), usize> {
+    let mut written = 0;
+    while written < frame.len() {
+        match writer.write(&frame[written..]).await {
+            Ok(0) | Err(_) => return Err(written),
+            Ok(n) => written += n,
+        }
+    }
+    Ok(())
+}
+
+/// Dernier relevé d'une session, avec le bilan de ce qui n'est pas passé.
+fn report_session_end(tx: &EventSender, meter: &mut StatsMeter) {
+    let stats = meter.tick(Instant::now());
+    let _ = tx.send(ProxyEvent::Stats(stats));
+    if !stats.faults.is_empty() {
+        tx.log(LogLevel::Warn, "tunnel", format!("Session faults: {}", stats.faults.summary()));
+    }
+}
+
+/// Au retour d'un côté, ce qui a été perdu faute de file.
+fn log_lost<T>(tx: &EventSender, meter: &mut StatsMeter, backlog: &mut Backlog<T>, side: &str) {
+    let lost = backlog.take_dropped();
+    meter.record_dropped(lost);
+    if lost > 0 {
+        tx.log(LogLevel::Warn, "tunnel", format!("Lost {} message(s) while {} was down", lost, side));
+    }
+}
+
+/// Pourquoi le côté WebSocket n'a pas pu être établi.
+#[derive(Debug, PartialEq)]
+enum WsFailure {
+    /// Injoignable, timeout... : une nouvelle tentative peut passer
+    Transient,
+    /// Le relais a refusé l'authentification, ou ne parle aucune de nos versions du protocole
+    Refused,
+}
+
+/// Le socket sous le WebSocket, TLS ou non.
+fn ws_socket(ws: &WsStream) -> Option<&TcpStream> {
+    match ws.get_ref() {
+        MaybeTlsStream::Plain(stream) => Some(stream),
+        MaybeTlsStream::Rustls(stream) => Some(stream.get_ref().0),
+        _ => None,
+    }
+}
+
+/// Établit le côté WebSocket : on compose `ws_url`, ou en mode serveur on attend le prochain client.
+async fn connect_ws(
+    config: &ProxyConfig,
+    listener: Option<&TcpListener>,
+    tx: &EventSender,
+) -> Result<(WsWrite, WsRead), WsFailure> {
+    let mut ws_stream = match listener {
+        Some(listener) => accept_ws(config, listener, tx).await?,
+        None => dial_ws(config, tx).await?,
+    };
+    if let Some((idle, socket)) = config.timeouts.keepalive().zip(ws_socket(&ws_stream)) {
+        local::set_keepalive(socket, idle);
+    }
+    tx.log(LogLevel::Info, "ws", "[OK] WebSocket Connected");
+
+    let mut auth_config = config.auth.clone();
+    if config.negotiate_version {
+        let _ = tx.send(ProxyEvent::Status("Negotiating protocol version...".to_string()));
+        match version::negotiate(&mut ws_stream, version::REPLY_TIMEOUT).await {
+            Ok(chosen) => {
+                tx.log(LogLevel::Info, "ws", format!("[OK] Relay protocol version {}", chosen));
+                // L'authentification annonce la version retenue, pas celle des réglages
+                auth_config.protocol_version = chosen;
+            }
+            Err(version::VersionError::Incompatible(reason)) => {
+                tx.log(LogLevel::Error, "ws", format!("Incompatible relay: {}", reason));
+                let _ = tx.send(ProxyEvent::Error(format!("Incompatible relay: {}", reason)));
+                return Err(WsFailure::Refused);
+            }
+            Err(e) => {
+                tx.log(LogLevel::Error, "ws", format!("Version negotiation failed: {}", e));
+                let _ = tx.send(ProxyEvent::Error(format!("Version negotiation failed: {}", e)));
+                return Err(WsFailure::Transient);
+            }
+        }
+    }
+
+    if auth_config.enabled {
+        let _ = tx.send(ProxyEvent::Status("Authenticating...".to_string()));
+        if let Err(e) = auth::authenticate(&mut ws_stream, &auth_config, auth::ACK_TIMEOUT).await {
+            tx.log(LogLevel::Error, "ws", format!("Authentication failed: {}", e));
+            let _ = tx.send(ProxyEvent::Error(format!("Auth failed: {}", e)));
+            return Err(match e {
+                auth::AuthError::Rejected(_) => WsFailure::Refused,
+                auth::AuthError::Failed(_) => WsFailure::Transient,
+            });
+        }
+        tx.log(LogLevel::Info, "ws", "[OK] Authenticated");
+    }
+    Ok(ws_stream.split())
+}
+
+async fn accept_ws(config: &ProxyConfig, listener: &TcpListener, tx: &EventSender) -> Result<WsStream, WsFailure> {
+    tx.log(LogLevel::Info, "ws", format!(
+        "Waiting for a WebSocket client on {}...",
+        tunnel::ws_listen_addr(&config.ws_url)
+    ));
+    let (stream, peer) = match listener.accept().await {
+        Ok(accepted) => accepted,
+        Err(e) => {
+            tx.log(LogLevel::Error, "ws", format!("WebSocket accept failed: {}", e));
+            return Err(WsFailure::Transient);
+        }
+    };
+    let _ = tx.send(ProxyEvent::Log(
+        LogRecord::new(LogLevel::Info, "ws", "WebSocket client connected").field("peer", peer),
+    ));
+    // Un client qui ouvre le TCP sans jamais faire l'upgrade ne bloque pas le port
+    let connect_timeout = config.timeouts.connect();
+    match tokio::time::timeout(connect_timeout, accept_async(MaybeTlsStream::Plain(stream))).await {
+        Ok(Ok(ws)) => Ok(ws),
+        Ok(Err(e)) => {
+            tx.log(LogLevel::Error, "ws", format!("WebSocket upgrade failed: {}", e));
+            Err(WsFailure::Transient)
+        }
+        Err(_) => {
+            tx.log(LogLevel::Warn, "ws", format!(
+                "WebSocket upgrade timed out after {}s",
+                connect_timeout.as_secs()
+            ));
+            Err(WsFailure::Transient)
+        }
+    }
+}
+
+async fn dial_ws(config: &ProxyConfig, tx: &EventSender) -> Result<WsStream, WsFailure> {
+    tx.log(LogLevel::Info, "ws", format!("Connecting to WebSocket at {}...", config.ws_url));
+    let url = match Url::parse(&config.ws_url) {
+        Ok(u) => u,
+        Err(e) => {
+            tx.log(LogLevel::Error, "ws", format!("Invalid URL: {}", e));
+            return Err(WsFailure::Transient);
+        }
+    };
+
+    let connect_timeout = config.timeouts.connect();
+    match tokio::time::timeout(connect_timeout, open_websocket(config, &url, tx)).await {
+        Ok(Some(ws)) => Ok(ws),
+        Ok(None) => Err(WsFailure::Transient),
+        Err(_) => {
+            let message = format!("WebSocket connect timed out after {}s", connect_timeout.as_secs());
+            tx.log(LogLevel::Warn, "ws", message.clone());
+            let _ = tx.send(ProxyEvent::Status(message));
+            Err(WsFailure::Transient)
+        }
+    }
+}
+
+/// Connecte le côté TCP : on compose `tcp_addr`, ou en mode écoute on attend le prochain client.
+async fn connect_tcp(
+    config: &ProxyConfig,
+    listener: Option<&LocalListener>,
+    tx: &EventSender,
+    room_code: Option<&str>,
+) -> Option<(LocalRead, LocalWrite)> {
+    let result = match listener {
+        Some(listener) => {
+            tx.log(LogLevel::Info, "tcp", format!("Waiting for a TCP client on {}...", config.tcp_addr));
+            listener.accept(config.timeouts.keepalive()).await.map(|(read, write, peer)| {
+                let record = LogRecord::new(LogLevel::Info, "tcp", "TCP client connected").field("peer", &peer);
+                let _ = tx.send(ProxyEvent::Log(record));
+                (read, write)
+            })
+        }
+        None => {
+            tx.log(LogLevel::Info, "tcp", format!("Connecting to TCP Server at {}...", config.tcp_addr));
+            let connect_timeout = config.timeouts.connect();
+            let connect = local::connect(&config.tcp_addr, config.timeouts.keepalive());
+            match tokio::time::timeout(connect_timeout, connect).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let message = format!("TCP connect timed out after {}s", connect_timeout.as_secs());
+                    tx.log(LogLevel::Warn, "tcp", message.clone());
+                    let _ = tx.send(ProxyEvent::Status(message));
+                    return None;
+                }
+            }
+        }
+    };
+    let (tcp_read, mut tcp_write) = match result {
+        Ok(halves) => halves,
+        Err(e) => {
+            tx.log(LogLevel::Error, "tcp", format!("TCP connection failed: {}", e));
+            return None;
+        }
+    };
+
+    // La reprise de room s'adresse au serveur relais, pas à un client qui se connecte chez nous
+    if let Some(code) = room_code.filter(|_| listener.is_none()) {
+        tx.log(LogLevel::Info, "relay", format!("Attempting to resume session for Room: {}", code));
+        let sent = match tunnel::resume_handshake(code, config.tcp_framing) {
+            Ok(frame) => tcp_write.write_all(&frame).await.is_ok(),
+            Err(_) => false,
+        };
+        if !sent {
+            tx.log(LogLevel::Error, "relay", "Failed to send Handshake");
+            return None;
+        }
+    }
+
+    tx.log(LogLevel::Info, "tcp", "[OK] TCP Connected");
+    Some((tcp_read, tcp_write))
+}
+
+// Les reconnexions d'un côté tournent dans leur propre tâche pour que l'autre côté continue
+// d'être servi pendant ce temps.
+fn spawn_ws_reconnect(
+    config: &ProxyConfig,
+    listener: Option<Arc<TcpListener>>,
+    tx: &EventSender,
+    delay: Duration,
+) -> JoinHandle<Result<(WsWrite, WsRead), WsFailure>> {
+    let config = config.clone();
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        connect_ws(&config, listener.as_deref(), &tx).await
+    })
+}
+
+fn spawn_tcp_reconnect(
+    config: &ProxyConfig,
+    listener: Option<Arc<LocalListener>>,
+    tx: &EventSender,
+    room_code: Option<String>,
+    delay: Duration,
+) -> JoinHandle<Option<(LocalRead, LocalWrite)>> {
+    let config = config.clone();
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        connect_tcp(&config, listener.as_deref(), &tx, room_code.as_deref()).await
+    })
+}
+
+fn describe_network(network: &NetworkConditions) -> String {
+    format!(
+        "Network conditions: WS → TCP {}, TCP → WS {}",
+        network.ws_to_tcp.describe(),
+        network.tcp_to_ws.describe()
+    )
+}
+
+/// Passe à la tentative suivante et l'annonce ; `None` quand il faut abandonner.
+fn next_retry(backoff: &mut Backoff, side: &str, tx: &EventSender) -> Option<Duration> {
+    match backoff.next_delay() {
+        Some(delay) => {
+            let _ = tx.send(ProxyEvent::Reconnecting(side.to_string()));
+            let _ = tx.send(ProxyEvent::Status(format!("{} lost. {}", side, backoff.status())));
+            if !delay.is_zero() {
+                tx.log(LogLevel::Info, "tunnel", format!("Retrying in {}s...", delay.as_secs()));
+            }
+            Some(delay)
+        }
+        None => {
+            tx.log(LogLevel::Error, "tunnel", format!(
+                "{} still down after {} attempt(s), giving up",
+                side,
+                backoff.attempts()
+            ));
+            None
+        }
+    }
+}
+
+/// Fin d'une session : la dernière room connue, et s'il vaut la peine de recommencer.
+struct SessionEnd {
+    room_code: Option<String>,
+    retry: bool,
+    /// Les deux côtés ont été là : la session a marché, les tentatives repartent de zéro
+    established: bool,
+    /// Relancée par le chien de garde, auto-reconnect ou pas
+    restart: bool,
+}
+
+impl SessionEnd {
+    /// Avant que la session ne tourne : connexion ratée, port pris, arrêt demandé...
+    fn early(room_code: Option<String>, retry: bool) -> Self {
+        Self {
+            room_code,
+            retry,
+            established: false,
+            restart: false,
+        }
+    }
+}
+
+/// Ce qu'un tour de la boucle laisse à traiter après le select : arrêter la session, ou
+/// reconnecter un côté. Un même tour peut en produire plusieurs (un WebSocket qui tombe au
+/// milieu d'un lot TCP qui déclenche un kick) : les fins de session passent d'abord.
+enum Exit {
+    Shutdown,
+    /// Aucune donnée depuis `timeouts.idle` : on ferme sans retenter
+    Idle(Duration),
+    /// Plus rien ne passe depuis `timeouts.watchdog` : la session est relancée
+    Wedged(Duration),
+    /// Le relais nous met dehors
+    Kicked(String),
+    /// Reconnexion refusée, ou tentatives épuisées
+    GaveUp,
+    /// La gateway a refusé la session : se reconnecter renverrait la même réponse
+    WsRefused(String),
+    WsLost(String),
+    TcpLost(String),
+}
+
+impl Exit {
+    fn rank(&self) -> u8 {
+        match self {
+            Exit::Shutdown => 0,
+            Exit::Idle(_) => 1,
+            Exit::Wedged(_) => 2,
+            Exit::Kicked(_) => 3,
+            Exit::GaveUp => 4,
+            Exit::WsRefused(_) | Exit::WsLost(_) => 5,
+            Exit::TcpLost(_) => 6,
+        }
+    }
+}
+
+/// Fait tourner une session. Si un seul côté tombe (et que l'auto-reconnect est actif), il est
+/// reconnecté sans toucher à l'autre ; la fonction ne rend la main qu'à l'arrêt, ou si la
+/// connexion initiale échoue.
+async fn run_proxy_logic(
+    config: &ProxyConfig,
+    tx: EventSender,
+    room_code: Option<String>,
+    signals: &mut Signals,
+    recorder: &mut Option<Recorder>,
+) -> SessionEnd {
+    // Comme le port TCP en mode écoute, celui du WebSocket reste ouvert toute la session
+    let ws_listener = match config.ws_mode {
+        WsMode::Connect => None,
+        WsMode::Listen => {
+            let addr = tunnel::ws_listen_addr(&config.ws_url);
+            match TcpListener::bind(addr).await {
+                Ok(listener) => {
+                    let _ = tx.send(ProxyEvent::Status("Waiting for WebSocket client...".to_string()));
+                    Some(Arc::new(listener))
+                }
+                Err(e) => {
+                    tx.log(LogLevel::Error, "tcp", format!("Cannot listen on {}: {}", addr, e));
+                    return SessionEnd::early(room_code, true);
+                }
+            }
+        }
+    };
+    let ws = tokio::select! {
+        ws = connect_ws(config, ws_listener.as_deref(), &tx) => ws,
+        _ = signals.shutdown.changed() => return SessionEnd::early(room_code, false),
+    };
+    let ws = match ws {
+        Ok(ws) => ws,
+        Err(failure) => {
+            return SessionEnd::early(room_code, failure == WsFailure::Transient);
+        }
+    };
+    if config.tcp_mode == TcpMode::Udp {
+        return run_udp_session(config, tx, room_code, ws.0, ws.1, signals).await;
+    }
+    if matches!(config.tcp_mode, TcpMode::Multiplex | TcpMode::Socks5) {
+        return run_mux_session(config, tx, room_code, ws.0, ws.1, signals).await;
+    }
+    // En mode écoute le port reste ouvert toute la session : un client qui revient n'attend pas un re-bind
+    let listener = match config.tcp_mode {
+        TcpMode::Connect | TcpMode::Udp | TcpMode::Multiplex | TcpMode::Socks5 => None,
+        TcpMode::Listen => match LocalListener::bind(&config.tcp_addr).await {
+            Ok(listener) => {
+                let _ = tx.send(ProxyEvent::Status("Waiting for TCP client...".to_string()));
+                Some(Arc::new(listener))
+            }
+            Err(e) => {
+                tx.log(LogLevel::Error, "tcp", format!("Cannot listen on {}: {}", config.tcp_addr, e));
+                return SessionEnd::early(room_code, true);
+            }
+        },
+    };
+    // Un client peut ne jamais venir : STOP doit pouvoir interrompre l'attente
+    let tcp = tokio::select! {
+        tcp = connect_tcp(config, listener.as_deref(), &tx, room_code.as_deref()) => tcp,
+        _ = signals.shutdown.changed() => None,
+    };
+    let Some(tcp) = tcp else {
+        return SessionEnd::early(room_code, true);
+    };
+    tx.log(LogLevel::Info, "tunnel", "[OK] Tunnel active.");
+
+    let sides = Sides {
+        ws_listener,
+        listener,
+        ws,
+        tcp,
+    };
+    let mut session = Session::new(config, tx, room_code, sides, signals, recorder);
+    let mut pause_open = true;
+    let mut directions_open = true;
+    let mut rates_open = true;
+    let mut network_open = true;
+    let ping_enabled = config.ping_interval_secs > 0;
+    let mut ping_timer = tokio::time::interval(Duration::from_secs(config.ping_interval_secs.max(1)));
+    let mut stats_timer = tokio::time::interval(STATS_INTERVAL);
+
+    loop {
+        let now = Instant::now();
+        let ws_paced = session.ws_to_tcp_ready > now;
+        let tcp_paced = session.tcp_to_ws_ready > now;
+        let next_ready = match (ws_paced, tcp_paced) {
+            (true, true) => session.ws_to_tcp_ready.min(session.tcp_to_ws_ready),
+            (true, false) => session.ws_to_tcp_ready,
+            _ => session.tcp_to_ws_ready,
+        };
+        let chaos_due = [session.ws_to_tcp_chaos.next_due(), session.tcp_to_ws_chaos.next_due()];
+        let chaos_due = chaos_due.into_iter().flatten().min();
+        let paused = session.paused;
+        let ws_held = session.hold && session.held.ws_to_tcp;
+        let tcp_held = session.hold && session.held.tcp_to_ws;
+        let ws_reading = session.ws_read.is_some() && !ws_paced && !paused && !ws_held;
+        let tcp_reading = session.tcp_read.is_some() && !tcp_paced && !paused && !tcp_held;
+        let ws_reconnecting = session.ws_reconnect.is_some();
+        let tcp_reconnecting = session.tcp_reconnect.is_some();
+        let pinging = ping_enabled && session.ws_write.is_some();
+        let read_len = session.tcp_read_len;
+        // Les futures n'empruntent que leur champ : une fois l'un d'eux prêt, son bras a toute la session
+        let Session {
+            ws_read,
+            tcp_read,
+            tcp_frames,
+            ws_reconnect,
+            tcp_reconnect,
+            ..
+        } = &mut session;
+
+        tokio::select! {
+            // WebSocket -> TCP
+            // En pause on ne lit plus du tout : les buffers se remplissent et la contre-pression
+            // remonte naturellement jusqu'aux deux pairs, sans fermer les sockets.
+            msg = async { ws_read.as_mut().unwrap().next().await }, if ws_reading => session.on_ws_message(msg).await,
+
+            // TCP -> WebSocket
+            // Lu directement dans le décodeur : les messages en sortent sans copie jusqu'au WebSocket
+            result = async { tcp_read.as_mut().unwrap().read_buf(&mut tcp_frames.read_buffer(read_len)).await }, if tcp_reading => {
+                session.on_tcp_read(result).await;
+            }
+
+            result = async { ws_reconnect.as_mut().unwrap().await }, if ws_reconnecting => {
+                session.on_ws_reconnect(result).await;
+            }
+
+            result = async { tcp_reconnect.as_mut().unwrap().await }, if tcp_reconnecting => {
+                session.on_tcp_reconnect(result).await;
+            }
+
+            changed = signals.pause.changed(), if pause_open => match changed {
+                Ok(()) => session.set_paused(*signals.pause.borrow_and_update()),
+                Err(_) => pause_open = false,
+            },
+
+            changed = signals.directions.changed(), if directions_open => match changed {
+                Ok(()) => session.set_held(*signals.directions.borrow_and_update()),
+                Err(_) => directions_open = false,
+            },
+
+            Some(request) = signals.relay.recv() => session.on_relay_request(request).await,
+
+            // Messages retenus par les conditions simulées, arrivés à échéance
+            _ = tokio::time::sleep_until(chaos_due.unwrap_or(now).into()), if chaos_due.is_some() => {
+                session.on_chaos_due().await;
+            }
+
+            changed = signals.network.changed(), if network_open => match changed {
+                Ok(()) => session.set_network(*signals.network.borrow_and_update()),
+                Err(_) => network_open = false,
+            },
+
+            changed = signals.rates.changed(), if rates_open => match changed {
+                Ok(()) => session.set_rates(*signals.rates.borrow_and_update()),
+                Err(_) => rates_open = false,
+            },
+
+            _ = signals.shutdown.changed() => session.exits.push(Exit::Shutdown),
+
+            // En pause ou bridé on ne lit plus le WebSocket : les pongs attendent, ce n'est pas une panne
+            _ = ping_timer.tick(), if pinging => session.ping(!paused && !ws_paced && !ws_held).await,
+
+            _ = stats_timer.tick() => session.on_stats(),
+
+            _ = tokio::time::sleep_until(next_ready.into()), if ws_paced || tcp_paced => {}
+        }
+
+        if !session.settle().await {
+            break;
+        }
+    }
+    session.finish()
+}
+
+/// Les deux côtés d'une session qui démarre, et les ports qu'elle garde ouverts pour les reconnexions.
+struct Sides {
+    ws_listener: Option<Arc<TcpListener>>,
+    listener: Option<Arc<LocalListener>>,
+    ws: (WsWrite, WsRead),
+    tcp: (LocalRead, LocalWrite),
+}
+
+/// L'état d'une session Connect ou Listen : chaque bras du select de `run_proxy_logic` en est une
+/// méthode, et ce qu'il perd attend dans `exits` jusqu'à [`Session::settle`].
+struct Session<'a> {
+    config: &'a ProxyConfig,
+    tx: EventSender,
+    recorder: &'a mut Option<Recorder>,
+    ws_listener: Option<Arc<TcpListener>>,
+    listener: Option<Arc<LocalListener>>,
+    ws_write: Option<WsWrite>,
+    ws_read: Option<WsRead>,
+    tcp_read: Option<LocalRead>,
+    tcp_write: Option<LocalWrite>,
+    ws_reconnect: Option<JoinHandle<Result<(WsWrite, WsRead), WsFailure>>>,
+    tcp_reconnect: Option<JoinHandle<Option<(LocalRead, LocalWrite)>>>,
+    ws_backoff: Backoff,
+    tcp_backoff: Backoff,
+    // Ce qui arrive pendant qu'un côté est tombé attend ici (les plus vieux partent si ça déborde)
+    to_tcp: Backlog<Vec<u8>>,
+    to_ws: Backlog<Message>,
+    buffer_size: usize,
+    tcp_frames: FrameDecoder,
+    codec: PayloadCodec,
+    max_frame: usize,
+    chunks: Option<Reassembler>,
+    room_code: Option<String>,
+    retry: bool,
+    restart: bool,
+    // La pause survit aux reconnexions
+    paused: bool,
+    // Un sens suspendu n'est plus lu (Hold), ou ses messages sont jetés (Drop) et comptés ici
+    held: PausedDirections,
+    hold: bool,
+    dropped_to_tcp: usize,
+    dropped_to_ws: usize,
+    // Chaque direction a son propre seau : quand l'une est à sec, on arrête juste de lire
+    // de son côté, l'autre continue d'avancer dans le select. Comme la pause, les débits
+    // changés en cours de route survivent aux reconnexions.
+    start: Instant,
+    ws_to_tcp_bucket: Option<TokenBucket>,
+    tcp_to_ws_bucket: Option<TokenBucket>,
+    ws_to_tcp_ready: Instant,
+    tcp_to_ws_ready: Instant,
+    tcp_read_len: usize,
+    // Conditions réseau simulées : les messages concernés passent par une file à échéances
+    ws_to_tcp_chaos: Chaos<(Vec<u8>, usize)>,
+    tcp_to_ws_chaos: Chaos<(Message, usize)>,
+    fanout: Fanout,
+    pings: PingTracker,
+    meter: StatsMeter,
+    // Dernières lectures de chaque côté, et dernier payload dans un sens ou l'autre
+    last_ws_read: Instant,
+    last_tcp_read: Instant,
+    last_payload: Instant,
+    exits: Vec<Exit>,
+}
+
+impl<'a> Session<'a> {
+    fn new(
+        config: &'a ProxyConfig,
+        tx: EventSender,
+        room_code: Option<String>,
+        sides: Sides,
+        signals: &mut Signals,
+        recorder: &'a mut Option<Recorder>,
+    ) -> Self {
+        let buffer = config.reconnect_buffer;
+        let buffer_size = config
+            .read_buffer_size
+            .clamp(*READ_BUFFER_RANGE.start(), *READ_BUFFER_RANGE.end());
+        tx.log(LogLevel::Debug, "tunnel", format!("read buffer: {} bytes", buffer_size));
+        let codec = PayloadCodec::new(config);
+        if codec.cipher.is_some() {
+            tx.log(LogLevel::Debug, "tunnel", "End-to-end encryption on");
+        }
+        if codec.compress {
+            tx.log(LogLevel::Debug, "tunnel", "Payload compression on");
+        }
+        let max_frame = config.max_frame_size;
+        if max_frame > 0 {
+            let frame = max_frame.max(chunk::MIN_FRAME);
+            tx.log(LogLevel::Debug, "tunnel", format!("Chunking WebSocket messages above {} bytes", frame));
+        }
+
+        let paused = *signals.pause.borrow_and_update();
+        let held = *signals.directions.borrow_and_update();
+        let _ = tx.send(ProxyEvent::Status(session_status(paused, held)));
+        let _ = tx.send(ProxyEvent::Connected);
+
+        let start = Instant::now();
+        let (ws_to_tcp_rate, tcp_to_ws_rate) = *signals.rates.borrow_and_update();
+        let tcp_to_ws_bucket = TokenBucket::new(tcp_to_ws_rate, start);
+        let network = *signals.network.borrow_and_update();
+        if network.is_active() {
+            tx.log(LogLevel::Warn, "tunnel", describe_network(&network));
+        }
+        let fanout = Fanout::start(&config.fanout, config.timeouts.keepalive(), &tx);
+
+        Self {
+            config,
+            recorder,
+            ws_listener: sides.ws_listener,
+            listener: sides.listener,
+            ws_write: Some(sides.ws.0),
+            ws_read: Some(sides.ws.1),
+            tcp_read: Some(sides.tcp.0),
+            tcp_write: Some(sides.tcp.1),
+            ws_reconnect: None,
+            tcp_reconnect: None,
+            ws_backoff: Backoff::new(config.max_retries),
+            tcp_backoff: Backoff::new(config.max_retries),
+            to_tcp: Backlog::new(buffer.max_messages, buffer.max_bytes()),
+            to_ws: Backlog::new(buffer.max_messages, buffer.max_bytes()),
+            buffer_size,
+            tcp_frames: FrameDecoder::new(config.tcp_framing, *READ_BUFFER_RANGE.end()),
+            codec,
+            max_frame,
+            // Ce qu'un message recollé peut peser : le plus gros message TCP, plus l'en-tête du codec
+            chunks: (max_frame > 0).then(|| Reassembler::new(*READ_BUFFER_RANGE.end() + 1024)),
+            room_code,
+            retry: true,
+            restart: false,
+            paused,
+            held,
+            hold: config.pause_mode == PauseMode::Hold,
+            dropped_to_tcp: 0,
+            dropped_to_ws: 0,
+            start,
+            ws_to_tcp_bucket: TokenBucket::new(ws_to_tcp_rate, start),
+            tcp_read_len: throttle::read_len(tcp_to_ws_bucket.as_ref(), buffer_size),
+            tcp_to_ws_bucket,
+            ws_to_tcp_ready: start,
+            tcp_to_ws_ready: start,
+            ws_to_tcp_chaos: Chaos::new(network.ws_to_tcp),
+            tcp_to_ws_chaos: Chaos::new(network.tcp_to_ws),
+            fanout,
+            pings: PingTracker::default(),
+            meter: StatsMeter::new(start),
+            last_ws_read: start,
+            last_tcp_read: start,
+            last_payload: start,
+            exits: Vec::new(),
+            tx,
+        }
+    }
+
+    fn ws_lost(&mut self, reason: String) {
+        self.exits.push(Exit::WsLost(reason));
+    }
+
+    fn tcp_lost(&mut self, reason: String) {
+        self.exits.push(Exit::TcpLost(reason));
+    }
+
+    fn drop_message(&mut self, target: &'static str, reason: String) {
+        drop_message(&self.tx, &mut self.meter, target, reason);
+    }
+
+    async fn on_ws_message(&mut self, msg: Option<Result<Message, tungstenite::Error>>) {
+        if let Some(Ok(_)) = &msg {
+            self.last_ws_read = Instant::now();
+        }
+        match msg {
+            Some(Ok(Message::Pong(payload))) => {
+                if let Some(rtt) = self.pings.on_pong(&payload, Instant::now()) {
+                    let _ = self.tx.send(ProxyEvent::Latency(rtt));
+                }
+            }
+            // tungstenite répond lui-même aux pings (le pong part à la lecture suivante)
+            Some(Ok(Message::Ping(_) | Message::Frame(_))) => {}
+            Some(Ok(Message::Close(frame))) => {
+                let reason = tunnel::describe_close(frame.as_ref());
+                if tunnel::close_allows_reconnect(frame.as_ref()) {
+                    self.ws_lost(reason);
+                } else {
+                    self.exits.push(Exit::WsRefused(reason));
+                }
+            }
+            Some(Ok(message)) => self.forward_ws_message(message).await,
+            Some(Err(e)) => self.ws_lost(format!("WebSocket read error: {}", e)),
+            None => self.ws_lost("WebSocket stream ended without a close handshake".to_string()),
+        }
+    }
+
+    async fn forward_ws_message(&mut self, message: Message) {
+        let config = self.config;
+        let is_text = message.is_text();
+        let mut data = message.into_data();
+        if let Some(chunks) = &mut self.chunks {
+            let whole = if is_text {
+                Err("chunked tunnel expects binary frames".to_string())
+            } else {
+                chunks.push(&data).map_err(|e| e.to_string())
+            };
+            match whole {
+                Ok(Some(whole)) => data = whole.into(),
+                // La suite arrive dans les prochaines frames
+                Ok(None) => return,
+                Err(e) => {
+                    self.drop_message("ws", format!("Dropping WebSocket message: {}", e));
+                    return;
+                }
+            }
+        }
+        if self.codec.is_active() {
+            if self.codec.compress {
+                self.meter.record_wire(data.len());
+            }
+            match self.codec.decode(is_text, &data) {
+                Ok(payload) => data = payload.into(),
+                Err(e) => {
+                    self.drop_message("ws", format!("Dropping WebSocket message: {}", e));
+                    return;
+                }
+            }
+        }
+        if self.held.ws_to_tcp {
+            self.dropped_to_tcp += 1;
+            return;
+        }
+        let (route, header) = fanout::route(&config.fanout, &data);
+        data = data.slice(header..);
+        if let Route::Unknown(key) = route {
+            self.drop_message("fanout", format!("Dropping WebSocket message: no target {}", key));
+            return;
+        }
+        if data.is_empty() {
+            return;
+        }
+        self.last_payload = self.last_ws_read;
+        if config.inspect {
+            let frame = CapturedFrame::new(Direction::WsToTcp, &data, config.inspect_max_bytes, is_text);
+            let _ = self.tx.send(ProxyEvent::Frame(frame));
+        }
+        record(self.recorder, &self.tx, Direction::WsToTcp, &data);
+        if let Some(damaged) = self.ws_to_tcp_chaos.corrupt(&data) {
+            data = damaged.into();
+        }
+        let frame = match config.tcp_framing.encode(&data) {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.drop_message("ws", format!("Dropping WebSocket message: {}", e));
+                return;
+            }
+        };
+        match route {
+            Route::Primary { also_targets: true } => self.fanout.send_all(&frame, &self.tx),
+            // Pour une cible seulement : le serveur principal n'en saura rien
+            Route::Target(index) => {
+                self.fanout.send(index, &frame, &self.tx);
+                return;
+            }
+            _ => {}
+        }
+        let size = data.len();
+        match &mut self.tcp_write {
+            // Écrit plus tard, par le bras des échéances
+            _ if self.ws_to_tcp_chaos.is_active() => {
+                self.ws_to_tcp_chaos.admit((frame, size), Instant::now());
+                if let Some(bucket) = &mut self.ws_to_tcp_bucket {
+                    self.ws_to_tcp_ready = bucket.consume(size, Instant::now());
+                }
+            }
+            // Écrit dans le bras du select : tant qu'un serveur TCP lent n'a pas tout pris,
+            // le WebSocket n'est plus lu et la contre-pression remonte jusqu'à la gateway
+            Some(writer) => {
+                if let Err(written) = write_frame(writer, &frame).await {
+                    self.meter.record_write_error(written);
+                    self.to_tcp.push(frame, size);
+                    self.tcp_lost("TCP write error".to_string());
+                } else {
+                    let _ = writer.flush().await;
+                    self.meter.record_down(size);
+                    if let Some(bucket) = &mut self.ws_to_tcp_bucket {
+                        self.ws_to_tcp_ready = bucket.consume(size, Instant::now());
+                    }
+                }
+            }
+            None => {
+                self.to_tcp.push(frame, size);
+                if self.to_tcp.len() == 1 {
+                    self.tx.log(LogLevel::Warn, "tcp", "Queueing messages until TCP is back");
+                }
+                log_dropped(&self.tx, &mut self.meter, &mut self.to_tcp, "TCP");
+            }
+        }
+    }
+
+    async fn on_tcp_read(&mut self, result: std::io::Result<usize>) {
+        let n = match result {
+            Ok(0) => {
+                self.tcp_lost("TCP server closed connection".to_string());
+                return;
+            }
+            Ok(n) => n,
+            Err(e) => {
+                self.tcp_lost(format!("TCP read error: {}", e));
+                return;
+            }
+        };
+        self.last_tcp_read = Instant::now();
+        self.last_payload = self.last_tcp_read;
+        loop {
+            match self.tcp_frames.next_message() {
+                Ok(Some(message)) => self.forward_tcp_message(message).await,
+                Ok(None) => break,
+                Err(e) => {
+                    self.tcp_lost(format!("TCP stream out of sync: {}", e));
+                    break;
+                }
+            }
+        }
+        if let Some(bucket) = &mut self.tcp_to_ws_bucket {
+            self.tcp_to_ws_ready = bucket.consume(n, Instant::now());
+        }
+    }
+
+    async fn forward_tcp_message(&mut self, message: Bytes) {
+        let config = self.config;
+        let inspection = tunnel::inspect_tcp_chunk(&message, &config.control);
+        for _ in 0..inspection.control_messages {
+            self.tx.log(LogLevel::Debug, "relay", "Control message detected");
+        }
+        let forwarded = inspection.forwarded(&message);
+        for control in inspection.messages {
+            self.on_control(control);
+        }
+        let Some(message) = forwarded else {
+            return;
+        };
+        if self.held.tcp_to_ws {
+            self.dropped_to_ws += 1;
+            return;
+        }
+        let message = self.tcp_to_ws_chaos.corrupt(&message).map_or(message, Bytes::from);
+
+        let ws_message = if self.codec.is_active() {
+            match self.codec.encode(&message) {
+                Ok(frame) => {
+                    if self.codec.compress {
+                        self.meter.record_wire(frame.len());
+                    }
+                    Message::Binary(frame.into())
+                }
+                Err(e) => {
+                    self.drop_message("tcp", format!("Dropping TCP message: {}", e));
+                    return;
+                }
+            }
+        } else if self.chunks.is_some() {
+            // Le drapeau de découpage impose le binaire
+            Message::Binary(message.clone())
+        } else {
+            let ws_message = tunnel::ws_message_for(message.clone());
+            if !ws_message.is_text() {
+                self.meter.record_binary_fallback();
+            }
+            ws_message
+        };
+        if config.inspect {
+            let is_text = ws_message.is_text();
+            let frame = CapturedFrame::new(Direction::TcpToWs, &message, config.inspect_max_bytes, is_text);
+            let _ = self.tx.send(ProxyEvent::Frame(frame));
+        }
+        record(self.recorder, &self.tx, Direction::TcpToWs, &message);
+
+        match &mut self.ws_write {
+            _ if self.tcp_to_ws_chaos.is_active() => {
+                self.tcp_to_ws_chaos.admit((tunnel::detach(ws_message), message.len()), Instant::now());
+            }
+            // `send` = `feed` + `flush` : pas besoin de flush en plus derrière
+            Some(writer) => match send_ws(writer, ws_message, self.max_frame).await {
+                Ok(()) => self.meter.record_up(message.len()),
+                Err(e) => {
+                    self.meter.record_write_error(0);
+                    self.meter.record_dropped(1);
+                    self.ws_lost(format!("WebSocket send error: {}", e));
+                    // La suite du lot part dans la file
+                    self.ws_write = None;
+                }
+            },
+            None => {
+                self.to_ws.push(tunnel::detach(ws_message), message.len());
+                if self.to_ws.len() == 1 {
+                    self.tx.log(LogLevel::Warn, "ws", "Queueing messages until WebSocket is back");
+                }
+                log_dropped(&self.tx, &mut self.meter, &mut self.to_ws, "WebSocket");
+            }
+        }
+    }
+
+    fn on_control(&mut self, control: ControlMessage) {
+        let tx = &self.tx;
+        match control {
+            ControlMessage::Room(code) => {
+                let _ = tx.send(ProxyEvent::RoomCode(code.clone()));
+                tx.log(LogLevel::Info, "relay", format!("Room ID confirmed: {}", code));
+                self.room_code = Some(code);
+            }
+            ControlMessage::Error(message) => {
+                tx.log(LogLevel::Error, "relay", format!("Relay error: {}", message));
+                let _ = tx.send(ProxyEvent::RelayError(message));
+            }
+            ControlMessage::PeerJoined(peer) => {
+                tx.log(LogLevel::Info, "relay", format!("Peer joined: {}", peer));
+                let _ = tx.send(ProxyEvent::PeerJoined(peer));
+            }
+            ControlMessage::PeerLeft(peer) => {
+                tx.log(LogLevel::Info, "relay", format!("Peer left: {}", peer));
+                let _ = tx.send(ProxyEvent::PeerLeft(peer));
+            }
+            ControlMessage::Kick(reason) => self.exits.push(Exit::Kicked(reason)),
+            ControlMessage::Joined(code) => {
+                tx.log(LogLevel::Info, "relay", format!("Joined room {}", code));
+                let _ = tx.send(ProxyEvent::Joined(code.clone()));
+                // C'est désormais cette room qu'on redemande après une coupure
+                self.room_code = Some(code);
+            }
+            ControlMessage::JoinFailed(reason) => {
+                tx.log(LogLevel::Warn, "relay", format!("Join refused: {}", reason));
+                let _ = tx.send(ProxyEvent::JoinFailed(reason));
+            }
+            ControlMessage::Unknown(kind) => {
+                let message = format!("Ignoring unknown control message type '{}'", kind);
+                tx.log(LogLevel::Warn, "relay", message);
+            }
+        }
+    }
+
+    /// Le côté revenu n'est de nouveau "connecté" que si l'autre est là aussi.
+    fn restored(&mut self) {
+        if self.ws_write.is_some() && self.tcp_write.is_some() {
+            let _ = self.tx.send(ProxyEvent::Status(session_status(self.paused, self.held)));
+            let _ = self.tx.send(ProxyEvent::Connected);
+        }
+    }
+
+    async fn on_ws_reconnect(&mut self, result: Result<Result<(WsWrite, WsRead), WsFailure>, JoinError>) {
+        self.ws_reconnect = None;
+        match result {
+            Ok(Ok((mut writer, reader))) => {
+                let (mut flushed, mut failed) = (0, None);
+                while let Some((message, size)) = self.to_ws.pop() {
+                    if let Err(e) = send_ws(&mut writer, message, self.max_frame).await {
+                        self.meter.record_write_error(0);
+                        self.meter.record_dropped(1);
+                        failed = Some(e);
+                        break;
+                    }
+                    self.meter.record_up(size);
+                    flushed += 1;
+                }
+                if flushed > 0 {
+                    let message = format!("Flushed {} queued message(s) to WebSocket", flushed);
+                    self.tx.log(LogLevel::Info, "ws", message);
+                }
+                log_lost(&self.tx, &mut self.meter, &mut self.to_ws, "WebSocket");
+                // Le nouveau socket est déjà mort : rien d'annoncé, `settle` relance avec le backoff en cours
+                if let Some(e) = failed {
+                    self.ws_lost(format!("WebSocket send error: {}", e));
+                    return;
+                }
+                self.ws_write = Some(writer);
+                self.ws_read = Some(reader);
+                self.last_ws_read = Instant::now();
+                self.ws_backoff.reset();
+                self.pings = PingTracker::default();
+                self.tx.log(LogLevel::Info, "ws", "WebSocket restored");
+                self.restored();
+            }
+            Ok(Err(WsFailure::Refused)) => self.exits.push(Exit::GaveUp),
+            _ => {
+                if !self.retry_ws() {
+                    self.exits.push(Exit::GaveUp);
+                }
+            }
+        }
+    }
+
+    async fn on_tcp_reconnect(&mut self, result: Result<Option<(LocalRead, LocalWrite)>, JoinError>) {
+        self.tcp_reconnect = None;
+        match result {
+            Ok(Some((reader, mut writer))) => {
+                let (mut flushed, mut failed) = (0, false);
+                while let Some((frame, size)) = self.to_tcp.pop() {
+                    if let Err(written) = write_frame(&mut writer, &frame).await {
+                        self.meter.record_write_error(written);
+                        self.meter.record_dropped(1);
+                        failed = true;
+                        break;
+                    }
+                    self.meter.record_down(size);
+                    flushed += 1;
+                }
+                if flushed > 0 {
+                    self.tx.log(LogLevel::Info, "tcp", format!("Flushed {} queued message(s) to TCP", flushed));
+                }
+                log_lost(&self.tx, &mut self.meter, &mut self.to_tcp, "TCP");
+                // Comme côté WebSocket : pas de "TCP restored" pour une connexion déjà perdue
+                if failed {
+                    self.tcp_lost("TCP write error".to_string());
+                    return;
+                }
+                self.tcp_read = Some(reader);
+                self.tcp_write = Some(writer);
+                self.last_tcp_read = Instant::now();
+                self.tcp_backoff.reset();
+                self.tx.log(LogLevel::Info, "tcp", "TCP restored");
+                self.restored();
+            }
+            _ => {
+                if !self.retry_tcp() {
+                    self.exits.push(Exit::GaveUp);
+                }
+            }
+        }
+    }
+
+    /// Programme la prochaine tentative vers la gateway ; `false` quand elles sont épuisées.
+    fn retry_ws(&mut self) -> bool {
+        let Some(delay) = next_retry(&mut self.ws_backoff, "WebSocket", &self.tx) else {
+            return false;
+        };
+        let listener = self.ws_listener.clone();
+        self.ws_reconnect = Some(spawn_ws_reconnect(self.config, listener, &self.tx, delay));
+        true
+    }
+
+    fn retry_tcp(&mut self) -> bool {
+        let Some(delay) = next_retry(&mut self.tcp_backoff, "TCP", &self.tx) else {
+            return false;
+        };
+        let (listener, room_code) = (self.listener.clone(), self.room_code.clone());
+        self.tcp_reconnect = Some(spawn_tcp_reconnect(self.config, listener, &self.tx, room_code, delay));
+        true
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if !paused {
+            let now = Instant::now();
+            (self.last_ws_read, self.last_tcp_read, self.last_payload) = (now, now, now);
+        }
+        self.tx.log(LogLevel::Info, "tunnel", if paused { "Tunnel paused" } else { "Tunnel resumed" }.to_string());
+        let _ = self.tx.send(ProxyEvent::Status(session_status(paused, self.held)));
+    }
+
+    fn set_held(&mut self, held: PausedDirections) {
+        let previous = std::mem::replace(&mut self.held, held);
+        let hold = self.hold;
+        let sides = [
+            ("WS → TCP", previous.ws_to_tcp, held.ws_to_tcp, &mut self.dropped_to_tcp),
+            ("TCP → WS", previous.tcp_to_ws, held.tcp_to_ws, &mut self.dropped_to_ws),
+        ];
+        for (name, was, is, dropped) in sides {
+            let message = match (was, is) {
+                (false, true) if hold => format!("{} paused, holding messages", name),
+                (false, true) => format!("{} paused, dropping messages", name),
+                (true, false) => match std::mem::take(dropped) {
+                    0 => format!("{} resumed", name),
+                    n => format!("{} resumed, {} message(s) dropped", name, n),
+                },
+                _ => continue,
+            };
+            self.tx.log(LogLevel::Info, "tunnel", message);
+        }
+        // Comme après la pause générale, le silence voulu ne compte pas
+        let now = Instant::now();
+        (self.last_ws_read, self.last_tcp_read, self.last_payload) = (now, now, now);
+        let _ = self.tx.send(ProxyEvent::Status(session_status(self.paused, held)));
+    }
+
+    async fn on_relay_request(&mut self, request: RelayRequest) {
+        let framing = self.config.tcp_framing;
+        let frame = match &request {
+            RelayRequest::Join(code) => {
+                self.tx.log(LogLevel::Info, "relay", format!("Asking the relay to join room {}", code));
+                tunnel::join_request(code, framing)
+            }
+            RelayRequest::NewRoom => {
+                self.tx.log(LogLevel::Info, "relay", "Asking the relay for a new room code");
+                tunnel::new_room_request(framing)
+            }
+        };
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.tx.log(LogLevel::Error, "relay", format!("Cannot send relay request: {}", e));
+                return;
+            }
+        };
+        let size = frame.len();
+        match &mut self.tcp_write {
+            Some(writer) => {
+                if let Err(written) = write_frame(writer, &frame).await {
+                    self.meter.record_write_error(written);
+                    self.to_tcp.push(frame, size);
+                    self.tcp_lost("TCP write error".to_string());
+                }
+            }
+            // Part avec la file quand le TCP revient
+            None => self.to_tcp.push(frame, size),
+        }
+    }
+
+    async fn on_chaos_due(&mut self) {
+        let now = Instant::now();
+        let mut tcp_failed = false;
+        while let Some((frame, size)) = self.ws_to_tcp_chaos.pop_due(now) {
+            let Some(writer) = self.tcp_write.as_mut().filter(|_| !tcp_failed) else {
+                self.to_tcp.push(frame, size);
+                log_dropped(&self.tx, &mut self.meter, &mut self.to_tcp, "TCP");
+                continue;
+            };
+            if let Err(written) = write_frame(writer, &frame).await {
+                self.meter.record_write_error(written);
+                self.to_tcp.push(frame, size);
+                self.tcp_lost("TCP write error".to_string());
+                tcp_failed = true;
+            } else {
+                let _ = writer.flush().await;
+                self.meter.record_down(size);
+            }
+        }
+        let mut ws_failed = false;
+        while let Some((message, size)) = self.tcp_to_ws_chaos.pop_due(now) {
+            let Some(writer) = self.ws_write.as_mut().filter(|_| !ws_failed) else {
+                self.to_ws.push(message, size);
+                log_dropped(&self.tx, &mut self.meter, &mut self.to_ws, "WebSocket");
+                continue;
+            };
+            match send_ws(writer, message, self.max_frame).await {
+                Ok(()) => self.meter.record_up(size),
+                Err(e) => {
+                    self.meter.record_write_error(0);
+                    self.meter.record_dropped(1);
+                    self.ws_lost(format!("WebSocket send error: {}", e));
+                    ws_failed = true;
+                }
+            }
+        }
+    }
+
+    fn set_network(&mut self, network: NetworkConditions) {
+        self.ws_to_tcp_chaos.set_conditions(network.ws_to_tcp);
+        self.tcp_to_ws_chaos.set_conditions(network.tcp_to_ws);
+        self.tx.log(LogLevel::Info, "tunnel", describe_network(&network));
+    }
+
+    fn set_rates(&mut self, (ws_to_tcp_rate, tcp_to_ws_rate): Rates) {
+        // Une dette accumulée à l'ancien débit ne doit pas bloquer le nouveau
+        let now = Instant::now();
+        self.ws_to_tcp_bucket = TokenBucket::new(ws_to_tcp_rate, now);
+        self.tcp_to_ws_bucket = TokenBucket::new(tcp_to_ws_rate, now);
+        self.ws_to_tcp_ready = now;
+        self.tcp_to_ws_ready = now;
+        self.tcp_read_len = throttle::read_len(self.tcp_to_ws_bucket.as_ref(), self.buffer_size);
+        self.tx.log(LogLevel::Info, "tunnel", format!(
+            "Rate limits: WS → TCP {}, TCP → WS {}",
+            throttle::describe(ws_to_tcp_rate),
+            throttle::describe(tcp_to_ws_rate)
+        ));
+    }
+
+    /// `reading` : le WebSocket est lu, un pong manquant veut donc dire quelque chose.
+    async fn ping(&mut self, reading: bool) {
+        if reading && self.pings.unanswered() >= MISSED_PONGS_LIMIT {
+            let unanswered = self.pings.unanswered();
+            self.ws_lost(format!("WebSocket keepalive timed out ({} pings unanswered)", unanswered));
+            return;
+        }
+        let payload = self.pings.next_ping(Instant::now());
+        if let Some(writer) = &mut self.ws_write
+            && let Err(e) = writer.send(Message::Ping(payload.into())).await
+        {
+            self.ws_lost(format!("WebSocket ping error: {}", e));
+        }
+    }
+
+    fn on_stats(&mut self) {
+        let now = Instant::now();
+        let _ = self.tx.send(ProxyEvent::Stats(self.meter.tick(now)));
+        // En pause rien n'est lu : les délais ne courent pas
+        if self.paused {
+            return;
+        }
+        let timeouts = &self.config.timeouts;
+        if let Some(limit) = timeouts.read() {
+            let ws_held = self.hold && self.held.ws_to_tcp;
+            let tcp_held = self.hold && self.held.tcp_to_ws;
+            if self.ws_read.is_some() && !ws_held && now.duration_since(self.last_ws_read) >= limit {
+                self.ws_lost(format!("WebSocket read timed out after {}s", limit.as_secs()));
+            }
+            if self.tcp_read.is_some() && !tcp_held && now.duration_since(self.last_tcp_read) >= limit {
+                self.tcp_lost(format!("TCP read timed out after {}s", limit.as_secs()));
+            }
+        }
+        let silent = now.duration_since(self.last_payload);
+        if let Some(limit) = timeouts.idle().filter(|limit| !self.held.any() && silent >= *limit) {
+            self.exits.push(Exit::Idle(limit));
+        }
+        // `last_payload` n'avance qu'avec des données : tant qu'il vaut `start`, rien n'a
+        // encore circulé et le silence n'a rien d'anormal (lobby, pair pas encore là)
+        let up = self.ws_read.is_some() && self.tcp_read.is_some() && !self.held.any();
+        let up = up && self.last_payload > self.start;
+        if let Some(limit) = timeouts.watchdog().filter(|limit| up && silent >= *limit) {
+            self.exits.push(Exit::Wedged(limit));
+        }
+    }
+
+    /// Traite ce que le tour a laissé dans `exits` ; `false` quand la session s'arrête.
+    async fn settle(&mut self) -> bool {
+        let mut exits = std::mem::take(&mut self.exits);
+        exits.sort_by_key(Exit::rank);
+        for exit in exits {
+            match exit {
+                Exit::Shutdown => {
+                    if let Some(writer) = &mut self.ws_write {
+                        self.tx.log(LogLevel::Debug, "ws", "Closing WebSocket...");
+                        if let Err(e) = writer.send(Message::Close(None)).await {
+                            self.tx.log(LogLevel::Error, "ws", format!("WebSocket close error: {}", e));
+                        }
+                        let _ = writer.flush().await;
+                    }
+                    // FIN côté serveur plutôt qu'un RST au drop : il voit une fin de flux propre
+                    if let Some(writer) = &mut self.tcp_write
+                        && let Err(e) = writer.shutdown().await
+                    {
+                        self.tx.log(LogLevel::Error, "tcp", format!("TCP shutdown error: {}", e));
+                    }
+                    return false;
+                }
+                Exit::Idle(limit) => {
+                    let message = format!("No traffic for {}s, closing the tunnel", limit.as_secs());
+                    self.tx.log(LogLevel::Warn, "tunnel", message);
+                    self.close_ws().await;
+                    self.retry = false;
+                    return false;
+                }
+                Exit::Wedged(limit) => {
+                    self.tx.log(LogLevel::Warn, "tunnel", format!(
+                        "Watchdog: no data in either direction for {}s, restarting the tunnel",
+                        limit.as_secs()
+                    ));
+                    self.close_ws().await;
+                    if let Some(writer) = &mut self.tcp_write {
+                        let _ = writer.shutdown().await;
+                    }
+                    self.restart = true;
+                    return false;
+                }
+                Exit::Kicked(reason) => {
+                    self.tx.log(LogLevel::Warn, "relay", format!("Kicked by the relay: {}", reason));
+                    let _ = self.tx.send(ProxyEvent::Kicked(reason));
+                    self.close_ws().await;
+                    self.retry = false;
+                    return false;
+                }
+                Exit::GaveUp => {
+                    self.retry = false;
+                    return false;
+                }
+                Exit::WsRefused(reason) => {
+                    self.drop_ws(reason);
+                    self.tx.log(LogLevel::Warn, "ws", "Not reconnecting: the gateway refused this session");
+                    self.retry = false;
+                    return false;
+                }
+                Exit::WsLost(reason) => {
+                    self.drop_ws(reason);
+                    if !self.config.auto_reconnect {
+                        return false;
+                    }
+                    if self.ws_reconnect.is_none() && !self.retry_ws() {
+                        self.retry = false;
+                        return false;
+                    }
+                }
+                Exit::TcpLost(reason) => {
+                    self.tx.log(LogLevel::Warn, "tcp", reason);
+                    self.tcp_read = None;
+                    self.tcp_write = None;
+                    self.tcp_frames.clear();
+                    if !self.config.auto_reconnect {
+                        return false;
+                    }
+                    if self.tcp_reconnect.is_none() && !self.retry_tcp() {
+                        self.retry = false;
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    async fn close_ws(&mut self) {
+        if let Some(writer) = &mut self.ws_write {
+            let _ = writer.send(Message::Close(None)).await;
+        }
+    }
+
+    fn drop_ws(&mut self, reason: String) {
+        self.tx.log(LogLevel::Warn, "ws", reason);
+        self.ws_write = None;
+        self.ws_read = None;
+        if let Some(chunks) = &mut self.chunks {
+            chunks.clear();
+        }
+    }
+
+    fn finish(mut self) -> SessionEnd {
+        // Ce qui attendait encore un côté ne partira plus
+        let (to_tcp, to_ws) = (&mut self.to_tcp, &mut self.to_ws);
+        self.meter.record_dropped(to_tcp.take_dropped() + to_tcp.len() + to_ws.take_dropped() + to_ws.len());
+        report_session_end(&self.tx, &mut self.meter);
+        if let Some(handle) = self.ws_reconnect {
+            handle.abort();
+        }
+        if let Some(handle) = self.tcp_reconnect {
+            handle.abort();
+        }
+        SessionEnd {
+            room_code: self.room_code,
+            retry: self.retry,
+            established: true,
+            restart: self.restart,
+        }
+    }
+}
+
+/// Une écriture ratée (disque plein...) arrête l'enregistrement, pas le tunnel.
+fn record(recorder: &mut Option<Recorder>, tx: &EventSender, dir: Direction, data: &[u8]) {
+    if let Some(Err(e)) = recorder.as_mut().map(|file| file.record(dir, data)) {
+        tx.log(LogLevel::Error, "record", format!("Recording stopped: {}", e));
+        *recorder = None;
+    }
+}
+
+pub(crate) async fn run_replay(
+    config: ProxyConfig,
+    frames: Vec<RecordedFrame>,
+    tx: EventSender,
+    mut signals: Signals,
+) {
+    replay_frames(&config, frames, &tx, &mut signals.shutdown).await;
+    let _ = tx.send(ProxyEvent::Stopped);
+}
+
+async fn replay_frames(
+    config: &ProxyConfig,
+    frames: Vec<RecordedFrame>,
+    tx: &EventSender,
+    shutdown: &mut watch::Receiver<bool>,
+) {
+    let listener = match config.tcp_mode {
+        TcpMode::Connect => None,
+        TcpMode::Listen => match LocalListener::bind(&config.tcp_addr).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                let _ = tx.send(ProxyEvent::Error(format!("Cannot listen on {}: {}", config.tcp_addr, e)));
+                return;
+            }
+        },
+        TcpMode::Udp | TcpMode::Multiplex | TcpMode::Socks5 => {
+            let _ = tx.send(ProxyEvent::Error("Replay needs the Connect or Listen mode".to_string()));
+            return;
+        }
+    };
+    let tcp = tokio::select! {
+        tcp = connect_tcp(config, listener.as_ref(), tx, None) => tcp,
+        _ = shutdown.changed() => None,
+    };
+    let Some((mut tcp_read, mut tcp_write)) = tcp else {
+        return;
+    };
+
+    // Seul ce qui allait vers le serveur TCP se rejoue ; le temps passé à attendre la première
+    // frame à l'enregistrement ne compte pas
+    let frames: Vec<_> = frames.into_iter().filter(|frame| frame.dir == Direction::WsToTcp).collect();
+    let offset = frames.first().map_or(Duration::ZERO, |frame| frame.at);
+    let _ = tx.send(ProxyEvent::Status(format!("Replaying {} frames", frames.len())));
+
+    let start = Instant::now();
+    let mut received = 0;
+    let mut buffer = vec![0u8; 16 * 1024];
+    for frame in &frames {
+        let due = start + frame.at.saturating_sub(offset);
+        // Les réponses du serveur sont lues (et jetées) pour qu'il ne bloque pas en écriture
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(due.into()) => break,
+                result = tcp_read.read(&mut buffer) => match result {
+                    Ok(0) | Err(_) => {
+                        tx.log(LogLevel::Warn, "record", "TCP connection closed during replay");
+                        return;
+                    }
+                    Ok(n) => received += n,
+                },
+                _ = shutdown.changed() => {
+                    tx.log(LogLevel::Warn, "record", "Replay interrupted");
+                    return;
+                }
+            }
+        }
+        let bytes = match config.tcp_framing.encode(&frame.data) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tx.log(LogLevel::Warn, "record", format!("Skipping recorded frame: {}", e));
+                continue;
+            }
+        };
+        if let Err(e) = tcp_write.write_all(&bytes).await {
+            tx.log(LogLevel::Error, "record", format!("TCP write error during replay: {}", e));
+            return;
+        }
+        if config.inspect {
+            let captured = CapturedFrame::new(Direction::WsToTcp, &frame.data, config.inspect_max_bytes, false);
+            let _ = tx.send(ProxyEvent::Frame(captured));
+        }
+    }
+    tx.log(LogLevel::Info, "record", format!(
+        "[OK] Replay finished: {} frames sent, {} bytes received",
+        frames.len(),
+        received
+    ));
+    let _ = tcp_write.shutdown().await;
+}
+
+/// Session en mode UDP : un socket local, un datagramme = une frame binaire (voir `udp`).
+/// Sans connexion à préserver côté UDP, une perte du WebSocket relance toute la session ;
+/// pas de bridage de débit non plus, un datagramme ne se découpe pas.
+async fn run_udp_session(
+    config: &ProxyConfig,
+    tx: EventSender,
+    room_code: Option<String>,
+    mut ws_write: WsWrite,
+    mut ws_read: WsRead,
+    signals: &mut Signals,
+) -> SessionEnd {
+    let socket = match UdpSocket::bind(&config.tcp_addr).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            tx.log(LogLevel::Error, "udp", format!("Cannot listen for UDP on {}: {}", config.tcp_addr, e));
+            return SessionEnd::early(room_code, true);
+        }
+    };
+    tx.log(LogLevel::Info, "udp", format!("[OK] Listening for UDP datagrams on {}", config.tcp_addr));
+
+    let mut peers = udp::Peers::default();
+    let mut buffer = vec![0u8; udp::MAX_DATAGRAM];
+    let mut retry = true;
+    let mut paused = *signals.pause.borrow_and_update();
+    let mut pause_open = true;
+    let _ = tx.send(ProxyEvent::Status(active_status(paused).to_string()));
+    let _ = tx.send(ProxyEvent::Connected);
+
+    let mut pings = PingTracker::default();
+    let ping_enabled = config.ping_interval_secs > 0;
+    let mut ping_timer = tokio::time::interval(Duration::from_secs(config.ping_interval_secs.max(1)));
+    let mut meter = StatsMeter::new(Instant::now());
+    let mut stats_timer = tokio::time::interval(STATS_INTERVAL);
+
+    loop {
+        let mut ws_lost: Option<String> = None;
+        let mut ws_refused = false;
+
+        tokio::select! {
+            msg = ws_read.next(), if !paused => {
+                match msg {
+                    Some(Ok(Message::Pong(payload))) => {
+                        if let Some(rtt) = pings.on_pong(&payload, Instant::now()) {
+                            let _ = tx.send(ProxyEvent::Latency(rtt));
+                        }
+                    }
+                    Some(Ok(Message::Ping(_) | Message::Frame(_))) => {}
+                    Some(Ok(Message::Close(frame))) => {
+                        ws_lost = Some(tunnel::describe_close(frame.as_ref()));
+                        ws_refused = !tunnel::close_allows_reconnect(frame.as_ref());
+                    }
+                    Some(Ok(message)) => {
+                        let data = message.into_data();
+                        let (peer, payload) = match udp::unwrap(&data) {
+                            Ok(datagram) => datagram,
+                            Err(e) => {
+                                drop_message(&tx, &mut meter, "ws", format!("Dropping WebSocket message: {}", e));
+                                continue;
+                            }
+                        };
+                        let Some(addr) = peers.addr(peer) else {
+                            let reason = format!("Dropping datagram for unknown UDP peer {}", peer);
+                            drop_message(&tx, &mut meter, "udp", reason);
+                            continue;
+                        };
+                        if config.inspect {
+                            let frame = CapturedFrame::new(Direction::WsToTcp, payload, config.inspect_max_bytes, false);
+                            let _ = tx.send(ProxyEvent::Frame(frame));
+                        }
+                        match socket.send_to(payload, addr).await {
+                            Ok(_) => meter.record_down(payload.len()),
+                            Err(e) => {
+                                meter.record_write_error(0);
+                                meter.record_dropped(1);
+                                tx.log(LogLevel::Error, "udp", format!("UDP send to {} failed: {}", addr, e));
+                            }
+                        }
+                    }
+                    Some(Err(e)) => ws_lost = Some(format!("WebSocket read error: {}", e)),
+                    None => ws_lost = Some("WebSocket stream ended without a close handshake".to_string()),
+                }
+            }
+
+            result = socket.recv_from(&mut buffer), if !paused => {
+                match result {
+                    Ok((n, addr)) => {
+                        let Some((peer, new)) = peers.id(addr) else {
+                            let message = format!("Too many UDP peers, dropping datagram from {}", addr);
+                            drop_message(&tx, &mut meter, "udp", message);
+                            continue;
+                        };
+                        if new {
+                            let record = LogRecord::new(LogLevel::Debug, "udp", "New UDP peer").field("peer", peer);
+                            let _ = tx.send(ProxyEvent::Log(record.field("addr", addr)));
+                        }
+                        if config.inspect {
+                            let frame = CapturedFrame::new(Direction::TcpToWs, &buffer[..n], config.inspect_max_bytes, false);
+                            let _ = tx.send(ProxyEvent::Frame(frame));
+                        }
+                        match ws_write.send(Message::Binary(udp::wrap(peer, &buffer[..n]).into())).await {
+                            Ok(()) => meter.record_up(n),
+                            Err(e) => {
+                                meter.record_write_error(0);
+                                meter.record_dropped(1);
+                                ws_lost = Some(format!("WebSocket send error: {}", e));
+                            }
+                        }
+                    }
+                    // Typiquement un ICMP "port unreachable" renvoyé par un envoi précédent
+                    Err(e) => {
+                        tx.log(LogLevel::Error, "udp", format!("UDP receive error: {}", e));
+                    }
+                }
+            }
+
+            changed = signals.pause.changed(), if pause_open => {
+                if changed.is_err() {
+                    pause_open = false;
+                    continue;
+                }
+                paused = *signals.pause.borrow_and_update();
+                tx.log(LogLevel::Info, "tunnel", if paused { "Tunnel paused" } else { "Tunnel resumed" }.to_string());
+                let _ = tx.send(ProxyEvent::Status(active_status(paused).to_string()));
+            }
+
+            _ = signals.shutdown.changed() => {
+                tx.log(LogLevel::Debug, "ws", "Closing WebSocket...");
+                if let Err(e) = ws_write.send(Message::Close(None)).await {
+                    tx.log(LogLevel::Error, "ws", format!("WebSocket close error: {}", e));
+                }
+                break;
+            }
+
+            _ = ping_timer.tick(), if ping_enabled => {
+                if !paused && pings.unanswered() >= MISSED_PONGS_LIMIT {
+                    ws_lost = Some(format!("WebSocket keepalive timed out ({} pings unanswered)", pings.unanswered()));
+                } else {
+                    let payload = pings.next_ping(Instant::now());
+                    if let Err(e) = ws_write.send(Message::Ping(payload.into())).await {
+                        ws_lost = Some(format!("WebSocket ping error: {}", e));
+                    }
+                }
+            }
+
+            _ = stats_timer.tick() => {
+                let _ = tx.send(ProxyEvent::Stats(meter.tick(Instant::now())));
+            }
+        }
+
+        if let Some(reason) = ws_lost {
+            tx.log(LogLevel::Warn, "ws", reason);
+            if ws_refused {
+                tx.log(LogLevel::Warn, "ws", "Not reconnecting: the gateway refused this session");
+                retry = false;
+            }
+            break;
+        }
+    }
+
+    report_session_end(&tx, &mut meter);
+    SessionEnd {
+        room_code,
+        retry,
+        established: true,
+        restart: false,
+    }
+}
+
+/// Un client du mode multiplexé : sa moitié écriture, et la tâche qui lit l'autre.
+struct MuxStream {
+    write: LocalWrite,
+    reader: AbortHandle,
+}
+
+/// Lit un client jusqu'à sa fin de flux ; `None` signale la fermeture à la session.
+async fn read_mux_stream(
+    id: StreamId,
+    mut read: LocalRead,
+    buffer_size: usize,
+    out: mpsc::Sender<(StreamId, Option<Vec<u8>>)>,
+) {
+    let mut buffer = vec![0u8; buffer_size];
+    loop {
+        match read.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if out.send((id, Some(buffer[..n].to_vec()))).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+    let _ = out.send((id, None)).await;
+}
+
+/// Client SOCKS5 prêt : ses deux moitiés, son adresse et la destination qu'il a demandée.
+type SocksClient = (LocalRead, LocalWrite, String, String);
+
+/// Handshake d'un client SOCKS5, dans sa propre tâche pour qu'un client lent ne bloque pas les
+/// autres ; seuls ceux qui aboutissent reviennent à la session.
+async fn accept_socks(
+    mut read: LocalRead,
+    mut write: LocalWrite,
+    peer: String,
+    limit: Duration,
+    out: mpsc::Sender<SocksClient>,
+    tx: EventSender,
+) {
+    let record = match tokio::time::timeout(limit, socks::handshake(&mut read, &mut write)).await {
+        Ok(Ok(target)) => {
+            let _ = out.send((read, write, peer, target)).await;
+            return;
+        }
+        Ok(Err(e)) => LogRecord::new(LogLevel::Warn, "socks", format!("SOCKS5 handshake failed: {}", e)),
+        Err(_) => LogRecord::new(LogLevel::Warn, "socks", "SOCKS5 handshake timed out"),
+    };
+    let _ = tx.send(ProxyEvent::Log(record.field("peer", &peer)));
+}
+
+/// Session en mode multiplexé : on écoute, et chaque client accepté devient un stream du même
+/// WebSocket (voir `mux`). En SOCKS5, le client passe d'abord le handshake et son stream s'ouvre
+/// vers la destination demandée. Comme en UDP, une perte du WebSocket ferme tous les clients et
+/// relance la session ; pas de bridage ni de découpage en messages.
+async fn run_mux_session(
+    config: &ProxyConfig,
+    tx: EventSender,
+    room_code: Option<String>,
+    mut ws_write: WsWrite,
+    mut ws_read: WsRead,
+    signals: &mut Signals,
+) -> SessionEnd {
+    let listener = match LocalListener::bind(&config.tcp_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tx.log(LogLevel::Error, "tcp", format!("Cannot listen on {}: {}", config.tcp_addr, e));
+            return SessionEnd::early(room_code, true);
+        }
+    };
+    let socks = config.tcp_mode == TcpMode::Socks5;
+    if socks {
+        tx.log(LogLevel::Info, "socks", format!("[OK] SOCKS5 proxy listening on {}", config.tcp_addr));
+    } else {
+        tx.log(LogLevel::Info, "tcp", format!("[OK] Listening for TCP clients on {}", config.tcp_addr));
+    }
+
+    let buffer_size = config
+        .read_buffer_size
+        .clamp(*READ_BUFFER_RANGE.start(), *READ_BUFFER_RANGE.end());
+    // Borné : un client trop bavard attend plutôt que de remplir la mémoire
+    let (reads_tx, mut reads) = mpsc::channel::<(StreamId, Option<Vec<u8>>)>(64);
+    let (socks_tx, mut socks_clients) = mpsc::channel::<SocksClient>(16);
+    let mut streams: HashMap<StreamId, MuxStream> = HashMap::new();
+    let mut next_id: StreamId = 1;
+    let mut retry = true;
+    let mut paused = *signals.pause.borrow_and_update();
+    let mut pause_open = true;
+    let _ = tx.send(ProxyEvent::Status(active_status(paused).to_string()));
+    let _ = tx.send(ProxyEvent::Connected);
+
+    let mut pings = PingTracker::default();
+    let ping_enabled = config.ping_interval_secs > 0;
+    let mut ping_timer = tokio::time::interval(Duration::from_secs(config.ping_interval_secs.max(1)));
+    let mut meter = StatsMeter::new(Instant::now());
+    let mut stats_timer = tokio::time::interval(STATS_INTERVAL);
+
+    loop {
+        let mut ws_lost: Option<String> = None;
+        let mut ws_refused = false;
+        // Client à ouvrir comme stream, avec sa destination en SOCKS5
+        let mut opened: Option<(LocalRead, LocalWrite, String, Option<String>)> = None;
+
+        tokio::select! {
+            accepted = listener.accept(config.timeouts.keepalive()), if !paused => {
+                match accepted {
+                    Ok((read, write, addr)) if socks => {
+                        let limit = config.timeouts.connect();
+                        tokio::spawn(accept_socks(read, write, addr, limit, socks_tx.clone(), tx.clone()));
+                    }
+                    Ok((read, write, addr)) => opened = Some((read, write, addr, None)),
+                    Err(e) => {
+                        tx.log(LogLevel::Error, "tcp", format!("TCP accept error: {}", e));
+                    }
+                }
+            }
+
+            Some((read, write, addr, target)) = socks_clients.recv(), if !paused => {
+                opened = Some((read, write, addr, Some(target)));
+            }
+
+            msg = ws_read.next(), if !paused => {
+                match msg {
+                    Some(Ok(Message::Pong(payload))) => {
+                        if let Some(rtt) = pings.on_pong(&payload, Instant::now()) {
+                            let _ = tx.send(ProxyEvent::Latency(rtt));
+                        }
+                    }
+                    Some(Ok(Message::Ping(_) | Message::Frame(_))) => {}
+                    Some(Ok(Message::Close(frame))) => {
+                        ws_lost = Some(tunnel::describe_close(frame.as_ref()));
+                        ws_refused = !tunnel::close_allows_reconnect(frame.as_ref());
+                    }
+                    Some(Ok(message)) => {
+                        let data = message.into_data();
+                        match MuxFrame::decode(&data) {
+                            Ok(MuxFrame::Data(id, payload)) => {
+                                let Some(stream) = streams.get_mut(&id) else {
+                                    let reason = format!("Dropping data for unknown stream {}", id);
+                                    drop_message(&tx, &mut meter, "mux", reason);
+                                    continue;
+                                };
+                                if config.inspect {
+                                    let frame = CapturedFrame::new(Direction::WsToTcp, payload, config.inspect_max_bytes, false);
+                                    let _ = tx.send(ProxyEvent::Frame(frame));
+                                }
+                                match stream.write.write_all(payload).await {
+                                    Ok(()) => meter.record_down(payload.len()),
+                                    Err(e) => {
+                                        meter.record_write_error(0);
+                                        meter.record_dropped(1);
+                                        tx.log(LogLevel::Error, "mux", format!("Stream {} write error: {}", id, e));
+                                        let stream = streams.remove(&id).unwrap();
+                                        stream.reader.abort();
+                                        if let Err(e) = ws_write.send(Message::Binary(MuxFrame::Close(id).encode().into())).await {
+                                            ws_lost = Some(format!("WebSocket send error: {}", e));
+                                        }
+                                    }
+                                }
+                            }
+                            Ok(MuxFrame::Close(id)) => {
+                                if let Some(mut stream) = streams.remove(&id) {
+                                    stream.reader.abort();
+                                    let _ = stream.write.shutdown().await;
+                                    let record = LogRecord::new(LogLevel::Debug, "mux", "Stream closed by the gateway");
+                                    let _ = tx.send(ProxyEvent::Log(record.field("stream", id)));
+                                }
+                            }
+                            // Seul TrouDeVer ouvre des streams : il n'a rien vers quoi se connecter
+                            Ok(MuxFrame::Open(id) | MuxFrame::Connect(id, _)) => {
+                                let message = format!("Ignoring gateway request to open stream {}", id);
+                                tx.log(LogLevel::Warn, "mux", message);
+                            }
+                            Err(e) => {
+                                drop_message(&tx, &mut meter, "ws", format!("Dropping WebSocket message: {}", e));
+                            }
+                        }
+                    }
+                    Some(Err(e)) => ws_lost = Some(format!("WebSocket read error: {}", e)),
+                    None => ws_lost = Some("WebSocket stream ended without a close handshake".to_string()),
+                }
+            }
+
+            Some((id, data)) = reads.recv(), if !paused => {
+                let frame = match &data {
+                    Some(payload) => {
+                        if config.inspect {
+                            let frame = CapturedFrame::new(Direction::TcpToWs, payload, config.inspect_max_bytes, false);
+                            let _ = tx.send(ProxyEvent::Frame(frame));
+                        }
+                        MuxFrame::Data(id, payload)
+                    }
+                    None => {
+                        // Déjà fermé par la gateway : la fin de lecture n'est plus à signaler
+                        if streams.remove(&id).is_none() {
+                            continue;
+                        }
+                        let record = LogRecord::new(LogLevel::Debug, "mux", "Stream closed by the client");
+                        let _ = tx.send(ProxyEvent::Log(record.field("stream", id)));
+                        MuxFrame::Close(id)
+                    }
+                };
+                match ws_write.send(Message::Binary(frame.encode().into())).await {
+                    Ok(()) => meter.record_up(data.map_or(0, |payload| payload.len())),
+                    Err(e) => {
+                        meter.record_write_error(0);
+                        meter.record_dropped(1);
+                        ws_lost = Some(format!("WebSocket send error: {}", e));
+                    }
+                }
+            }
+
+            changed = signals.pause.changed(), if pause_open => {
+                if changed.is_err() {
+                    pause_open = false;
+                    continue;
+                }
+                paused = *signals.pause.borrow_and_update();
+                tx.log(LogLevel::Info, "tunnel", if paused { "Tunnel paused" } else { "Tunnel resumed" }.to_string());
+                let _ = tx.send(ProxyEvent::Status(active_status(paused).to_string()));
+            }
+
+            _ = signals.shutdown.changed() => {
+                tx.log(LogLevel::Debug, "ws", "Closing WebSocket...");
+                if let Err(e) = ws_write.send(Message::Close(None)).await {
+                    tx.log(LogLevel::Error, "ws", format!("WebSocket close error: {}", e));
+                }
+                break;
+            }
+
+            _ = ping_timer.tick(), if ping_enabled => {
+                if !paused && pings.unanswered() >= MISSED_PONGS_LIMIT {
+                    ws_lost = Some(format!("WebSocket keepalive timed out ({} pings unanswered)", pings.unanswered()));
+                } else {
+                    let payload = pings.next_ping(Instant::now());
+                    if let Err(e) = ws_write.send(Message::Ping(payload.into())).await {
+                        ws_lost = Some(format!("WebSocket ping error: {}", e));
+                    }
+                }
+            }
+
+            _ = stats_timer.tick() => {
+                let _ = tx.send(ProxyEvent::Stats(meter.tick(Instant::now())));
+            }
+        }
+
+        if let Some((read, mut write, addr, target)) = opened {
+            // Un id encore ouvert fusionnerait deux clients : plutôt refuser le nouveau
+            match mux::allocate_stream_id(&mut next_id, streams.len(), |id| streams.contains_key(&id)) {
+                None => {
+                    tx.log(LogLevel::Warn, "mux", format!("Refusing {}: every stream id is in use", addr));
+                    let _ = write.shutdown().await;
+                }
+                Some(id) => {
+                    let frame = match &target {
+                        Some(target) => MuxFrame::Connect(id, target),
+                        None => MuxFrame::Open(id),
+                    };
+                    if let Err(e) = ws_write.send(Message::Binary(frame.encode().into())).await {
+                        ws_lost = Some(format!("WebSocket send error: {}", e));
+                    } else {
+                        let record = LogRecord::new(LogLevel::Debug, "mux", "Stream opened").field("stream", id);
+                        let record = match &target {
+                            Some(target) => record.field("peer", &addr).field("target", target),
+                            None => record.field("peer", &addr),
+                        };
+                        let _ = tx.send(ProxyEvent::Log(record));
+                        let reader = tokio::spawn(read_mux_stream(id, read, buffer_size, reads_tx.clone()));
+                        streams.insert(id, MuxStream { write, reader: reader.abort_handle() });
+                    }
+                }
+            }
+        }
+
+        if let Some(reason) = ws_lost {
+            tx.log(LogLevel::Warn, "ws", reason);
+            if ws_refused {
+                tx.log(LogLevel::Warn, "ws", "Not reconnecting: the gateway refused this session");
+                retry = false;
+            }
+            break;
+        }
+    }
+
+    // Leurs streams n'existent plus de l'autre côté : les clients sont fermés proprement
+    for (_, mut stream) in streams.drain() {
+        stream.reader.abort();
+        let _ = stream.write.shutdown().await;
+    }
+    report_session_end(&tx, &mut meter);
+    SessionEnd {
+        room_code,
+        retry,
+        established: true,
+        restart: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Timeouts;
+    use crate::tunnel::TcpFraming;
+    use crossbeam_channel::unbounded;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use tokio_tungstenite::{accept_async, accept_hdr_async, connect_async};
+
+    // La branche TCP -> WS compte sur `send` pour flusher : si ça change, les messages
+    // restent dans le buffer de tungstenite jusqu'au prochain envoi.
+    #[tokio::test]
+    async fn ws_send_delivers_without_explicit_flush() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            ws.next().await.unwrap().unwrap()
+        });
+
+        let (ws, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+        let (mut ws_write, _ws_read) = ws.split();
+        ws_write.send(Message::Text("hello".into())).await.unwrap();
+
+        let received = tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .expect("message stuck in the sink buffer")
+            .unwrap();
+        assert_eq!(received, Message::Text("hello".into()));
+    }
+
+    #[tokio::test]
+    async fn tunnel_forwards_both_ways_and_keeps_control_messages() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        // WS -> TCP : préfixe de longueur
+        gateway.send(Message::Text("ping".into())).await.unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04ping");
+
+        // TCP -> WS : le message de contrôle reste côté proxy, le reste passe
+        server.write_all(br#"{"internal":true,"room":"ABCD"}"#).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        server.write_all(b"score=3").await.unwrap();
+        let forwarded = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(forwarded, Message::Text("score=3".into()));
+
+        senders.shutdown.send(true).unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert_eq!(end.room_code.as_deref(), Some("ABCD"));
+        // Arrêt propre des deux côtés : Close WebSocket, puis fin de flux TCP
+        let closing = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert!(matches!(closing, Message::Close(_)));
+        assert_eq!(server.read(&mut frame).await.unwrap(), 0);
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::RoomCode(code) if code == "ABCD")));
+    }
+
+    #[tokio::test]
+    async fn network_conditions_hold_and_duplicate_until_lifted() {
+        use crate::chaos::Conditions;
+
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let network = NetworkConditions {
+            ws_to_tcp: Conditions {
+                delay_ms: 150,
+                duplicate: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            network,
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), network);
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        let sent = Instant::now();
+        gateway.send(Message::Text("ping".into())).await.unwrap();
+        let mut frames = [0u8; 16];
+        server.read_exact(&mut frames).await.unwrap();
+        assert!(sent.elapsed() >= Duration::from_millis(150));
+        assert_eq!(&frames, b"\0\0\0\x04ping\0\0\0\x04ping");
+
+        // Levées en cours de route : le message suivant passe seul et tout de suite
+        senders.network.send(NetworkConditions::default()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        gateway.send(Message::Text("pong".into())).await.unwrap();
+        gateway.send(Message::Text("done".into())).await.unwrap();
+        server.read_exact(&mut frames).await.unwrap();
+        assert_eq!(&frames, b"\0\0\0\x04pong\0\0\0\x04done");
+
+        senders.shutdown.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn paused_direction_drops_only_its_own_traffic() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            pause_mode: PauseMode::Drop,
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        let paused = PausedDirections {
+            ws_to_tcp: true,
+            ..Default::default()
+        };
+        senders.directions.send(paused).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        gateway.send(Message::Text("lost".into())).await.unwrap();
+        // L'autre sens continue
+        server.write_all(b"back").await.unwrap();
+        let back = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(back, Message::Text("back".into()));
+
+        senders.directions.send(PausedDirections::default()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        gateway.send(Message::Text("kept".into())).await.unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04kept");
+
+        senders.shutdown.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        let logs: Vec<String> = rx
+            .try_iter()
+            .filter_map(|(_, event)| match event {
+                ProxyEvent::Log(record) => Some(record.message),
+                _ => None,
+            })
+            .collect();
+        assert!(logs.iter().any(|log| log == "WS → TCP resumed, 1 message(s) dropped"), "{:?}", logs);
+    }
+
+    #[tokio::test]
+    async fn fanout_copies_to_targets_or_routes_by_key() {
+        use crate::fanout::{FanoutConfig, Routing};
+
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let spectator = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            fanout: FanoutConfig {
+                targets: vec![spectator.local_addr().unwrap().to_string()],
+                routing: Routing::Keyed,
+            },
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        let (mut watcher, _) = spectator.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // 1 : la cible seule, 255 : tout le monde ; l'octet de routage ne part pas
+        gateway.send(Message::Binary(b"\x01spec".to_vec().into())).await.unwrap();
+        gateway.send(Message::Binary(b"\xffboth".to_vec().into())).await.unwrap();
+        let mut frames = [0u8; 16];
+        watcher.read_exact(&mut frames).await.unwrap();
+        assert_eq!(&frames, b"\0\0\0\x04spec\0\0\0\x04both");
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04both");
+
+        senders.shutdown.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Target(status) if status.connected)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_server_is_picked_from_the_address() {
+        let path = std::env::temp_dir().join(format!("troudever-proxy-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let unix_listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: format!("unix://{}", path.display()),
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = unix_listener.accept().await.unwrap();
+
+        gateway.send(Message::Text("ping".into())).await.unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04ping");
+        server.write_all(b"pong").await.unwrap();
+        let forwarded = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(forwarded, Message::Text("pong".into()));
+
+        senders.shutdown.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn session_end_reports_binary_fallbacks_and_lost_messages() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        // Pas de l'UTF-8 : part en Binary
+        server.write_all(&[0xff, 0xfe]).await.unwrap();
+        let forwarded = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(forwarded, Message::Binary(vec![0xff, 0xfe].into()));
+
+        // Le serveur ne revient pas : le message reste dans la file jusqu'à l'arrêt
+        drop(server);
+        drop(tcp_listener);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        gateway.send(Message::Text("never".into())).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        senders.shutdown.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+
+        let events: Vec<ProxyEvent> = rx.try_iter().map(|(_, event)| event).collect();
+        let faults = events
+            .iter()
+            .rev()
+            .find_map(|event| match event {
+                ProxyEvent::Stats(stats) => Some(stats.faults),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((faults.binary_fallbacks, faults.dropped), (1, 1));
+        let summary = "Session faults: 1 dropped, 0 failed writes, 1 sent as binary, 0 partial writes";
+        assert!(events.iter().any(|event| matches!(event, ProxyEvent::Log(record) if record.message == summary)));
+    }
+
+    #[tokio::test]
+    async fn tcp_bounce_keeps_websocket_and_resumes_room() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        server.write_all(br#"{"internal":true,"room":"ABCD"}"#).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(server);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Arrive pendant que TCP est tombé : doit attendre dans la file
+        gateway.send(Message::Text("queued".into())).await.unwrap();
+
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        let mut read_frame = async || {
+            let len = server.read_u32().await.unwrap() as usize;
+            let mut payload = vec![0u8; len];
+            server.read_exact(&mut payload).await.unwrap();
+            payload
+        };
+        let handshake: serde_json::Value = serde_json::from_slice(&read_frame().await).unwrap();
+        assert_eq!(handshake["request_room"], "ABCD");
+        assert_eq!(read_frame().await, b"queued");
+
+        // Le WebSocket n'a pas bougé
+        let second_ws = tokio::time::timeout(Duration::from_millis(200), ws_listener.accept()).await;
+        assert!(second_ws.is_err());
+    }
+
+    #[tokio::test]
+    async fn relay_requests_go_to_the_relay_and_replies_are_reported() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let _gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        senders.relay.send(RelayRequest::Join("WXYZ".to_string())).unwrap();
+        let len = server.read_u32().await.unwrap() as usize;
+        let mut payload = vec![0u8; len];
+        server.read_exact(&mut payload).await.unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(request["internal"], true);
+        assert_eq!(request["join"], "WXYZ");
+
+        server
+            .write_all(br#"{"internal":true,"type":"join","ok":true,"room":"WXYZ"}"#)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Joined(code) if code == "WXYZ")));
+
+        // Nouveau code à la demande : la réponse est un room code ordinaire
+        senders.relay.send(RelayRequest::NewRoom).unwrap();
+        let len = server.read_u32().await.unwrap() as usize;
+        let mut payload = vec![0u8; len];
+        server.read_exact(&mut payload).await.unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(request["new_room"], true);
+        server.write_all(br#"{"internal":true,"room":"NEWC"}"#).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        senders.shutdown.send(true).unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        // Reprise après coupure : c'est la dernière room qu'on redemande
+        assert_eq!(end.room_code.as_deref(), Some("NEWC"));
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::RoomCode(code) if code == "NEWC")));
+    }
+
+    #[test]
+    fn payload_codec_compresses_then_encrypts() {
+        let config = ProxyConfig {
+            e2e_key: "correct horse".to_string(),
+            compress: true,
+            ..Default::default()
+        };
+        let (mut codec, mut peer) = (PayloadCodec::new(&config), PayloadCodec::new(&config));
+        let state = "{\"x\":1,\"y\":2}".repeat(20);
+        let frame = codec.encode(state.as_bytes()).unwrap();
+        assert!(frame.len() < state.len());
+        assert!(peer.decode(true, &frame).is_err());
+        assert_eq!(peer.decode(false, &frame).unwrap(), state.as_bytes());
+        // Le relais ne peut ni rejouer la frame, ni la renvoyer à son auteur
+        assert!(peer.decode(false, &frame).is_err());
+        assert!(codec.decode(false, &frame).is_err());
+        assert!(!PayloadCodec::new(&ProxyConfig::default()).is_active());
+    }
+
+    #[tokio::test]
+    async fn encrypted_tunnel_only_shows_ciphertext_to_the_websocket() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            e2e_key: "correct horse".to_string(),
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        let mut peer = Cipher::new("correct horse");
+
+        // Du texte en clair est écarté, le message chiffré arrive déchiffré
+        gateway.send(Message::Text("plain".into())).await.unwrap();
+        gateway
+            .send(Message::Binary(peer.seal(b"ping").unwrap().into()))
+            .await
+            .unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04ping");
+
+        server.write_all(b"score=3").await.unwrap();
+        let sealed = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        let Message::Binary(sealed) = sealed else {
+            panic!("expected a binary frame, got {:?}", sealed);
+        };
+        assert_eq!(peer.open(&sealed).unwrap(), b"score=3");
+    }
+
+    #[tokio::test]
+    async fn oversized_messages_cross_in_chunks() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            max_frame_size: 256,
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        // Trois morceaux côté WebSocket, un seul message côté TCP
+        for frame in [&b"\x01sta"[..], b"\x01te=", b"\x0042"] {
+            gateway.send(Message::Binary(frame.to_vec().into())).await.unwrap();
+        }
+        let mut frame = [0u8; 12];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x08state=42");
+
+        let sync = vec![7u8; 600];
+        server.write_all(&sync).await.unwrap();
+        let mut reassembler = Reassembler::new(4096);
+        let whole = loop {
+            let Message::Binary(frame) = gateway.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            assert!(frame.len() <= 256);
+            if let Some(whole) = reassembler.push(&frame).unwrap() {
+                break whole;
+            }
+        };
+        assert_eq!(whole, sync);
+    }
+
+    #[tokio::test]
+    async fn policy_close_is_logged_and_not_retried() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (_server, _) = tcp_listener.accept().await.unwrap();
+
+        let frame = tokio_tungstenite::tungstenite::protocol::CloseFrame {
+            code: 1008.into(),
+            reason: "banned".into(),
+        };
+        gateway.close(Some(frame)).await.unwrap();
+
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert!(!end.retry);
+        assert!(rx.try_iter().any(
+            |(_, e)| matches!(e, ProxyEvent::Log(log) if log.message == "WebSocket closed: 1008 Policy Violation - banned")
+        ));
+    }
+
+    // Sans connector explicite, rustls (compilé sans provider) paniquait ici
+    #[tokio::test]
+    async fn wss_failure_is_logged_not_panicking() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("wss://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            drop(socket);
+        });
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        assert!(connect_ws(&config, None, &events).await.is_err());
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Log(log) if log.message.starts_with("WebSocket failed"))));
+    }
+
+    #[tokio::test]
+    async fn upgrade_carries_custom_headers_and_subprotocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+            ws_headers: vec![("Authorization".to_string(), "Bearer s3cret".to_string())],
+            ws_subprotocol: "game.v2".to_string(),
+            ..Default::default()
+        };
+        let gateway = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut seen = None;
+            // La signature (et son gros `ErrorResponse`) est imposée par tungstenite
+            #[allow(clippy::result_large_err)]
+            let callback = |request: &Request, mut response: Response| {
+                let headers = request.headers();
+                seen = Some((headers["Authorization"].clone(), headers["Sec-WebSocket-Protocol"].clone()));
+                response.headers_mut().insert("Sec-WebSocket-Protocol", "game.v2".parse().unwrap());
+                Ok(response)
+            };
+            let _ws = accept_hdr_async(socket, callback).await.unwrap();
+            seen.unwrap()
+        });
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        assert!(connect_ws(&config, None, &events).await.is_ok());
+        let (authorization, protocol) = gateway.await.unwrap();
+        assert_eq!(authorization, "Bearer s3cret");
+        assert_eq!(protocol, "game.v2");
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Log(log) if log.message == "Subprotocol: game.v2")));
+    }
+
+    #[tokio::test]
+    async fn silent_gateway_times_out_instead_of_hanging() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+            timeouts: Timeouts {
+                connect_secs: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // Accepte le TCP mais ne répond jamais à l'upgrade
+        let _gateway = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            drop(socket);
+        });
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let result = tokio::time::timeout(Duration::from_secs(3), connect_ws(&config, None, &events)).await;
+        assert_eq!(result.unwrap().err(), Some(WsFailure::Transient));
+        assert!(rx.try_iter().any(
+            |(_, e)| matches!(e, ProxyEvent::Status(msg) if msg == "WebSocket connect timed out after 1s")
+        ));
+    }
+
+    #[tokio::test]
+    async fn idle_tunnel_is_closed() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ping_interval_secs: 0,
+            timeouts: Timeouts {
+                idle_secs: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (_server, _) = tcp_listener.accept().await.unwrap();
+
+        let end = tokio::time::timeout(Duration::from_secs(4), proxy).await.unwrap().unwrap();
+        assert!(!end.retry);
+        assert!(matches!(gateway.next().await.unwrap().unwrap(), Message::Close(_)));
+        assert!(rx.try_iter().any(
+            |(_, e)| matches!(e, ProxyEvent::Log(log) if log.message == "No traffic for 1s, closing the tunnel")
+        ));
+    }
+
+    #[tokio::test]
+    async fn watchdog_restarts_a_tunnel_that_stopped_carrying_data() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ping_interval_secs: 0,
+            // Une seule tentative et pas de reconnexion : les relances ne dépendent ni de l'un ni de l'autre
+            max_retries: 1,
+            auto_reconnect: false,
+            timeouts: Timeouts {
+                watchdog_secs: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, signals) = signals((0, 0), NetworkConditions::default());
+        let tunnel = tokio::spawn(run_tunnel(config, events, None, signals));
+        let watchdog_fired = |e: &ProxyEvent| {
+            matches!(e, ProxyEvent::Log(log)
+                if log.message == "Watchdog: no data in either direction for 1s, restarting the tunnel")
+        };
+
+        for session in 0..3 {
+            let accepted = tokio::time::timeout(Duration::from_secs(5), ws_listener.accept()).await;
+            let (ws_socket, _) = accepted.unwrap().unwrap();
+            let mut gateway = accept_async(ws_socket).await.unwrap();
+            let (mut server, _) = tcp_listener.accept().await.unwrap();
+            if session == 0 {
+                // Rien n'a encore circulé : le silence ne déclenche rien
+                tokio::time::sleep(Duration::from_millis(2500)).await;
+                assert!(!rx.try_iter().any(|(_, e)| watchdog_fired(&e)));
+            }
+            gateway.send(Message::Binary(b"state".to_vec().into())).await.unwrap();
+            // Préfixe de longueur du framing par défaut, puis le payload
+            let mut frame = [0u8; 9];
+            server.read_exact(&mut frame).await.unwrap();
+            assert_eq!(&frame[4..], b"state");
+            if session == 2 {
+                break;
+            }
+            let close = tokio::time::timeout(Duration::from_secs(4), gateway.next()).await.unwrap();
+            assert!(matches!(close.unwrap().unwrap(), Message::Close(_)));
+            assert_eq!(server.read(&mut frame).await.unwrap(), 0);
+            assert!(rx.try_iter().any(|(_, e)| watchdog_fired(&e)));
+        }
+
+        senders.shutdown.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), tunnel).await.unwrap().unwrap();
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Stopped)));
+    }
+
+    #[tokio::test]
+    async fn server_mode_bridges_an_incoming_websocket() {
+        let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let ws_addr = probe.local_addr().unwrap();
+        drop(probe);
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}/", ws_addr),
+            ws_mode: WsMode::Listen,
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let client = loop {
+            match connect_async(format!("ws://{}", ws_addr)).await {
+                Ok((client, _)) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        };
+        let (mut client_write, _client_read) = client.split();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        client_write.send(Message::Text("ping".into())).await.unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04ping");
+    }
+
+    #[tokio::test]
+    async fn split_control_message_is_reassembled() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            tcp_framing: TcpFraming::Newline,
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        server.write_all(br#"{"internal":true,"#).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        server.write_all(b"\"room\":\"WXYZ\"}\nscore=3\n").await.unwrap();
+        let forwarded = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(forwarded, Message::Text("score=3".into()));
+        // Même framing dans l'autre sens
+        gateway.send(Message::Text("ping".into())).await.unwrap();
+        let mut line = [0u8; 5];
+        server.read_exact(&mut line).await.unwrap();
+        assert_eq!(&line, b"ping\n");
+
+        senders.shutdown.send(true).unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert_eq!(end.room_code.as_deref(), Some("WXYZ"));
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::RoomCode(code) if code == "WXYZ")));
+    }
+
+    #[tokio::test]
+    async fn split_control_message_is_reassembled_without_framing() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        // Deux `read()` pour un seul message de contrôle : aucun des morceaux ne part au WebSocket
+        server.write_all(br#"{"internal":true,"#).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        server.write_all(br#""room":"WXYZ"}"#).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        server.write_all(b"score=3").await.unwrap();
+        let forwarded = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(forwarded, Message::Text("score=3".into()));
+
+        senders.shutdown.send(true).unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert_eq!(end.room_code.as_deref(), Some("WXYZ"));
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::RoomCode(code) if code == "WXYZ")));
+    }
+
+    #[tokio::test]
+    async fn payload_read_with_a_control_message_is_forwarded() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&config, events, None, &mut signals, &mut None).await
+        });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        // Un seul `read()` : le message de contrôle reste au proxy, les octets du jeu passent
+        server.write_all(br#"{"internal":true,"room":"WXYZ"}score=3"#).await.unwrap();
+        let forwarded = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(forwarded, Message::Text("score=3".into()));
+
+        senders.shutdown.send(true).unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert_eq!(end.room_code.as_deref(), Some("WXYZ"));
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::RoomCode(code) if code == "WXYZ")));
+    }
+
+    #[tokio::test]
+    async fn listen_mode_accepts_successive_clients() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let free = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: free.to_string(),
+            tcp_mode: TcpMode::Listen,
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, Some("ABCD".to_string()), &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+
+        for round in ["one", "two"] {
+            let mut client = loop {
+                match TcpStream::connect(free).await {
+                    Ok(client) => break client,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            };
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            gateway.send(Message::Text(round.into())).await.unwrap();
+            // Pas de handshake de reprise vers un client : la première frame est la donnée
+            let len = client.read_u32().await.unwrap() as usize;
+            let mut payload = vec![0u8; len];
+            client.read_exact(&mut payload).await.unwrap();
+            assert_eq!(payload, round.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn gateway_ping_is_answered_not_forwarded() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ping_interval_secs: 0,
+            ..Default::default()
+        };
+
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+
+        gateway.send(Message::Ping("hb".into())).await.unwrap();
+        gateway.send(Message::Text("data".into())).await.unwrap();
+        let pong = tokio::time::timeout(Duration::from_secs(2), gateway.next()).await.unwrap();
+        assert_eq!(pong.unwrap().unwrap(), Message::Pong("hb".into()));
+
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04data");
+    }
+
+    #[tokio::test]
+    async fn auth_ack_gates_the_tunnel() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            auth: auth::AuthConfig {
+                enabled: true,
+                token: "s3cret".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Refus : pas de TCP, pas de nouvelle tentative, statut en erreur
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut rejected_signals) = signals((0, 0), NetworkConditions::default());
+        let rejected_config = config.clone();
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&rejected_config, events, None, &mut rejected_signals, &mut None).await
+        });
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let hello = gateway.next().await.unwrap().unwrap();
+        let hello: serde_json::Value = serde_json::from_str(hello.to_text().unwrap()).unwrap();
+        assert_eq!(hello["token"], "s3cret");
+        gateway
+            .send(Message::Text(r#"{"type":"auth_error","error":"bad token"}"#.into()))
+            .await
+            .unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert!(!end.retry);
+        assert!(rx.try_iter().any(
+            |(_, e)| matches!(e, ProxyEvent::Error(msg) if msg == "Auth failed: rejected by relay: bad token")
+        ));
+
+        // Accusé reçu : le tunnel s'active et forwarde
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        gateway.next().await.unwrap().unwrap();
+        gateway.send(Message::Text(r#"{"type":"auth_ok"}"#.into())).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        gateway.send(Message::Text("ping".into())).await.unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04ping");
+    }
+
+    #[tokio::test]
+    async fn version_handshake_stops_an_incompatible_relay() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            negotiate_version: true,
+            ..Default::default()
+        };
+
+        // Pas de version commune : arrêt sans nouvelle tentative, la raison dans le statut
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut refused_signals) = signals((0, 0), NetworkConditions::default());
+        let refused_config = config.clone();
+        let proxy = tokio::spawn(async move {
+            run_proxy_logic(&refused_config, events, None, &mut refused_signals, &mut None).await
+        });
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        let hello = gateway.next().await.unwrap().unwrap();
+        let hello: serde_json::Value = serde_json::from_str(hello.to_text().unwrap()).unwrap();
+        assert_eq!(hello["versions"], serde_json::json!(version::PROTOCOL_VERSIONS));
+        let refusal = r#"{"type":"version","ok":false,"reason":"client too old","supported":[2]}"#;
+        gateway.send(Message::Text(refusal.into())).await.unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(2), proxy).await.unwrap().unwrap();
+        assert!(!end.retry);
+        let expected = "Incompatible relay: client too old (relay supports 2, this build supports 1)";
+        assert!(rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Error(msg) if msg == expected)));
+
+        // Version choisie : le tunnel s'active
+        let (tx, _rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        gateway.next().await.unwrap().unwrap();
+        gateway.send(Message::Text(r#"{"type":"version","version":1}"#.into())).await.unwrap();
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        gateway.send(Message::Text("ping".into())).await.unwrap();
+        let mut frame = [0u8; 8];
+        server.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\0\0\0\x04ping");
+    }
+
+    #[tokio::test]
+    async fn udp_datagrams_are_wrapped_both_ways() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: port.to_string(),
+            tcp_mode: TcpMode::Udp,
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        // Le socket est prêt une fois l'écoute annoncée
+        while !rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Log(log) if log.message.starts_with("[OK] Listening"))) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"hello", port).await.unwrap();
+        let wrapped = loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other,
+            }
+        };
+        assert_eq!(wrapped.into_data().as_ref(), udp::wrap(0, b"hello").as_slice());
+
+        gateway.send(Message::Binary(udp::wrap(0, b"back").into())).await.unwrap();
+        let mut reply = [0u8; 16];
+        let (n, _) = tokio::time::timeout(Duration::from_secs(2), client.recv_from(&mut reply)).await.unwrap().unwrap();
+        assert_eq!(&reply[..n], b"back");
+    }
+
+    #[tokio::test]
+    async fn multiplexed_clients_share_the_websocket() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: port.to_string(),
+            tcp_mode: TcpMode::Multiplex,
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        while !rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Log(log) if log.message.starts_with("[OK] Listening"))) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mut next_frame = async || loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other.into_data().to_vec(),
+            }
+        };
+
+        let mut first = TcpStream::connect(port).await.unwrap();
+        assert_eq!(next_frame().await, MuxFrame::Open(1).encode());
+        let mut second = TcpStream::connect(port).await.unwrap();
+        assert_eq!(next_frame().await, MuxFrame::Open(2).encode());
+
+        second.write_all(b"two").await.unwrap();
+        assert_eq!(next_frame().await, MuxFrame::Data(2, b"two").encode());
+        first.shutdown().await.unwrap();
+        assert_eq!(next_frame().await, MuxFrame::Close(1).encode());
+
+        gateway.send(Message::Binary(MuxFrame::Data(2, b"back").encode().into())).await.unwrap();
+        let mut reply = [0u8; 4];
+        second.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"back");
+        gateway.send(Message::Binary(MuxFrame::Close(2).encode().into())).await.unwrap();
+        assert_eq!(second.read(&mut reply).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn socks_clients_open_streams_to_their_destination() {
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = ProxyConfig {
+            ws_url: format!("ws://{}", ws_listener.local_addr().unwrap()),
+            tcp_addr: port.to_string(),
+            tcp_mode: TcpMode::Socks5,
+            ..Default::default()
+        };
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, mut signals) = signals((0, 0), NetworkConditions::default());
+        tokio::spawn(async move { run_proxy_logic(&config, events, None, &mut signals, &mut None).await });
+
+        let (ws_socket, _) = ws_listener.accept().await.unwrap();
+        let mut gateway = accept_async(ws_socket).await.unwrap();
+        while !rx.try_iter().any(|(_, e)| matches!(e, ProxyEvent::Log(log) if log.message.starts_with("[OK] SOCKS5"))) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mut next_frame = async || loop {
+            match gateway.next().await.unwrap().unwrap() {
+                Message::Ping(_) => continue,
+                other => break other.into_data().to_vec(),
+            }
+        };
+
+        let mut client = TcpStream::connect(port).await.unwrap();
+        client.write_all(b"\x05\x01\x00\x05\x01\x00\x03\x0bexample.com\x00\x50").await.unwrap();
+        let mut replies = [0u8; 12];
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(&replies[..4], b"\x05\x00\x05\x00");
+        assert_eq!(next_frame().await, MuxFrame::Connect(1, "example.com:80").encode());
+        client.write_all(b"GET /").await.unwrap();
+        assert_eq!(next_frame().await, MuxFrame::Data(1, b"GET /").encode());
+
+        // Un client qui ne parle pas SOCKS5 n'ouvre rien
+        let mut stranger = TcpStream::connect(port).await.unwrap();
+        stranger.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(stranger.read(&mut replies).await.unwrap(), 0);
+        client.shutdown().await.unwrap();
+        assert_eq!(next_frame().await, MuxFrame::Close(1).encode());
+    }
+
+    #[tokio::test]
+    async fn replay_sends_recorded_frames_to_tcp() {
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ProxyConfig {
+            tcp_addr: tcp_listener.local_addr().unwrap().to_string(),
+            ..Default::default()
+        };
+        let frame = |ms, dir, data: &[u8]| RecordedFrame {
+            at: Duration::from_millis(ms),
+            dir,
+            data: data.to_vec(),
+        };
+        let frames = vec![
+            frame(5000, Direction::WsToTcp, b"first"),
+            frame(5010, Direction::TcpToWs, b"answer"),
+            frame(5050, Direction::WsToTcp, b"second"),
+        ];
+
+        let (tx, rx) = unbounded();
+        let events = EventSender::new(0, tx);
+        let (_senders, signals) = signals((0, 0), NetworkConditions::default());
+        let started = Instant::now();
+        let replay = tokio::spawn(run_replay(config, frames, events, signals));
+
+        let (mut server, _) = tcp_listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        let mut expected = tunnel::encode_frame(b"first").unwrap();
+        expected.extend(tunnel::encode_frame(b"second").unwrap());
+        assert_eq!(received, expected);
+        // Le décalage de 5 s avant la première frame saute, l'écart de 50 ms reste
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(50) && elapsed < Duration::from_secs(2), "{elapsed:?}");
+
+        replay.await.unwrap();
+        assert!(matches!(rx.try_iter().last(), Some((_, ProxyEvent::Stopped))));
+    }
+
+    #[test]
+    fn full_channel_drops_only_lossy_events() {
+        let (tx, rx) = crossbeam_channel::bounded(2);
+        let events = EventSender::new(0, tx);
+        let debug = |message: &str| ProxyEvent::Log(LogRecord::new(LogLevel::Debug, "test", message));
+        for i in 0..3 {
+            events.send(debug(&i.to_string())).unwrap();
+        }
+        let logs = |rx: &crossbeam_channel::Receiver<(TunnelId, ProxyEvent)>| -> Vec<String> {
+            rx.try_iter()
+                .map(|(_, event)| match event {
+                    ProxyEvent::Log(record) => record.message,
+                    _ => "other".to_string(),
+                })
+                .collect()
+        };
+        assert_eq!(logs(&rx), ["0", "1"]);
+
+        events.send(debug("3")).unwrap();
+        assert_eq!(logs(&rx), ["3", "Frontend lagging: 1 events dropped"]);
+
+        // Un événement d'état, ou un warning, attend sa place au lieu d'être perdu, sans bloquer
+        events.send(debug("4")).unwrap();
+        events.send(debug("5")).unwrap();
+        events.log(LogLevel::Warn, "test", "6");
+        events.send(ProxyEvent::Stopped).unwrap();
+        assert_eq!(logs(&rx), ["4", "5"]);
+        // Sans runtime, la file part au prochain envoi, avant lui
+        events.send(debug("7")).unwrap();
+        assert_eq!(logs(&rx), ["6", "other"]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Enregistrement du trafic tunnelé, une frame par ligne JSON :
+//! `{"t_ms":1520,"dir":"ws_to_tcp","data":"<base64>"}`, `t_ms` comptant depuis le début de
+//! l'enregistrement. Les frames `ws_to_tcp` peuvent ensuite être rejouées vers le serveur TCP
+//! (voir `TunnelBuilder::replay`) pour reproduire un bug sans le pair distant.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::inspect::Direction;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub at: Duration,
+    pub dir: Direction,
+    pub data: Vec<u8>,
+}
+
+fn dir_name(dir: Direction) -> &'static str {
+    match dir {
+        Direction::WsToTcp => "ws_to_tcp",
+        Direction::TcpToWs => "tcp_to_ws",
+    }
+}
+
+impl RecordedFrame {
+    pub fn to_line(&self) -> String {
+        json!({
+            "t_ms": self.at.as_millis() as u64,
+            "dir": dir_name(self.dir),
+            "data": STANDARD.encode(&self.data),
+        })
+        .to_string()
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        let at = value["t_ms"].as_u64().ok_or("missing t_ms")?;
+        let dir = match value["dir"].as_str() {
+            Some("ws_to_tcp") => Direction::WsToTcp,
+            Some("tcp_to_ws") => Direction::TcpToWs,
+            _ => return Err("dir must be ws_to_tcp or tcp_to_ws".to_string()),
+        };
+        let data = STANDARD
+            .decode(value["data"].as_str().ok_or("missing data")?)
+            .map_err(|e| format!("data: {}", e))?;
+        Ok(Self {
+            at: Duration::from_millis(at),
+            dir,
+            data,
+        })
+    }
+}
+
+/// Fichier d'enregistrement d'un tunnel, recréé à chaque lancement.
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, dir: Direction, data: &[u8]) -> io::Result<()> {
+        let frame = RecordedFrame {
+            at: self.start.elapsed(),
+            dir,
+            data: data.to_vec(),
+        };
+        writeln!(self.out, "{}", frame.to_line())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Lignes vides ignorées ; la première ligne invalide fait échouer tout le chargement.
+pub fn load(path: &Path) -> io::Result<Vec<RecordedFrame>> {
+    let mut frames = Vec::new();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame = RecordedFrame::parse(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", index + 1, e)))?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_line_roundtrip() {
+        let frame = RecordedFrame {
+            at: Duration::from_millis(1520),
+            dir: Direction::WsToTcp,
+            data: vec![0, 159, 146, 150],
+        };
+        let line = frame.to_line();
+        assert!(line.contains(r#""dir":"ws_to_tcp""#));
+        assert_eq!(RecordedFrame::parse(&line), Ok(frame));
+        assert!(RecordedFrame::parse(r#"{"t_ms":1,"dir":"up","data":""}"#).is_err());
+    }
+}
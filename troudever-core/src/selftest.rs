@@ -0,0 +1,206 @@
+//! Auto-test de l'installation : un serveur TCP d'écho et un endpoint WebSocket en mémoire, et
+//! le vrai tunnel entre les deux. L'endpoint envoie des sondes, le serveur TCP les renvoie, et
+//! chacune doit revenir intacte après l'aller-retour complet. Sert aussi à la CI.
+
+use crossbeam_channel::{Receiver, unbounded};
+use futures_util::{SinkExt, StreamExt};
+use std::fmt;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{WebSocketStream, accept_async};
+
+use crate::logging::LogLevel;
+use crate::tunnel::TcpFraming;
+use crate::{ProxyConfig, ProxyEvent, SHUTDOWN_GRACE, Tunnel, TunnelId};
+
+pub const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CONNECT_CHECK: &str = "Tunnel connects both sides";
+const PROBES: [&str; 3] = [
+    "Text message round trip",
+    "Binary message round trip",
+    "Large message round trip (256 KB)",
+];
+
+pub struct Check {
+    pub name: &'static str,
+    /// Durée de l'étape, ou pourquoi elle a échoué
+    pub result: Result<Duration, String>,
+}
+
+/// Les étapes jouées, dans l'ordre ; la première qui échoue arrête le test.
+#[derive(Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.len() == PROBES.len() + 1 && self.checks.iter().all(|check| check.result.is_ok())
+    }
+
+    fn pass(&mut self, name: &'static str, started: Instant) {
+        self.checks.push(Check {
+            name,
+            result: Ok(started.elapsed()),
+        });
+    }
+
+    fn fail(&mut self, name: &'static str, error: String) {
+        self.checks.push(Check {
+            name,
+            result: Err(error),
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match &check.result {
+                Ok(took) => writeln!(f, "PASS  {} ({} ms)", check.name, took.as_millis())?,
+                Err(e) => writeln!(f, "FAIL  {}: {}", check.name, e)?,
+            }
+        }
+        write!(f, "{}", if self.passed() { "Self-test passed" } else { "Self-test failed" })
+    }
+}
+
+/// Lance le test sur le runtime tokio courant ; `limit` borne l'ensemble des étapes.
+pub async fn run(limit: Duration) -> Report {
+    let mut report = Report::default();
+    let listeners = tokio::try_join!(TcpListener::bind("127.0.0.1:0"), TcpListener::bind("127.0.0.1:0"));
+    let (ws_listener, tcp_listener) = match listeners {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            report.fail(CONNECT_CHECK, format!("Cannot listen on loopback: {}", e));
+            return report;
+        }
+    };
+    let (Ok(ws_addr), Ok(tcp_addr)) = (ws_listener.local_addr(), tcp_listener.local_addr()) else {
+        report.fail(CONNECT_CHECK, "Cannot listen on loopback".to_string());
+        return report;
+    };
+    // Préfixe de longueur dans les deux sens : chaque sonde revient en un seul message
+    let config = ProxyConfig {
+        ws_url: format!("ws://{}", ws_addr),
+        tcp_addr: tcp_addr.to_string(),
+        tcp_framing: TcpFraming::LengthPrefixed,
+        ..Default::default()
+    };
+    let echo = tokio::spawn(echo(tcp_listener));
+    // Non borné : le test est court, et un canal plein ferait attendre les warnings du tunnel
+    let (tx, rx) = unbounded();
+    let tunnel = Tunnel::builder(config).events(tx).start();
+
+    let gateway = exercise(ws_listener, Instant::now() + limit, &mut report).await;
+    if let Some(Check { result: Err(e), .. }) = report.checks.last_mut()
+        && let Some(cause) = last_error(&rx)
+    {
+        e.push_str(&format!(" (tunnel: {})", cause));
+    }
+    tunnel.shutdown(SHUTDOWN_GRACE).await;
+    drop(gateway);
+    echo.abort();
+    report
+}
+
+/// Rend l'endpoint WebSocket, à garder ouvert jusqu'à l'arrêt du tunnel pour qu'il ne se
+/// reconnecte pas entre-temps.
+async fn exercise(
+    ws_listener: TcpListener,
+    deadline: Instant,
+    report: &mut Report,
+) -> Option<WebSocketStream<TcpStream>> {
+    let started = Instant::now();
+    let accept = async {
+        let (stream, _) = ws_listener.accept().await.map_err(|e| e.to_string())?;
+        accept_async(stream).await.map_err(|e| e.to_string())
+    };
+    let mut gateway = match within(deadline, accept).await {
+        Ok(gateway) => gateway,
+        Err(e) => {
+            report.fail(CONNECT_CHECK, e);
+            return None;
+        }
+    };
+    report.pass(CONNECT_CHECK, started);
+    for (index, name) in PROBES.into_iter().enumerate() {
+        let started = Instant::now();
+        match within(deadline, round_trip(&mut gateway, &probe(index))).await {
+            Ok(()) => report.pass(name, started),
+            Err(e) => {
+                report.fail(name, e);
+                break;
+            }
+        }
+    }
+    Some(gateway)
+}
+
+async fn within<T>(deadline: Instant, step: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+    tokio::time::timeout_at(deadline, step).await.unwrap_or_else(|_| Err("timed out".to_string()))
+}
+
+fn probe(index: usize) -> Vec<u8> {
+    match index {
+        0 => b"troudever self-test".to_vec(),
+        1 => (0..=255).collect(),
+        _ => (0..256 * 1024).map(|n| (n % 251) as u8).collect(),
+    }
+}
+
+async fn round_trip(gateway: &mut WebSocketStream<TcpStream>, payload: &[u8]) -> Result<(), String> {
+    let message = match std::str::from_utf8(payload) {
+        Ok(text) => Message::Text(text.into()),
+        Err(_) => Message::Binary(payload.to_vec().into()),
+    };
+    gateway.send(message).await.map_err(|e| e.to_string())?;
+    loop {
+        let message = gateway.next().await.ok_or("WebSocket closed")?.map_err(|e| e.to_string())?;
+        let back = match &message {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(data) => data,
+            Message::Close(_) => return Err("WebSocket closed".to_string()),
+            _ => continue,
+        };
+        if back != payload {
+            return Err(format!("sent {} bytes, got {} different bytes back", payload.len(), back.len()));
+        }
+        return Ok(());
+    }
+}
+
+/// Le serveur "de jeu" : renvoie tel quel tout ce qu'il reçoit, une connexion à la fois.
+async fn echo(listener: TcpListener) {
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let (mut reader, mut writer) = stream.split();
+        let _ = tokio::io::copy(&mut reader, &mut writer).await;
+    }
+}
+
+/// La dernière erreur du tunnel, pour expliquer l'étape qui a échoué.
+fn last_error(rx: &Receiver<(TunnelId, ProxyEvent)>) -> Option<String> {
+    rx.try_iter()
+        .filter_map(|(_, event)| match event {
+            ProxyEvent::Error(e) => Some(e),
+            ProxyEvent::Log(record) if record.level <= LogLevel::Warn => Some(record.message),
+            _ => None,
+        })
+        .last()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn every_check_passes_through_the_tunnel() {
+        let report = run(SELF_TEST_TIMEOUT).await;
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.checks.len(), 4);
+        assert!(report.to_string().ends_with("Self-test passed"));
+    }
+}
@@ -0,0 +1,144 @@
+//! Serveur SOCKS5 local du mode `Socks5` : chaque client dit où il veut aller, et son stream
+//! multiplexé (voir `mux`) emporte la destination dans la frame d'ouverture. Seuls la commande
+//! CONNECT et l'accès sans authentification sont gérés (RFC 1928).
+
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+const IPV4: u8 = 1;
+const DOMAIN: u8 = 3;
+const IPV6: u8 = 4;
+
+const SUCCEEDED: u8 = 0;
+const COMMAND_NOT_SUPPORTED: u8 = 7;
+const ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+#[derive(Debug)]
+pub enum SocksError {
+    Io(std::io::Error),
+    Version(u8),
+    /// Le client exige une authentification
+    NoAcceptableMethod,
+    Command(u8),
+    AddressType(u8),
+}
+
+impl fmt::Display for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocksError::Io(e) => write!(f, "{}", e),
+            SocksError::Version(version) => write!(f, "not a SOCKS5 client (version {})", version),
+            SocksError::NoAcceptableMethod => write!(f, "the client requires authentication"),
+            SocksError::Command(command) => write!(f, "unsupported SOCKS command {}", command),
+            SocksError::AddressType(kind) => write!(f, "unsupported SOCKS address type {}", kind),
+        }
+    }
+}
+
+impl From<std::io::Error> for SocksError {
+    fn from(e: std::io::Error) -> Self {
+        SocksError::Io(e)
+    }
+}
+
+/// Mène le handshake jusqu'à la réponse et rend la destination demandée (`host:port`, IPv6
+/// entre crochets). Le succès est annoncé tout de suite : si l'autre bout ne la joint pas, le
+/// stream se fermera, comme une connexion refusée.
+pub async fn handshake<R, W>(read: &mut R, write: &mut W) -> Result<String, SocksError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let [version, methods] = read_array(read).await?;
+    if version != VERSION {
+        return Err(SocksError::Version(version));
+    }
+    let mut offered = vec![0u8; methods as usize];
+    read.read_exact(&mut offered).await?;
+    if !offered.contains(&NO_AUTH) {
+        write.write_all(&[VERSION, NO_ACCEPTABLE_METHOD]).await?;
+        return Err(SocksError::NoAcceptableMethod);
+    }
+    write.write_all(&[VERSION, NO_AUTH]).await?;
+
+    let [version, command, _, kind] = read_array(read).await?;
+    if version != VERSION {
+        return Err(SocksError::Version(version));
+    }
+    let host = match kind {
+        IPV4 => Ipv4Addr::from(read_array::<4, _>(read).await?).to_string(),
+        IPV6 => format!("[{}]", Ipv6Addr::from(read_array::<16, _>(read).await?)),
+        DOMAIN => {
+            let [len] = read_array(read).await?;
+            let mut name = vec![0u8; len as usize];
+            read.read_exact(&mut name).await?;
+            String::from_utf8_lossy(&name).into_owned()
+        }
+        other => {
+            reply(write, ADDRESS_NOT_SUPPORTED).await?;
+            return Err(SocksError::AddressType(other));
+        }
+    };
+    let port = u16::from_be_bytes(read_array(read).await?);
+    if command != CONNECT {
+        reply(write, COMMAND_NOT_SUPPORTED).await?;
+        return Err(SocksError::Command(command));
+    }
+    reply(write, SUCCEEDED).await?;
+    Ok(format!("{}:{}", host, port))
+}
+
+async fn read_array<const N: usize, R: AsyncRead + Unpin>(read: &mut R) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    read.read_exact(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// Adresse liée inconnue de ce côté : `0.0.0.0:0`, que les clients ignorent pour CONNECT.
+async fn reply<W: AsyncWrite + Unpin>(write: &mut W, status: u8) -> std::io::Result<()> {
+    write.write_all(&[VERSION, status, 0, IPV4, 0, 0, 0, 0, 0, 0]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(request: &[u8]) -> (Result<String, SocksError>, Vec<u8>) {
+        let mut read = request;
+        let mut written = Vec::new();
+        let result = handshake(&mut read, &mut written).await;
+        (result, written)
+    }
+
+    #[tokio::test]
+    async fn connect_requests_give_their_destination() {
+        let (result, written) = run(b"\x05\x01\x00\x05\x01\x00\x03\x0bexample.com\x01\xbb").await;
+        assert_eq!(result.unwrap(), "example.com:443");
+        assert_eq!(written, b"\x05\x00\x05\x00\x00\x01\0\0\0\0\0\0");
+
+        let (result, _) = run(b"\x05\x02\x02\x00\x05\x01\x00\x01\x7f\x00\x00\x01\x1f\x90").await;
+        assert_eq!(result.unwrap(), "127.0.0.1:8080");
+        let mut ipv6 = b"\x05\x01\x00\x05\x01\x00\x04".to_vec();
+        ipv6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        ipv6.extend_from_slice(&[0, 80]);
+        assert_eq!(run(&ipv6).await.0.unwrap(), "[::1]:80");
+    }
+
+    #[tokio::test]
+    async fn unsupported_requests_are_refused() {
+        // Authentification exigée : aucune méthode commune
+        let (result, written) = run(b"\x05\x01\x02").await;
+        assert!(matches!(result, Err(SocksError::NoAcceptableMethod)));
+        assert_eq!(written, b"\x05\xff");
+        // BIND
+        let (result, written) = run(b"\x05\x01\x00\x05\x02\x00\x01\x7f\x00\x00\x01\x00\x50").await;
+        assert!(matches!(result, Err(SocksError::Command(2))));
+        assert_eq!(written[2..4], [VERSION, COMMAND_NOT_SUPPORTED]);
+        assert!(matches!(run(b"\x04\x01").await.0, Err(SocksError::Version(4))));
+    }
+}
@@ -0,0 +1,322 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Counters {
+    pub bytes: u64,
+    pub messages: u64,
+}
+
+impl Counters {
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        self.messages += 1;
+    }
+}
+
+/// Ce qui ne s'est pas passé comme prévu pendant une session.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct Faults {
+    /// Messages perdus : envoi raté, file pleine, message illisible...
+    pub dropped: u64,
+    /// Écritures WebSocket ou TCP en échec ; côté TCP le message attend souvent la reconnexion
+    pub write_errors: u64,
+    /// Messages TCP qui n'étaient pas de l'UTF-8, partis en Binary au lieu de Text
+    pub binary_fallbacks: u64,
+    /// Écritures TCP coupées au milieu d'une frame, qui repart entière à la reconnexion
+    pub partial_writes: u64,
+}
+
+impl Faults {
+    pub fn is_empty(&self) -> bool {
+        *self == Faults::default()
+    }
+
+    /// "3 dropped, 1 failed writes, 12 sent as binary, 0 partial writes"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} dropped, {} failed writes, {} sent as binary, {} partial writes",
+            self.dropped, self.write_errors, self.binary_fallbacks, self.partial_writes
+        )
+    }
+
+    fn fields(&self) -> [u64; 4] {
+        [self.dropped, self.write_errors, self.binary_fallbacks, self.partial_writes]
+    }
+
+    fn add_since(&mut self, now: &Faults, before: &Faults) {
+        self.dropped += now.dropped - before.dropped;
+        self.write_errors += now.write_errors - before.write_errors;
+        self.binary_fallbacks += now.binary_fallbacks - before.binary_fallbacks;
+        self.partial_writes += now.partial_writes - before.partial_writes;
+    }
+}
+
+/// Instantané envoyé à l'UI. ↑ = vers la gateway (TCP -> WS), ↓ = vers le serveur TCP.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Stats {
+    pub up: Counters,
+    pub down: Counters,
+    /// Octets par seconde depuis l'instantané précédent
+    pub up_rate: f64,
+    pub down_rate: f64,
+    /// Octets passés sur le WebSocket, dans les deux sens, quand la compression est active
+    pub wire_bytes: u64,
+    pub faults: Faults,
+}
+
+impl Stats {
+    /// "↑ 1.2 MB/s ↓ 340 KB/s, 4521 msgs", suivi de ", compressed to 38%" avec la compression.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "↑ {} ↓ {}, {} msgs",
+            format_rate(self.up_rate),
+            format_rate(self.down_rate),
+            self.up.messages + self.down.messages
+        );
+        if let Some(ratio) = self.compression_ratio() {
+            summary.push_str(&format!(", compressed to {:.0}%", ratio * 100.0));
+        }
+        summary
+    }
+
+    /// Taille sur le WebSocket rapportée à celle des payloads.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let payload = self.up.bytes + self.down.bytes;
+        (self.wire_bytes > 0 && payload > 0).then(|| self.wire_bytes as f64 / payload as f64)
+    }
+}
+
+/// Compteurs d'une session, relevés une fois par seconde par la boucle du proxy.
+pub struct StatsMeter {
+    stats: Stats,
+    last_up: u64,
+    last_down: u64,
+    last_tick: Instant,
+}
+
+impl StatsMeter {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            stats: Stats::default(),
+            last_up: 0,
+            last_down: 0,
+            last_tick: now,
+        }
+    }
+
+    pub fn record_up(&mut self, bytes: usize) {
+        self.stats.up.add(bytes);
+    }
+
+    pub fn record_down(&mut self, bytes: usize) {
+        self.stats.down.add(bytes);
+    }
+
+    pub fn record_wire(&mut self, bytes: usize) {
+        self.stats.wire_bytes += bytes as u64;
+    }
+
+    pub fn record_dropped(&mut self, messages: usize) {
+        self.stats.faults.dropped += messages as u64;
+    }
+
+    /// `written` : octets partis avant l'erreur, pour repérer les écritures partielles.
+    pub fn record_write_error(&mut self, written: usize) {
+        self.stats.faults.write_errors += 1;
+        if written > 0 {
+            self.stats.faults.partial_writes += 1;
+        }
+    }
+
+    pub fn record_binary_fallback(&mut self) {
+        self.stats.faults.binary_fallbacks += 1;
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Stats {
+        let elapsed = now.duration_since(self.last_tick).as_secs_f64();
+        if elapsed > 0.0 {
+            self.stats.up_rate = (self.stats.up.bytes - self.last_up) as f64 / elapsed;
+            self.stats.down_rate = (self.stats.down.bytes - self.last_down) as f64 / elapsed;
+        }
+        self.last_up = self.stats.up.bytes;
+        self.last_down = self.stats.down.bytes;
+        self.last_tick = now;
+        self.stats
+    }
+}
+
+/// Cumul sur toute la vie d'un tunnel, tenu par le frontend à partir de `ProxyEvent::Stats` :
+/// les compteurs du moteur repartent de zéro à chaque reconnexion.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Totals {
+    pub up: Counters,
+    pub down: Counters,
+    pub faults: Faults,
+    last: Stats,
+}
+
+impl Totals {
+    pub fn observe(&mut self, stats: &Stats) {
+        // Un compteur plus bas qu'au relevé précédent : nouvelle session, on repart de zéro
+        let last = &self.last;
+        if stats.up.bytes < last.up.bytes
+            || stats.down.bytes < last.down.bytes
+            || stats.up.messages < last.up.messages
+            || stats.down.messages < last.down.messages
+            || stats.faults.fields().iter().zip(last.faults.fields()).any(|(now, before)| *now < before)
+        {
+            self.last = Stats::default();
+        }
+        self.up.bytes += stats.up.bytes - self.last.up.bytes;
+        self.up.messages += stats.up.messages - self.last.up.messages;
+        self.down.bytes += stats.down.bytes - self.last.down.bytes;
+        self.down.messages += stats.down.messages - self.last.down.messages;
+        self.faults.add_since(&stats.faults, &self.last.faults);
+        self.last = *stats;
+    }
+}
+
+/// Historique des RTT d'une session, tenu par le frontend à partir de `ProxyEvent::Latency`.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct LatencyStats {
+    pub last: Option<Duration>,
+    pub max: Duration,
+    total: Duration,
+    samples: u32,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, rtt: Duration) {
+        self.last = Some(rtt);
+        self.max = self.max.max(rtt);
+        self.total += rtt;
+        self.samples += 1;
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        (self.samples > 0).then(|| self.total / self.samples)
+    }
+
+    /// "RTT: 42 ms (avg 40, max 95)"
+    pub fn summary(&self) -> String {
+        match (self.last, self.average()) {
+            (Some(last), Some(average)) => format!(
+                "RTT: {} ms (avg {}, max {})",
+                last.as_millis(),
+                average.as_millis(),
+                self.max.as_millis()
+            ),
+            _ => "RTT: —".to_string(),
+        }
+    }
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub fn format_rate(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_are_per_interval() {
+        let start = Instant::now();
+        let mut meter = StatsMeter::new(start);
+        meter.record_up(1_200_000);
+        meter.record_down(340_000);
+        meter.record_down(0);
+
+        let stats = meter.tick(start + Duration::from_secs(1));
+        assert_eq!(stats.up, Counters { bytes: 1_200_000, messages: 1 });
+        assert_eq!(stats.down.messages, 2);
+        assert_eq!(stats.summary(), "↑ 1.2 MB/s ↓ 340.0 KB/s, 3 msgs");
+        assert_eq!(stats.compression_ratio(), None);
+
+        // Rien de neuf : les débits retombent, les totaux restent
+        let stats = meter.tick(start + Duration::from_secs(3));
+        assert_eq!(stats.up_rate, 0.0);
+        assert_eq!(stats.up.bytes, 1_200_000);
+    }
+
+    #[test]
+    fn compression_ratio_covers_both_directions() {
+        let mut meter = StatsMeter::new(Instant::now());
+        meter.record_up(600);
+        meter.record_down(400);
+        meter.record_wire(380);
+        let stats = meter.tick(Instant::now());
+        assert_eq!(stats.compression_ratio(), Some(0.38));
+        assert!(stats.summary().ends_with(", 2 msgs, compressed to 38%"));
+    }
+
+    #[test]
+    fn totals_survive_counter_resets() {
+        let snapshot = |bytes, messages| Stats {
+            up: Counters { bytes, messages },
+            ..Default::default()
+        };
+        let mut totals = Totals::default();
+        totals.observe(&snapshot(100, 2));
+        totals.observe(&snapshot(150, 3));
+        // Reconnexion : le moteur repart de zéro
+        totals.observe(&snapshot(40, 1));
+        assert_eq!(totals.up, Counters { bytes: 190, messages: 4 });
+    }
+
+    #[test]
+    fn faults_are_counted_and_totaled() {
+        let mut meter = StatsMeter::new(Instant::now());
+        meter.record_dropped(2);
+        meter.record_write_error(0);
+        meter.record_write_error(512);
+        meter.record_binary_fallback();
+        let stats = meter.tick(Instant::now());
+        assert_eq!(stats.faults.summary(), "2 dropped, 2 failed writes, 1 sent as binary, 1 partial writes");
+
+        let mut totals = Totals::default();
+        totals.observe(&stats);
+        // Session suivante : les pertes repartent de zéro sans effacer le cumul
+        let mut meter = StatsMeter::new(Instant::now());
+        meter.record_dropped(1);
+        totals.observe(&meter.tick(Instant::now()));
+        assert_eq!(totals.faults.dropped, 3);
+        assert_eq!(totals.faults.partial_writes, 1);
+        assert!(Faults::default().is_empty());
+    }
+
+    #[test]
+    fn byte_units() {
+        assert_eq!(format_bytes(999.0), "999 B");
+        assert_eq!(format_bytes(1500.0), "1.5 KB");
+        assert_eq!(format_bytes(3.2e12), "3200.0 GB");
+    }
+
+    #[test]
+    fn latency_keeps_average_and_max() {
+        let mut latency = LatencyStats::default();
+        assert_eq!(latency.summary(), "RTT: —");
+        for ms in [40, 90, 20] {
+            latency.record(Duration::from_millis(ms));
+        }
+        assert_eq!(latency.average(), Some(Duration::from_millis(50)));
+        assert_eq!(latency.summary(), "RTT: 20 ms (avg 50, max 90)");
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use crate::stats;
+
+/// Débits (WS → TCP, TCP → WS) en octets par seconde, 0 = illimité.
+pub type Rates = (u64, u64);
+
+/// Token bucket simple, en octets par seconde.
+///
+/// Le seau peut passer en négatif : une écriture n'est jamais coupée en deux,
+/// c'est la lecture suivante dans la même direction qui attend que la dette soit remboursée.
+pub struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// `rate == 0` veut dire illimité : pas de seau du tout.
+    pub fn new(rate: u64, now: Instant) -> Option<Self> {
+        (rate > 0).then_some(Self {
+            rate,
+            tokens: 0.0,
+            last: now,
+        })
+    }
+
+    /// Taille max d'une lecture, pour ne pas dépasser une seconde de budget d'un coup.
+    pub fn max_chunk(&self) -> usize {
+        self.rate.try_into().unwrap_or(usize::MAX)
+    }
+
+    /// Consomme `bytes` et retourne l'instant à partir duquel la direction peut repartir.
+    pub fn consume(&mut self, bytes: usize, now: Instant) -> Instant {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        // Rafale max : une seconde de débit
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.tokens -= bytes as f64;
+
+        if self.tokens >= 0.0 {
+            now
+        } else {
+            now + Duration::from_secs_f64(-self.tokens / self.rate as f64)
+        }
+    }
+}
+
+/// Taille de lecture TCP pour ce seau : pas plus d'une seconde de budget, ni plus que le buffer.
+pub fn read_len(bucket: Option<&TokenBucket>, buffer_len: usize) -> usize {
+    bucket.map_or(buffer_len, |b| b.max_chunk().clamp(1, buffer_len))
+}
+
+/// "unlimited", "64 KB/s"
+pub fn describe(rate: u64) -> String {
+    if rate == 0 {
+        "unlimited".to_string()
+    } else {
+        stats::format_rate(rate as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_has_no_bucket() {
+        assert!(TokenBucket::new(0, Instant::now()).is_none());
+    }
+
+    #[test]
+    fn one_kib_per_second_takes_ten_seconds_for_ten_kib() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1024, start).unwrap();
+
+        // On simule la boucle : chaque chunk attend que la direction soit de nouveau prête
+        let mut now = start;
+        let mut ready = start;
+        for _ in 0..10 {
+            now = now.max(ready);
+            ready = bucket.consume(bucket.max_chunk(), now);
+        }
+
+        let elapsed = ready.duration_since(start).as_secs_f64();
+        assert!((9.5..=10.5).contains(&elapsed), "took {elapsed}s");
+    }
+
+    #[test]
+    fn read_len_follows_the_rate() {
+        let bucket = TokenBucket::new(1000, Instant::now());
+        assert_eq!(read_len(bucket.as_ref(), 8192), 1000);
+        assert_eq!(read_len(None, 8192), 8192);
+        assert_eq!(describe(0), "unlimited");
+    }
+
+    #[test]
+    fn idle_time_refills_at_most_one_second_of_budget() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000, start).unwrap();
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.consume(1000, later), later);
+        let ready = bucket.consume(500, later);
+        assert_eq!(ready.duration_since(later), Duration::from_millis(500));
+    }
+}
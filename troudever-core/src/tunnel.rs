@@ -0,0 +1,784 @@
+//! Logique octet par octet du tunnel, sans aucune socket : framing côté TCP et tri des
+//! messages de contrôle du relais. `run_proxy_logic` ne fait que brancher ces fonctions
+//! sur les streams.
+
+use bytes::buf::Limit;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+use tokio_tungstenite::tungstenite::Utf8Bytes;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+
+use crate::control::{ControlMessage, ControlRules};
+
+/// Taille du préfixe de longueur (u32) devant chaque message, dans les framings qui en ont un.
+pub const LEN_PREFIX: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    /// Le payload ne tient pas dans un préfixe u32, ou dépasse la limite du décodeur.
+    TooLarge(usize),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::TooLarge(len) => write!(f, "frame too large ({} bytes)", len),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Préfixe `payload` avec sa longueur, prêt à être écrit en un seul `write_all`.
+pub fn encode_frame(payload: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let len = u32::try_from(payload.len()).map_err(|_| FrameError::TooLarge(payload.len()))?;
+    let mut frame = Vec::with_capacity(LEN_PREFIX + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Lit une frame en tête de `buf`.
+///
+/// `Ok(None)` tant que la frame est incomplète (préfixe ou payload coupé entre deux lectures),
+/// sinon le payload et le nombre d'octets consommés.
+pub fn decode_frame(buf: &[u8], max_len: usize) -> Result<Option<(&[u8], usize)>, FrameError> {
+    decode_prefixed(buf, max_len, u32::from_be_bytes)
+}
+
+fn decode_prefixed(
+    buf: &[u8],
+    max_len: usize,
+    read_len: fn([u8; LEN_PREFIX]) -> u32,
+) -> Result<Option<(&[u8], usize)>, FrameError> {
+    let Some(prefix) = buf.get(..LEN_PREFIX) else {
+        return Ok(None);
+    };
+    let len = read_len(prefix.try_into().unwrap()) as usize;
+    if len > max_len {
+        return Err(FrameError::TooLarge(len));
+    }
+    let end = LEN_PREFIX + len;
+    Ok(buf.get(LEN_PREFIX..end).map(|payload| (payload, end)))
+}
+
+/// Qui ouvre la connexion côté TCP.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum TcpMode {
+    /// On compose l'adresse du serveur
+    #[default]
+    Connect,
+    /// On écoute sur l'adresse et on attend le client du jeu (un seul à la fois)
+    Listen,
+    /// Socket UDP local à la place du TCP, datagrammes encapsulés (voir `udp`)
+    Udp,
+    /// Comme `Listen`, mais plusieurs clients à la fois, chacun sur son stream (voir `mux`)
+    Multiplex,
+    /// Multiplexé derrière un serveur SOCKS5 local : chaque client choisit sa destination (voir `socks`)
+    Socks5,
+}
+
+impl TcpMode {
+    pub const ALL: [TcpMode; 5] = [
+        TcpMode::Connect,
+        TcpMode::Listen,
+        TcpMode::Multiplex,
+        TcpMode::Socks5,
+        TcpMode::Udp,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TcpMode::Connect => "Connect to server",
+            TcpMode::Listen => "Listen for client",
+            TcpMode::Udp => "UDP",
+            TcpMode::Multiplex => "Listen, multiplexed",
+            TcpMode::Socks5 => "SOCKS5 proxy",
+        }
+    }
+}
+
+/// Qui ouvre le WebSocket. En `Listen`, `ws_url` donne l'adresse d'écoute (voir [`ws_listen_addr`]).
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum WsMode {
+    /// On se connecte à la gateway
+    #[default]
+    Connect,
+    /// L'autre côté ne sait être que client : on attend son WebSocket (un seul à la fois, sans TLS)
+    Listen,
+}
+
+impl WsMode {
+    pub const ALL: [WsMode; 2] = [WsMode::Connect, WsMode::Listen];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            WsMode::Connect => "Connect to gateway",
+            WsMode::Listen => "Accept WebSocket client",
+        }
+    }
+}
+
+/// Les sens suspendus un à un, en plus de la pause générale. Modes Connect et Listen.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct PausedDirections {
+    pub ws_to_tcp: bool,
+    pub tcp_to_ws: bool,
+}
+
+impl PausedDirections {
+    pub fn any(self) -> bool {
+        self.ws_to_tcp || self.tcp_to_ws
+    }
+
+    /// "WS → TCP", "TCP → WS" ou "both directions", `None` si rien n'est suspendu.
+    pub fn describe(self) -> Option<&'static str> {
+        match (self.ws_to_tcp, self.tcp_to_ws) {
+            (true, true) => Some("both directions"),
+            (true, false) => Some("WS → TCP"),
+            (false, true) => Some("TCP → WS"),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Ce que devient le trafic d'un sens suspendu.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum PauseMode {
+    /// Plus rien n'est lu de ce côté : les messages attendent dans les sockets, puis repartent
+    /// dans l'ordre à la reprise. Les messages de contrôle du relais attendent aussi
+    #[default]
+    Hold,
+    /// Lu puis jeté, compté dans les logs à la reprise. Les messages de contrôle du relais
+    /// restent traités
+    Drop,
+}
+
+impl PauseMode {
+    pub const ALL: [PauseMode; 2] = [PauseMode::Hold, PauseMode::Drop];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PauseMode::Hold => "Hold messages",
+            PauseMode::Drop => "Drop messages",
+        }
+    }
+}
+
+/// `ws://0.0.0.0:4455/` comme `0.0.0.0:4455` : l'URL déjà saisie pour le mode client reste valable.
+pub fn ws_listen_addr(ws_url: &str) -> &str {
+    let addr = ws_url.trim();
+    let addr = addr.strip_prefix("ws://").unwrap_or(addr);
+    addr.split('/').next().unwrap_or(addr)
+}
+
+/// Découpage en messages du flux TCP, dans les deux sens.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum TcpFraming {
+    /// Le protocole historique du relais : préfixe u32 big-endian vers le TCP, chaque `read()`
+    /// tel quel dans l'autre sens, sauf un objet JSON entamé en fin de lecture, qui attend sa
+    /// suite. Garde son ancien nom dans les réglages sauvegardés
+    #[default]
+    #[serde(rename = "Raw")]
+    Relay,
+    /// Aucun découpage : les octets passent tels quels, un `read()` fait un message (même
+    /// exception que `Relay` pour un objet JSON coupé)
+    Passthrough,
+    /// Préfixe u32 big-endian
+    LengthPrefixed,
+    LengthPrefixedLe,
+    /// Un message par ligne, `\r\n` toléré à la lecture
+    Newline,
+}
+
+impl TcpFraming {
+    pub const ALL: [TcpFraming; 5] = [
+        TcpFraming::Relay,
+        TcpFraming::Passthrough,
+        TcpFraming::LengthPrefixed,
+        TcpFraming::LengthPrefixedLe,
+        TcpFraming::Newline,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TcpFraming::Relay => "Relay (u32 BE to TCP, raw back)",
+            TcpFraming::Passthrough => "Raw passthrough",
+            TcpFraming::LengthPrefixed => "Length prefix (u32 BE)",
+            TcpFraming::LengthPrefixedLe => "Length prefix (u32 LE)",
+            TcpFraming::Newline => "Newline-delimited",
+        }
+    }
+
+    /// Un message prêt à être écrit vers le TCP en un seul `write_all`. En `Newline`, un
+    /// message qui contient lui-même des `\n` arrive en plusieurs lignes.
+    pub fn encode(self, payload: &[u8]) -> Result<Vec<u8>, FrameError> {
+        match self {
+            TcpFraming::Relay | TcpFraming::LengthPrefixed => encode_frame(payload),
+            TcpFraming::LengthPrefixedLe => {
+                let len = u32::try_from(payload.len()).map_err(|_| FrameError::TooLarge(payload.len()))?;
+                let mut frame = Vec::with_capacity(LEN_PREFIX + payload.len());
+                frame.extend_from_slice(&len.to_le_bytes());
+                frame.extend_from_slice(payload);
+                Ok(frame)
+            }
+            TcpFraming::Passthrough => Ok(payload.to_vec()),
+            TcpFraming::Newline => {
+                let mut line = Vec::with_capacity(payload.len() + 1);
+                line.extend_from_slice(payload);
+                line.push(b'\n');
+                Ok(line)
+            }
+        }
+    }
+}
+
+/// Accumule les lectures TCP et ne rend que des messages complets.
+pub struct FrameDecoder {
+    framing: TcpFraming,
+    // Les messages rendus en sont découpés sans copie ; la place se recycle une fois qu'ils
+    // sont tous relâchés
+    buf: BytesMut,
+    max_len: usize,
+}
+
+impl FrameDecoder {
+    pub fn new(framing: TcpFraming, max_len: usize) -> Self {
+        Self {
+            framing,
+            buf: BytesMut::new(),
+            max_len,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// De quoi lire au plus `len` octets directement dans le tampon du décodeur
+    /// (`AsyncReadExt::read_buf`), plutôt que dans un buffer à recopier.
+    pub fn read_buffer(&mut self, len: usize) -> Limit<&mut BytesMut> {
+        self.buf.reserve(len);
+        (&mut self.buf).limit(len)
+    }
+
+    /// Un morceau de message reçu avant une reconnexion n'a plus de suite.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Prochain message complet. Une erreur veut dire que le flux est désynchronisé.
+    pub fn next_message(&mut self) -> Result<Option<Bytes>, FrameError> {
+        let message = match self.framing {
+            TcpFraming::Relay | TcpFraming::Passthrough => {
+                let mut end = self.buf.len() - incomplete_json_tail(&self.buf);
+                // Un objet qui ne finit jamais : on le rend plutôt que de grossir sans limite
+                if end == 0 && self.buf.len() > self.max_len {
+                    end = self.buf.len();
+                }
+                (end > 0).then(|| self.buf.split_to(end).freeze())
+            }
+            TcpFraming::LengthPrefixed | TcpFraming::LengthPrefixedLe => {
+                let read_len = match self.framing {
+                    TcpFraming::LengthPrefixedLe => u32::from_le_bytes,
+                    _ => u32::from_be_bytes,
+                };
+                match decode_prefixed(&self.buf, self.max_len, read_len)? {
+                    Some((_, used)) => Some(self.buf.split_to(used).freeze().slice(LEN_PREFIX..)),
+                    None => None,
+                }
+            }
+            TcpFraming::Newline => loop {
+                let Some(end) = self.buf.iter().position(|&b| b == b'\n') else {
+                    if self.buf.len() > self.max_len {
+                        return Err(FrameError::TooLarge(self.buf.len()));
+                    }
+                    break None;
+                };
+                let mut line = self.buf.split_to(end + 1).freeze();
+                line.truncate(if line[..end].ends_with(b"\r") { end - 1 } else { end });
+                // Les lignes vides (keepalive) ne valent pas un message
+                if !line.is_empty() {
+                    break Some(line);
+                }
+            },
+        };
+        Ok(message)
+    }
+}
+
+/// Octets d'un objet JSON entamé en fin de `buf`, à garder pour la lecture suivante : un message
+/// de contrôle coupé entre deux `read()` passerait sinon au WebSocket en deux morceaux. Seuls les
+/// objets comptent (les messages de contrôle en sont), et rien n'est gardé derrière du non-JSON.
+fn incomplete_json_tail(buf: &[u8]) -> usize {
+    let mut stream = serde_json::Deserializer::from_slice(buf).into_iter::<serde::de::IgnoredAny>();
+    loop {
+        let start = stream.byte_offset();
+        match stream.next() {
+            Some(Ok(_)) => continue,
+            Some(Err(e)) if e.is_eof() => {
+                let tail = &buf[start..];
+                let skipped = tail.iter().take_while(|b| b.is_ascii_whitespace()).count();
+                return if tail.get(skipped) == Some(&b'{') { tail.len() - skipped } else { 0 };
+            }
+            _ => return 0,
+        }
+    }
+}
+
+/// Demande de reprise de room envoyée au serveur TCP après une reconnexion. Comme les autres
+/// demandes au relais, elle suit le framing du tunnel.
+pub fn resume_handshake(room_code: &str, framing: TcpFraming) -> Result<Vec<u8>, FrameError> {
+    let json = serde_json::json!({ "request_room": room_code });
+    framing.encode(json.to_string().as_bytes())
+}
+
+/// Demande au relais de rejoindre la room d'un autre (réponse : `ControlMessage::Joined`
+/// ou `JoinFailed`).
+pub fn join_request(room_code: &str, framing: TcpFraming) -> Result<Vec<u8>, FrameError> {
+    let json = serde_json::json!({ "internal": true, "join": room_code });
+    framing.encode(json.to_string().as_bytes())
+}
+
+/// Demande au relais de changer le room code de la session ; le nouveau arrive comme un
+/// room code ordinaire (`{"internal":true,"room":...}`).
+pub fn new_room_request(framing: TcpFraming) -> Result<Vec<u8>, FrameError> {
+    let json = serde_json::json!({ "internal": true, "new_room": true });
+    framing.encode(json.to_string().as_bytes())
+}
+
+/// Ce qu'il faut faire d'un chunk lu côté TCP.
+#[derive(Debug, Default, PartialEq)]
+pub struct Inspection {
+    /// Morceaux du chunk à forwarder, dans l'ordre : tout le chunk sans message de contrôle,
+    /// rien s'il n'y a que ça. Les messages de contrôle restent côté proxy.
+    pub forward: Vec<Range<usize>>,
+    pub control_messages: usize,
+    /// Ce que demandent les messages de contrôle, dans l'ordre.
+    pub messages: Vec<ControlMessage>,
+}
+
+impl Inspection {
+    /// Le chunk sans ses messages de contrôle, `None` s'il ne reste rien. Sans copie tant que
+    /// ce qui reste est d'un seul tenant.
+    pub fn forwarded(&self, chunk: &Bytes) -> Option<Bytes> {
+        match self.forward.as_slice() {
+            [] => None,
+            [range] => Some(chunk.slice(range.clone())),
+            ranges => Some(ranges.iter().flat_map(|range| &chunk[range.clone()]).copied().collect::<Vec<u8>>().into()),
+        }
+    }
+}
+
+/// Cherche les messages de contrôle du relais dans un chunk TCP.
+///
+/// Le chunk peut contenir plusieurs JSON à la suite, et des octets du jeu derrière : tout ce qui
+/// n'est pas un message de contrôle (texte libre, binaire, JSON tronqué, JSON du jeu) est
+/// forwardé. Un message de contrôle emporte les blancs qui le suivent, et un morceau fait
+/// seulement de blancs n'est pas envoyé : `{"internal":...}\n` ne laisse pas de ligne vide.
+pub fn inspect_tcp_chunk(chunk: &[u8], rules: &ControlRules) -> Inspection {
+    let mut inspection = Inspection::default();
+    let blanks = |from: usize| chunk[from..].iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+    // Début de ce qui n'a pas encore été forwardé, et fin du dernier JSON lu
+    let (mut kept, mut read) = (0, 0);
+    let mut stream = serde_json::Deserializer::from_slice(chunk).into_iter::<Value>();
+    // Le premier JSON invalide arrête la lecture : la suite part telle quelle
+    while let Some(Ok(value)) = stream.next() {
+        let end = stream.byte_offset();
+        if rules.is_control(&value) {
+            let start = read + blanks(read);
+            if chunk[kept..start].iter().any(|byte| !byte.is_ascii_whitespace()) {
+                inspection.forward.push(kept..start);
+            }
+            kept = end + blanks(end);
+            inspection.control_messages += 1;
+            inspection.messages.extend(rules.message(&value));
+        }
+        read = end;
+    }
+    // Sans message de contrôle, `kept` est resté à 0 : tout le chunk, même vide
+    if inspection.control_messages == 0 || kept < chunk.len() {
+        inspection.forward.push(kept..chunk.len());
+    }
+    inspection
+}
+
+/// Texte si le chunk est de l'UTF-8 valide, binaire sinon.
+pub fn ws_message_for(chunk: Bytes) -> Message {
+    // Le clone ne copie rien : c'est le même buffer, vu deux fois
+    match Utf8Bytes::try_from(chunk.clone()) {
+        Ok(text) => Message::Text(text),
+        Err(_) => Message::Binary(chunk),
+    }
+}
+
+/// Copie à part d'un message découpé dans le buffer de lecture, pour ceux qui vont attendre
+/// (file de reconnexion, retard simulé) : tant qu'il vit, tout le buffer d'origine resterait
+/// alloué avec lui.
+pub fn detach(message: Message) -> Message {
+    match message {
+        Message::Text(text) => Message::Text(text.as_str().into()),
+        Message::Binary(data) => Message::Binary(Bytes::copy_from_slice(&data)),
+        other => other,
+    }
+}
+
+/// Nom RFC 6455 d'un code de fermeture WebSocket.
+pub fn close_code_name(code: u16) -> &'static str {
+    match code {
+        1000 => "Normal Closure",
+        1001 => "Going Away",
+        1002 => "Protocol Error",
+        1003 => "Unsupported Data",
+        1005 => "No Status Received",
+        1006 => "Abnormal Closure",
+        1007 => "Invalid Payload",
+        1008 => "Policy Violation",
+        1009 => "Message Too Big",
+        1010 => "Mandatory Extension",
+        1011 => "Internal Error",
+        1012 => "Service Restart",
+        1013 => "Try Again Later",
+        1014 => "Bad Gateway",
+        1015 => "TLS Handshake",
+        3000..=3999 => "Registered",
+        4000..=4999 => "Application",
+        _ => "Unknown",
+    }
+}
+
+/// Ligne de log pour un `Message::Close` reçu de la gateway.
+pub fn describe_close(frame: Option<&CloseFrame>) -> String {
+    let Some(frame) = frame else {
+        return "WebSocket closed: no close frame (no status code)".to_string();
+    };
+    let code = u16::from(frame.code);
+    let mut line = format!("WebSocket closed: {} {}", code, close_code_name(code));
+    if !frame.reason.is_empty() {
+        line.push_str(&format!(" - {}", frame.reason));
+    }
+    line
+}
+
+/// `false` quand la gateway nous a refusés : se reconnecter renverrait la même réponse.
+pub fn close_allows_reconnect(frame: Option<&CloseFrame>) -> bool {
+    // Protocol Error, Unsupported Data, Invalid Payload, Policy Violation, Message Too Big, Mandatory Extension
+    !frame.is_some_and(|frame| matches!(u16::from(frame.code), 1002 | 1003 | 1007 | 1008 | 1009 | 1010))
+}
+
+/// File d'attente bornée pour les messages qui arrivent pendant qu'un côté se reconnecte.
+/// Quand elle déborde, ce sont les plus vieux messages qui partent ; avec `max_items == 0`
+/// elle ne garde rien et se contente de compter.
+pub struct Backlog<T> {
+    items: VecDeque<(T, usize)>,
+    bytes: usize,
+    max_items: usize,
+    max_bytes: usize,
+    dropped: usize,
+}
+
+impl<T> Backlog<T> {
+    pub fn new(max_items: usize, max_bytes: usize) -> Self {
+        Self {
+            items: VecDeque::new(),
+            bytes: 0,
+            max_items,
+            max_bytes,
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, item: T, size: usize) {
+        self.items.push_back((item, size));
+        self.bytes += size;
+        while self.items.len() > self.max_items || self.bytes > self.max_bytes {
+            let Some((_, size)) = self.items.pop_front() else {
+                break;
+            };
+            self.bytes -= size;
+            self.dropped += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_items > 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Nombre de messages jetés depuis le dernier appel.
+    pub fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.dropped)
+    }
+
+    pub fn pop(&mut self) -> Option<(T, usize)> {
+        let (item, size) = self.items.pop_front()?;
+        self.bytes -= size;
+        Some((item, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_empty_payload() {
+        assert_eq!(encode_frame(b"").unwrap(), vec![0, 0, 0, 0]);
+        assert_eq!(decode_frame(&[0, 0, 0, 0], 16), Ok(Some((&b""[..], 4))));
+    }
+
+    #[test]
+    fn encode_then_decode_roundtrip() {
+        let frame = encode_frame(b"hello").unwrap();
+        assert_eq!(&frame[..4], &[0, 0, 0, 5]);
+        assert_eq!(decode_frame(&frame, 16), Ok(Some((&b"hello"[..], 9))));
+    }
+
+    #[test]
+    fn split_frames_wait_for_more_bytes() {
+        let frame = encode_frame(b"hello").unwrap();
+        // Préfixe coupé, puis payload coupé
+        assert_eq!(decode_frame(&frame[..2], 16), Ok(None));
+        assert_eq!(decode_frame(&frame[..6], 16), Ok(None));
+
+        // Deux frames collées : la première est lue, le reste attend
+        let mut two = frame.clone();
+        two.extend(encode_frame(b"world").unwrap());
+        let (payload, used) = decode_frame(&two, 16).unwrap().unwrap();
+        assert_eq!(payload, b"hello");
+        assert_eq!(decode_frame(&two[used..], 16), Ok(Some((&b"world"[..], 9))));
+    }
+
+    #[test]
+    fn oversized_frame_is_rejected() {
+        let frame = encode_frame(&[0u8; 32]).unwrap();
+        assert_eq!(decode_frame(&frame, 16), Err(FrameError::TooLarge(32)));
+    }
+
+    fn drain(decoder: &mut FrameDecoder) -> Vec<Bytes> {
+        std::iter::from_fn(|| decoder.next_message().unwrap()).collect()
+    }
+
+    #[test]
+    fn decoder_reassembles_split_messages() {
+        let frame = encode_frame(br#"{"internal":true,"room":"ABCD"}"#).unwrap();
+        let mut decoder = FrameDecoder::new(TcpFraming::LengthPrefixed, 1024);
+        decoder.push(&frame[..2]);
+        assert!(drain(&mut decoder).is_empty());
+        decoder.push(&frame[2..10]);
+        assert!(drain(&mut decoder).is_empty());
+        decoder.push(&frame[10..]);
+        decoder.push(&encode_frame(b"next").unwrap());
+        assert_eq!(drain(&mut decoder), vec![frame[4..].to_vec(), b"next".to_vec()]);
+
+        let mut lines = FrameDecoder::new(TcpFraming::Newline, 1024);
+        lines.push(b"{\"internal\":tr");
+        assert!(drain(&mut lines).is_empty());
+        lines.push(b"ue}\r\n\nscore=3\npart");
+        assert_eq!(drain(&mut lines), vec![br#"{"internal":true}"#.to_vec(), b"score=3".to_vec()]);
+        lines.push(b"ial\n");
+        assert_eq!(drain(&mut lines), vec![b"partial".to_vec()]);
+
+        // Sans framing, ce qui précède l'objet coupé part tout de suite, le reste attend
+        let mut relay = FrameDecoder::new(TcpFraming::Relay, 1024);
+        relay.push(b"{\"score\":3} {\"internal\":tr");
+        assert_eq!(drain(&mut relay), vec![b"{\"score\":3} ".to_vec()]);
+        relay.push(b"ue}");
+        assert_eq!(drain(&mut relay), vec![br#"{"internal":true}"#.to_vec()]);
+        // Texte libre, binaire ou tableau coupé : rien à attendre
+        for chunk in [&b"score=3"[..], b"\xff\x00{", b"[1,2", b"\"open"] {
+            relay.push(chunk);
+            assert_eq!(drain(&mut relay), vec![chunk.to_vec()]);
+        }
+    }
+
+    #[test]
+    fn every_framing_decodes_what_it_encodes() {
+        // `Relay` seul est asymétrique : préfixe à l'aller, lectures brutes au retour
+        for framing in TcpFraming::ALL.into_iter().filter(|&framing| framing != TcpFraming::Relay) {
+            let mut decoder = FrameDecoder::new(framing, 1024);
+            decoder.push(&framing.encode(b"score=3").unwrap());
+            assert_eq!(drain(&mut decoder), vec![b"score=3".to_vec()], "{:?}", framing);
+        }
+        assert_eq!(TcpFraming::LengthPrefixedLe.encode(b"hi").unwrap(), b"\x02\0\0\0hi");
+        assert_eq!(TcpFraming::Relay.encode(b"hi").unwrap(), b"\0\0\0\x02hi");
+        assert_eq!(TcpFraming::Newline.encode(b"hi").unwrap(), b"hi\n");
+        assert_eq!(TcpFraming::Passthrough.encode(b"hi").unwrap(), b"hi");
+
+        // Les réglages d'avant ce choix disaient `Raw` : même comportement qu'alors
+        let framing: TcpFraming = serde_json::from_str("\"Raw\"").unwrap();
+        assert_eq!(framing, TcpFraming::Relay);
+    }
+
+    #[test]
+    fn decoded_messages_share_the_read_buffer() {
+        let mut decoder = FrameDecoder::new(TcpFraming::LengthPrefixed, 1024);
+        let mut buffer = decoder.read_buffer(64);
+        buffer.put_slice(b"\0\0\0\x03one\0\0\0\x03two");
+        assert_eq!(buffer.remaining_mut(), 64 - 14);
+        let messages = drain(&mut decoder);
+        assert_eq!(messages, [&b"one"[..], b"two"]);
+        // Découpés dans le même buffer, l'un derrière l'autre : rien n'a été recopié
+        assert_eq!(messages[1].as_ptr(), messages[0].as_ptr().wrapping_add(3 + LEN_PREFIX));
+    }
+
+    #[test]
+    fn decoder_raw_and_limits() {
+        let mut raw = FrameDecoder::new(TcpFraming::Passthrough, 4);
+        raw.push(b"anything");
+        assert_eq!(drain(&mut raw), vec![b"anything".to_vec()]);
+        // Un objet entamé attend sa suite, mais pas au-delà de la limite
+        raw.push(b"{\"a");
+        assert!(drain(&mut raw).is_empty());
+        raw.push(b"\":1");
+        assert_eq!(drain(&mut raw), vec![b"{\"a\":1".to_vec()]);
+
+        let mut lines = FrameDecoder::new(TcpFraming::Newline, 4);
+        lines.push(b"too long");
+        assert_eq!(lines.next_message(), Err(FrameError::TooLarge(8)));
+        lines.clear();
+        lines.push(b"ok\n");
+        assert_eq!(drain(&mut lines), vec![b"ok".to_vec()]);
+    }
+
+    #[test]
+    fn listen_address_from_url() {
+        assert_eq!(ws_listen_addr("ws://0.0.0.0:4455/socket"), "0.0.0.0:4455");
+        assert_eq!(ws_listen_addr(" 127.0.0.1:4455 "), "127.0.0.1:4455");
+    }
+
+    #[test]
+    fn resume_handshake_is_framed_json() {
+        let frame = resume_handshake("ABCD", TcpFraming::Relay).unwrap();
+        let (payload, _) = decode_frame(&frame, 1024).unwrap().unwrap();
+        let value: Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(value["request_room"], "ABCD");
+    }
+
+    #[test]
+    fn join_request_is_internal() {
+        let frame = join_request("WXYZ", TcpFraming::Relay).unwrap();
+        let (payload, _) = decode_frame(&frame, 1024).unwrap().unwrap();
+        let value: Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(value, serde_json::json!({"internal": true, "join": "WXYZ"}));
+
+        let frame = new_room_request(TcpFraming::Relay).unwrap();
+        let (payload, _) = decode_frame(&frame, 1024).unwrap().unwrap();
+        let value: Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(value, serde_json::json!({"internal": true, "new_room": true}));
+    }
+
+    #[test]
+    fn control_message_is_kept_and_room_extracted() {
+        let inspection = inspect_tcp_chunk(br#"{"internal":true,"room":"ABCD"}"#, &ControlRules::default());
+        assert!(inspection.forward.is_empty());
+        assert_eq!(inspection.control_messages, 1);
+        assert_eq!(inspection.messages, vec![ControlMessage::Room("ABCD".to_string())]);
+    }
+
+    #[test]
+    fn regular_json_and_concatenated_messages() {
+        let rules = ControlRules::default();
+        let chunk = Bytes::from_static(br#"{"event":"score"}"#);
+        assert_eq!(inspect_tcp_chunk(&chunk, &rules).forwarded(&chunk), Some(chunk));
+
+        let chunk = Bytes::from_static(br#"{"event":1}{"internal":1,"room":"R"}"#);
+        let inspection = inspect_tcp_chunk(&chunk, &rules);
+        assert_eq!(inspection.forwarded(&chunk).unwrap(), &br#"{"event":1}"#[..]);
+        assert_eq!(inspection.messages, vec![ControlMessage::Room("R".to_string())]);
+    }
+
+    #[test]
+    fn payload_next_to_a_control_message_is_forwarded() {
+        let rules = ControlRules::default();
+        let forwarded = |chunk: &'static [u8]| {
+            let chunk = Bytes::from_static(chunk);
+            let inspection = inspect_tcp_chunk(&chunk, &rules);
+            assert_eq!(inspection.control_messages, 1);
+            inspection.forwarded(&chunk)
+        };
+        // Octets du jeu juste derrière, en une seule lecture
+        assert_eq!(forwarded(b"{\"internal\":1,\"room\":\"R\"}\x01\x02game").unwrap(), &b"\x01\x02game"[..]);
+        assert_eq!(forwarded(b"{\"internal\":1}\n{\"event\":2}\n").unwrap(), &b"{\"event\":2}\n"[..]);
+        // Devant et derrière : recollés dans l'ordre
+        let chunk = b"{\"a\":1}\n{\"internal\":1}\n{\"b\":2}";
+        assert_eq!(forwarded(chunk).unwrap(), &b"{\"a\":1}\n{\"b\":2}"[..]);
+        assert_eq!(forwarded(b" {\"internal\":1} \n"), None);
+    }
+
+    #[test]
+    fn malformed_json_is_forwarded() {
+        let rules = ControlRules::default();
+        for chunk in [&b"not json"[..], br#"{"internal":tr"#, &[0xff, 0x00, 0x7b], b""] {
+            let inspection = inspect_tcp_chunk(chunk, &rules);
+            let chunk = Bytes::copy_from_slice(chunk);
+            assert_eq!(inspection.forwarded(&chunk), Some(chunk));
+            assert!(inspection.messages.is_empty());
+        }
+    }
+
+    #[test]
+    fn ws_message_text_or_binary() {
+        assert_eq!(ws_message_for(Bytes::from_static(b"hi")), Message::Text("hi".into()));
+        assert_eq!(ws_message_for(Bytes::from_static(&[0xff])), Message::Binary(vec![0xff].into()));
+    }
+
+    #[test]
+    fn close_frames_are_described() {
+        let frame = CloseFrame {
+            code: 1008.into(),
+            reason: "banned".into(),
+        };
+        assert_eq!(describe_close(Some(&frame)), "WebSocket closed: 1008 Policy Violation - banned");
+        assert!(!close_allows_reconnect(Some(&frame)));
+
+        let frame = CloseFrame {
+            code: 1012.into(),
+            reason: "".into(),
+        };
+        assert_eq!(describe_close(Some(&frame)), "WebSocket closed: 1012 Service Restart");
+        assert!(close_allows_reconnect(Some(&frame)));
+
+        assert!(describe_close(None).contains("no close frame"));
+        assert!(close_allows_reconnect(None));
+        assert_eq!(close_code_name(4321), "Application");
+    }
+
+    #[test]
+    fn backlog_drops_oldest_when_full() {
+        let mut backlog = Backlog::new(3, 10);
+        for i in 0..5 {
+            backlog.push(i, 1);
+        }
+        assert_eq!(backlog.len(), 3);
+        assert_eq!(backlog.take_dropped(), 2);
+        assert_eq!(backlog.take_dropped(), 0);
+        assert_eq!(backlog.pop(), Some((2, 1)));
+    }
+
+    #[test]
+    fn backlog_respects_byte_budget() {
+        let mut backlog = Backlog::new(100, 10);
+        backlog.push("a", 6);
+        backlog.push("b", 6);
+        assert_eq!(backlog.len(), 1);
+        assert_eq!(backlog.pop(), Some(("b", 6)));
+        assert_eq!(backlog.pop(), None);
+
+        let mut disabled = Backlog::new(0, 10);
+        disabled.push("c", 1);
+        assert!(!disabled.is_enabled() && disabled.is_empty());
+        assert_eq!(disabled.take_dropped(), 1);
+    }
+}
@@ -0,0 +1,93 @@
+//! Mode UDP : chaque datagramme voyage dans une frame WebSocket binaire précédée d'un en-tête
+//! de 3 octets, `[version][peer u16 big-endian]`. Le numéro de pair permet à la gateway de
+//! répondre au bon client quand plusieurs envoient vers le même port local.
+
+use std::fmt;
+use std::net::SocketAddr;
+
+pub const HEADER_LEN: usize = 3;
+/// Plus grand datagramme UDP possible (IPv4)
+pub const MAX_DATAGRAM: usize = 65_507;
+const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum UdpFrameError {
+    TooShort(usize),
+    UnknownVersion(u8),
+}
+
+impl fmt::Display for UdpFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpFrameError::TooShort(len) => write!(f, "UDP frame too short ({} bytes)", len),
+            UdpFrameError::UnknownVersion(version) => write!(f, "unknown UDP frame version {}", version),
+        }
+    }
+}
+
+pub fn wrap(peer: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.push(VERSION);
+    frame.extend_from_slice(&peer.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub fn unwrap(frame: &[u8]) -> Result<(u16, &[u8]), UdpFrameError> {
+    if frame.len() < HEADER_LEN {
+        return Err(UdpFrameError::TooShort(frame.len()));
+    }
+    if frame[0] != VERSION {
+        return Err(UdpFrameError::UnknownVersion(frame[0]));
+    }
+    Ok((u16::from_be_bytes([frame[1], frame[2]]), &frame[HEADER_LEN..]))
+}
+
+/// Adresses des clients UDP vus pendant la session, numérotées dans l'ordre d'arrivée.
+#[derive(Default)]
+pub struct Peers {
+    addrs: Vec<SocketAddr>,
+}
+
+impl Peers {
+    /// Numéro du pair, et s'il vient d'être ajouté. `None` une fois les 65536 numéros pris.
+    pub fn id(&mut self, addr: SocketAddr) -> Option<(u16, bool)> {
+        if let Some(index) = self.addrs.iter().position(|known| *known == addr) {
+            return Some((index as u16, false));
+        }
+        let id = u16::try_from(self.addrs.len()).ok()?;
+        self.addrs.push(addr);
+        Some((id, true))
+    }
+
+    pub fn addr(&self, id: u16) -> Option<SocketAddr> {
+        self.addrs.get(id as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let frame = wrap(258, b"hello");
+        assert_eq!(&frame[..HEADER_LEN], &[1, 1, 2]);
+        assert_eq!(unwrap(&frame), Ok((258, &b"hello"[..])));
+        assert_eq!(unwrap(&wrap(0, b"")), Ok((0, &b""[..])));
+        assert_eq!(unwrap(&[1, 0]), Err(UdpFrameError::TooShort(2)));
+        assert_eq!(unwrap(&[9, 0, 0, 1]), Err(UdpFrameError::UnknownVersion(9)));
+    }
+
+    #[test]
+    fn peers_keep_their_number() {
+        let mut peers = Peers::default();
+        let a: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let b: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        assert_eq!(peers.id(a), Some((0, true)));
+        assert_eq!(peers.id(b), Some((1, true)));
+        assert_eq!(peers.id(a), Some((0, false)));
+        assert_eq!(peers.addr(1), Some(b));
+        assert_eq!(peers.addr(2), None);
+    }
+}
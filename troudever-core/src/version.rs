@@ -0,0 +1,139 @@
+//! Négociation de la version du protocole avec le relais, juste après l'upgrade WebSocket et
+//! avant l'authentification : on annonce les versions qu'on parle, le relais en choisit une.
+//! Sans version commune, le tunnel s'arrête aussitôt plutôt que d'échanger des frames que
+//! l'autre côté comprendrait de travers.
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Error, protocol::Message};
+
+/// Versions du protocole relais que ce build sait parler, de la plus ancienne à la plus récente
+pub const PROTOCOL_VERSIONS: [u32; 1] = [1];
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq)]
+pub enum VersionError {
+    /// Pas de version commune : réessayer ne changera rien.
+    Incompatible(String),
+    /// Pas de réponse, connexion perdue... : une nouvelle tentative peut passer.
+    Failed(String),
+}
+
+impl std::fmt::Display for VersionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionError::Incompatible(reason) | VersionError::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// `{"internal":true,"type":"hello","versions":[1],"client":"troudever/0.1.0"}`
+pub fn hello() -> String {
+    serde_json::json!({
+        "internal": true,
+        "type": "hello",
+        "versions": PROTOCOL_VERSIONS,
+        "client": format!("troudever/{}", env!("CARGO_PKG_VERSION")),
+    })
+    .to_string()
+}
+
+fn supported() -> String {
+    PROTOCOL_VERSIONS.map(|version| version.to_string()).join(", ")
+}
+
+/// Réponse du relais : `Some(Ok)` avec la version choisie pour `{"type":"version","version":N}`,
+/// `Some(Err)` pour `{"type":"version","ok":false}` (raison dans `reason`, versions du relais
+/// dans `supported`) ou une version qu'on ne parle pas, `None` pour tout le reste.
+pub fn parse_reply(text: &str) -> Option<Result<u32, String>> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    if value.get("type").and_then(Value::as_str) != Some("version") {
+        return None;
+    }
+    let chosen = value.get("version").and_then(Value::as_u64);
+    if value.get("ok").and_then(Value::as_bool) != Some(false)
+        && let Some(version) = chosen
+    {
+        return Some(match u32::try_from(version).ok().filter(|v| PROTOCOL_VERSIONS.contains(v)) {
+            Some(version) => Ok(version),
+            None => Err(format!("relay chose version {}, this build supports {}", version, supported())),
+        });
+    }
+    let reason = value.get("reason").and_then(Value::as_str).unwrap_or("no common protocol version");
+    let theirs: Vec<String> = value
+        .get("supported")
+        .and_then(Value::as_array)
+        .map(|versions| versions.iter().filter_map(Value::as_u64).map(|v| v.to_string()).collect())
+        .unwrap_or_default();
+    Some(Err(if theirs.is_empty() {
+        format!("{} (this build supports {})", reason, supported())
+    } else {
+        format!("{} (relay supports {}, this build supports {})", reason, theirs.join(", "), supported())
+    }))
+}
+
+/// Envoie l'annonce et attend le choix du relais (au plus `timeout`). Comme pour
+/// l'authentification, les messages sans rapport reçus entre-temps sont ignorés.
+pub async fn negotiate<S>(ws: &mut S, timeout: Duration) -> Result<u32, VersionError>
+where
+    S: Sink<Message, Error = Error> + Stream<Item = Result<Message, Error>> + Unpin,
+{
+    ws.send(Message::Text(hello().into()))
+        .await
+        .map_err(|e| VersionError::Failed(format!("cannot send the version handshake: {}", e)))?;
+
+    let wait_reply = async {
+        while let Some(message) = ws.next().await {
+            match message {
+                Ok(Message::Text(text)) => {
+                    if let Some(reply) = parse_reply(&text) {
+                        return reply.map_err(VersionError::Incompatible);
+                    }
+                }
+                Ok(Message::Close(frame)) => {
+                    return Err(VersionError::Incompatible(crate::tunnel::describe_close(frame.as_ref())));
+                }
+                Ok(_) => {}
+                Err(e) => return Err(VersionError::Failed(e.to_string())),
+            }
+        }
+        Err(VersionError::Failed("connection closed before the version reply".to_string()))
+    };
+    tokio::time::timeout(timeout, wait_reply).await.unwrap_or_else(|_| {
+        Err(VersionError::Failed(format!("no version reply within {}s", timeout.as_secs())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hello_lists_supported_versions() {
+        let value: Value = serde_json::from_str(&hello()).unwrap();
+        assert_eq!(value["type"], "hello");
+        assert_eq!(value["internal"], true);
+        assert_eq!(value["versions"], serde_json::json!(PROTOCOL_VERSIONS));
+        assert!(value["client"].as_str().unwrap().starts_with("troudever/"));
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(parse_reply(r#"{"type":"version","version":1}"#), Some(Ok(1)));
+        assert_eq!(
+            parse_reply(r#"{"type":"version","version":7}"#),
+            Some(Err("relay chose version 7, this build supports 1".to_string()))
+        );
+        assert_eq!(
+            parse_reply(r#"{"type":"version","ok":false,"reason":"client too old","supported":[2,3]}"#),
+            Some(Err("client too old (relay supports 2, 3, this build supports 1)".to_string()))
+        );
+        assert_eq!(
+            parse_reply(r#"{"type":"version","ok":false}"#),
+            Some(Err("no common protocol version (this build supports 1)".to_string()))
+        );
+        assert_eq!(parse_reply(r#"{"type":"auth_ok"}"#), None);
+        assert_eq!(parse_reply("not json"), None);
+    }
+}